@@ -0,0 +1,5 @@
+DROP TABLE IF EXISTS tbltransactions;
+DROP TABLE IF EXISTS tblcategorybudgets;
+DROP TABLE IF EXISTS tbltransactioncategories;
+DROP TABLE IF EXISTS tblcategorytypes;
+DROP TABLE IF EXISTS tblaccounts;
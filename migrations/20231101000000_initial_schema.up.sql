@@ -0,0 +1,74 @@
+-- Initial schema for the finance API.
+-- Tables are created only when missing so existing databases can adopt migrations.
+
+CREATE TABLE IF NOT EXISTS tblaccounts (
+    id INT NOT NULL AUTO_INCREMENT,
+    name VARCHAR(255) NOT NULL,
+    description TEXT NULL,
+    star TINYINT(1) NOT NULL DEFAULT 0,
+    type VARCHAR(100) NOT NULL,
+    balance DECIMAL(20, 0) NOT NULL DEFAULT 0,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    PRIMARY KEY (id)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
+
+CREATE TABLE IF NOT EXISTS tblcategorytypes (
+    id INT NOT NULL AUTO_INCREMENT,
+    type VARCHAR(100) NOT NULL,
+    description TEXT NULL,
+    icon VARCHAR(100) NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    PRIMARY KEY (id)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
+
+CREATE TABLE IF NOT EXISTS tbltransactioncategories (
+    id INT NOT NULL AUTO_INCREMENT,
+    name VARCHAR(255) NOT NULL,
+    description TEXT NULL,
+    typeid INT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    PRIMARY KEY (id),
+    INDEX idx_tbltransactioncategories_typeid (typeid),
+    CONSTRAINT fk_tbltransactioncategories_typeid
+        FOREIGN KEY (typeid) REFERENCES tblcategorytypes (id)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
+
+CREATE TABLE IF NOT EXISTS tblcategorybudgets (
+    id INT NOT NULL AUTO_INCREMENT,
+    periode VARCHAR(50) NOT NULL,
+    allocated DECIMAL(20, 0) NOT NULL DEFAULT 0,
+    spent DECIMAL(20, 0) NOT NULL DEFAULT 0,
+    available DECIMAL(20, 0) NOT NULL DEFAULT 0,
+    categoryid INT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    PRIMARY KEY (id),
+    INDEX idx_tblcategorybudgets_categoryid (categoryid),
+    CONSTRAINT fk_tblcategorybudgets_categoryid
+        FOREIGN KEY (categoryid) REFERENCES tbltransactioncategories (id)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
+
+CREATE TABLE IF NOT EXISTS tbltransactions (
+    id INT NOT NULL AUTO_INCREMENT,
+    credit DECIMAL(20, 0) NOT NULL DEFAULT 0,
+    debit DECIMAL(20, 0) NOT NULL DEFAULT 0,
+    description TEXT NULL,
+    balance_before DECIMAL(20, 0) NOT NULL DEFAULT 0,
+    balance_after DECIMAL(20, 0) NOT NULL DEFAULT 0,
+    datetime DATETIME NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    accountid INT NOT NULL,
+    categoryid INT NOT NULL,
+    PRIMARY KEY (id),
+    INDEX idx_tbltransactions_accountid (accountid),
+    INDEX idx_tbltransactions_categoryid (categoryid),
+    INDEX idx_tbltransactions_datetime (datetime),
+    CONSTRAINT fk_tbltransactions_accountid
+        FOREIGN KEY (accountid) REFERENCES tblaccounts (id),
+    CONSTRAINT fk_tbltransactions_categoryid
+        FOREIGN KEY (categoryid) REFERENCES tbltransactioncategories (id)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
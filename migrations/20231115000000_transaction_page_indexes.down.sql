@@ -0,0 +1 @@
+DROP INDEX idx_tbltransactions_accountid_datetime ON tbltransactions;
@@ -0,0 +1,2 @@
+-- Keyset pagination of an account's transactions walks (accountid, datetime, id).
+CREATE INDEX idx_tbltransactions_accountid_datetime ON tbltransactions (accountid, datetime);
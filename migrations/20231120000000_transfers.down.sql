@@ -0,0 +1,10 @@
+-- Transfer legs cannot keep a NULL category, drop them before restoring the constraint.
+DELETE FROM tbltransactions WHERE transferid IS NOT NULL;
+
+ALTER TABLE tbltransactions
+    DROP FOREIGN KEY fk_tbltransactions_transferid,
+    DROP INDEX idx_tbltransactions_transferid,
+    DROP COLUMN transferid,
+    MODIFY categoryid INT NOT NULL;
+
+DROP TABLE IF EXISTS tbltransfers;
@@ -0,0 +1,25 @@
+-- Transfers move money between two accounts as a linked debit/credit pair.
+CREATE TABLE tbltransfers (
+    id INT NOT NULL AUTO_INCREMENT,
+    amount DECIMAL(20, 0) NOT NULL DEFAULT 0,
+    description TEXT NULL,
+    datetime DATETIME NOT NULL,
+    fromaccountid INT NOT NULL,
+    toaccountid INT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    PRIMARY KEY (id),
+    INDEX idx_tbltransfers_datetime (datetime),
+    CONSTRAINT fk_tbltransfers_fromaccountid
+        FOREIGN KEY (fromaccountid) REFERENCES tblaccounts (id),
+    CONSTRAINT fk_tbltransfers_toaccountid
+        FOREIGN KEY (toaccountid) REFERENCES tblaccounts (id)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
+
+-- Transfer legs have no category, so they never touch category budgets.
+ALTER TABLE tbltransactions
+    MODIFY categoryid INT NULL,
+    ADD COLUMN transferid INT NULL AFTER categoryid,
+    ADD INDEX idx_tbltransactions_transferid (transferid),
+    ADD CONSTRAINT fk_tbltransactions_transferid
+        FOREIGN KEY (transferid) REFERENCES tbltransfers (id);
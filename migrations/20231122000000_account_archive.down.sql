@@ -0,0 +1,3 @@
+ALTER TABLE tblaccounts
+    DROP INDEX idx_tblaccounts_archived_at,
+    DROP COLUMN archived_at;
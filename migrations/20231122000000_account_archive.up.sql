@@ -0,0 +1,4 @@
+-- Archived accounts are hidden from lists but keep their transactions for history.
+ALTER TABLE tblaccounts
+    ADD COLUMN archived_at TIMESTAMP NULL DEFAULT NULL AFTER balance,
+    ADD INDEX idx_tblaccounts_archived_at (archived_at);
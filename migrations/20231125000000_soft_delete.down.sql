@@ -0,0 +1,24 @@
+-- Deleted transactions and budgets would count again once the column is gone, drop them first.
+-- Deleted accounts, categories and category types simply come back.
+DELETE FROM tbltransactions WHERE deleted_at IS NOT NULL;
+DELETE FROM tblcategorybudgets WHERE deleted_at IS NOT NULL;
+
+ALTER TABLE tbltransactions
+    DROP INDEX idx_tbltransactions_deleted_at,
+    DROP COLUMN deleted_at;
+
+ALTER TABLE tblcategorybudgets
+    DROP INDEX idx_tblcategorybudgets_deleted_at,
+    DROP COLUMN deleted_at;
+
+ALTER TABLE tbltransactioncategories
+    DROP INDEX idx_tbltransactioncategories_deleted_at,
+    DROP COLUMN deleted_at;
+
+ALTER TABLE tblcategorytypes
+    DROP INDEX idx_tblcategorytypes_deleted_at,
+    DROP COLUMN deleted_at;
+
+ALTER TABLE tblaccounts
+    DROP INDEX idx_tblaccounts_deleted_at,
+    DROP COLUMN deleted_at;
@@ -0,0 +1,20 @@
+-- Deleted rows are kept with a `deleted_at` timestamp until the purge job removes them.
+ALTER TABLE tblaccounts
+    ADD COLUMN deleted_at TIMESTAMP NULL DEFAULT NULL AFTER updated_at,
+    ADD INDEX idx_tblaccounts_deleted_at (deleted_at);
+
+ALTER TABLE tblcategorytypes
+    ADD COLUMN deleted_at TIMESTAMP NULL DEFAULT NULL AFTER updated_at,
+    ADD INDEX idx_tblcategorytypes_deleted_at (deleted_at);
+
+ALTER TABLE tbltransactioncategories
+    ADD COLUMN deleted_at TIMESTAMP NULL DEFAULT NULL AFTER updated_at,
+    ADD INDEX idx_tbltransactioncategories_deleted_at (deleted_at);
+
+ALTER TABLE tblcategorybudgets
+    ADD COLUMN deleted_at TIMESTAMP NULL DEFAULT NULL AFTER updated_at,
+    ADD INDEX idx_tblcategorybudgets_deleted_at (deleted_at);
+
+ALTER TABLE tbltransactions
+    ADD COLUMN deleted_at TIMESTAMP NULL DEFAULT NULL AFTER updated_at,
+    ADD INDEX idx_tbltransactions_deleted_at (deleted_at);
@@ -0,0 +1,16 @@
+-- Every add, update, delete and restore of an entity, with its state before and after.
+-- Snapshots are the JSON the API returns for the entity; there is no foreign key so
+-- entries outlive the rows they describe.
+CREATE TABLE tblauditlog (
+    id INT NOT NULL AUTO_INCREMENT,
+    entity VARCHAR(32) NOT NULL,
+    entityid INT NOT NULL,
+    action VARCHAR(16) NOT NULL,
+    actor VARCHAR(255) NULL DEFAULT NULL,
+    before_json LONGTEXT NULL,
+    after_json LONGTEXT NULL,
+    created_at TIMESTAMP(6) NOT NULL DEFAULT CURRENT_TIMESTAMP(6),
+    PRIMARY KEY (id),
+    INDEX idx_tblauditlog_entity (entity, entityid),
+    INDEX idx_tblauditlog_created_at (created_at)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
@@ -0,0 +1,8 @@
+ALTER TABLE tbltransfers
+    DROP COLUMN rate,
+    DROP COLUMN toamount;
+
+DROP TABLE IF EXISTS tblexchangerates;
+
+ALTER TABLE tblaccounts
+    DROP COLUMN currency;
@@ -0,0 +1,25 @@
+-- Accounts hold one currency; their transactions are recorded in it.
+-- Existing accounts take the default base currency, update them if it differs.
+ALTER TABLE tblaccounts
+    ADD COLUMN currency CHAR(3) NOT NULL DEFAULT 'IDR' AFTER type;
+
+-- One `currency` is worth `rate` of `base` from `date` on.
+CREATE TABLE tblexchangerates (
+    id INT NOT NULL AUTO_INCREMENT,
+    currency CHAR(3) NOT NULL,
+    base CHAR(3) NOT NULL,
+    rate DECIMAL(30, 10) NOT NULL,
+    date DATE NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    PRIMARY KEY (id),
+    UNIQUE INDEX idx_tblexchangerates_pair_date (currency, base, date)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
+
+-- A transfer debits `amount` in the source currency and credits `toamount` in the
+-- destination one, `toamount` being `amount * rate`.
+ALTER TABLE tbltransfers
+    ADD COLUMN toamount DECIMAL(20, 0) NOT NULL DEFAULT 0 AFTER amount,
+    ADD COLUMN rate DECIMAL(30, 10) NOT NULL DEFAULT 1 AFTER toamount;
+
+UPDATE tbltransfers SET toamount = amount;
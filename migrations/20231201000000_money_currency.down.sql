@@ -0,0 +1,6 @@
+ALTER TABLE tbltransfers
+    DROP COLUMN tocurrency,
+    DROP COLUMN currency;
+
+ALTER TABLE tbltransactions
+    DROP COLUMN currency;
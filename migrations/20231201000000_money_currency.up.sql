@@ -0,0 +1,18 @@
+-- Amounts are stored in minor units of their currency; rows carry the currency so
+-- an amount can be read without its account.
+ALTER TABLE tbltransactions
+    ADD COLUMN currency CHAR(3) NOT NULL DEFAULT 'IDR' AFTER debit;
+
+UPDATE tbltransactions t
+    JOIN tblaccounts a ON a.id = t.accountid
+    SET t.currency = a.currency;
+
+-- `amount` is in `currency` of the source account, `toamount` in `tocurrency`.
+ALTER TABLE tbltransfers
+    ADD COLUMN currency CHAR(3) NOT NULL DEFAULT 'IDR' AFTER amount,
+    ADD COLUMN tocurrency CHAR(3) NOT NULL DEFAULT 'IDR' AFTER toamount;
+
+UPDATE tbltransfers t
+    JOIN tblaccounts a ON a.id = t.fromaccountid
+    JOIN tblaccounts b ON b.id = t.toaccountid
+    SET t.currency = a.currency, t.tocurrency = b.currency;
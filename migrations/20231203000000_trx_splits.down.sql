@@ -0,0 +1,10 @@
+-- Split transactions fall back to the category of their largest line.
+UPDATE tbltransactions t
+    SET t.categoryid = (
+        SELECT s.categoryid FROM tbltransactionsplits s
+        WHERE s.transactionid = t.id
+        ORDER BY s.amount DESC, s.id ASC LIMIT 1
+    )
+    WHERE t.categoryid IS NULL AND t.transferid IS NULL;
+
+DROP TABLE IF EXISTS tbltransactionsplits;
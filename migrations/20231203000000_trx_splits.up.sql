@@ -0,0 +1,19 @@
+-- A transaction is charged either to its single `categoryid` or, with a NULL
+-- `categoryid`, to the categories of its split lines. Split amounts are positive
+-- and add up to the `credit + debit` of the transaction.
+CREATE TABLE tbltransactionsplits (
+    id INT NOT NULL AUTO_INCREMENT,
+    transactionid INT NOT NULL,
+    categoryid INT NOT NULL,
+    amount DECIMAL(20, 0) NOT NULL DEFAULT 0,
+    memo TEXT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    PRIMARY KEY (id),
+    INDEX idx_tbltransactionsplits_transactionid (transactionid),
+    INDEX idx_tbltransactionsplits_categoryid (categoryid),
+    CONSTRAINT fk_tbltransactionsplits_transactionid
+        FOREIGN KEY (transactionid) REFERENCES tbltransactions (id) ON DELETE CASCADE,
+    CONSTRAINT fk_tbltransactionsplits_categoryid
+        FOREIGN KEY (categoryid) REFERENCES tbltransactioncategories (id)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
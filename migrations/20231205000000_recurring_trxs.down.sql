@@ -0,0 +1,2 @@
+-- Transactions already posted by the templates are kept.
+DROP TABLE IF EXISTS tblrecurringtrxs;
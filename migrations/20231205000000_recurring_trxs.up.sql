@@ -0,0 +1,28 @@
+-- Recurring templates post a transaction on every occurrence of their schedule.
+-- `posted` counts the occurrences already posted or skipped and `nextdate` is
+-- the one due next, NULL once the end date or the number of occurrences is reached.
+CREATE TABLE tblrecurringtrxs (
+    id INT NOT NULL AUTO_INCREMENT,
+    credit DECIMAL(20, 0) NOT NULL DEFAULT 0,
+    debit DECIMAL(20, 0) NOT NULL DEFAULT 0,
+    currency CHAR(3) NOT NULL,
+    description TEXT NULL,
+    accountid INT NOT NULL,
+    categoryid INT NOT NULL,
+    frequency VARCHAR(16) NOT NULL,
+    every INT NOT NULL DEFAULT 1,
+    dayofmonth INT NULL,
+    startdate DATETIME NOT NULL,
+    enddate DATETIME NULL,
+    occurrences INT NULL,
+    posted INT NOT NULL DEFAULT 0,
+    nextdate DATETIME NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    PRIMARY KEY (id),
+    INDEX idx_tblrecurringtrxs_nextdate (nextdate),
+    CONSTRAINT fk_tblrecurringtrxs_accountid
+        FOREIGN KEY (accountid) REFERENCES tblaccounts (id),
+    CONSTRAINT fk_tblrecurringtrxs_categoryid
+        FOREIGN KEY (categoryid) REFERENCES tbltransactioncategories (id)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
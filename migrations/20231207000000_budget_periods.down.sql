@@ -0,0 +1,9 @@
+-- `periode` keeps the label of each period.
+ALTER TABLE tblcategorybudgets
+    DROP INDEX idx_tblcategorybudgets_period,
+    DROP COLUMN rollover,
+    DROP COLUMN carryover,
+    DROP COLUMN carried,
+    DROP COLUMN periodend,
+    DROP COLUMN periodstart,
+    DROP COLUMN periodtype;
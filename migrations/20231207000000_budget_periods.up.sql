@@ -0,0 +1,30 @@
+-- Budget periods become date ranges: `periode` is the label of a month
+-- (2023-11), an ISO week (2023-W48) or a year (2023) and `periodstart` to
+-- `periodend` the days it covers. Existing budgets whose `periode` is not one
+-- of those keep NULL dates and are left out of the rollover.
+ALTER TABLE tblcategorybudgets
+    ADD COLUMN periodtype VARCHAR(16) NULL AFTER periode,
+    ADD COLUMN periodstart DATE NULL AFTER periodtype,
+    ADD COLUMN periodend DATE NULL AFTER periodstart,
+    ADD COLUMN carried DECIMAL(20, 0) NOT NULL DEFAULT 0 AFTER allocated,
+    ADD COLUMN carryover VARCHAR(16) NOT NULL DEFAULT 'none' AFTER available,
+    ADD COLUMN rollover TINYINT(1) NOT NULL DEFAULT 1 AFTER carryover,
+    ADD INDEX idx_tblcategorybudgets_period (categoryid, periodstart);
+
+UPDATE tblcategorybudgets
+    SET periodtype = 'monthly',
+        periodstart = STR_TO_DATE(CONCAT(periode, '-01'), '%Y-%m-%d'),
+        periodend = LAST_DAY(STR_TO_DATE(CONCAT(periode, '-01'), '%Y-%m-%d'))
+    WHERE periode REGEXP '^[0-9]{4}-(0[1-9]|1[0-2])$';
+
+UPDATE tblcategorybudgets
+    SET periodtype = 'weekly',
+        periodstart = STR_TO_DATE(CONCAT(periode, ' Monday'), '%x-W%v %W'),
+        periodend = STR_TO_DATE(CONCAT(periode, ' Monday'), '%x-W%v %W') + INTERVAL 6 DAY
+    WHERE periode REGEXP '^[0-9]{4}-W(0[1-9]|[1-4][0-9]|5[0-3])$';
+
+UPDATE tblcategorybudgets
+    SET periodtype = 'yearly',
+        periodstart = MAKEDATE(CAST(periode AS UNSIGNED), 1),
+        periodend = MAKEDATE(CAST(periode AS UNSIGNED), 1) + INTERVAL 1 YEAR - INTERVAL 1 DAY
+    WHERE periode REGEXP '^[0-9]{4}$';
@@ -0,0 +1,3 @@
+-- Budgets go back to counting every transaction as spent.
+ALTER TABLE tblcategorytypes
+    DROP COLUMN direction;
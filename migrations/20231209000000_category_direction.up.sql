@@ -0,0 +1,12 @@
+-- Category types declare which way their money goes: `expense` budgets count
+-- what is spent, `income` budgets what is received, `transfer` and `neutral`
+-- categories are not budgeted. Types named after income or transfers are
+-- guessed, every other one stays an expense.
+ALTER TABLE tblcategorytypes
+    ADD COLUMN direction VARCHAR(16) NOT NULL DEFAULT 'expense' AFTER type;
+
+UPDATE tblcategorytypes SET direction = 'income'
+    WHERE LOWER(TRIM(type)) IN ('income', 'pemasukan');
+
+UPDATE tblcategorytypes SET direction = 'transfer'
+    WHERE LOWER(TRIM(type)) IN ('transfer', 'transfers');
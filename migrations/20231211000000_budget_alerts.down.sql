@@ -0,0 +1,5 @@
+-- Budgets go back to having no thresholds.
+DROP TABLE IF EXISTS tblbudgetalerts;
+
+ALTER TABLE tblcategorybudgets
+    DROP COLUMN thresholds;
@@ -0,0 +1,21 @@
+-- Warning thresholds of each budget, percents of `allocated` such as `80,100`,
+-- and the alerts raised once `spent` reaches one of them. A threshold raises
+-- a single alert per budget, so once per period. Like the audit log, alerts
+-- have no foreign key and outlive the budget they are about.
+ALTER TABLE tblcategorybudgets
+    ADD COLUMN thresholds VARCHAR(255) NOT NULL DEFAULT '' AFTER rollover;
+
+CREATE TABLE tblbudgetalerts (
+    id INT NOT NULL AUTO_INCREMENT,
+    budgetid INT NOT NULL,
+    categoryid INT NOT NULL,
+    periode VARCHAR(50) NOT NULL,
+    threshold INT NOT NULL,
+    allocated DECIMAL(20, 0) NOT NULL,
+    spent DECIMAL(20, 0) NOT NULL,
+    notified_at TIMESTAMP NULL DEFAULT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id),
+    UNIQUE INDEX idx_tblbudgetalerts_budget_threshold (budgetid, threshold),
+    INDEX idx_tblbudgetalerts_notified_at (notified_at)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
@@ -0,0 +1,4 @@
+-- Alerts go back to being sent in id order only.
+ALTER TABLE tblbudgetalerts
+    DROP COLUMN attempted_at,
+    DROP COLUMN attempts;
@@ -0,0 +1,6 @@
+-- Alerts count the runs that failed to send them. The job takes the alerts
+-- never tried first, then those tried the longest ago, so alerts that keep
+-- failing no longer hold back the newer ones.
+ALTER TABLE tblbudgetalerts
+    ADD COLUMN attempts INT NOT NULL DEFAULT 0 AFTER spent,
+    ADD COLUMN attempted_at TIMESTAMP NULL DEFAULT NULL AFTER attempts;
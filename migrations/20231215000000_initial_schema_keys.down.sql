@@ -0,0 +1,2 @@
+-- The keys belong to the initial schema, which drops them with its tables.
+DO 0;
@@ -0,0 +1,93 @@
+-- Keys of the initial schema. `CREATE TABLE IF NOT EXISTS` left out those of tables
+-- that existed before migrations, so each one is added here unless it is already there.
+-- MySQL has no `ADD INDEX IF NOT EXISTS`, the check goes through information_schema.
+
+SET @ddl = IF(
+    (SELECT COUNT(*) FROM information_schema.statistics
+        WHERE table_schema = DATABASE() AND table_name = 'tbltransactioncategories' AND index_name = 'idx_tbltransactioncategories_typeid') = 0,
+    'ALTER TABLE tbltransactioncategories ADD INDEX idx_tbltransactioncategories_typeid (typeid)',
+    'DO 0'
+);
+PREPARE stmt FROM @ddl;
+EXECUTE stmt;
+DEALLOCATE PREPARE stmt;
+
+SET @ddl = IF(
+    (SELECT COUNT(*) FROM information_schema.statistics
+        WHERE table_schema = DATABASE() AND table_name = 'tblcategorybudgets' AND index_name = 'idx_tblcategorybudgets_categoryid') = 0,
+    'ALTER TABLE tblcategorybudgets ADD INDEX idx_tblcategorybudgets_categoryid (categoryid)',
+    'DO 0'
+);
+PREPARE stmt FROM @ddl;
+EXECUTE stmt;
+DEALLOCATE PREPARE stmt;
+
+SET @ddl = IF(
+    (SELECT COUNT(*) FROM information_schema.statistics
+        WHERE table_schema = DATABASE() AND table_name = 'tbltransactions' AND index_name = 'idx_tbltransactions_accountid') = 0,
+    'ALTER TABLE tbltransactions ADD INDEX idx_tbltransactions_accountid (accountid)',
+    'DO 0'
+);
+PREPARE stmt FROM @ddl;
+EXECUTE stmt;
+DEALLOCATE PREPARE stmt;
+
+SET @ddl = IF(
+    (SELECT COUNT(*) FROM information_schema.statistics
+        WHERE table_schema = DATABASE() AND table_name = 'tbltransactions' AND index_name = 'idx_tbltransactions_categoryid') = 0,
+    'ALTER TABLE tbltransactions ADD INDEX idx_tbltransactions_categoryid (categoryid)',
+    'DO 0'
+);
+PREPARE stmt FROM @ddl;
+EXECUTE stmt;
+DEALLOCATE PREPARE stmt;
+
+SET @ddl = IF(
+    (SELECT COUNT(*) FROM information_schema.statistics
+        WHERE table_schema = DATABASE() AND table_name = 'tbltransactions' AND index_name = 'idx_tbltransactions_datetime') = 0,
+    'ALTER TABLE tbltransactions ADD INDEX idx_tbltransactions_datetime (datetime)',
+    'DO 0'
+);
+PREPARE stmt FROM @ddl;
+EXECUTE stmt;
+DEALLOCATE PREPARE stmt;
+
+SET @ddl = IF(
+    (SELECT COUNT(*) FROM information_schema.table_constraints
+        WHERE constraint_schema = DATABASE() AND table_name = 'tbltransactioncategories' AND constraint_name = 'fk_tbltransactioncategories_typeid' AND constraint_type = 'FOREIGN KEY') = 0,
+    'ALTER TABLE tbltransactioncategories ADD CONSTRAINT fk_tbltransactioncategories_typeid FOREIGN KEY (typeid) REFERENCES tblcategorytypes (id)',
+    'DO 0'
+);
+PREPARE stmt FROM @ddl;
+EXECUTE stmt;
+DEALLOCATE PREPARE stmt;
+
+SET @ddl = IF(
+    (SELECT COUNT(*) FROM information_schema.table_constraints
+        WHERE constraint_schema = DATABASE() AND table_name = 'tblcategorybudgets' AND constraint_name = 'fk_tblcategorybudgets_categoryid' AND constraint_type = 'FOREIGN KEY') = 0,
+    'ALTER TABLE tblcategorybudgets ADD CONSTRAINT fk_tblcategorybudgets_categoryid FOREIGN KEY (categoryid) REFERENCES tbltransactioncategories (id)',
+    'DO 0'
+);
+PREPARE stmt FROM @ddl;
+EXECUTE stmt;
+DEALLOCATE PREPARE stmt;
+
+SET @ddl = IF(
+    (SELECT COUNT(*) FROM information_schema.table_constraints
+        WHERE constraint_schema = DATABASE() AND table_name = 'tbltransactions' AND constraint_name = 'fk_tbltransactions_accountid' AND constraint_type = 'FOREIGN KEY') = 0,
+    'ALTER TABLE tbltransactions ADD CONSTRAINT fk_tbltransactions_accountid FOREIGN KEY (accountid) REFERENCES tblaccounts (id)',
+    'DO 0'
+);
+PREPARE stmt FROM @ddl;
+EXECUTE stmt;
+DEALLOCATE PREPARE stmt;
+
+SET @ddl = IF(
+    (SELECT COUNT(*) FROM information_schema.table_constraints
+        WHERE constraint_schema = DATABASE() AND table_name = 'tbltransactions' AND constraint_name = 'fk_tbltransactions_categoryid' AND constraint_type = 'FOREIGN KEY') = 0,
+    'ALTER TABLE tbltransactions ADD CONSTRAINT fk_tbltransactions_categoryid FOREIGN KEY (categoryid) REFERENCES tbltransactioncategories (id)',
+    'DO 0'
+);
+PREPARE stmt FROM @ddl;
+EXECUTE stmt;
+DEALLOCATE PREPARE stmt;
@@ -0,0 +1,80 @@
+use crate::config::AlertsConfig;
+use crate::errors::AppError;
+use crate::models::alerts::AlertReport;
+use crate::notifiers::{self, Notifier};
+use crate::repositories::alerts::{AlertRepo, AlertTrait};
+
+use sqlx::mysql::MySqlPool;
+
+type GenericError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Alerts delivered by one run at most, the rest wait for the next run.
+const BATCH: i64 = 100;
+
+/// Entry point of the `alerts` subcommand, a single run whether or not the
+/// background job is enabled.
+pub async fn command(pool: &MySqlPool, config: &AlertsConfig, base_currency: &str, args: &[String]) -> Result<(), GenericError> {
+    if let Some(other) = args.first() {
+        return Err(format!("unknown alerts argument `{}`", other).into());
+    }
+
+    let notifiers = notifiers::from_config(config)?;
+    let report = run(pool, &notifiers, base_currency).await?;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+
+    Ok(())
+}
+
+/// Starts the background job delivering the pending alerts every `interval`.
+pub fn spawn(pool: MySqlPool, config: AlertsConfig, base_currency: String) -> Result<(), GenericError> {
+    let notifiers = notifiers::from_config(&config)?;
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(config.interval());
+
+        loop {
+            interval.tick().await;
+
+            match run(&pool, &notifiers, &base_currency).await {
+                Ok(report) if report.delivered > 0 || report.failed > 0 => log::info!("delivered budget alerts: {:?}", report),
+                Ok(_) => {}
+                Err(err) => log::error!("alerts run failed: {}", err),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Sends the pending alerts through each notifier. An alert is marked sent
+/// only once all of them delivered it, a failing one is sent again, to every
+/// notifier, by a next run. Failed alerts wait behind the ones never tried, so
+/// a batch of them that keeps failing does not hold back the newer ones.
+async fn run(pool: &MySqlPool, notifiers: &[Box<dyn Notifier>], base_currency: &str) -> Result<AlertReport, AppError> {
+    let mut repo = AlertRepo::new(pool.clone());
+    let alerts = repo.alerts_pending(BATCH).await?;
+
+    let mut report = AlertReport::default();
+    for alert in alerts {
+        let mut delivered = true;
+        for notifier in notifiers.iter() {
+            if let Err(err) = notifier.notify(&alert, base_currency).await {
+                log::error!("budget alert {} not sent by {}: {}", alert.id, notifier.name(), err);
+                delivered = false;
+            }
+        }
+
+        match delivered {
+            true => {
+                repo.alert_notified(alert.id).await?;
+                report.delivered += 1;
+            },
+            false => {
+                repo.alert_failed(alert.id).await?;
+                report.failed += 1;
+            },
+        }
+    }
+
+    Ok(report)
+}
@@ -0,0 +1,149 @@
+use std::fmt;
+use hyper::{header, Body, Method, Response, StatusCode};
+use serde::Serialize;
+
+/// Error type shared by repositories and handlers.
+///
+/// Every variant maps to one HTTP status code and is rendered as a JSON
+/// body by [`AppError::into_response`].
+#[derive(Debug)]
+pub enum AppError {
+    /// The requested row does not exist (404).
+    NotFound(String),
+    /// The request could not be read or parsed (400).
+    BadRequest(String),
+    /// The request was well formed but its content is invalid (422).
+    Validation(String),
+    /// The request conflicts with the current state of the data (409).
+    Conflict(String),
+    /// The route exists but does not accept this method (405), carries the allowed ones.
+    MethodNotAllowed(Vec<Method>),
+    /// The request body exceeds the configured limit (413).
+    PayloadTooLarge(usize),
+    /// Any other database failure (500).
+    Database(sqlx::Error),
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: &'static str,
+    message: String,
+}
+
+impl AppError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::MethodNotAllowed(_) => StatusCode::METHOD_NOT_ALLOWED,
+            AppError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            AppError::NotFound(_) => "not_found",
+            AppError::BadRequest(_) => "bad_request",
+            AppError::Validation(_) => "validation",
+            AppError::Conflict(_) => "conflict",
+            AppError::MethodNotAllowed(_) => "method_not_allowed",
+            AppError::PayloadTooLarge(_) => "payload_too_large",
+            AppError::Database(_) => "database",
+        }
+    }
+
+    pub fn into_response(self) -> Response<Body> {
+        // Never leak driver messages to the client, log them instead.
+        let message = match &self {
+            AppError::Database(err) => {
+                log::error!("database error: {}", err);
+                String::from("Internal Server Error")
+            }
+            _ => self.to_string(),
+        };
+
+        let body = ErrorBody {
+            error: self.code(),
+            message,
+        };
+
+        let json = serde_json::to_string(&body)
+            .unwrap_or_else(|_| String::from(r#"{"error":"internal"}"#));
+
+        let mut builder = Response::builder()
+            .status(self.status())
+            .header(header::CONTENT_TYPE, "application/json");
+
+        if let AppError::MethodNotAllowed(methods) = &self {
+            let allow: Vec<&str> = methods.iter().map(Method::as_str).collect();
+            builder = builder.header(header::ALLOW, allow.join(", "));
+        }
+
+        builder.body(Body::from(json)).unwrap()
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::NotFound(msg)
+            | AppError::BadRequest(msg)
+            | AppError::Validation(msg)
+            | AppError::Conflict(msg) => write!(f, "{}", msg),
+            AppError::MethodNotAllowed(_) => write!(f, "method not allowed"),
+            AppError::PayloadTooLarge(limit) => write!(f, "request body exceeds {} bytes", limit),
+            AppError::Database(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for AppError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AppError::Database(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(err: sqlx::Error) -> Self {
+        match &err {
+            sqlx::Error::RowNotFound => AppError::NotFound(String::from("Not Found")),
+            sqlx::Error::Database(db_err) => match db_err.kind() {
+                sqlx::error::ErrorKind::UniqueViolation => {
+                    AppError::Conflict(String::from("record already exists"))
+                }
+                sqlx::error::ErrorKind::ForeignKeyViolation => {
+                    AppError::Conflict(String::from("record is referenced by or references missing data"))
+                }
+                _ => AppError::Database(err),
+            },
+            _ => AppError::Database(err),
+        }
+    }
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(err: serde_json::Error) -> Self {
+        match err.classify() {
+            serde_json::error::Category::Data => AppError::Validation(err.to_string()),
+            _ => AppError::BadRequest(format!("invalid JSON body: {}", err)),
+        }
+    }
+}
+
+impl From<hyper::Error> for AppError {
+    fn from(err: hyper::Error) -> Self {
+        AppError::BadRequest(format!("failed to read request body: {}", err))
+    }
+}
+
+impl From<std::str::Utf8Error> for AppError {
+    fn from(_: std::str::Utf8Error) -> Self {
+        AppError::BadRequest(String::from("request body is not valid UTF-8"))
+    }
+}
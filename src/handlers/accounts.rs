@@ -1,34 +1,39 @@
 
-use crate::handlers::req_query_id;
-use crate::models::accounts::{ new_account, AccountModel, NewAccount, UpdateAccount };
-use crate::repositories::accounts::{AccountRepo, AccountTrait};
+use crate::errors::AppError;
+use crate::handlers::{AppState, Route, read_body, req_actor, get_req_query, req_include_deleted, req_money_format, req_page_params, req_query_parse};
+use crate::models::accounts::{ new_account, account_delete_policy, AccountModel, NewAccount, UpdateAccount, ACCOUNT_SORT_FIELDS };
+use crate::models::pagination::Page;
+use crate::repositories::accounts::{AccountRepo, AccountTrait, Trait};
 
-use std::env;
 use sqlx::mysql::MySqlPool;
 use hyper::{header, Body, Method, Request, Response, StatusCode};
 
-type GenericError = Box<dyn std::error::Error + Send + Sync>;
-type Result<T> = std::result::Result<T, GenericError>;
+type Result<T> = std::result::Result<T, AppError>;
 
 static INTERNAL_SERVER_ERROR: &[u8] = b"Internal Server Error";
-static NOTFOUND: &[u8] = b"Not Found";
 
 pub struct AccountHandler<'a>{
     account_repo: AccountRepo,
     request: &'a Request<Body>,
+    route: Route,
 }
 
 impl<'a> AccountHandler<'a> {
-    pub fn new(req: &'a Request<Body>, pool: MySqlPool) -> Self {
+    pub fn new(req: &'a Request<Body>, pool: MySqlPool, base_currency: &str, route: Route) -> Self {
         Self { 
-            account_repo: AccountRepo::new(pool),
+            account_repo: AccountRepo::new(pool).with_actor(req_actor(req)).with_base_currency(base_currency),
             request: req,
+            route,
         }
     }
 
     async fn list(&mut self) -> Result<Response<Body>> {
 
-        let datas = self.account_repo.account_list().await?;
+        let format = req_money_format(self.request)?;
+        let page = req_page_params(self.request, ACCOUNT_SORT_FIELDS, "id")?;
+        let include_archived = req_query_parse::<bool>(self.request, "includeArchived")?.unwrap_or(false);
+        let include_deleted = req_include_deleted(self.request)?;
+        let (datas, next_cursor) = self.account_repo.account_list(page, include_archived, include_deleted).await?;
 
         let accounts: Vec<AccountModel> = datas.iter().map(|account| new_account(
             &account.id, 
@@ -36,12 +41,17 @@ impl<'a> AccountHandler<'a> {
             &account.description, 
             &account.star, 
             &account.r#type, 
+            &account.currency,
             &account.balance,
+            &account.archived_at,
             &account.created_at,
             &account.updated_at,
+            &account.deleted_at,
+            format,
         )).collect();
+        let page = Page { data: accounts, next_cursor };
 
-        let res = match serde_json::to_string(&accounts) {
+        let res = match serde_json::to_string(&page) {
             Ok(json) => Response::builder()
                 .header(header::CONTENT_TYPE, "application/json")
                 .body(Body::from(json))
@@ -56,8 +66,10 @@ impl<'a> AccountHandler<'a> {
 
     async fn detail(&mut self) -> Result<Response<Body>> { 
 
-        let query_id = req_query_id(self.request);
-        let datas = self.account_repo.account_detail(query_id).await?;
+        let format = req_money_format(self.request)?;
+        let query_id = self.route.id()?;
+        let include_deleted = req_include_deleted(self.request)?;
+        let datas = self.account_repo.account_detail(query_id, include_deleted).await?;
 
         let account = new_account(
             &datas.id, 
@@ -65,9 +77,13 @@ impl<'a> AccountHandler<'a> {
             &datas.description, 
             &datas.star, 
             &datas.r#type, 
+            &datas.currency,
             &datas.balance,
+            &datas.archived_at,
             &datas.created_at,
             &datas.updated_at,
+            &datas.deleted_at,
+            format,
         );
 
         let res = match serde_json::to_string(&account) {
@@ -85,8 +101,12 @@ impl<'a> AccountHandler<'a> {
 
     async fn add(&mut self, body: &str) -> Result<Response<Body>> { 
 
+        let format = req_money_format(self.request)?;
         let data: NewAccount = serde_json::from_str(body)?;
-        let new_acc = self.account_repo.account_add(data.clone()).await?;
+
+        let mut tx = self.account_repo.start_transaction().await?;
+        let new_acc = tx.account_add(data.clone()).await?;
+        tx.commit().await?;
 
         let account = new_account(
             &new_acc.id, 
@@ -94,9 +114,13 @@ impl<'a> AccountHandler<'a> {
             &new_acc.description, 
             &new_acc.star, 
             &new_acc.r#type, 
+            &new_acc.currency,
             &new_acc.balance,
+            &new_acc.archived_at,
             &new_acc.created_at,
             &new_acc.updated_at,
+            &new_acc.deleted_at,
+            format,
         );
 
         let res = match serde_json::to_string(&account) {
@@ -114,9 +138,13 @@ impl<'a> AccountHandler<'a> {
 
     async fn update(&mut self, body: &str) -> Result<Response<Body>> { 
 
-        let query_id = req_query_id(self.request);
+        let format = req_money_format(self.request)?;
+        let query_id = self.route.id()?;
         let data: UpdateAccount = serde_json::from_str(body)?;
-        let update_acc = self.account_repo.account_update(query_id, data.clone()).await?;
+
+        let mut tx = self.account_repo.start_transaction().await?;
+        let update_acc = tx.account_update(query_id, data.clone()).await?;
+        tx.commit().await?;
 
         let account = new_account(
             &update_acc.id, 
@@ -124,9 +152,13 @@ impl<'a> AccountHandler<'a> {
             &update_acc.description, 
             &update_acc.star, 
             &update_acc.r#type, 
+            &update_acc.currency,
             &update_acc.balance,
+            &update_acc.archived_at,
             &update_acc.created_at,
             &update_acc.updated_at,
+            &update_acc.deleted_at,
+            format,
         );
 
         let res = match serde_json::to_string(&account) {
@@ -144,8 +176,14 @@ impl<'a> AccountHandler<'a> {
 
     async fn delete(&mut self) -> Result<Response<Body>> { 
 
-        let query_id = req_query_id(self.request);
-        let delete_acc = self.account_repo.account_delete(query_id).await?;
+        let query_id = self.route.id()?;
+        let policy = get_req_query(self.request, String::from("policy"));
+        let to = req_query_parse::<i32>(self.request, "to")?;
+        let policy = account_delete_policy(policy.as_deref(), to)?;
+
+        let mut tx = self.account_repo.start_transaction().await?;
+        let delete_acc = tx.account_delete(query_id, policy).await?;
+        tx.commit().await?;
 
         let res = match delete_acc {
             true => Response::builder()
@@ -159,35 +197,68 @@ impl<'a> AccountHandler<'a> {
         };
         Ok(res)
     }
+
+    async fn restore(&mut self) -> Result<Response<Body>> { 
+
+        let format = req_money_format(self.request)?;
+        let query_id = self.route.id()?;
+        let mut tx = self.account_repo.start_transaction().await?;
+        let restore_acc = tx.account_restore(query_id).await?;
+        tx.commit().await?;
+
+        let account = new_account(
+            &restore_acc.id, 
+            &restore_acc.name, 
+            &restore_acc.description, 
+            &restore_acc.star, 
+            &restore_acc.r#type, 
+            &restore_acc.currency,
+            &restore_acc.balance,
+            &restore_acc.archived_at,
+            &restore_acc.created_at,
+            &restore_acc.updated_at,
+            &restore_acc.deleted_at,
+            format,
+        );
+
+        let res = match serde_json::to_string(&account) {
+            Ok(json) => Response::builder()
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(json))
+                .unwrap(),
+            Err(_) => Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(INTERNAL_SERVER_ERROR.into())
+                .unwrap(),
+        };
+        Ok(res)
+    }
 }
 
-pub async fn handler( req: Request<Body> ) -> Result<Response<Body>> {
-    let pool = MySqlPool::connect(&env::var("DATABASE_URL").unwrap()).await?;
+pub async fn handler( req: Request<Body>, state: AppState, route: Route ) -> Result<Response<Body>> {
+    let pool = state.pool;
 
     let (parts, body) = req.into_parts();
-    let body_bytes = hyper::body::to_bytes(body).await.unwrap();
-    let body = std::str::from_utf8(&body_bytes).unwrap();
+    let body_bytes = read_body(body, state.config.server.body_limit).await?;
+    let body = std::str::from_utf8(&body_bytes)?;
     
     let request: hyper::Request<Body> = Request::from_parts(parts, body_bytes.clone().into());
-    let mut account_handler = AccountHandler::new(&request, pool);
+    let mut account_handler = AccountHandler::new(&request, pool, &state.config.currency.base, route);
 
-    match (request.method(), request.uri().query().is_none()) {
+    match (request.method(), route.id.is_some()) {
 
-        (&Method::GET, true) => account_handler.list().await,
-        (&Method::GET, false) => account_handler.detail().await,
-        (&Method::PUT, true) => account_handler.add(body).await,
-        (&Method::POST, false) => account_handler.update(body).await,
-        (&Method::DELETE, false) => account_handler.delete().await,
+        (&Method::POST, true) if route.restore => account_handler.restore().await,
+        _ if route.restore => Err(AppError::MethodNotAllowed(route.allowed())),
+
+        (&Method::GET, false) => account_handler.list().await,
+        (&Method::GET, true) => account_handler.detail().await,
+        (&Method::POST, false) => account_handler.add(body).await,
+        (&Method::PUT, true) | (&Method::PATCH, true) => account_handler.update(body).await,
+        (&Method::DELETE, true) => account_handler.delete().await,
 
         // 
-        _ => {
-            // Return 404 not found response.
-            Ok(Response::builder()
-                .status(StatusCode::NOT_FOUND)
-                .body(NOTFOUND.into())
-                .unwrap())
-        }
+        _ => Err(AppError::MethodNotAllowed(route.allowed())),
         
     }
 
-}
\ No newline at end of file
+}
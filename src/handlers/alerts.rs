@@ -0,0 +1,77 @@
+use crate::errors::AppError;
+use crate::handlers::{AppState, Route, req_money_format, req_page_params, req_query_parse};
+use crate::models::alerts::{ AlertModel, AlertFilter, build_model_from_exist, ALERT_SORT_FIELDS };
+use crate::models::pagination::Page;
+use crate::repositories::alerts::{AlertRepo, AlertTrait};
+
+use sqlx::mysql::MySqlPool;
+use hyper::{header, Body, Method, Request, Response, StatusCode};
+
+type Result<T> = std::result::Result<T, AppError>;
+
+static INTERNAL_SERVER_ERROR: &[u8] = b"Internal Server Error";
+
+pub struct AlertHandler<'a>{
+    alert_repo: AlertRepo,
+    request: &'a Request<Body>,
+    base_currency: String,
+}
+
+impl<'a> AlertHandler<'a> {
+    pub fn new(req: &'a Request<Body>, pool: MySqlPool, base_currency: &str) -> Self {
+        Self { 
+            alert_repo: AlertRepo::new(pool),
+            request: req,
+            base_currency: base_currency.to_string(),
+        }
+    }
+
+    fn filter(&self) -> Result<AlertFilter> {
+
+        Ok(AlertFilter {
+            budgetid: req_query_parse(self.request, "budgetid")?,
+            categoryid: req_query_parse(self.request, "categoryid")?,
+            notified: req_query_parse(self.request, "notified")?,
+        })
+    }
+
+    async fn list(&mut self) -> Result<Response<Body>> {
+
+        let filter = self.filter()?;
+        let format = req_money_format(self.request)?;
+        let page = req_page_params(self.request, ALERT_SORT_FIELDS, "-createdAt")?;
+        let (datas, next_cursor) = self.alert_repo.alerts_list(filter, page).await?;
+
+        let alerts: Vec<AlertModel> = datas.into_iter().map(|data| build_model_from_exist(data, &self.base_currency, format)).collect();
+        let page = Page { data: alerts, next_cursor };
+
+        let res = match serde_json::to_string(&page) {
+            Ok(json) => Response::builder()
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(json))
+                .unwrap(),
+            Err(_) => Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(INTERNAL_SERVER_ERROR.into())
+                .unwrap(),
+        };
+        Ok(res)
+    }
+}
+
+pub async fn handler( req: Request<Body>, state: AppState, route: Route ) -> Result<Response<Body>> {
+    let pool = state.pool;
+
+    let mut alert_handler = AlertHandler::new(&req, pool, &state.config.currency.base);
+
+    match (req.method(), route.id.is_some()) {
+
+        (&Method::GET, false) => alert_handler.list().await,
+
+        // alerts are raised by budget changes and only listed
+        (_, true) => Err(AppError::NotFound(String::from("Not Found"))),
+        _ => Err(AppError::MethodNotAllowed(vec![Method::GET])),
+        
+    }
+
+}
@@ -0,0 +1,85 @@
+use crate::errors::AppError;
+use crate::handlers::{AppState, Route, get_req_query, req_page_params, req_query_parse};
+use crate::models::audit::{ AuditModel, AuditFilter, build_model_from_exist, AUDIT_SORT_FIELDS };
+use crate::models::pagination::Page;
+use crate::models::trxs::parse_filter_datetime;
+use crate::repositories::audit::{AuditRepo, AuditTrait};
+
+use sqlx::mysql::MySqlPool;
+use hyper::{header, Body, Method, Request, Response, StatusCode};
+
+type Result<T> = std::result::Result<T, AppError>;
+
+static INTERNAL_SERVER_ERROR: &[u8] = b"Internal Server Error";
+
+pub struct AuditHandler<'a>{
+    audit_repo: AuditRepo,
+    request: &'a Request<Body>,
+}
+
+impl<'a> AuditHandler<'a> {
+    pub fn new(req: &'a Request<Body>, pool: MySqlPool) -> Self {
+        Self { 
+            audit_repo: AuditRepo::new(pool),
+            request: req,
+        }
+    }
+
+    fn filter(&self) -> Result<AuditFilter> {
+
+        let from = match get_req_query(self.request, String::from("from")) {
+            Some(value) => Some(parse_filter_datetime("from", &value, false)?),
+            None => None,
+        };
+        let to = match get_req_query(self.request, String::from("to")) {
+            Some(value) => Some(parse_filter_datetime("to", &value, true)?),
+            None => None,
+        };
+
+        Ok(AuditFilter {
+            entity: req_query_parse(self.request, "entity")?,
+            entityid: req_query_parse(self.request, "entityid")?,
+            from,
+            to,
+        })
+    }
+
+    async fn list(&mut self) -> Result<Response<Body>> {
+
+        let filter = self.filter()?;
+        let page = req_page_params(self.request, AUDIT_SORT_FIELDS, "-createdAt")?;
+        let (datas, next_cursor) = self.audit_repo.audit_list(filter, page).await?;
+
+        let entries: Vec<AuditModel> = datas.into_iter().map(build_model_from_exist).collect();
+        let page = Page { data: entries, next_cursor };
+
+        let res = match serde_json::to_string(&page) {
+            Ok(json) => Response::builder()
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(json))
+                .unwrap(),
+            Err(_) => Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(INTERNAL_SERVER_ERROR.into())
+                .unwrap(),
+        };
+        Ok(res)
+    }
+}
+
+pub async fn handler( req: Request<Body>, state: AppState, route: Route ) -> Result<Response<Body>> {
+    let pool = state.pool;
+
+    let mut audit_handler = AuditHandler::new(&req, pool);
+
+    match (req.method(), route.id.is_some()) {
+
+        (&Method::GET, false) => audit_handler.list().await,
+
+        // the log is read only and its entries are only listed
+        (_, true) => Err(AppError::NotFound(String::from("Not Found"))),
+        _ => Err(AppError::MethodNotAllowed(vec![Method::GET])),
+        
+    }
+
+}
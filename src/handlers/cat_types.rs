@@ -1,29 +1,45 @@
 
-use crate::handlers::req_query_id;
-use crate::models::bigdecimal_to_int;
+use crate::errors::AppError;
+use crate::handlers::{AppState, Route, get_req_query, read_body, req_actor, req_include_deleted, req_money_format};
 use crate::models::cat_types::{ CatTypeModel, CatTypeModelWithBudget, AddCatType, UpdateCatType };
-use crate::repositories::cat_types::{CatTypeRepo, CatTypeTrait};
+use crate::models::exchange_rates::parse_currency;
+use crate::repositories::cat_types::{CatTypeRepo, CatTypeTrait, Trait};
 
-use std::env;
 use sqlx::mysql::MySqlPool;
 use hyper::{header, Body, Method, Request, Response, StatusCode};
 
-type GenericError = Box<dyn std::error::Error + Send + Sync>;
-type Result<T> = std::result::Result<T, GenericError>;
+type Result<T> = std::result::Result<T, AppError>;
 
 static INTERNAL_SERVER_ERROR: &[u8] = b"Internal Server Error";
-static NOTFOUND: &[u8] = b"Not Found";
 
 pub struct CatTypeHandler<'a>{
     cat_type_repo: CatTypeRepo,
     request: &'a Request<Body>,
+    route: Route,
+    base_currency: String,
 }
 
 impl<'a> CatTypeHandler<'a> {
-    pub fn new(req: &'a Request<Body>, pool: MySqlPool) -> Self {
+    pub fn new(req: &'a Request<Body>, pool: MySqlPool, base_currency: &str, derived_budgets: bool, route: Route) -> Self {
         Self { 
-            cat_type_repo: CatTypeRepo::new(pool),
+            cat_type_repo: CatTypeRepo::new(pool)
+                .with_actor(req_actor(req))
+                .with_base_currency(base_currency)
+                .with_derived_budgets(derived_budgets),
             request: req,
+            route,
+            base_currency: base_currency.to_string(),
+        }
+    }
+
+    /// `?currency=` the budget totals are reported in, none keeps the base currency.
+    fn currency(&self) -> Result<Option<String>> {
+        match get_req_query(self.request, String::from("currency")) {
+            Some(value) => {
+                let currency = parse_currency("currency", &value)?;
+                Ok(Some(currency).filter(|currency| *currency != self.base_currency))
+            },
+            None => Ok(None),
         }
     }
 
@@ -31,20 +47,27 @@ impl<'a> CatTypeHandler<'a> {
 
         let mut types: Vec<CatTypeModelWithBudget> = Vec::new();
 
-        let datas = self.cat_type_repo.cat_types_list().await?;
+        let include_deleted = req_include_deleted(self.request)?;
+        let format = req_money_format(self.request)?;
+        let currency = self.currency()?;
+        let datas = self.cat_type_repo.cat_types_list(include_deleted, currency.clone()).await?;
+        let currency = currency.unwrap_or_else(|| self.base_currency.clone());
         for data in datas.iter() {
 
-            let allocated: i64 = if data.allocated != None { bigdecimal_to_int(data.allocated.clone().unwrap()) } else { 0 };
-            let spent: i64 = if data.spent != None { bigdecimal_to_int(data.spent.clone().unwrap()) } else { 0 };
-            let available: i64 = if data.available != None { bigdecimal_to_int(data.available.clone().unwrap()) } else { 0 };
+            let allocated = data.allocated.clone().unwrap_or_default().amount(&currency, format);
+            let spent = data.spent.clone().unwrap_or_default().amount(&currency, format);
+            let available = data.available.clone().unwrap_or_default().amount(&currency, format);
 
             let data_type = CatTypeModelWithBudget {
                 id: data.id, 
                 r#type: data.r#type.clone(), 
+                direction: data.direction.clone(),
                 description: data.description.clone(),  
                 icon: data.icon.clone(),  
                 created_at: data.created_at,
                 updated_at: data.updated_at,
+                deleted_at: data.deleted_at,
+                currency: currency.clone(),
                 allocated: allocated,
                 spent: spent,
                 available: available,
@@ -69,20 +92,27 @@ impl<'a> CatTypeHandler<'a> {
 
     async fn detail(&mut self) -> Result<Response<Body>> { 
 
-        let query_id = req_query_id(self.request);
-        let data = self.cat_type_repo.cat_type_detail(query_id).await?;
+        let query_id = self.route.id()?;
+        let include_deleted = req_include_deleted(self.request)?;
+        let format = req_money_format(self.request)?;
+        let currency = self.currency()?;
+        let data = self.cat_type_repo.cat_type_detail(query_id, include_deleted, currency.clone()).await?;
+        let currency = currency.unwrap_or_else(|| self.base_currency.clone());
 
-        let allocated: i64 = if data.allocated != None { bigdecimal_to_int(data.allocated.clone().unwrap()) } else { 0 };
-        let spent: i64 = if data.spent != None { bigdecimal_to_int(data.spent.clone().unwrap()) } else { 0 };
-        let available: i64 = if data.available != None { bigdecimal_to_int(data.available.clone().unwrap()) } else { 0 };
+        let allocated = data.allocated.clone().unwrap_or_default().amount(&currency, format);
+        let spent = data.spent.clone().unwrap_or_default().amount(&currency, format);
+        let available = data.available.clone().unwrap_or_default().amount(&currency, format);
 
         let cat_type = CatTypeModelWithBudget {
             id: data.id, 
             r#type: data.r#type.clone(), 
+            direction: data.direction.clone(),
             description: data.description.clone(),  
             icon: data.icon.clone(),  
             created_at: data.created_at,
             updated_at: data.updated_at,
+            deleted_at: data.deleted_at,
+            currency,
             allocated: allocated,
             spent: spent,
             available: available,
@@ -104,15 +134,20 @@ impl<'a> CatTypeHandler<'a> {
     async fn add(&mut self, body: &str) -> Result<Response<Body>> { 
 
         let data: AddCatType = serde_json::from_str(body)?;
-        let new_type = self.cat_type_repo.cat_type_add(data.clone()).await?;
+
+        let mut tx = self.cat_type_repo.start_transaction().await?;
+        let new_type = tx.cat_type_add(data.clone()).await?;
+        tx.commit().await?;
 
         let cat_type = CatTypeModel {
             id: new_type.id, 
             r#type: new_type.r#type.clone(), 
+            direction: new_type.direction.clone(),
             description: new_type.description.clone(),  
             icon: new_type.icon.clone(),  
             created_at: new_type.created_at,
             updated_at: new_type.updated_at,
+            deleted_at: new_type.deleted_at,
         };
 
         let res = match serde_json::to_string(&cat_type) {
@@ -130,17 +165,22 @@ impl<'a> CatTypeHandler<'a> {
 
     async fn update(&mut self, body: &str) -> Result<Response<Body>> { 
 
-        let query_id = req_query_id(self.request);
+        let query_id = self.route.id()?;
         let data: UpdateCatType = serde_json::from_str(body)?;
-        let update_type = self.cat_type_repo.cat_type_update(query_id, data.clone()).await?;
+
+        let mut tx = self.cat_type_repo.start_transaction().await?;
+        let update_type = tx.cat_type_update(query_id, data.clone()).await?;
+        tx.commit().await?;
 
         let cat_type = CatTypeModel {
             id: update_type.id, 
             r#type: update_type.r#type.clone(), 
+            direction: update_type.direction.clone(),
             description: update_type.description.clone(),  
             icon: update_type.icon.clone(),  
             created_at: update_type.created_at,
             updated_at: update_type.updated_at,
+            deleted_at: update_type.deleted_at,
         };
 
         let res = match serde_json::to_string(&cat_type) {
@@ -158,16 +198,53 @@ impl<'a> CatTypeHandler<'a> {
 
     async fn delete(&mut self) -> Result<Response<Body>> { 
 
-        let query_id = req_query_id(self.request);
-        let delete_cat = self.cat_type_repo.cat_type_delete(query_id).await?;
+        let query_id = self.route.id()?;
+
+        let mut tx = self.cat_type_repo.start_transaction().await?;
+        let delete_cat = tx.cat_type_delete(query_id).await?;
+        tx.commit().await?;
 
         let cat_type = CatTypeModel {
             id: delete_cat.id, 
             r#type: delete_cat.r#type.clone(), 
+            direction: delete_cat.direction.clone(),
             description: delete_cat.description.clone(),  
             icon: delete_cat.icon.clone(),  
             created_at: delete_cat.created_at,
             updated_at: delete_cat.updated_at,
+            deleted_at: delete_cat.deleted_at,
+        };
+
+        let res = match serde_json::to_string(&cat_type) {
+            Ok(json) => Response::builder()
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(json))
+                .unwrap(),
+            Err(_) => Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(INTERNAL_SERVER_ERROR.into())
+                .unwrap(),
+        };
+        Ok(res)
+    }
+
+    async fn restore(&mut self) -> Result<Response<Body>> { 
+
+        let query_id = self.route.id()?;
+
+        let mut tx = self.cat_type_repo.start_transaction().await?;
+        let restore_cat = tx.cat_type_restore(query_id).await?;
+        tx.commit().await?;
+
+        let cat_type = CatTypeModel {
+            id: restore_cat.id, 
+            r#type: restore_cat.r#type.clone(), 
+            direction: restore_cat.direction.clone(),
+            description: restore_cat.description.clone(),  
+            icon: restore_cat.icon.clone(),  
+            created_at: restore_cat.created_at,
+            updated_at: restore_cat.updated_at,
+            deleted_at: restore_cat.deleted_at,
         };
 
         let res = match serde_json::to_string(&cat_type) {
@@ -184,33 +261,30 @@ impl<'a> CatTypeHandler<'a> {
     }
 }
 
-pub async fn handler( req: Request<Body> ) -> Result<Response<Body>> {
-    let pool = MySqlPool::connect(&env::var("DATABASE_URL").unwrap()).await?;
+pub async fn handler( req: Request<Body>, state: AppState, route: Route ) -> Result<Response<Body>> {
+    let pool = state.pool;
 
     let (parts, body) = req.into_parts();
-    let body_bytes = hyper::body::to_bytes(body).await.unwrap();
-    let body = std::str::from_utf8(&body_bytes).unwrap();
+    let body_bytes = read_body(body, state.config.server.body_limit).await?;
+    let body = std::str::from_utf8(&body_bytes)?;
     
     let request: hyper::Request<Body> = Request::from_parts(parts, body_bytes.clone().into());
-    let mut cat_type_handler = CatTypeHandler::new(&request, pool);
+    let mut cat_type_handler = CatTypeHandler::new(&request, pool, &state.config.currency.base, state.config.features.derived_budgets, route);
 
-    match (request.method(), request.uri().query().is_none()) {
+    match (request.method(), route.id.is_some()) {
 
-        (&Method::GET, true) => cat_type_handler.list().await,
-        (&Method::GET, false) => cat_type_handler.detail().await,
-        (&Method::PUT, true) => cat_type_handler.add(body).await,
-        (&Method::POST, false) => cat_type_handler.update(body).await,
-        (&Method::DELETE, false) => cat_type_handler.delete().await,
+        (&Method::POST, true) if route.restore => cat_type_handler.restore().await,
+        _ if route.restore => Err(AppError::MethodNotAllowed(route.allowed())),
+
+        (&Method::GET, false) => cat_type_handler.list().await,
+        (&Method::GET, true) => cat_type_handler.detail().await,
+        (&Method::POST, false) => cat_type_handler.add(body).await,
+        (&Method::PUT, true) | (&Method::PATCH, true) => cat_type_handler.update(body).await,
+        (&Method::DELETE, true) => cat_type_handler.delete().await,
 
         // 
-        _ => {
-            // Return 404 not found response.
-            Ok(Response::builder()
-                .status(StatusCode::NOT_FOUND)
-                .body(NOTFOUND.into())
-                .unwrap())
-        }
+        _ => Err(AppError::MethodNotAllowed(route.allowed())),
         
     }
 
-}
\ No newline at end of file
+}
@@ -0,0 +1,223 @@
+use crate::errors::AppError;
+use crate::handlers::{AppState, Route, get_req_query, read_body, req_page_params};
+use crate::models::exchange_rates::{ ExchangeRateModel, ExchangeRateFilter, NewExchangeRate, UpdateExchangeRate, build_model_from_exist, parse_currency, parse_rates_csv, EXCHANGE_RATE_SORT_FIELDS };
+use crate::models::pagination::Page;
+use crate::repositories::exchange_rates::{ExchangeRateRepo, ExchangeRateTrait, Trait};
+
+use sqlx::mysql::MySqlPool;
+use hyper::{header, Body, Method, Request, Response, StatusCode};
+
+type Result<T> = std::result::Result<T, AppError>;
+
+static INTERNAL_SERVER_ERROR: &[u8] = b"Internal Server Error";
+
+pub struct ExchangeRateHandler<'a>{
+    rate_repo: ExchangeRateRepo,
+    request: &'a Request<Body>,
+    route: Route,
+}
+
+impl<'a> ExchangeRateHandler<'a> {
+    pub fn new(req: &'a Request<Body>, pool: MySqlPool, route: Route) -> Self {
+        Self { 
+            rate_repo: ExchangeRateRepo::new(pool),
+            request: req,
+            route,
+        }
+    }
+
+    fn filter(&self) -> Result<ExchangeRateFilter> {
+
+        let currency = match get_req_query(self.request, String::from("currency")) {
+            Some(value) => Some(parse_currency("currency", &value)?),
+            None => None,
+        };
+        let base = match get_req_query(self.request, String::from("base")) {
+            Some(value) => Some(parse_currency("base", &value)?),
+            None => None,
+        };
+
+        Ok(ExchangeRateFilter { currency, base })
+    }
+
+    async fn list(&mut self) -> Result<Response<Body>> {
+
+        let filter = self.filter()?;
+        let page = req_page_params(self.request, EXCHANGE_RATE_SORT_FIELDS, "-date")?;
+        let (datas, next_cursor) = self.rate_repo.rates_list(filter, page).await?;
+
+        let rates: Vec<ExchangeRateModel> = datas.into_iter().map(build_model_from_exist).collect();
+        let page = Page { data: rates, next_cursor };
+
+        let res = match serde_json::to_string(&page) {
+            Ok(json) => Response::builder()
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(json))
+                .unwrap(),
+            Err(_) => Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(INTERNAL_SERVER_ERROR.into())
+                .unwrap(),
+        };
+        Ok(res)
+    }
+
+    async fn detail(&mut self) -> Result<Response<Body>> { 
+
+        let query_id = self.route.id()?;
+        let data = self.rate_repo.rate_detail(query_id).await?;
+
+        let rate = build_model_from_exist(data);
+
+        let res = match serde_json::to_string(&rate) {
+            Ok(json) => Response::builder()
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(json))
+                .unwrap(),
+            Err(_) => Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(INTERNAL_SERVER_ERROR.into())
+                .unwrap(),
+        };
+        Ok(res)
+    }
+
+    async fn add(&mut self, body: &str) -> Result<Response<Body>> { 
+
+        let data: NewExchangeRate = serde_json::from_str(body)?;
+
+        let mut tx = self.rate_repo.start_transaction().await?;
+        let new_rate = tx.rate_add(data).await?;
+        tx.commit().await?;
+
+        let rate = build_model_from_exist(new_rate);
+
+        let res = match serde_json::to_string(&rate) {
+            Ok(json) => Response::builder()
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(json))
+                .unwrap(),
+            Err(_) => Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(INTERNAL_SERVER_ERROR.into())
+                .unwrap(),
+        };
+        Ok(res)
+    }
+
+    async fn update(&mut self, body: &str) -> Result<Response<Body>> { 
+
+        let query_id = self.route.id()?;
+        let data: UpdateExchangeRate = serde_json::from_str(body)?;
+
+        let mut tx = self.rate_repo.start_transaction().await?;
+        let update_rate = tx.rate_update(query_id, data).await?;
+        tx.commit().await?;
+
+        let rate = build_model_from_exist(update_rate);
+
+        let res = match serde_json::to_string(&rate) {
+            Ok(json) => Response::builder()
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(json))
+                .unwrap(),
+            Err(_) => Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(INTERNAL_SERVER_ERROR.into())
+                .unwrap(),
+        };
+        Ok(res)
+    }
+
+    async fn delete(&mut self) -> Result<Response<Body>> { 
+
+        let query_id = self.route.id()?;
+
+        let mut tx = self.rate_repo.start_transaction().await?;
+        let delete_rate = tx.rate_delete(query_id).await?;
+        tx.commit().await?;
+
+        let rate = build_model_from_exist(delete_rate);
+
+        let res = match serde_json::to_string(&rate) {
+            Ok(json) => Response::builder()
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(json))
+                .unwrap(),
+            Err(_) => Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(INTERNAL_SERVER_ERROR.into())
+                .unwrap(),
+        };
+        Ok(res)
+    }
+
+    /// Imports a JSON array of rates or `date,currency,base,rate` CSV lines.
+    async fn import(&mut self, body: &str) -> Result<Response<Body>> { 
+
+        let rates: Vec<NewExchangeRate> = match body.trim_start().starts_with('[') {
+            true => serde_json::from_str(body)?,
+            false => parse_rates_csv(body)?,
+        };
+
+        // the whole file is imported or none of it
+        let mut tx = self.rate_repo.start_transaction().await?;
+        let report = tx.rates_import(rates).await?;
+        tx.commit().await?;
+
+        let res = match serde_json::to_string(&report) {
+            Ok(json) => Response::builder()
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(json))
+                .unwrap(),
+            Err(_) => Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(INTERNAL_SERVER_ERROR.into())
+                .unwrap(),
+        };
+        Ok(res)
+    }
+}
+
+pub async fn handler( req: Request<Body>, state: AppState, route: Route ) -> Result<Response<Body>> {
+    let pool = state.pool;
+
+    let (parts, body) = req.into_parts();
+    let body_bytes = read_body(body, state.config.server.body_limit).await?;
+    let body = std::str::from_utf8(&body_bytes)?;
+    
+    let request: hyper::Request<Body> = Request::from_parts(parts, body_bytes.clone().into());
+    let mut rate_handler = ExchangeRateHandler::new(&request, pool, route);
+
+    match (request.method(), route.id.is_some()) {
+
+        (&Method::GET, false) => rate_handler.list().await,
+        (&Method::GET, true) => rate_handler.detail().await,
+        (&Method::POST, false) => rate_handler.add(body).await,
+        (&Method::PUT, true) | (&Method::PATCH, true) => rate_handler.update(body).await,
+        (&Method::DELETE, true) => rate_handler.delete().await,
+
+        // 
+        _ => Err(AppError::MethodNotAllowed(route.allowed())),
+        
+    }
+
+}
+
+/// `POST /exchange_rates/import`
+pub async fn import_handler( req: Request<Body>, state: AppState, route: Route ) -> Result<Response<Body>> {
+    let pool = state.pool;
+
+    let (parts, body) = req.into_parts();
+    let body_bytes = read_body(body, state.config.server.body_limit).await?;
+    let body = std::str::from_utf8(&body_bytes)?;
+    
+    let request: hyper::Request<Body> = Request::from_parts(parts, body_bytes.clone().into());
+    let mut rate_handler = ExchangeRateHandler::new(&request, pool, route);
+
+    match request.method() {
+        &Method::POST => rate_handler.import(body).await,
+        _ => Err(AppError::MethodNotAllowed(vec![Method::POST])),
+    }
+
+}
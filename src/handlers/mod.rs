@@ -1,29 +1,96 @@
 
+use crate::errors::AppError;
 use crate::handlers::accounts as accounts_handlers;
+use crate::handlers::alerts as alerts_handlers;
+use crate::handlers::audit as audit_handlers;
 use crate::handlers::cat_types as cat_types_handlers;
+use crate::handlers::exchange_rates as exchange_rates_handlers;
+use crate::handlers::recurring as recurring_handlers;
+use crate::handlers::reconciliation as reconciliation_handlers;
+use crate::handlers::transfers as transfers_handlers;
 use crate::handlers::trx_cats as trx_cats_handlers;
 use crate::handlers::trx_cat_budgets as trx_cat_budgets_handlers;
 use crate::handlers::trxs as trxs_handlers;
+use crate::config::Config;
+use crate::models::money::MoneyFormat;
+use crate::models::pagination::{Cursor, PageParams, Sort, SortField, DEFAULT_LIMIT};
 
 use std::collections::HashMap;
-use hyper::client::HttpConnector;
-use hyper::{Body, Client, Request, Response, StatusCode};
+use std::str::FromStr;
+use std::sync::Arc;
+use bytes::{Bytes, BytesMut};
+use hyper::body::HttpBody;
+use hyper::header::{self, HeaderValue};
+use hyper::{Body, Method, Request, Response};
+use serde::de::DeserializeOwned;
+use sqlx::mysql::MySqlPool;
 
 type GenericError = Box<dyn std::error::Error + Send + Sync>;
 type Result<T> = std::result::Result<T, GenericError>;
 
 pub mod accounts;
+pub mod alerts;
+pub mod audit;
 pub mod cat_types;
+pub mod exchange_rates;
+pub mod recurring;
+pub mod reconciliation;
+pub mod transfers;
 pub mod trx_cats;
 pub mod trx_cat_budgets;
 pub mod trxs;
 
 static INDEX: &[u8] = b"test";
-static NOTFOUND: &[u8] = b"Not Found";
+
+/// Resources whose items are soft deleted and can be restored.
+static RESTORABLE: &[&str] = &["accounts", "cat_types", "trx_cats", "trx_cats_budgets", "trxs"];
+
+/// Shared state handed to every request, created once in `main`.
+#[derive(Debug, Clone)]
+pub struct AppState {
+    pub pool: MySqlPool,
+    pub config: Arc<Config>,
+}
+
+impl AppState {
+    pub fn new(pool: MySqlPool, config: Config) -> Self {
+        Self { pool, config: Arc::new(config) }
+    }
+}
+
+/// Target of a request, parsed from its path.
+///
+/// `id` is the item of `/resource/{id}` and `parent` the owner of a nested
+/// collection such as `/accounts/{id}/trxs`. `restore` marks
+/// `/resource/{id}/restore`, bringing a soft deleted item back.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Route {
+    pub id: Option<i32>,
+    pub parent: Option<i32>,
+    pub restore: bool,
+}
+
+impl Route {
+    pub fn id(&self) -> std::result::Result<i32, AppError> {
+        self.id.ok_or_else(|| AppError::BadRequest(String::from("resource id is required")))
+    }
+
+    /// Methods accepted on this route, sent back in the `Allow` header of a 405.
+    pub fn allowed(&self) -> Vec<Method> {
+        if self.restore {
+            return vec![Method::POST];
+        }
+
+        match self.id {
+            Some(_) => vec![Method::GET, Method::PUT, Method::PATCH, Method::DELETE],
+            None => vec![Method::GET, Method::POST],
+        }
+    }
+}
 
 pub async fn handler(
     req: Request<Body>,
-    _client: Client<HttpConnector>,
+    state: AppState,
 ) -> Result<Response<Body>> {
 
     let default_pages = vec!["/", "/index.html"];
@@ -32,20 +99,110 @@ pub async fn handler(
         return Ok(Response::new(INDEX.into()))
     }
 
-    match req.uri().path() {
-        "/accounts" => accounts_handlers::handler(req).await,
-        "/cat_types" => cat_types_handlers::handler(req).await,
-        "/trx_cats" => trx_cats_handlers::handler(req).await,
-        "/trx_cats_budgets" => trx_cat_budgets_handlers::handler(req).await,
-        "/trxs" => trxs_handlers::handler(req).await,
-        _ => {
-            // Return 404 not found response.
-            Ok(Response::builder()
-                .status(StatusCode::NOT_FOUND)
-                .body(NOTFOUND.into())
-                .unwrap())
+    let res = route(req, state).await;
+
+    // Render every error as a JSON body with its status code.
+    Ok(res.unwrap_or_else(AppError::into_response))
+}
+
+async fn route(
+    mut req: Request<Body>,
+    state: AppState,
+) -> std::result::Result<Response<Body>, AppError> {
+
+    let path = req.uri().path().trim_matches('/').to_string();
+    let segments: Vec<&str> = path.split('/').collect();
+
+    let (resource, mut route, nested) = match segments.as_slice() {
+        [resource] => (*resource, Route::default(), None),
+        ["exchange_rates", "import"] => ("exchange_rates", Route::default(), Some("import")),
+        ["trx_cats_budgets", "rebuild"] => ("trx_cats_budgets", Route::default(), Some("rebuild")),
+        [resource, id] => (*resource, Route { id: Some(parse_path_id(id)?), ..Route::default() }, None),
+        [resource, id, "restore"] if RESTORABLE.contains(resource) => {
+            (*resource, Route { id: Some(parse_path_id(id)?), restore: true, ..Route::default() }, None)
+        },
+        [resource, id, nested] => (*resource, Route { parent: Some(parse_path_id(id)?), ..Route::default() }, Some(*nested)),
+        _ => return Err(AppError::NotFound(String::from("Not Found"))),
+    };
+
+    // deprecated query form: `PUT /accounts` creates, `GET|POST|DELETE /accounts?id=1` targets one item
+    let mut legacy = false;
+    if state.config.features.legacy_routes && route.id.is_none() && nested.is_none() {
+        if get_req_query(&req, String::from("id")).is_some() {
+            route.id = Some(req_query_id(&req)?);
+            legacy = true;
+
+            if req.method() == Method::POST {
+                *req.method_mut() = Method::PATCH;
+            }
+        } else if req.method() == Method::PUT {
+            *req.method_mut() = Method::POST;
+            legacy = true;
         }
     }
+
+    let res = match (resource, nested) {
+        ("accounts", None) => accounts_handlers::handler(req, state, route).await,
+        ("accounts", Some("trxs")) => trxs_handlers::handler(req, state, route).await,
+        ("alerts", None) => alerts_handlers::handler(req, state, route).await,
+        ("audit", None) => audit_handlers::handler(req, state, route).await,
+        ("cat_types", None) => cat_types_handlers::handler(req, state, route).await,
+        ("exchange_rates", None) => exchange_rates_handlers::handler(req, state, route).await,
+        ("exchange_rates", Some("import")) => exchange_rates_handlers::import_handler(req, state, route).await,
+        ("reconciliation", None) => reconciliation_handlers::handler(req, state, route).await,
+        ("recurring", None) => recurring_handlers::handler(req, state, route).await,
+        ("recurring", Some(action @ ("upcoming" | "skip" | "post"))) => recurring_handlers::action_handler(req, state, route, action).await,
+        ("transfers", None) => transfers_handlers::handler(req, state, route).await,
+        ("trx_cats", None) => trx_cats_handlers::handler(req, state, route).await,
+        ("trx_cats", Some("budgets")) => trx_cat_budgets_handlers::handler(req, state, route).await,
+        ("trx_cats_budgets", None) => trx_cat_budgets_handlers::handler(req, state, route).await,
+        ("trx_cats_budgets", Some("rebuild")) => trx_cat_budgets_handlers::rebuild_handler(req, state, route).await,
+        ("trxs", None) => trxs_handlers::handler(req, state, route).await,
+        _ => Err(AppError::NotFound(String::from("Not Found"))),
+    };
+
+    match legacy {
+        true => res.map(|res| deprecated(res, resource, route)),
+        false => res,
+    }
+}
+
+fn parse_path_id( id: &str ) -> std::result::Result<i32, AppError> {
+    id.parse::<i32>()
+        .map_err(|_| AppError::NotFound(format!("`{}` is not a valid id", id)))
+}
+
+/// Flags a response served through the query-string routes and points at the new path.
+fn deprecated( mut res: Response<Body>, resource: &str, route: Route ) -> Response<Body> {
+
+    let successor = match route.id {
+        Some(id) => format!("</{}/{}>; rel=\"successor-version\"", resource, id),
+        None => format!("</{}>; rel=\"successor-version\"", resource),
+    };
+
+    let headers = res.headers_mut();
+    headers.insert("deprecation", HeaderValue::from_static("true"));
+    if let Ok(link) = HeaderValue::from_str(&successor) {
+        headers.insert(header::LINK, link);
+    }
+
+    res
+}
+
+/// Parses a create body, filling the parent id of a nested route such as
+/// `POST /accounts/{id}/trxs` into `key`.
+pub fn body_with_parent<T: DeserializeOwned>(
+    body: &str,
+    key: &str,
+    parent: Option<i32>,
+) -> std::result::Result<T, AppError> {
+
+    let mut value: serde_json::Value = serde_json::from_str(body)?;
+    if let (Some(parent), Some(fields)) = (parent, value.as_object_mut()) {
+        fields.insert(key.to_string(), parent.into());
+    }
+
+    Ok(serde_json::from_value(value)?)
 }
 
 pub fn get_req_query( 
@@ -75,25 +232,115 @@ pub fn get_req_query(
     }
 }
 
-pub fn req_query_id( req: &Request<Body> ) -> i32 {
+pub fn req_query_id( req: &Request<Body> ) -> std::result::Result<i32, AppError> {
 
-    let queries = req.uri().query();
-    if queries.is_none() {
-        return 0;
+    let str_id = match get_req_query(req, String::from("id")) {
+        Some(value) => value,
+        None => return Err(AppError::BadRequest(String::from("query parameter `id` is required"))),
+    };
+
+    str_id
+        .parse::<i32>()
+        .map_err(|_| AppError::BadRequest(format!("query parameter `id` must be an integer, got `{}`", str_id)))
+}
+
+/// Reads the whole request body, refusing anything larger than `limit` bytes.
+pub async fn read_body( mut body: Body, limit: usize ) -> std::result::Result<Bytes, AppError> {
+
+    // reject early when the client announces an oversized body
+    let announced = body.size_hint().lower() as usize;
+    if announced > limit {
+        return Err(AppError::PayloadTooLarge(limit));
     }
 
-    // get data with filter query
-    let params: HashMap<String, String> = req
-        .uri()
-        .query()
-        .map(|v| {
-            url::form_urlencoded::parse(v.as_bytes())
-                .into_owned()
-                .collect()
-        })
-        .unwrap_or_else(HashMap::new);
+    let mut buf = BytesMut::with_capacity(announced);
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        if buf.len() + chunk.len() > limit {
+            return Err(AppError::PayloadTooLarge(limit));
+        }
+        buf.extend_from_slice(&chunk);
+    }
+
+    Ok(buf.freeze())
+}
+
+/// Reads `limit`, `cursor` and `sort` of a list request.
+///
+/// `fields` are the sortable fields of the resource and `default_sort` is used
+/// when `sort` is missing, e.g. `-datetime`.
+pub fn req_page_params(
+    req: &Request<Body>,
+    fields: &[SortField],
+    default_sort: &str,
+) -> std::result::Result<PageParams, AppError> {
+
+    let limit = match get_req_query(req, String::from("limit")) {
+        Some(value) => value
+            .parse::<i64>()
+            .map_err(|_| AppError::BadRequest(format!("query parameter `limit` must be an integer, got `{}`", value)))?,
+        None => DEFAULT_LIMIT,
+    };
+
+    let sort = match get_req_query(req, String::from("sort")) {
+        Some(value) => Sort::parse(&value, fields)?,
+        None => Sort::parse(default_sort, fields)?,
+    };
+
+    let cursor = match get_req_query(req, String::from("cursor")) {
+        Some(value) => Some(Cursor::decode(&value)?),
+        None => None,
+    };
 
-    let str_account_id = params.get("id").unwrap().to_string();
-    return str_account_id.parse::<i32>().unwrap();
+    PageParams::new(limit, cursor, sort)
+}
+
+/// Optional query parameter parsed as `T`, a value that does not parse is a bad request.
+pub fn req_query_parse<T: FromStr>( req: &Request<Body>, key: &str ) -> std::result::Result<Option<T>, AppError> {
+
+    match get_req_query(req, key.to_string()) {
+        Some(value) => value
+            .trim()
+            .parse::<T>()
+            .map(Some)
+            .map_err(|_| AppError::BadRequest(format!("query parameter `{}` has an invalid value `{}`", key, value))),
+        None => Ok(None),
+    }
+}
 
-}
\ No newline at end of file
+/// `?includeDeleted=true` lists and shows soft deleted items too.
+pub fn req_include_deleted( req: &Request<Body> ) -> std::result::Result<bool, AppError> {
+    Ok(req_query_parse::<bool>(req, "includeDeleted")?.unwrap_or(false))
+}
+
+/// `?amounts=minor|decimal` picks how a response writes its amounts, minor units by default.
+pub fn req_money_format( req: &Request<Body> ) -> std::result::Result<MoneyFormat, AppError> {
+    Ok(req_query_parse::<MoneyFormat>(req, "amounts")?.unwrap_or_default())
+}
+
+/// Who the audit log credits with a write, from the `X-Actor` header. The api
+/// has no users of its own, so the value is taken as sent, cut to 255 chars.
+pub fn req_actor( req: &Request<Body> ) -> Option<String> {
+    req.headers()
+        .get("x-actor")
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().chars().take(255).collect::<String>())
+        .filter(|value| !value.is_empty())
+}
+
+/// Comma separated ids such as `?accountid=1,2,3`.
+pub fn req_query_ids( req: &Request<Body>, key: &str ) -> std::result::Result<Vec<i32>, AppError> {
+
+    let value = match get_req_query(req, key.to_string()) {
+        Some(value) => value,
+        None => return Ok(Vec::new()),
+    };
+
+    value
+        .split(',')
+        .filter(|id| !id.trim().is_empty())
+        .map(|id| id.trim().parse::<i32>().map_err(|_| {
+            AppError::BadRequest(format!("query parameter `{}` must be comma separated integers, got `{}`", key, value))
+        }))
+        .collect()
+}
@@ -0,0 +1,76 @@
+use crate::errors::AppError;
+use crate::handlers::{AppState, Route};
+use crate::repositories::reconciliation::{ReconcileRepo, ReconcileTrait, Trait};
+
+use sqlx::mysql::MySqlPool;
+use hyper::{header, Body, Method, Request, Response, StatusCode};
+
+type Result<T> = std::result::Result<T, AppError>;
+
+static INTERNAL_SERVER_ERROR: &[u8] = b"Internal Server Error";
+
+pub struct ReconcileHandler {
+    reconcile_repo: ReconcileRepo,
+}
+
+impl ReconcileHandler {
+    pub fn new(pool: MySqlPool, base_currency: &str) -> Self {
+        Self { 
+            reconcile_repo: ReconcileRepo::new(pool).with_base_currency(base_currency),
+        }
+    }
+
+    async fn audit(&mut self) -> Result<Response<Body>> {
+
+        let report = self.reconcile_repo.reconcile(false).await?;
+
+        let res = match serde_json::to_string(&report) {
+            Ok(json) => Response::builder()
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(json))
+                .unwrap(),
+            Err(_) => Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(INTERNAL_SERVER_ERROR.into())
+                .unwrap(),
+        };
+        Ok(res)
+    }
+
+    async fn repair(&mut self) -> Result<Response<Body>> {
+
+        let mut tx = self.reconcile_repo.start_transaction().await?;
+        let report = tx.reconcile(true).await?;
+        tx.commit().await?;
+
+        let res = match serde_json::to_string(&report) {
+            Ok(json) => Response::builder()
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(json))
+                .unwrap(),
+            Err(_) => Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(INTERNAL_SERVER_ERROR.into())
+                .unwrap(),
+        };
+        Ok(res)
+    }
+}
+
+pub async fn handler( req: Request<Body>, state: AppState, route: Route ) -> Result<Response<Body>> {
+    let pool = state.pool;
+
+    let mut reconcile_handler = ReconcileHandler::new(pool, &state.config.currency.base);
+
+    match (req.method(), route.id.is_some()) {
+
+        (&Method::GET, false) => reconcile_handler.audit().await,
+        (&Method::POST, false) => reconcile_handler.repair().await,
+
+        // the report is a single resource, there are no items below it
+        (_, true) => Err(AppError::NotFound(String::from("Not Found"))),
+        _ => Err(AppError::MethodNotAllowed(route.allowed())),
+        
+    }
+
+}
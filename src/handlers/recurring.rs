@@ -0,0 +1,267 @@
+use crate::errors::AppError;
+use crate::handlers::{AppState, Route, read_body, req_actor, req_money_format, req_page_params, req_query_parse};
+use crate::models::pagination::Page;
+use crate::models::recurring::{ RecurringModel, UpcomingModel, PostedModel, NewRecurring, UpdateRecurring, build_model_from_exist, build_upcoming_model, MAX_UPCOMING, RECURRING_SORT_FIELDS };
+use crate::models::trxs::build_trx_model;
+use crate::repositories::recurring::{RecurringRepo, RecurringTrait, Trait};
+
+use sqlx::mysql::MySqlPool;
+use hyper::{header, Body, Method, Request, Response, StatusCode};
+
+type Result<T> = std::result::Result<T, AppError>;
+
+static INTERNAL_SERVER_ERROR: &[u8] = b"Internal Server Error";
+
+pub struct RecurringHandler<'a>{
+    recurring_repo: RecurringRepo,
+    request: &'a Request<Body>,
+    route: Route,
+}
+
+impl<'a> RecurringHandler<'a> {
+    pub fn new(req: &'a Request<Body>, pool: MySqlPool, base_currency: &str, route: Route) -> Self {
+        Self {
+            recurring_repo: RecurringRepo::new(pool).with_actor(req_actor(req)).with_base_currency(base_currency),
+            request: req,
+            route,
+        }
+    }
+
+    async fn list(&mut self) -> Result<Response<Body>> {
+
+        let page = req_page_params(self.request, RECURRING_SORT_FIELDS, "id")?;
+        let format = req_money_format(self.request)?;
+        let (datas, next_cursor) = self.recurring_repo.recurring_list(page).await?;
+
+        let recurring: Vec<RecurringModel> = datas.into_iter().map(|data| build_model_from_exist(data, format)).collect();
+        let page = Page { data: recurring, next_cursor };
+
+        let res = match serde_json::to_string(&page) {
+            Ok(json) => Response::builder()
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(json))
+                .unwrap(),
+            Err(_) => Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(INTERNAL_SERVER_ERROR.into())
+                .unwrap(),
+        };
+        Ok(res)
+    }
+
+    async fn detail(&mut self) -> Result<Response<Body>> {
+
+        let query_id = self.route.id()?;
+        let format = req_money_format(self.request)?;
+        let datas = self.recurring_repo.recurring_detail(query_id).await?;
+
+        let recurring = build_model_from_exist(datas, format);
+
+        let res = match serde_json::to_string(&recurring) {
+            Ok(json) => Response::builder()
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(json))
+                .unwrap(),
+            Err(_) => Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(INTERNAL_SERVER_ERROR.into())
+                .unwrap(),
+        };
+        Ok(res)
+    }
+
+    async fn add(&mut self, body: &str) -> Result<Response<Body>> {
+
+        let data: NewRecurring = serde_json::from_str(body)?;
+        let format = req_money_format(self.request)?;
+
+        let mut tx = self.recurring_repo.start_transaction().await?;
+        let new_recurring = tx.recurring_add(data.clone()).await?;
+        tx.commit().await?;
+
+        let recurring = build_model_from_exist(new_recurring, format);
+
+        let res = match serde_json::to_string(&recurring) {
+            Ok(json) => Response::builder()
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(json))
+                .unwrap(),
+            Err(_) => Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(INTERNAL_SERVER_ERROR.into())
+                .unwrap(),
+        };
+        Ok(res)
+    }
+
+    async fn update(&mut self, body: &str) -> Result<Response<Body>> {
+
+        let query_id = self.route.id()?;
+        let data: UpdateRecurring = serde_json::from_str(body)?;
+        let format = req_money_format(self.request)?;
+
+        let mut tx = self.recurring_repo.start_transaction().await?;
+        let update_recurring = tx.recurring_update(query_id, data.clone()).await?;
+        tx.commit().await?;
+
+        let recurring = build_model_from_exist(update_recurring, format);
+
+        let res = match serde_json::to_string(&recurring) {
+            Ok(json) => Response::builder()
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(json))
+                .unwrap(),
+            Err(_) => Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(INTERNAL_SERVER_ERROR.into())
+                .unwrap(),
+        };
+        Ok(res)
+    }
+
+    async fn delete(&mut self) -> Result<Response<Body>> {
+
+        let query_id = self.route.id()?;
+        let format = req_money_format(self.request)?;
+
+        let mut tx = self.recurring_repo.start_transaction().await?;
+        let delete_recurring = tx.recurring_delete(query_id).await?;
+        tx.commit().await?;
+
+        let recurring = build_model_from_exist(delete_recurring, format);
+
+        let res = match serde_json::to_string(&recurring) {
+            Ok(json) => Response::builder()
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(json))
+                .unwrap(),
+            Err(_) => Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(INTERNAL_SERVER_ERROR.into())
+                .unwrap(),
+        };
+        Ok(res)
+    }
+
+    async fn upcoming(&mut self) -> Result<Response<Body>> {
+
+        let query_id = self.route.parent.ok_or_else(|| AppError::BadRequest(String::from("resource id is required")))?;
+        let limit = req_query_parse::<usize>(self.request, "limit")?.unwrap_or(10);
+        if limit == 0 || limit > MAX_UPCOMING {
+            return Err(AppError::BadRequest(format!("query parameter `limit` must be between 1 and {}", MAX_UPCOMING)));
+        }
+
+        let datas = self.recurring_repo.recurring_upcoming(query_id, limit).await?;
+
+        let upcoming: Vec<UpcomingModel> = datas.into_iter().map(build_upcoming_model).collect();
+        let page = Page { data: upcoming, next_cursor: None };
+
+        let res = match serde_json::to_string(&page) {
+            Ok(json) => Response::builder()
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(json))
+                .unwrap(),
+            Err(_) => Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(INTERNAL_SERVER_ERROR.into())
+                .unwrap(),
+        };
+        Ok(res)
+    }
+
+    async fn skip(&mut self) -> Result<Response<Body>> {
+
+        let query_id = self.route.parent.ok_or_else(|| AppError::BadRequest(String::from("resource id is required")))?;
+        let format = req_money_format(self.request)?;
+
+        let mut tx = self.recurring_repo.start_transaction().await?;
+        let skip_recurring = tx.recurring_skip(query_id).await?;
+        tx.commit().await?;
+
+        let recurring = build_model_from_exist(skip_recurring, format);
+
+        let res = match serde_json::to_string(&recurring) {
+            Ok(json) => Response::builder()
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(json))
+                .unwrap(),
+            Err(_) => Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(INTERNAL_SERVER_ERROR.into())
+                .unwrap(),
+        };
+        Ok(res)
+    }
+
+    async fn post(&mut self) -> Result<Response<Body>> {
+
+        let query_id = self.route.parent.ok_or_else(|| AppError::BadRequest(String::from("resource id is required")))?;
+        let format = req_money_format(self.request)?;
+
+        let mut tx = self.recurring_repo.start_transaction().await?;
+        let (post_recurring, post_trx) = tx.recurring_post(query_id).await?;
+        tx.commit().await?;
+
+        let posted = PostedModel {
+            recurring: build_model_from_exist(post_recurring, format),
+            trx: build_trx_model(post_trx, format),
+        };
+
+        let res = match serde_json::to_string(&posted) {
+            Ok(json) => Response::builder()
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(json))
+                .unwrap(),
+            Err(_) => Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(INTERNAL_SERVER_ERROR.into())
+                .unwrap(),
+        };
+        Ok(res)
+    }
+}
+
+pub async fn handler( req: Request<Body>, state: AppState, route: Route ) -> Result<Response<Body>> {
+    let pool = state.pool;
+
+    let (parts, body) = req.into_parts();
+    let body_bytes = read_body(body, state.config.server.body_limit).await?;
+    let body = std::str::from_utf8(&body_bytes)?;
+
+    let request: hyper::Request<Body> = Request::from_parts(parts, body_bytes.clone().into());
+    let mut recurring_handler = RecurringHandler::new(&request, pool, &state.config.currency.base, route);
+
+    match (request.method(), route.id.is_some()) {
+
+        (&Method::GET, false) => recurring_handler.list().await,
+        (&Method::GET, true) => recurring_handler.detail().await,
+        (&Method::POST, false) => recurring_handler.add(body).await,
+        (&Method::PUT, true) | (&Method::PATCH, true) => recurring_handler.update(body).await,
+        (&Method::DELETE, true) => recurring_handler.delete().await,
+
+        //
+        _ => Err(AppError::MethodNotAllowed(route.allowed())),
+
+    }
+
+}
+
+/// `/recurring/{id}/upcoming`, `/recurring/{id}/skip` and `/recurring/{id}/post`.
+pub async fn action_handler( req: Request<Body>, state: AppState, route: Route, action: &str ) -> Result<Response<Body>> {
+    let pool = state.pool;
+
+    let (parts, body) = req.into_parts();
+    read_body(body, state.config.server.body_limit).await?;
+
+    let request: hyper::Request<Body> = Request::from_parts(parts, Body::empty());
+    let mut recurring_handler = RecurringHandler::new(&request, pool, &state.config.currency.base, route);
+
+    match (request.method(), action) {
+        (&Method::GET, "upcoming") => recurring_handler.upcoming().await,
+        (_, "upcoming") => Err(AppError::MethodNotAllowed(vec![Method::GET])),
+        (&Method::POST, "skip") => recurring_handler.skip().await,
+        (&Method::POST, "post") => recurring_handler.post().await,
+        _ => Err(AppError::MethodNotAllowed(vec![Method::POST])),
+    }
+
+}
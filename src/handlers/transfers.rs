@@ -0,0 +1,169 @@
+use crate::errors::AppError;
+use crate::handlers::{AppState, Route, read_body, req_actor, req_money_format, req_page_params};
+use crate::models::pagination::Page;
+use crate::models::transfers::{ TransferModel, NewTransfer, UpdateTransfer, build_model_from_exist, TRANSFER_SORT_FIELDS };
+use crate::repositories::transfers::{TransferRepo, TransferTrait, Trait};
+
+use sqlx::mysql::MySqlPool;
+use hyper::{header, Body, Method, Request, Response, StatusCode};
+
+type Result<T> = std::result::Result<T, AppError>;
+
+static INTERNAL_SERVER_ERROR: &[u8] = b"Internal Server Error";
+
+pub struct TransferHandler<'a>{
+    transfer_repo: TransferRepo,
+    request: &'a Request<Body>,
+    route: Route,
+}
+
+impl<'a> TransferHandler<'a> {
+    pub fn new(req: &'a Request<Body>, pool: MySqlPool, route: Route) -> Self {
+        Self { 
+            transfer_repo: TransferRepo::new(pool).with_actor(req_actor(req)),
+            request: req,
+            route,
+        }
+    }
+
+    async fn list(&mut self) -> Result<Response<Body>> {
+
+        let page = req_page_params(self.request, TRANSFER_SORT_FIELDS, "-datetime")?;
+        let format = req_money_format(self.request)?;
+        let (datas, next_cursor) = self.transfer_repo.transfers_list(page).await?;
+
+        let transfers: Vec<TransferModel> = datas.into_iter().map(|data| build_model_from_exist(data, format)).collect();
+        let page = Page { data: transfers, next_cursor };
+
+        let res = match serde_json::to_string(&page) {
+            Ok(json) => Response::builder()
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(json))
+                .unwrap(),
+            Err(_) => Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(INTERNAL_SERVER_ERROR.into())
+                .unwrap(),
+        };
+        Ok(res)
+    }
+
+    async fn detail(&mut self) -> Result<Response<Body>> { 
+
+        let query_id = self.route.id()?;
+        let format = req_money_format(self.request)?;
+        let datas = self.transfer_repo.transfer_detail(query_id).await?;
+
+        let transfer = build_model_from_exist(datas, format);
+
+        let res = match serde_json::to_string(&transfer) {
+            Ok(json) => Response::builder()
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(json))
+                .unwrap(),
+            Err(_) => Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(INTERNAL_SERVER_ERROR.into())
+                .unwrap(),
+        };
+        Ok(res)
+    }
+
+    async fn add(&mut self, body: &str) -> Result<Response<Body>> { 
+
+        let data: NewTransfer = serde_json::from_str(body)?;
+        let format = req_money_format(self.request)?;
+
+        let mut tx = self.transfer_repo.start_transaction().await?;
+        let new_transfer = tx.transfer_add(data.clone()).await?;
+        tx.commit().await?;
+
+        let transfer = build_model_from_exist(new_transfer, format);
+
+        let res = match serde_json::to_string(&transfer) {
+            Ok(json) => Response::builder()
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(json))
+                .unwrap(),
+            Err(_) => Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(INTERNAL_SERVER_ERROR.into())
+                .unwrap(),
+        };
+        Ok(res)
+    }
+
+    async fn update(&mut self, body: &str) -> Result<Response<Body>> { 
+
+        let query_id = self.route.id()?;
+        let data: UpdateTransfer = serde_json::from_str(body)?;
+        let format = req_money_format(self.request)?;
+
+        let mut tx = self.transfer_repo.start_transaction().await?;
+        let update_transfer = tx.transfer_update(query_id, data.clone()).await?;
+        tx.commit().await?;
+
+        let transfer = build_model_from_exist(update_transfer, format);
+
+        let res = match serde_json::to_string(&transfer) {
+            Ok(json) => Response::builder()
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(json))
+                .unwrap(),
+            Err(_) => Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(INTERNAL_SERVER_ERROR.into())
+                .unwrap(),
+        };
+        Ok(res)
+    }
+
+    async fn delete(&mut self) -> Result<Response<Body>> { 
+
+        let query_id = self.route.id()?;
+        let format = req_money_format(self.request)?;
+
+        let mut tx = self.transfer_repo.start_transaction().await?;
+        let delete_transfer = tx.transfer_delete(query_id).await?;
+        tx.commit().await?;
+
+        let transfer = build_model_from_exist(delete_transfer, format);
+
+        let res = match serde_json::to_string(&transfer) {
+            Ok(json) => Response::builder()
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(json))
+                .unwrap(),
+            Err(_) => Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(INTERNAL_SERVER_ERROR.into())
+                .unwrap(),
+        };
+        Ok(res)
+    }
+}
+
+pub async fn handler( req: Request<Body>, state: AppState, route: Route ) -> Result<Response<Body>> {
+    let pool = state.pool;
+
+    let (parts, body) = req.into_parts();
+    let body_bytes = read_body(body, state.config.server.body_limit).await?;
+    let body = std::str::from_utf8(&body_bytes)?;
+    
+    let request: hyper::Request<Body> = Request::from_parts(parts, body_bytes.clone().into());
+    let mut transfer_handler = TransferHandler::new(&request, pool, route);
+
+    match (request.method(), route.id.is_some()) {
+
+        (&Method::GET, false) => transfer_handler.list().await,
+        (&Method::GET, true) => transfer_handler.detail().await,
+        (&Method::POST, false) => transfer_handler.add(body).await,
+        (&Method::PUT, true) | (&Method::PATCH, true) => transfer_handler.update(body).await,
+        (&Method::DELETE, true) => transfer_handler.delete().await,
+
+        // 
+        _ => Err(AppError::MethodNotAllowed(route.allowed())),
+        
+    }
+
+}
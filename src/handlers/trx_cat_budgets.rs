@@ -1,51 +1,55 @@
 
-use crate::handlers::{req_query_id, get_req_query};
-use crate::models::bigdecimal_to_int;
-use crate::models::trx_cat_budgets::{ TrxCatBudgetModel, NewTrxCatBudget, UpdateTrxCatBudget };
-use crate::repositories::trx_cat_budgets::{TrxCatBudgetRepo, TrxCatBudgetTrait};
+use crate::errors::AppError;
+use crate::handlers::{AppState, Route, body_with_parent, read_body, req_actor, get_req_query, req_include_deleted, req_money_format, req_page_params};
+use crate::models::pagination::Page;
+use crate::models::trx_cat_budgets::{ TrxCatBudgetModel, NewTrxCatBudget, UpdateTrxCatBudget, RebuildTrxCatBudget, build_model_from_exist, BUDGET_SORT_FIELDS };
+use crate::repositories::trx_cat_budgets::{TrxCatBudgetRepo, TrxCatBudgetTrait, Trait};
 
-use std::env;
 use sqlx::mysql::MySqlPool;
 use hyper::{header, Body, Method, Request, Response, StatusCode};
 
-type GenericError = Box<dyn std::error::Error + Send + Sync>;
-type Result<T> = std::result::Result<T, GenericError>;
+type Result<T> = std::result::Result<T, AppError>;
 
 static INTERNAL_SERVER_ERROR: &[u8] = b"Internal Server Error";
-static NOTFOUND: &[u8] = b"Not Found";
 
 pub struct TrxCatBudgetHandler<'a>{
     trx_cat_budget_repo: TrxCatBudgetRepo,
     request: &'a Request<Body>,
+    route: Route,
+    /// Currency the budget amounts are kept in.
+    base_currency: String,
 }
 
 impl<'a> TrxCatBudgetHandler<'a> {
-    pub fn new(req: &'a Request<Body>, pool: MySqlPool) -> Self {
+    pub fn new(req: &'a Request<Body>, pool: MySqlPool, base_currency: &str, derived_budgets: bool, route: Route) -> Self {
         Self { 
-            trx_cat_budget_repo: TrxCatBudgetRepo::new(pool),
+            trx_cat_budget_repo: TrxCatBudgetRepo::new(pool)
+                .with_actor(req_actor(req))
+                .with_base_currency(base_currency)
+                .with_derived_budgets(derived_budgets),
             request: req,
+            route,
+            base_currency: base_currency.to_string(),
         }
     }
 
     async fn list(&mut self) -> Result<Response<Body>> { 
 
-        let str_category_id: Option<String> = get_req_query(self.request, String::from("categoryid"));
+        // `/trx_cats/{id}/budgets` scopes the list to that category
+        let str_category_id: Option<String> = match self.route.parent {
+            Some(category_id) => Some(category_id.to_string()),
+            None => get_req_query(self.request, String::from("categoryid")),
+        };
         let int_category_id: i32 = str_category_id.unwrap_or("0".to_string()).parse().ok().unwrap_or_default();
-        let datas = self.trx_cat_budget_repo.trx_cat_budget_list(int_category_id).await?;
-
-        let budget: Vec<TrxCatBudgetModel> = datas.iter().map(|data| TrxCatBudgetModel {
-            id: data.id,
-            periode: data.periode.clone(),
-            allocated: bigdecimal_to_int(data.allocated.clone()),
-            spent: bigdecimal_to_int(data.spent.clone()),
-            available: bigdecimal_to_int(data.available.clone()),
-            created_at: data.created_at,
-            updated_at: data.updated_at,
-            categoryid: data.categoryid,
-            }
-        ).collect();
+        let page = req_page_params(self.request, BUDGET_SORT_FIELDS, "id")?;
+        let include_deleted = req_include_deleted(self.request)?;
+        let format = req_money_format(self.request)?;
+        let (datas, next_cursor) = self.trx_cat_budget_repo.trx_cat_budget_list(int_category_id, page, include_deleted).await?;
 
-        let res = match serde_json::to_string(&budget) {
+        let budget: Vec<TrxCatBudgetModel> = datas.iter().map(|data| build_model_from_exist(data.clone(), &self.base_currency, format)).collect();
+        let page = Page { data: budget, next_cursor };
+
+        let res = match serde_json::to_string(&page) {
             Ok(json) => Response::builder()
                 .header(header::CONTENT_TYPE, "application/json")
                 .body(Body::from(json))
@@ -60,19 +64,12 @@ impl<'a> TrxCatBudgetHandler<'a> {
 
     async fn detail(&mut self) -> Result<Response<Body>> { 
 
-        let query_id = req_query_id(self.request);
-        let data = self.trx_cat_budget_repo.trx_cat_budget_detail(query_id).await?;
-
-        let budget = TrxCatBudgetModel {
-            id: data.id,
-            periode: data.periode,
-            allocated: bigdecimal_to_int(data.allocated),
-            spent: bigdecimal_to_int(data.spent),
-            available: bigdecimal_to_int(data.available),
-            created_at: data.created_at,
-            updated_at: data.updated_at,
-            categoryid: data.categoryid,
-        };
+        let query_id = self.route.id()?;
+        let include_deleted = req_include_deleted(self.request)?;
+        let format = req_money_format(self.request)?;
+        let data = self.trx_cat_budget_repo.trx_cat_budget_detail(query_id, include_deleted).await?;
+
+        let budget = build_model_from_exist(data, &self.base_currency, format);
 
         let res = match serde_json::to_string(&budget) {
             Ok(json) => Response::builder()
@@ -89,19 +86,14 @@ impl<'a> TrxCatBudgetHandler<'a> {
 
     async fn add(&mut self, body: &str) -> Result<Response<Body>> { 
 
-        let data: NewTrxCatBudget = serde_json::from_str(body)?;
-        let new_budget = self.trx_cat_budget_repo.trx_cat_budget_add(data.clone()).await?;
-
-        let budget = TrxCatBudgetModel {
-            id: new_budget.id,
-            periode: new_budget.periode,
-            allocated: bigdecimal_to_int(new_budget.allocated),
-            spent: bigdecimal_to_int(new_budget.spent),
-            available: bigdecimal_to_int(new_budget.available),
-            created_at: new_budget.created_at,
-            updated_at: new_budget.updated_at,
-            categoryid: new_budget.categoryid,
-        };
+        let data: NewTrxCatBudget = body_with_parent(body, "categoryid", self.route.parent)?;
+        let format = req_money_format(self.request)?;
+
+        let mut tx = self.trx_cat_budget_repo.start_transaction().await?;
+        let new_budget = tx.trx_cat_budget_add(data.clone()).await?;
+        tx.commit().await?;
+
+        let budget: TrxCatBudgetModel = build_model_from_exist(new_budget, &self.base_currency, format);
 
         let res = match serde_json::to_string(&budget) {
             Ok(json) => Response::builder()
@@ -118,20 +110,15 @@ impl<'a> TrxCatBudgetHandler<'a> {
 
     async fn update(&mut self, body: &str) -> Result<Response<Body>> { 
 
-        let query_id = req_query_id(self.request);
+        let query_id = self.route.id()?;
         let data: UpdateTrxCatBudget = serde_json::from_str(body)?;
-        let update_budget = self.trx_cat_budget_repo.trx_cat_budget_update(query_id, data.clone()).await?;
-
-        let budget = TrxCatBudgetModel {
-            id: update_budget.id,
-            periode: update_budget.periode,
-            allocated: bigdecimal_to_int(update_budget.allocated),
-            spent: bigdecimal_to_int(update_budget.spent),
-            available: bigdecimal_to_int(update_budget.available),
-            created_at: update_budget.created_at,
-            updated_at: update_budget.updated_at,
-            categoryid: update_budget.categoryid,
-        };
+        let format = req_money_format(self.request)?;
+
+        let mut tx = self.trx_cat_budget_repo.start_transaction().await?;
+        let update_budget = tx.trx_cat_budget_update(query_id, data.clone()).await?;
+        tx.commit().await?;
+
+        let budget: TrxCatBudgetModel = build_model_from_exist(update_budget, &self.base_currency, format);
 
         let res = match serde_json::to_string(&budget) {
             Ok(json) => Response::builder()
@@ -148,19 +135,38 @@ impl<'a> TrxCatBudgetHandler<'a> {
 
     async fn delete(&mut self) -> Result<Response<Body>> { 
 
-        let query_id = req_query_id(self.request);
-        let delete_budget = self.trx_cat_budget_repo.trx_cat_budget_delete(query_id).await?;
-
-        let budget = TrxCatBudgetModel {
-            id: delete_budget.id,
-            periode: delete_budget.periode,
-            allocated: bigdecimal_to_int(delete_budget.allocated),
-            spent: bigdecimal_to_int(delete_budget.spent),
-            available: bigdecimal_to_int(delete_budget.available),
-            created_at: delete_budget.created_at,
-            updated_at: delete_budget.updated_at,
-            categoryid: delete_budget.categoryid,
+        let query_id = self.route.id()?;
+        let format = req_money_format(self.request)?;
+
+        let mut tx = self.trx_cat_budget_repo.start_transaction().await?;
+        let delete_budget = tx.trx_cat_budget_delete(query_id).await?;
+        tx.commit().await?;
+
+        let budget: TrxCatBudgetModel = build_model_from_exist(delete_budget, &self.base_currency, format);
+
+        let res = match serde_json::to_string(&budget) {
+            Ok(json) => Response::builder()
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(json))
+                .unwrap(),
+            Err(_) => Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(INTERNAL_SERVER_ERROR.into())
+                .unwrap(),
         };
+        Ok(res)
+    }
+
+    async fn restore(&mut self) -> Result<Response<Body>> { 
+
+        let query_id = self.route.id()?;
+        let format = req_money_format(self.request)?;
+
+        let mut tx = self.trx_cat_budget_repo.start_transaction().await?;
+        let restore_budget = tx.trx_cat_budget_restore(query_id).await?;
+        tx.commit().await?;
+
+        let budget: TrxCatBudgetModel = build_model_from_exist(restore_budget, &self.base_currency, format);
 
         let res = match serde_json::to_string(&budget) {
             Ok(json) => Response::builder()
@@ -174,39 +180,75 @@ impl<'a> TrxCatBudgetHandler<'a> {
         };
         Ok(res)
     }
+
+    async fn rebuild(&mut self, body: &str) -> Result<Response<Body>> { 
+
+        let data: RebuildTrxCatBudget = serde_json::from_str(body)?;
+        let format = req_money_format(self.request)?;
+
+        let mut tx = self.trx_cat_budget_repo.start_transaction().await?;
+        let rebuilt = tx.trx_cat_budget_rebuild(data).await?;
+        tx.commit().await?;
+
+        let budget: Vec<TrxCatBudgetModel> = rebuilt.into_iter().map(|data| build_model_from_exist(data, &self.base_currency, format)).collect();
+        let page = Page { data: budget, next_cursor: None };
+
+        let res = match serde_json::to_string(&page) {
+            Ok(json) => Response::builder()
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(json))
+                .unwrap(),
+            Err(_) => Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(INTERNAL_SERVER_ERROR.into())
+                .unwrap(),
+        };
+        Ok(res)
+    }
 }
 
-pub async fn handler( req: Request<Body> ) -> Result<Response<Body>> {
-    let pool = MySqlPool::connect(&env::var("DATABASE_URL").unwrap()).await?;
+pub async fn handler( req: Request<Body>, state: AppState, route: Route ) -> Result<Response<Body>> {
+    let pool = state.pool;
 
     let (parts, body) = req.into_parts();
-    let body_bytes = hyper::body::to_bytes(body).await.unwrap();
-    let body = std::str::from_utf8(&body_bytes).unwrap();
+    let body_bytes = read_body(body, state.config.server.body_limit).await?;
+    let body = std::str::from_utf8(&body_bytes)?;
     
     let request: hyper::Request<Body> = Request::from_parts(parts, body_bytes.clone().into());
-    let mut trx_cat_budget_handler = TrxCatBudgetHandler::new(&request, pool);
-    let is_specified: bool = match get_req_query(&request, "id".to_string()) {
-        Some(_) => true,
-        None => false
-    };
+    let mut trx_cat_budget_handler = TrxCatBudgetHandler::new(&request, pool, &state.config.currency.base, state.config.features.derived_budgets, route);
 
-    match (request.method(), is_specified) {
+    match (request.method(), route.id.is_some()) {
+
+        (&Method::POST, true) if route.restore => trx_cat_budget_handler.restore().await,
+        _ if route.restore => Err(AppError::MethodNotAllowed(route.allowed())),
 
         (&Method::GET, false) => trx_cat_budget_handler.list().await,
         (&Method::GET, true) => trx_cat_budget_handler.detail().await,
-        (&Method::PUT, false) => trx_cat_budget_handler.add(body).await,
-        (&Method::POST, true) => trx_cat_budget_handler.update(body).await,
+        (&Method::POST, false) => trx_cat_budget_handler.add(body).await,
+        (&Method::PUT, true) | (&Method::PATCH, true) => trx_cat_budget_handler.update(body).await,
         (&Method::DELETE, true) => trx_cat_budget_handler.delete().await,
 
         // 
-        _ => {
-            // Return 404 not found response.
-            Ok(Response::builder()
-                .status(StatusCode::NOT_FOUND)
-                .body(NOTFOUND.into())
-                .unwrap())
-        }
+        _ => Err(AppError::MethodNotAllowed(route.allowed())),
         
     }
 
-}
\ No newline at end of file
+}
+
+/// `POST /trx_cats_budgets/rebuild`.
+pub async fn rebuild_handler( req: Request<Body>, state: AppState, route: Route ) -> Result<Response<Body>> {
+    let pool = state.pool;
+
+    let (parts, body) = req.into_parts();
+    let body_bytes = read_body(body, state.config.server.body_limit).await?;
+    let body = std::str::from_utf8(&body_bytes)?;
+    
+    let request: hyper::Request<Body> = Request::from_parts(parts, body_bytes.clone().into());
+    let mut trx_cat_budget_handler = TrxCatBudgetHandler::new(&request, pool, &state.config.currency.base, state.config.features.derived_budgets, route);
+
+    match request.method() {
+        &Method::POST => trx_cat_budget_handler.rebuild(body).await,
+        _ => Err(AppError::MethodNotAllowed(vec![Method::POST])),
+    }
+
+}
@@ -1,29 +1,36 @@
 
-use crate::handlers::{req_query_id, get_req_query};
+use crate::errors::AppError;
+use crate::handlers::{AppState, Route, read_body, req_actor, get_req_query, req_include_deleted, req_money_format, req_page_params};
+use crate::models::pagination::Page;
 use crate::models::trx_cats;
-use crate::models::trx_cats::{ TrxCatModel, TrxCatModelWithType, ExistTrxCatWithBudgetType, ExistTrxCatWithBudget, AddTrxCat, UpdateTrxCat };
-use crate::repositories::trx_cats::{TrxCatRepo, TrxCatTrait};
+use crate::models::trx_cats::{ TrxCatModel, TrxCatModelWithType, ExistTrxCatWithBudgetType, ExistTrxCatWithBudget, AddTrxCat, UpdateTrxCat, TRX_CAT_SORT_FIELDS };
+use crate::repositories::trx_cats::{TrxCatRepo, TrxCatTrait, Trait};
 
-use std::env;
 use sqlx::mysql::MySqlPool;
 use hyper::{header, Body, Method, Request, Response, StatusCode};
 
-type GenericError = Box<dyn std::error::Error + Send + Sync>;
-type Result<T> = std::result::Result<T, GenericError>;
+type Result<T> = std::result::Result<T, AppError>;
 
 static INTERNAL_SERVER_ERROR: &[u8] = b"Internal Server Error";
-static NOTFOUND: &[u8] = b"Not Found";
 
 pub struct TrxCatHandler<'a>{
     trx_cat_repo: TrxCatRepo,
     request: &'a Request<Body>,
+    route: Route,
+    /// Currency the budget amounts are kept in.
+    base_currency: String,
 }
 
 impl<'a> TrxCatHandler<'a> {
-    pub fn new(req: &'a Request<Body>, pool: MySqlPool) -> Self {
+    pub fn new(req: &'a Request<Body>, pool: MySqlPool, base_currency: &str, derived_budgets: bool, route: Route) -> Self {
         Self { 
-            trx_cat_repo: TrxCatRepo::new(pool),
+            trx_cat_repo: TrxCatRepo::new(pool)
+                .with_actor(req_actor(req))
+                .with_base_currency(base_currency)
+                .with_derived_budgets(derived_budgets),
             request: req,
+            route,
+            base_currency: base_currency.to_string(),
         }
     }
 
@@ -31,10 +38,14 @@ impl<'a> TrxCatHandler<'a> {
 
         let str_type_id: Option<String> = get_req_query(self.request, "typeid".to_string());
         let type_id: i32 = str_type_id.unwrap_or("0".to_string()).parse().ok().unwrap_or_default();
-        let datas: Vec<ExistTrxCatWithBudgetType> = self.trx_cat_repo.trx_cats_list(type_id).await?;
-        let cats: Vec<TrxCatModelWithType> = datas.iter().map(|data| trx_cats::detail_model_from_exist(data.clone())).collect();
-
-        let res = match serde_json::to_string(&cats) {
+        let page = req_page_params(self.request, TRX_CAT_SORT_FIELDS, "id")?;
+        let include_deleted = req_include_deleted(self.request)?;
+        let format = req_money_format(self.request)?;
+        let (datas, next_cursor) = self.trx_cat_repo.trx_cats_list(type_id, page, include_deleted).await?;
+        let cats: Vec<TrxCatModelWithType> = datas.iter().map(|data| trx_cats::detail_model_from_exist(data.clone(), &self.base_currency, format)).collect();
+        let page = Page { data: cats, next_cursor };
+
+        let res = match serde_json::to_string(&page) {
             Ok(json) => Response::builder()
                 .header(header::CONTENT_TYPE, "application/json")
                 .body(Body::from(json))
@@ -49,9 +60,11 @@ impl<'a> TrxCatHandler<'a> {
 
     async fn detail(&mut self) -> Result<Response<Body>> { 
 
-        let query_id = req_query_id(self.request);
-        let data: ExistTrxCatWithBudgetType = self.trx_cat_repo.trx_cats_detail(query_id).await?;
-        let cat: TrxCatModelWithType = trx_cats::detail_model_from_exist(data);
+        let query_id = self.route.id()?;
+        let include_deleted = req_include_deleted(self.request)?;
+        let format = req_money_format(self.request)?;
+        let data: ExistTrxCatWithBudgetType = self.trx_cat_repo.trx_cats_detail(query_id, include_deleted).await?;
+        let cat: TrxCatModelWithType = trx_cats::detail_model_from_exist(data, &self.base_currency, format);
 
         let res = match serde_json::to_string(&cat) {
             Ok(json) => Response::builder()
@@ -69,8 +82,11 @@ impl<'a> TrxCatHandler<'a> {
     async fn add(&mut self, body: &str) -> Result<Response<Body>> { 
 
         let data: AddTrxCat = serde_json::from_str(body)?;
-        let new_cat: ExistTrxCatWithBudget = self.trx_cat_repo.trx_cats_add(data.clone()).await?;
-        let cat: TrxCatModel = trx_cats::build_model_from_exist(new_cat);
+        let format = req_money_format(self.request)?;
+        let mut tx = self.trx_cat_repo.start_transaction().await?;
+        let new_cat: ExistTrxCatWithBudget = tx.trx_cats_add(data.clone()).await?;
+        tx.commit().await?;
+        let cat: TrxCatModel = trx_cats::build_model_from_exist(new_cat, &self.base_currency, format);
 
         let res = match serde_json::to_string(&cat) {
             Ok(json) => Response::builder()
@@ -87,10 +103,14 @@ impl<'a> TrxCatHandler<'a> {
 
     async fn update(&mut self, body: &str) -> Result<Response<Body>> { 
 
-        let query_id = req_query_id(self.request);
+        let query_id = self.route.id()?;
         let data: UpdateTrxCat = serde_json::from_str(body)?;
-        let update_cat = self.trx_cat_repo.trx_cats_update(query_id, data.clone()).await?;
-        let cat: TrxCatModel = trx_cats::build_model_from_exist(update_cat);
+        let format = req_money_format(self.request)?;
+
+        let mut tx = self.trx_cat_repo.start_transaction().await?;
+        let update_cat = tx.trx_cats_update(query_id, data.clone()).await?;
+        tx.commit().await?;
+        let cat: TrxCatModel = trx_cats::build_model_from_exist(update_cat, &self.base_currency, format);
 
         let res = match serde_json::to_string(&cat) {
             Ok(json) => Response::builder()
@@ -107,9 +127,34 @@ impl<'a> TrxCatHandler<'a> {
 
     async fn delete(&mut self) -> Result<Response<Body>> { 
 
-        let query_id = req_query_id(self.request);
-        let delete_cat = self.trx_cat_repo.trx_cats_delete(query_id).await?;
-        let cat: TrxCatModel = trx_cats::build_model_from_exist(delete_cat);
+        let query_id = self.route.id()?;
+        let format = req_money_format(self.request)?;
+        let mut tx = self.trx_cat_repo.start_transaction().await?;
+        let delete_cat = tx.trx_cats_delete(query_id).await?;
+        tx.commit().await?;
+        let cat: TrxCatModel = trx_cats::build_model_from_exist(delete_cat, &self.base_currency, format);
+
+        let res = match serde_json::to_string(&cat) {
+            Ok(json) => Response::builder()
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(json))
+                .unwrap(),
+            Err(_) => Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(INTERNAL_SERVER_ERROR.into())
+                .unwrap(),
+        };
+        Ok(res)
+    }
+
+    async fn restore(&mut self) -> Result<Response<Body>> { 
+
+        let query_id = self.route.id()?;
+        let format = req_money_format(self.request)?;
+        let mut tx = self.trx_cat_repo.start_transaction().await?;
+        let restore_cat = tx.trx_cats_restore(query_id).await?;
+        tx.commit().await?;
+        let cat: TrxCatModel = trx_cats::build_model_from_exist(restore_cat, &self.base_currency, format);
 
         let res = match serde_json::to_string(&cat) {
             Ok(json) => Response::builder()
@@ -125,37 +170,30 @@ impl<'a> TrxCatHandler<'a> {
     }
 }
 
-pub async fn handler( req: Request<Body> ) -> Result<Response<Body>> {
-    let pool = MySqlPool::connect(&env::var("DATABASE_URL").unwrap()).await?;
+pub async fn handler( req: Request<Body>, state: AppState, route: Route ) -> Result<Response<Body>> {
+    let pool = state.pool;
 
     let (parts, body) = req.into_parts();
-    let body_bytes = hyper::body::to_bytes(body).await.unwrap();
-    let body = std::str::from_utf8(&body_bytes).unwrap();
+    let body_bytes = read_body(body, state.config.server.body_limit).await?;
+    let body = std::str::from_utf8(&body_bytes)?;
     
     let request: hyper::Request<Body> = Request::from_parts(parts, body_bytes.clone().into());
-    let mut trx_cat_handler = TrxCatHandler::new(&request, pool);
-    let is_specified: bool = match get_req_query(&request, "id".to_string()) {
-        Some(_) => true,
-        None => false
-    };
+    let mut trx_cat_handler = TrxCatHandler::new(&request, pool, &state.config.currency.base, state.config.features.derived_budgets, route);
+
+    match (request.method(), route.id.is_some()) {
 
-    match (request.method(), is_specified) {
+        (&Method::POST, true) if route.restore => trx_cat_handler.restore().await,
+        _ if route.restore => Err(AppError::MethodNotAllowed(route.allowed())),
 
         (&Method::GET, false) => trx_cat_handler.list().await,
         (&Method::GET, true) => trx_cat_handler.detail().await,
-        (&Method::PUT, false) => trx_cat_handler.add(body).await,
-        (&Method::POST, true) => trx_cat_handler.update(body).await,
+        (&Method::POST, false) => trx_cat_handler.add(body).await,
+        (&Method::PUT, true) | (&Method::PATCH, true) => trx_cat_handler.update(body).await,
         (&Method::DELETE, true) => trx_cat_handler.delete().await,
 
         // 
-        _ => {
-            // Return 404 not found response.
-            Ok(Response::builder()
-                .status(StatusCode::NOT_FOUND)
-                .body(NOTFOUND.into())
-                .unwrap())
-        }
+        _ => Err(AppError::MethodNotAllowed(route.allowed())),
         
     }
 
-}
\ No newline at end of file
+}
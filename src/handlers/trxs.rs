@@ -1,40 +1,78 @@
 
-use crate::handlers::{req_query_id, get_req_query};
-use crate::models::bigdecimal_to_int;
-use crate::models::trxs::{ TrxModel, TrxModelWithAccCat, NewTrx, UpdateTrx, build_model_from_exist };
-use crate::repositories::trxs::{TrxRepo, TrxTrait};
+use crate::errors::AppError;
+use crate::handlers::{AppState, Route, body_with_parent, read_body, req_actor, get_req_query, req_include_deleted, req_money_format, req_page_params, req_query_ids, req_query_parse};
+use crate::models::pagination::Page;
+use crate::models::trxs::{ TrxModel, TrxModelWithAccCat, NewTrx, UpdateTrx, TrxFilter, build_model_from_exist, build_trx_model, parse_filter_datetime, TRX_SORT_FIELDS };
+use crate::repositories::trxs::{TrxRepo, TrxTrait, Trait};
 
-use std::env;
 use sqlx::mysql::MySqlPool;
 use hyper::{header, Body, Method, Request, Response, StatusCode};
 
-type GenericError = Box<dyn std::error::Error + Send + Sync>;
-type Result<T> = std::result::Result<T, GenericError>;
+type Result<T> = std::result::Result<T, AppError>;
 
 static INTERNAL_SERVER_ERROR: &[u8] = b"Internal Server Error";
-static NOTFOUND: &[u8] = b"Not Found";
 
 pub struct TrxHandler<'a>{
     trx_repo: TrxRepo,
     request: &'a Request<Body>,
+    route: Route,
 }
 
 impl<'a> TrxHandler<'a> {
-    pub fn new(req: &'a Request<Body>, pool: MySqlPool) -> Self {
+    pub fn new(req: &'a Request<Body>, pool: MySqlPool, base_currency: &str, route: Route) -> Self {
         Self { 
-            trx_repo: TrxRepo::new(pool),
+            trx_repo: TrxRepo::new(pool).with_actor(req_actor(req)).with_base_currency(base_currency),
             request: req,
+            route,
         }
     }
 
+    fn filter(&self) -> Result<TrxFilter> {
+
+        let from = match get_req_query(self.request, String::from("from")) {
+            Some(value) => Some(parse_filter_datetime("from", &value, false)?),
+            None => None,
+        };
+        let to = match get_req_query(self.request, String::from("to")) {
+            Some(value) => Some(parse_filter_datetime("to", &value, true)?),
+            None => None,
+        };
+
+        // `/accounts/{id}/trxs` scopes the list to that account
+        let accountids = match self.route.parent {
+            Some(account_id) => vec![account_id],
+            None => req_query_ids(self.request, "accountid")?,
+        };
+
+        let q = get_req_query(self.request, String::from("q"))
+            .map(|q| q.trim().to_string())
+            .filter(|q| !q.is_empty());
+
+        Ok(TrxFilter {
+            from,
+            to,
+            min_amount: req_query_parse(self.request, "minAmount")?,
+            max_amount: req_query_parse(self.request, "maxAmount")?,
+            direction: req_query_parse(self.request, "type")?,
+            accountids,
+            categoryids: req_query_ids(self.request, "categoryid")?,
+            typeid: req_query_parse(self.request, "typeid")?,
+            q,
+            transfer: req_query_parse(self.request, "transfer")?,
+            include_deleted: req_include_deleted(self.request)?,
+        })
+    }
+
     async fn list(&mut self) -> Result<Response<Body>> {
 
-        let str_account_id: Option<String> = get_req_query(self.request, String::from("accountid"));
-        let str_category_id: Option<String> = get_req_query(self.request, String::from("categoryid"));
-        let datas = self.trx_repo.trxs_list(str_account_id, str_category_id).await?;
-        let trxs: Vec<TrxModelWithAccCat> = datas.iter().map(|data| build_model_from_exist(data.clone())).collect();
+        let filter = self.filter()?;
+        let format = req_money_format(self.request)?;
+        let page = req_page_params(self.request, TRX_SORT_FIELDS, "-datetime")?;
+        let (datas, next_cursor) = self.trx_repo.trxs_list(filter, page).await?;
+        let trxs: Vec<TrxModelWithAccCat> = datas.iter().map(|data| build_model_from_exist(data.clone(), format)).collect();
+        let page = Page { data: trxs, next_cursor };
 
-        let res = match serde_json::to_string(&trxs) {
+        let res = match serde_json::to_string(&page) {
             Ok(json) => Response::builder()
                 .header(header::CONTENT_TYPE, "application/json")
                 .body(Body::from(json))
@@ -49,9 +87,11 @@ impl<'a> TrxHandler<'a> {
 
     async fn detail(&mut self) -> Result<Response<Body>> { 
 
-        let query_id = req_query_id(self.request);
-        let data = self.trx_repo.trx_detail(query_id).await?;
-        let trx = build_model_from_exist(data);
+        let query_id = self.route.id()?;
+        let include_deleted = req_include_deleted(self.request)?;
+        let format = req_money_format(self.request)?;
+        let data = self.trx_repo.trx_detail(query_id, include_deleted).await?;
+        let trx = build_model_from_exist(data, format);
 
         let res = match serde_json::to_string(&trx) {
             Ok(json) => Response::builder()
@@ -68,22 +108,13 @@ impl<'a> TrxHandler<'a> {
 
     async fn add(&mut self, body: &str) -> Result<Response<Body>> { 
 
-        let data: NewTrx = serde_json::from_str(body)?;
-        let new_trx = self.trx_repo.trx_add(data.clone()).await?;
-
-        let trx = TrxModel {
-            id: new_trx.id,
-            credit: bigdecimal_to_int(new_trx.credit),
-            debit: bigdecimal_to_int(new_trx.debit),
-            description: new_trx.description,
-            balance_before: bigdecimal_to_int(new_trx.balance_before),
-            balance_after: bigdecimal_to_int(new_trx.balance_after),
-            datetime: new_trx.datetime,
-            created_at: new_trx.created_at,
-            updated_at: new_trx.updated_at,
-            accountid: new_trx.accountid,
-            categoryid: new_trx.categoryid,
-        };
+        let data: NewTrx = body_with_parent(body, "accountid", self.route.parent)?;
+        let format = req_money_format(self.request)?;
+        let mut tx = self.trx_repo.start_transaction().await?;
+        let new_trx = tx.trx_add(data.clone()).await?;
+        tx.commit().await?;
+
+        let trx: TrxModel = build_trx_model(new_trx, format);
 
         let res = match serde_json::to_string(&trx) {
             Ok(json) => Response::builder()
@@ -100,23 +131,14 @@ impl<'a> TrxHandler<'a> {
 
     async fn update(&mut self, body: &str) -> Result<Response<Body>> { 
 
-        let query_id = req_query_id(self.request);
+        let query_id = self.route.id()?;
         let data: UpdateTrx = serde_json::from_str(body)?;
-        let update_trx = self.trx_repo.trx_update(query_id, data.clone()).await?;
-
-        let trx = TrxModel {
-            id: update_trx.id,
-            credit: bigdecimal_to_int(update_trx.credit),
-            debit: bigdecimal_to_int(update_trx.debit),
-            description: update_trx.description,
-            balance_before: bigdecimal_to_int(update_trx.balance_before),
-            balance_after: bigdecimal_to_int(update_trx.balance_after),
-            datetime: update_trx.datetime,
-            created_at: update_trx.created_at,
-            updated_at: update_trx.updated_at,
-            accountid: update_trx.accountid,
-            categoryid: update_trx.categoryid,
-        };
+        let format = req_money_format(self.request)?;
+        let mut tx = self.trx_repo.start_transaction().await?;
+        let update_trx = tx.trx_update(query_id, data.clone()).await?;
+        tx.commit().await?;
+
+        let trx: TrxModel = build_trx_model(update_trx, format);
 
         let res = match serde_json::to_string(&trx) {
             Ok(json) => Response::builder()
@@ -133,22 +155,36 @@ impl<'a> TrxHandler<'a> {
 
     async fn delete(&mut self) -> Result<Response<Body>> { 
 
-        let query_id = req_query_id(self.request);
-        let delete_trx = self.trx_repo.trx_delete(query_id).await?;
-
-        let trx = TrxModel {
-            id: delete_trx.id,
-            credit: bigdecimal_to_int(delete_trx.credit),
-            debit: bigdecimal_to_int(delete_trx.debit),
-            description: delete_trx.description,
-            balance_before: bigdecimal_to_int(delete_trx.balance_before),
-            balance_after: bigdecimal_to_int(delete_trx.balance_after),
-            datetime: delete_trx.datetime,
-            created_at: delete_trx.created_at,
-            updated_at: delete_trx.updated_at,
-            accountid: delete_trx.accountid,
-            categoryid: delete_trx.categoryid,
+        let query_id = self.route.id()?;
+        let format = req_money_format(self.request)?;
+        let mut tx = self.trx_repo.start_transaction().await?;
+        let delete_trx = tx.trx_delete(query_id).await?;
+        tx.commit().await?;
+
+        let trx: TrxModel = build_trx_model(delete_trx, format);
+
+        let res = match serde_json::to_string(&trx) {
+            Ok(json) => Response::builder()
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(json))
+                .unwrap(),
+            Err(_) => Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(INTERNAL_SERVER_ERROR.into())
+                .unwrap(),
         };
+        Ok(res)
+    }
+
+    async fn restore(&mut self) -> Result<Response<Body>> { 
+
+        let query_id = self.route.id()?;
+        let format = req_money_format(self.request)?;
+        let mut tx = self.trx_repo.start_transaction().await?;
+        let restore_trx = tx.trx_restore(query_id).await?;
+        tx.commit().await?;
+
+        let trx: TrxModel = build_trx_model(restore_trx, format);
 
         let res = match serde_json::to_string(&trx) {
             Ok(json) => Response::builder()
@@ -164,37 +200,30 @@ impl<'a> TrxHandler<'a> {
     }
 }
 
-pub async fn handler( req: Request<Body> ) -> Result<Response<Body>> {
-    let pool = MySqlPool::connect(&env::var("DATABASE_URL").unwrap()).await?;
+pub async fn handler( req: Request<Body>, state: AppState, route: Route ) -> Result<Response<Body>> {
+    let pool = state.pool;
 
     let (parts, body) = req.into_parts();
-    let body_bytes = hyper::body::to_bytes(body).await.unwrap();
-    let body = std::str::from_utf8(&body_bytes).unwrap();
+    let body_bytes = read_body(body, state.config.server.body_limit).await?;
+    let body = std::str::from_utf8(&body_bytes)?;
     
     let request: hyper::Request<Body> = Request::from_parts(parts, body_bytes.clone().into());
-    let mut trx_handler = TrxHandler::new(&request, pool);
-    let is_specified: bool = match get_req_query(&request, "id".to_string()) {
-        Some(_) => true,
-        None => false
-    };
+    let mut trx_handler = TrxHandler::new(&request, pool, &state.config.currency.base, route);
 
-    match (request.method(), is_specified) {
+    match (request.method(), route.id.is_some()) {
+
+        (&Method::POST, true) if route.restore => trx_handler.restore().await,
+        _ if route.restore => Err(AppError::MethodNotAllowed(route.allowed())),
 
         (&Method::GET, false) => trx_handler.list().await,
         (&Method::GET, true) => trx_handler.detail().await,
-        (&Method::PUT, false) => trx_handler.add(body).await,
-        (&Method::POST, true) => trx_handler.update(body).await,
+        (&Method::POST, false) => trx_handler.add(body).await,
+        (&Method::PUT, true) | (&Method::PATCH, true) => trx_handler.update(body).await,
         (&Method::DELETE, true) => trx_handler.delete().await,
 
         // 
-        _ => {
-            // Return 404 not found response.
-            Ok(Response::builder()
-                .status(StatusCode::NOT_FOUND)
-                .body(NOTFOUND.into())
-                .unwrap())
-        }
+        _ => Err(AppError::MethodNotAllowed(route.allowed())),
         
     }
 
-}
\ No newline at end of file
+}
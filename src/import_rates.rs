@@ -0,0 +1,31 @@
+use crate::models::exchange_rates::parse_rates_csv;
+use crate::repositories::exchange_rates::{ExchangeRateRepo, Trait};
+
+use sqlx::mysql::MySqlPool;
+
+/// Entry point of the `import-rates` subcommand.
+///
+/// `import-rates <FILE>` reads `date,currency,base,rate` lines, the same CSV
+/// `POST /exchange_rates/import` accepts, and replaces the rates of the same
+/// pair and date.
+pub async fn command(pool: &MySqlPool, args: &[String]) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let path = match args {
+        [path] => path,
+        _ => return Err("import-rates expects exactly one csv file".into()),
+    };
+
+    let csv = std::fs::read_to_string(path)
+        .map_err(|err| format!("cannot read `{}`: {}", path, err))?;
+    let rates = parse_rates_csv(&csv)?;
+
+    let repo = ExchangeRateRepo::new(pool.clone());
+
+    // the whole file is imported or none of it
+    let mut tx = repo.start_transaction().await?;
+    let report = tx.rates_import(rates).await?;
+    tx.commit().await?;
+
+    println!("{}", serde_json::to_string_pretty(&report)?);
+
+    Ok(())
+}
@@ -1,41 +1,166 @@
 // #![deny(warnings)]
 
-use crate::handlers::handler;
+use crate::config::{Cli, Config, LogConfig, LogFormat, USAGE};
+use crate::handlers::{handler, AppState};
 
+use std::env;
+use std::io::Write;
+use std::process;
 use dotenv::dotenv;
 use hyper::service::{make_service_fn, service_fn};
-use hyper::{Client, Server};
+use hyper::Server;
+use sqlx::mysql::{MySqlPool, MySqlPoolOptions};
 
 type GenericError = Box<dyn std::error::Error + Send + Sync>;
 type Result<T> = std::result::Result<T, GenericError>;
 
+mod alerts;
+mod config;
+mod errors;
 mod handlers;
+mod import_rates;
+mod migrations;
 mod models;
+mod notifiers;
+mod purge;
+mod reconcile;
+mod recurring;
+mod rollover;
 mod repositories;
 
 #[tokio::main]
 async fn main() -> Result<()> {
     dotenv().ok();
-    pretty_env_logger::init();
 
-    let addr = ([127, 0, 0, 1], 1337).into();
-    let client = Client::new();
+    let cli = match Cli::parse(env::args().skip(1)) {
+        Ok(cli) => cli,
+        Err(err) => exit_with(err),
+    };
+
+    if cli.help {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+
+    // Refuse to start on a bad configuration instead of failing later.
+    let config = match Config::load(&cli) {
+        Ok(config) => config,
+        Err(err) => exit_with(err),
+    };
+
+    init_logger(&config.log);
+
+    let pool = connect_pool(&config).await?;
+
+    match cli.command.first().map(String::as_str) {
+        None | Some("serve") => serve(pool, config).await,
+
+        // `sohfin_api migrate [up|status|down [version]]` manages the schema and exits.
+        Some("migrate") => migrations::command(&pool, &config.currency.base, &cli.command[1..]).await,
+
+        // `sohfin_api reconcile [--repair]` audits the stored balances and exits.
+        Some("reconcile") => reconcile::command(&pool, &config.currency.base, &cli.command[1..]).await,
+
+        // `sohfin_api purge` hard deletes the expired soft deleted rows once and exits.
+        Some("purge") => purge::command(&pool, &config.purge, &cli.command[1..]).await,
+
+        // `sohfin_api recurring` posts the recurring transactions due now and exits.
+        Some("recurring") => recurring::command(&pool, &config.currency.base, &cli.command[1..]).await,
+
+        // `sohfin_api rollover` creates the budgets of the new periods and exits.
+        Some("rollover") => rollover::command(&pool, &config.currency.base, config.features.derived_budgets, &cli.command[1..]).await,
+
+        // `sohfin_api alerts` delivers the budget alerts not sent yet and exits.
+        Some("alerts") => alerts::command(&pool, &config.alerts, &config.currency.base, &cli.command[1..]).await,
+
+        // `sohfin_api import-rates <file.csv>` loads exchange rates and exits.
+        Some("import-rates") => import_rates::command(&pool, &cli.command[1..]).await,
+
+        Some(other) => exit_with(format!("unknown command `{}`\n\n{}", other, USAGE)),
+    }
+}
+
+async fn serve(pool: MySqlPool, config: Config) -> Result<()> {
+    if config.features.auto_migrate {
+        migrations::run(&pool, &config.currency.base).await?;
+    }
+
+    if config.purge.enabled {
+        purge::spawn(pool.clone(), config.purge.clone());
+    }
+
+    if config.recurring.enabled {
+        recurring::spawn(pool.clone(), config.recurring.clone(), config.currency.base.clone());
+    }
+
+    if config.rollover.enabled {
+        rollover::spawn(pool.clone(), config.rollover.clone(), config.currency.base.clone(), config.features.derived_budgets);
+    }
+
+    if config.alerts.enabled {
+        alerts::spawn(pool.clone(), config.alerts.clone(), config.currency.base.clone())?;
+    }
+
+    let addr = config.bind_addr();
+    let state = AppState::new(pool, config);
+
     let fin_service = make_service_fn(|_| {
 
-        // Move a clone of `client` into the `service_fn`.
-        let client = client.clone();
+        // Move a clone of `state` into the `service_fn`.
+        let state = state.clone();
         async {
             Ok::<_, GenericError>(service_fn(move |req| {
-                // Clone again to ensure that client outlives this closure.
-                handler(req, client.to_owned())
+                // Clone again so every request gets its own handle to the pool.
+                handler(req, state.to_owned())
             }))
         }
 
     });
 
-    let server = Server::bind(&addr).serve(fin_service);
-    println!("Listening on http://{}", addr);
+    let server = Server::try_bind(&addr)?.serve(fin_service);
+    log::info!("Listening on http://{}", addr);
     server.await?;
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Builds the single pool shared by every request.
+async fn connect_pool(config: &Config) -> Result<MySqlPool> {
+    let pool = MySqlPoolOptions::new()
+        .max_connections(config.database.max_connections)
+        .min_connections(config.database.min_connections)
+        .acquire_timeout(config.acquire_timeout())
+        .idle_timeout(config.idle_timeout())
+        .max_lifetime(config.max_lifetime())
+        .connect(&config.database.url)
+        .await?;
+
+    Ok(pool)
+}
+
+fn init_logger(config: &LogConfig) {
+    match config.format {
+        LogFormat::Pretty => pretty_env_logger::formatted_timed_builder()
+            .parse_filters(&config.level)
+            .init(),
+
+        // one JSON object per line, for log collectors
+        LogFormat::Json => env_logger::Builder::new()
+            .parse_filters(&config.level)
+            .format(|buf, record| {
+                let line = serde_json::json!({
+                    "timestamp": chrono::Utc::now().to_rfc3339(),
+                    "level": record.level().to_string(),
+                    "target": record.target(),
+                    "message": record.args().to_string(),
+                });
+                writeln!(buf, "{}", line)
+            })
+            .init(),
+    }
+}
+
+fn exit_with(err: impl std::fmt::Display) -> ! {
+    eprintln!("{}", err);
+    process::exit(2);
+}
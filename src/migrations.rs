@@ -0,0 +1,125 @@
+use crate::repositories::trx_cat_budgets;
+
+use sqlx::migrate::{Migrate, MigrateError, Migrator};
+use sqlx::mysql::MySqlPool;
+
+type GenericError = Box<dyn std::error::Error + Send + Sync>;
+
+/// SQL files under `migrations/`, embedded in the binary at compile time.
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+/// Migration giving category types a direction. Income and refunds count in
+/// budgets from then on, so the `spent` charged before has to be recomputed.
+const CATEGORY_DIRECTION: i64 = 20231209000000;
+
+#[derive(Debug)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub applied: bool,
+}
+
+/// Applies every pending migration, then recomputes the stored values they
+/// change the meaning of, which SQL alone can not.
+pub async fn run(pool: &MySqlPool, base_currency: &str) -> Result<(), GenericError> {
+    let pending: Vec<i64> = status(pool)
+        .await?
+        .iter()
+        .filter(|migration| !migration.applied)
+        .map(|migration| migration.version)
+        .collect();
+
+    MIGRATOR.run(pool).await?;
+
+    if pending.contains(&CATEGORY_DIRECTION) {
+        let mut tx = pool.begin().await?;
+        let rebuilt = trx_cat_budgets::rebuild_all_budgets(&mut tx, base_currency).await?;
+        tx.commit().await?;
+
+        log::info!("recomputed {} budgets for the category directions", rebuilt);
+    }
+
+    Ok(())
+}
+
+/// Lists every known migration and whether it has been applied.
+pub async fn status(pool: &MySqlPool) -> Result<Vec<MigrationStatus>, MigrateError> {
+    let mut conn = pool.acquire().await?;
+    conn.ensure_migrations_table().await?;
+
+    let applied: Vec<i64> = conn
+        .list_applied_migrations()
+        .await?
+        .iter()
+        .map(|migration| migration.version)
+        .collect();
+
+    let statuses = MIGRATOR
+        .iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+        .map(|migration| MigrationStatus {
+            version: migration.version,
+            description: migration.description.to_string(),
+            applied: applied.contains(&migration.version),
+        })
+        .collect();
+
+    Ok(statuses)
+}
+
+/// Reverts applied migrations newer than `target`.
+///
+/// Without a target only the latest applied migration is reverted.
+pub async fn rollback(pool: &MySqlPool, target: Option<i64>) -> Result<(), MigrateError> {
+    let target = match target {
+        Some(version) => version,
+        None => {
+            let applied: Vec<i64> = status(pool)
+                .await?
+                .iter()
+                .filter(|migration| migration.applied)
+                .map(|migration| migration.version)
+                .collect();
+
+            // revert the newest one by targeting the version right before it
+            match applied.len() {
+                0 => return Ok(()),
+                1 => 0,
+                len => applied[len - 2],
+            }
+        }
+    };
+
+    MIGRATOR.undo(pool, target).await
+}
+
+/// Entry point of the `migrate` subcommand.
+///
+/// Supported forms: `migrate [up]`, `migrate status` and `migrate down [version]`.
+pub async fn command(pool: &MySqlPool, base_currency: &str, args: &[String]) -> Result<(), GenericError> {
+    match args.first().map(String::as_str) {
+        None | Some("up") => {
+            run(pool, base_currency).await?;
+            println!("Migrations applied");
+        }
+        Some("status") => {
+            for migration in status(pool).await? {
+                let state = if migration.applied { "applied" } else { "pending" };
+                println!("{:<8} {} {}", state, migration.version, migration.description);
+            }
+        }
+        Some("down") => {
+            let target = match args.get(1) {
+                Some(version) => Some(version.parse::<i64>()?),
+                None => None,
+            };
+            rollback(pool, target).await?;
+            println!("Migrations reverted");
+        }
+        Some(other) => {
+            return Err(format!("unknown migrate command `{}`, expected up, status or down", other).into());
+        }
+    }
+
+    Ok(())
+}
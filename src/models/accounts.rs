@@ -1,23 +1,30 @@
 
-use crate::models::bigdecimal_to_int;
+use crate::errors::AppError;
+use crate::models::audit::Snapshot;
+use crate::models::money::{Amount, Money, MoneyFormat};
+use crate::models::pagination::{Paginated, SortField, datetime_cursor};
 
 use serde::{Deserialize, Serialize};
 use sqlx::types::chrono::{DateTime, Utc};
 use sqlx::FromRow;
-use sqlx::types::BigDecimal;
 
-#[derive(Debug, FromRow, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct AccountModel {
     pub id: i32,
     pub name: String,
     pub description: Option<String>,
     pub star: bool,
     pub r#type: String,
-    pub balance: i64,
+    pub currency: String,
+    pub balance: Amount,
+    #[serde(rename = "archivedAt")]
+    pub archived_at: Option<DateTime<Utc>>,
     #[serde(rename = "createdAt")]
     pub created_at: DateTime<Utc>,
     #[serde(rename = "updatedAt")]
     pub updated_at: DateTime<Utc>,
+    #[serde(rename = "deletedAt")]
+    pub deleted_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Default, Clone, FromRow)]
@@ -27,9 +34,12 @@ pub struct ExistAccount {
     pub description: Option<String>,
     pub star: bool,
     pub r#type: String,
-    pub balance: BigDecimal,
+    pub currency: String,
+    pub balance: Money,
+    pub archived_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub deleted_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Default, Clone, FromRow, Deserialize, Serialize)]
@@ -38,6 +48,8 @@ pub struct NewAccount {
     pub description: Option<String>,
     pub star: bool,
     pub r#type: String,
+    /// Defaults to the base currency.
+    pub currency: Option<String>,
     pub balance: i64,
 }
 
@@ -47,6 +59,8 @@ pub struct UpdateAccount {
     pub description: Option<String>,
     pub star: Option<bool>,
     pub r#type: Option<String>,
+    /// Only while the account has no transactions.
+    pub currency: Option<String>,
     pub balance: Option<i64>,
 }
 
@@ -55,23 +69,121 @@ pub fn new_account(
     name: &String,
     description: &Option<String>,
     star: &bool,
-    r#type: &String,
-    balance: &BigDecimal,
+    r#type: &str,
+    currency: &str,
+    balance: &Money,
+    archived_at: &Option<DateTime<Utc>>,
     created_at: &DateTime<Utc>,
     updated_at: &DateTime<Utc>,
+    deleted_at: &Option<DateTime<Utc>>,
+    format: MoneyFormat,
 ) -> AccountModel {
 
-    let int_balance = bigdecimal_to_int(balance.clone());
-
     AccountModel { 
         id: *id, 
         name: name.to_string(), 
         description: description.clone(), 
         star: star.clone(),
-        r#type: r#type.clone(),
-        balance: int_balance,
+        r#type: r#type.to_string(),
+        currency: currency.to_string(),
+        balance: balance.amount(currency, format),
+        archived_at: *archived_at,
         created_at: *created_at,
         updated_at: *updated_at,
+        deleted_at: *deleted_at,
     }
     
-}
\ No newline at end of file
+}
+
+pub fn validate_new_account(account: &NewAccount) -> Result<(), AppError> {
+    if account.name.trim().is_empty() {
+        return Err(AppError::Validation(String::from("account name is required")));
+    }
+
+    Ok(())
+}
+
+/// Validates that new trxs may still be booked on `account`.
+pub fn validate_active(account: &ExistAccount) -> Result<(), AppError> {
+    if account.archived_at.is_some() {
+        return Err(AppError::Conflict(format!("account {} is archived", account.id)));
+    }
+
+    Ok(())
+}
+
+/// What `DELETE /accounts/{id}` does with the transactions of the account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccountDeletePolicy {
+    /// Refuse while the account has transactions.
+    Restrict,
+    /// Delete its transactions too, reverting their category budgets; refused
+    /// while it has transfers.
+    Cascade,
+    /// Move its transactions to the given account first.
+    Reassign(i32),
+    /// Keep the account and its transactions, hidden from lists.
+    Archive,
+}
+
+/// Builds the policy from `?policy=` and `?to=`.
+pub fn account_delete_policy(policy: Option<&str>, to: Option<i32>) -> Result<AccountDeletePolicy, AppError> {
+    let policy = match (policy, to) {
+        (None, None) => AccountDeletePolicy::Restrict,
+        (Some("cascade"), None) => AccountDeletePolicy::Cascade,
+        (Some("archive"), None) => AccountDeletePolicy::Archive,
+        (Some("reassign"), Some(to)) => AccountDeletePolicy::Reassign(to),
+        (Some("reassign"), None) => {
+            return Err(AppError::BadRequest(String::from("query parameter `to` is required by policy=reassign")));
+        }
+        (Some("cascade"), Some(_)) | (Some("archive"), Some(_)) | (None, Some(_)) => {
+            return Err(AppError::BadRequest(String::from("query parameter `to` is only used by policy=reassign")));
+        }
+        (Some(other), _) => {
+            return Err(AppError::BadRequest(format!("query parameter `policy` must be cascade, reassign or archive, got `{}`", other)));
+        }
+    };
+
+    Ok(policy)
+}
+
+pub const ACCOUNT_SORT_FIELDS: &[SortField] = &[
+    SortField { name: "id", column: "id" },
+    SortField { name: "name", column: "name" },
+    SortField { name: "createdAt", column: "created_at" },
+];
+
+impl Paginated for ExistAccount {
+    fn cursor_value(&self, field: &SortField) -> String {
+        match field.column {
+            "name" => self.name.clone(),
+            "created_at" => datetime_cursor(&self.created_at),
+            _ => self.id.to_string(),
+        }
+    }
+
+    fn cursor_id(&self) -> i32 {
+        self.id
+    }
+}
+
+impl Snapshot for ExistAccount {
+    fn snapshot(&self) -> serde_json::Value {
+        let account = new_account(
+            &self.id,
+            &self.name,
+            &self.description,
+            &self.star,
+            &self.r#type,
+            &self.currency,
+            &self.balance,
+            &self.archived_at,
+            &self.created_at,
+            &self.updated_at,
+            &self.deleted_at,
+            MoneyFormat::Minor,
+        );
+
+        serde_json::to_value(account).unwrap_or_default()
+    }
+}
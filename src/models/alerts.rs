@@ -0,0 +1,97 @@
+use crate::models::money::{Amount, Money, MoneyFormat};
+use crate::models::pagination::{Paginated, SortField, datetime_cursor};
+
+use serde::Serialize;
+use sqlx::types::chrono::{DateTime, Utc};
+use sqlx::FromRow;
+
+/// Budget that reached one of its thresholds, with the amounts it had then.
+#[derive(Debug, Clone, Serialize)]
+pub struct AlertModel {
+    pub id: i32,
+    pub budgetid: i32,
+    pub categoryid: i32,
+    pub periode: String,
+    /// Percent of `allocated` that was reached.
+    pub threshold: i32,
+    pub allocated: Amount,
+    pub spent: Amount,
+    /// Runs that failed to send the alert.
+    pub attempts: i32,
+    /// When a run last failed to send the alert.
+    #[serde(rename = "attemptedAt")]
+    pub attempted_at: Option<DateTime<Utc>>,
+    /// When every notifier delivered the alert, none while it is pending.
+    #[serde(rename = "notifiedAt")]
+    pub notified_at: Option<DateTime<Utc>>,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default, Clone, FromRow)]
+pub struct ExistAlert {
+    pub id: i32,
+    pub budgetid: i32,
+    pub categoryid: i32,
+    pub periode: String,
+    pub threshold: i32,
+    pub allocated: Money,
+    pub spent: Money,
+    pub attempts: i32,
+    pub attempted_at: Option<DateTime<Utc>>,
+    pub notified_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct AlertFilter {
+    pub budgetid: Option<i32>,
+    pub categoryid: Option<i32>,
+    /// Delivered alerts only when true, pending ones when false.
+    pub notified: Option<bool>,
+}
+
+/// Outcome of a run of the alerts job.
+#[derive(Debug, Default, Serialize)]
+pub struct AlertReport {
+    /// Alerts every notifier delivered.
+    pub delivered: u64,
+    /// Alerts left for a next run after a notifier failed.
+    pub failed: u64,
+}
+
+/// Alert amounts are kept in the base currency like the budgets, `currency`
+/// is only used to render them in the requested format.
+pub fn build_model_from_exist(data: ExistAlert, currency: &str, format: MoneyFormat) -> AlertModel {
+    AlertModel {
+        id: data.id,
+        budgetid: data.budgetid,
+        categoryid: data.categoryid,
+        periode: data.periode,
+        threshold: data.threshold,
+        allocated: data.allocated.amount(currency, format),
+        spent: data.spent.amount(currency, format),
+        attempts: data.attempts,
+        attempted_at: data.attempted_at,
+        notified_at: data.notified_at,
+        created_at: data.created_at,
+    }
+}
+
+pub const ALERT_SORT_FIELDS: &[SortField] = &[
+    SortField { name: "createdAt", column: "created_at" },
+    SortField { name: "id", column: "id" },
+];
+
+impl Paginated for ExistAlert {
+    fn cursor_value(&self, field: &SortField) -> String {
+        match field.column {
+            "created_at" => datetime_cursor(&self.created_at),
+            _ => self.id.to_string(),
+        }
+    }
+
+    fn cursor_id(&self) -> i32 {
+        self.id
+    }
+}
@@ -0,0 +1,165 @@
+use crate::errors::AppError;
+use crate::models::pagination::{Paginated, SortField, datetime_cursor};
+
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::types::chrono::{DateTime, NaiveDateTime, Utc};
+use sqlx::FromRow;
+
+/// Entity an audit entry is about, named like its route.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditEntity {
+    Account,
+    CatType,
+    TrxCat,
+    TrxCatBudget,
+    Trx,
+}
+
+impl AuditEntity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditEntity::Account => "accounts",
+            AuditEntity::CatType => "cat_types",
+            AuditEntity::TrxCat => "trx_cats",
+            AuditEntity::TrxCatBudget => "trx_cats_budgets",
+            AuditEntity::Trx => "trxs",
+        }
+    }
+}
+
+impl std::str::FromStr for AuditEntity {
+    type Err = AppError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "accounts" => Ok(AuditEntity::Account),
+            "cat_types" => Ok(AuditEntity::CatType),
+            "trx_cats" => Ok(AuditEntity::TrxCat),
+            "trx_cats_budgets" => Ok(AuditEntity::TrxCatBudget),
+            "trxs" => Ok(AuditEntity::Trx),
+            _ => Err(AppError::BadRequest(format!(
+                "query parameter `entity` must be accounts, cat_types, trx_cats, trx_cats_budgets or trxs, got `{}`",
+                value,
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    Add,
+    Update,
+    Delete,
+    Restore,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Add => "add",
+            AuditAction::Update => "update",
+            AuditAction::Delete => "delete",
+            AuditAction::Restore => "restore",
+        }
+    }
+}
+
+/// State of an entity written to the audit log, the JSON the API returns for it.
+pub trait Snapshot {
+    fn snapshot(&self) -> Value;
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditModel {
+    pub id: i32,
+    pub entity: String,
+    pub entityid: i32,
+    pub action: String,
+    pub actor: Option<String>,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default, Clone, FromRow)]
+pub struct ExistAudit {
+    pub id: i32,
+    pub entity: String,
+    pub entityid: i32,
+    pub action: String,
+    pub actor: Option<String>,
+    pub before_json: Option<String>,
+    pub after_json: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Entry to write, `before` is empty on add and `after` once the row is gone.
+#[derive(Debug, Clone)]
+pub struct NewAudit {
+    pub entity: AuditEntity,
+    pub entityid: i32,
+    pub action: AuditAction,
+    pub actor: Option<String>,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct AuditFilter {
+    pub entity: Option<AuditEntity>,
+    pub entityid: Option<i32>,
+    pub from: Option<NaiveDateTime>,
+    pub to: Option<NaiveDateTime>,
+}
+
+pub fn build_model_from_exist(data: ExistAudit) -> AuditModel {
+    AuditModel {
+        id: data.id,
+        entity: data.entity,
+        entityid: data.entityid,
+        action: data.action,
+        actor: data.actor,
+        before: data.before_json.as_deref().map(parse_snapshot),
+        after: data.after_json.as_deref().map(parse_snapshot),
+        created_at: data.created_at,
+    }
+}
+
+// snapshots are written by this api, a row edited by hand is returned as text
+fn parse_snapshot(json: &str) -> Value {
+    serde_json::from_str(json).unwrap_or_else(|_| Value::String(json.to_string()))
+}
+
+pub fn validate_audit_filter(filter: &AuditFilter) -> Result<(), AppError> {
+    if let (Some(from), Some(to)) = (filter.from, filter.to) {
+        if from > to {
+            return Err(AppError::Validation(String::from("`from` must not be after `to`")));
+        }
+    }
+
+    if filter.entityid.is_some() && filter.entity.is_none() {
+        return Err(AppError::BadRequest(String::from("query parameter `entityid` requires `entity`")));
+    }
+
+    Ok(())
+}
+
+pub const AUDIT_SORT_FIELDS: &[SortField] = &[
+    SortField { name: "createdAt", column: "created_at" },
+    SortField { name: "id", column: "id" },
+];
+
+impl Paginated for ExistAudit {
+    fn cursor_value(&self, field: &SortField) -> String {
+        match field.column {
+            "created_at" => datetime_cursor(&self.created_at),
+            _ => self.id.to_string(),
+        }
+    }
+
+    fn cursor_id(&self) -> i32 {
+        self.id
+    }
+}
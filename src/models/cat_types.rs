@@ -1,61 +1,78 @@
+use crate::errors::AppError;
+use crate::models::audit::Snapshot;
+use crate::models::money::{Amount, Money};
+
 use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 use sqlx::types::chrono::{DateTime, Utc};
-use sqlx::types::BigDecimal;
 use sqlx::FromRow;
 
 #[derive(Debug, Default, Clone, FromRow, Deserialize, Serialize)]
 pub struct CatTypeModel {
     pub id: i32,
     pub r#type: String,
+    pub direction: String,
     pub description: Option<String>,
     pub icon: String,
     #[serde(rename = "createdAt")]
     pub created_at: DateTime<Utc>,
     #[serde(rename = "updatedAt")]
     pub updated_at: DateTime<Utc>,
+    #[serde(rename = "deletedAt")]
+    pub deleted_at: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug, Default, Clone, FromRow, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct CatTypeModelWithBudget {
     pub id: i32,
     pub r#type: String,
+    pub direction: String,
     pub description: Option<String>,
     pub icon: String,
     #[serde(rename = "createdAt")]
     pub created_at: DateTime<Utc>,
     #[serde(rename = "updatedAt")]
     pub updated_at: DateTime<Utc>,
-    pub allocated: i64,
-    pub spent: i64,
-    pub available: i64,
+    #[serde(rename = "deletedAt")]
+    pub deleted_at: Option<DateTime<Utc>>,
+    /// Currency of the budget totals.
+    pub currency: String,
+    pub allocated: Amount,
+    pub spent: Amount,
+    pub available: Amount,
 }
 
 #[derive(Debug, Default, Clone, FromRow)]
 pub struct ExistCatType {
     pub id: i32,
     pub r#type: String,
+    pub direction: String,
     pub description: Option<String>,
     pub icon: String,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub deleted_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Default, Clone, FromRow)]
 pub struct ExistCatTypeWithBudget {
     pub id: i32,
     pub r#type: String,
+    pub direction: String,
     pub description: Option<String>,
     pub icon: String,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
-    pub allocated: Option<BigDecimal>,
-    pub spent: Option<BigDecimal>,
-    pub available: Option<BigDecimal>,
+    pub deleted_at: Option<DateTime<Utc>>,
+    pub allocated: Option<Money>,
+    pub spent: Option<Money>,
+    pub available: Option<Money>,
 }
 
 #[derive(Debug, Default, Clone, FromRow, Deserialize, Serialize)]
 pub struct AddCatType {
     pub r#type: String,
+    pub direction: Option<String>,
     pub description: Option<String>,
     pub icon: String,
 }
@@ -63,6 +80,158 @@ pub struct AddCatType {
 #[derive(Debug, Default, Clone, FromRow, Deserialize, Serialize)]
 pub struct UpdateCatType {
     pub r#type: Option<String>,
+    pub direction: Option<String>,
     pub description: Option<String>,
     pub icon: Option<String>,
-}
\ No newline at end of file
+}
+
+/// Which way the money of a category goes, deciding what its budgets count.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Direction {
+    Income,
+    #[default]
+    Expense,
+    Transfer,
+    Neutral,
+}
+
+impl Direction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Direction::Income => "income",
+            Direction::Expense => "expense",
+            Direction::Transfer => "transfer",
+            Direction::Neutral => "neutral",
+        }
+    }
+
+    /// Transfer and neutral categories are left out of budgets.
+    pub fn budgeted(&self) -> bool {
+        matches!(self, Direction::Income | Direction::Expense)
+    }
+
+    /// Budget `spent` moved by a trx line of `amount`, `credit - debit`: what
+    /// an expense spends, less its refunds, or what an income receives.
+    pub fn spent(&self, amount: i64) -> i64 {
+        match self {
+            Direction::Income => amount,
+            Direction::Expense => amount.saturating_neg(),
+            Direction::Transfer | Direction::Neutral => 0,
+        }
+    }
+}
+
+impl FromStr for Direction {
+    type Err = AppError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "income" => Ok(Direction::Income),
+            "expense" => Ok(Direction::Expense),
+            "transfer" => Ok(Direction::Transfer),
+            "neutral" => Ok(Direction::Neutral),
+            _ => Err(AppError::Validation(format!("`direction` must be income, expense, transfer or neutral, got `{}`", value))),
+        }
+    }
+}
+
+pub fn validate_add_cat_type(cat_type: &AddCatType) -> Result<(), AppError> {
+    if cat_type.r#type.trim().is_empty() {
+        return Err(AppError::Validation(String::from("category type is required")));
+    }
+    if let Some(direction) = &cat_type.direction {
+        direction.parse::<Direction>()?;
+    }
+
+    Ok(())
+}
+
+pub fn validate_update_cat_type(cat_type: &UpdateCatType) -> Result<(), AppError> {
+    if let Some(direction) = &cat_type.direction {
+        direction.parse::<Direction>()?;
+    }
+
+    Ok(())
+}
+
+impl Snapshot for ExistCatType {
+    fn snapshot(&self) -> serde_json::Value {
+        let cat_type = CatTypeModel {
+            id: self.id,
+            r#type: self.r#type.clone(),
+            direction: self.direction.clone(),
+            description: self.description.clone(),
+            icon: self.icon.clone(),
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+            deleted_at: self.deleted_at,
+        };
+
+        serde_json::to_value(cat_type).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spent_follows_the_direction() {
+        // (direction, trx line as `credit - debit`, expected move of `spent`)
+        let cases = [
+            // a credit is what an income receives, or an expense refund
+            (Direction::Income, 1500, 1500),
+            (Direction::Expense, 1500, -1500),
+            (Direction::Transfer, 1500, 0),
+            (Direction::Neutral, 1500, 0),
+            // a debit is what an expense spends, or an income paid back
+            (Direction::Income, -2500, -2500),
+            (Direction::Expense, -2500, 2500),
+            (Direction::Transfer, -2500, 0),
+            (Direction::Neutral, -2500, 0),
+            // refunding the debit above gives back exactly what it moved
+            (Direction::Income, 2500, 2500),
+            (Direction::Expense, 2500, -2500),
+            (Direction::Transfer, 2500, 0),
+            (Direction::Neutral, 2500, 0),
+            (Direction::Income, 0, 0),
+            (Direction::Expense, 0, 0),
+        ];
+        for (direction, amount, expected) in cases {
+            assert_eq!(direction.spent(amount), expected, "{} of {}", direction.as_str(), amount);
+        }
+    }
+
+    #[test]
+    fn refund_cancels_the_charge() {
+        for direction in [Direction::Income, Direction::Expense, Direction::Transfer, Direction::Neutral] {
+            for amount in [1, 2500, -2500, i64::MAX] {
+                assert_eq!(direction.spent(amount) + direction.spent(-amount), 0, "{} of {}", direction.as_str(), amount);
+            }
+        }
+    }
+
+    #[test]
+    fn spent_saturates_at_the_bounds() {
+        assert_eq!(Direction::Expense.spent(i64::MIN), i64::MAX);
+        assert_eq!(Direction::Income.spent(i64::MIN), i64::MIN);
+    }
+
+    #[test]
+    fn only_income_and_expense_are_budgeted() {
+        assert!(Direction::Income.budgeted());
+        assert!(Direction::Expense.budgeted());
+        assert!(!Direction::Transfer.budgeted());
+        assert!(!Direction::Neutral.budgeted());
+        assert_eq!(Direction::default(), Direction::Expense);
+    }
+
+    #[test]
+    fn parse_round_trips() {
+        for direction in [Direction::Income, Direction::Expense, Direction::Transfer, Direction::Neutral] {
+            assert_eq!(direction.as_str().parse::<Direction>().unwrap(), direction);
+        }
+        assert!(matches!("Expense".parse::<Direction>(), Err(AppError::Validation(_))));
+        assert!(matches!("".parse::<Direction>(), Err(AppError::Validation(_))));
+    }
+}
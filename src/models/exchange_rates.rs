@@ -0,0 +1,185 @@
+use crate::errors::AppError;
+use crate::models::money::{Money, minor_digits};
+use crate::models::pagination::{Paginated, SortField};
+
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use serde_json::Number;
+use sqlx::types::chrono::{DateTime, NaiveDate, Utc};
+use sqlx::types::BigDecimal;
+use sqlx::FromRow;
+
+/// Currency of accounts created without one and of the base currency by default.
+pub const DEFAULT_CURRENCY: &str = "IDR";
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ExchangeRateModel {
+    pub id: i32,
+    pub currency: String,
+    pub base: String,
+    pub rate: Number,
+    pub date: NaiveDate,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default, Clone, FromRow)]
+pub struct ExistExchangeRate {
+    pub id: i32,
+    pub currency: String,
+    pub base: String,
+    pub rate: BigDecimal,
+    pub date: NaiveDate,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// One `currency` is worth `rate` of `base` from `date` on.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct NewExchangeRate {
+    pub currency: String,
+    pub base: String,
+    pub rate: Number,
+    pub date: String,
+}
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct UpdateExchangeRate {
+    pub rate: Option<Number>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ExchangeRateFilter {
+    pub currency: Option<String>,
+    pub base: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportReport {
+    pub imported: usize,
+}
+
+pub fn build_model_from_exist(data: ExistExchangeRate) -> ExchangeRateModel {
+    ExchangeRateModel {
+        id: data.id,
+        currency: data.currency,
+        base: data.base,
+        rate: rate_to_number(&data.rate),
+        date: data.date,
+        created_at: data.created_at,
+        updated_at: data.updated_at,
+    }
+}
+
+/// ISO 4217 style code, three letters; lower case is accepted and upper cased.
+pub fn parse_currency(key: &str, code: &str) -> Result<String, AppError> {
+    let code = code.trim().to_ascii_uppercase();
+    if code.len() != 3 || !code.chars().all(|c| c.is_ascii_uppercase()) {
+        return Err(AppError::Validation(format!("`{}` must be a three letter currency code, got `{}`", key, code)));
+    }
+
+    Ok(code)
+}
+
+/// Rate of a request, a positive decimal number.
+pub fn parse_rate(rate: &Number) -> Result<BigDecimal, AppError> {
+    let value = BigDecimal::from_str(&rate.to_string())
+        .map_err(|_| AppError::Validation(format!("`rate` must be a decimal number, got `{}`", rate)))?;
+
+    if value <= BigDecimal::from(0) {
+        return Err(AppError::Validation(String::from("`rate` must be greater than 0")));
+    }
+
+    Ok(value)
+}
+
+/// Rate as an exact JSON number, without the trailing zeros of the column.
+pub fn rate_to_number(rate: &BigDecimal) -> Number {
+    Number::from_str(&rate.normalized().to_string()).unwrap_or_else(|_| Number::from(0))
+}
+
+/// `amount` minor units of `from` in minor units of `to`, rounded half up.
+///
+/// Rates are quoted between major units, so the result is shifted by the
+/// difference of minor digits between both currencies.
+pub fn convert_amount(amount: i64, rate: &BigDecimal, from: &str, to: &str) -> Result<i64, AppError> {
+    let (digits, scale) = (BigDecimal::from(amount) * rate).as_bigint_and_exponent();
+    let shift = minor_digits(to) as i64 - minor_digits(from) as i64;
+    let converted = BigDecimal::new(digits, scale - shift).round(0).with_scale(0);
+
+    Money::from(converted).to_minor()
+}
+
+pub fn parse_rate_date(date: &str) -> Result<NaiveDate, AppError> {
+    NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d")
+        .map_err(|_| AppError::Validation(format!("`date` must use format yyyy-MM-dd, got `{}`", date)))
+}
+
+pub fn validate_new_rate(rate: &NewExchangeRate) -> Result<(), AppError> {
+    let currency = parse_currency("currency", &rate.currency)?;
+    let base = parse_currency("base", &rate.base)?;
+    if currency == base {
+        return Err(AppError::Validation(String::from("`currency` and `base` must differ")));
+    }
+
+    parse_rate(&rate.rate)?;
+    parse_rate_date(&rate.date)?;
+
+    Ok(())
+}
+
+/// Reads `date,currency,base,rate` lines, the header line being optional.
+pub fn parse_rates_csv(body: &str) -> Result<Vec<NewExchangeRate>, AppError> {
+    let mut rates: Vec<NewExchangeRate> = Vec::new();
+
+    for (i, line) in body.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || (i == 0 && line.to_ascii_lowercase().starts_with("date")) {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let (date, currency, base, rate) = match fields.as_slice() {
+            [date, currency, base, rate] => (*date, *currency, *base, *rate),
+            _ => return Err(AppError::Validation(format!("line {}: expected date,currency,base,rate", i + 1))),
+        };
+
+        let rate = Number::from_str(rate)
+            .map_err(|_| AppError::Validation(format!("line {}: `rate` must be a decimal number, got `{}`", i + 1, rate)))?;
+        let rate = NewExchangeRate {
+            currency: currency.to_string(),
+            base: base.to_string(),
+            rate,
+            date: date.to_string(),
+        };
+
+        validate_new_rate(&rate).map_err(|err| AppError::Validation(format!("line {}: {}", i + 1, err)))?;
+        rates.push(rate);
+    }
+
+    if rates.is_empty() {
+        return Err(AppError::Validation(String::from("no exchange rates to import")));
+    }
+
+    Ok(rates)
+}
+
+pub const EXCHANGE_RATE_SORT_FIELDS: &[SortField] = &[
+    SortField { name: "date", column: "date" },
+    SortField { name: "id", column: "id" },
+];
+
+impl Paginated for ExistExchangeRate {
+    fn cursor_value(&self, field: &SortField) -> String {
+        match field.column {
+            "date" => self.date.format("%Y-%m-%d").to_string(),
+            _ => self.id.to_string(),
+        }
+    }
+
+    fn cursor_id(&self) -> i32 {
+        self.id
+    }
+}
@@ -1,15 +1,15 @@
 
-use sqlx::types::BigDecimal;
-
 pub mod accounts;
+pub mod alerts;
+pub mod audit;
 pub mod cat_types;
+pub mod exchange_rates;
+pub mod money;
+pub mod pagination;
+pub mod purge;
+pub mod recurring;
+pub mod reconciliation;
+pub mod transfers;
 pub mod trx_cats;
 pub mod trx_cat_budgets;
 pub mod trxs;
-
-pub fn bigdecimal_to_int(value: BigDecimal) -> i64 {
-    let (big_int, _) = value.into_bigint_and_exponent();
-    let int_value = big_int.to_string().parse::<i64>().unwrap();
-
-    int_value
-}
\ No newline at end of file
@@ -0,0 +1,228 @@
+use crate::errors::AppError;
+
+use std::ops::{Add, Neg, Sub};
+use std::str::FromStr;
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::Number;
+use sqlx::types::BigDecimal;
+
+/// Exact amount in minor units of its currency, as stored in the `DECIMAL`
+/// columns.
+///
+/// It keeps the value and scale the column returns. Amounts are computed as
+/// whole minor units in an `i64`, [`Money::to_minor`] refuses the values that
+/// do not fit instead of truncating them.
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, sqlx::Type)]
+#[sqlx(transparent)]
+pub struct Money(BigDecimal);
+
+/// How a response writes its amounts, picked with `?amounts=`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MoneyFormat {
+    /// JSON number of minor units, `1250` for USD 12.50.
+    #[default]
+    Minor,
+    /// String in major units, `"12.50"` for USD 12.50.
+    Decimal,
+}
+
+/// Amount of an API model, written in the [`MoneyFormat`] of the request.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Amount {
+    Minor(Number),
+    Decimal(String),
+}
+
+impl Money {
+    pub fn from_minor(minor: i64) -> Self {
+        Money(BigDecimal::from(minor))
+    }
+
+    /// Value as whole minor units.
+    pub fn to_minor(&self) -> Result<i64, AppError> {
+        let whole = self.0.with_scale(0);
+        if whole != self.0 {
+            return Err(AppError::Validation(format!("amount {} is not a whole number of minor units", self.0)));
+        }
+
+        whole.to_string().parse::<i64>()
+            .map_err(|_| AppError::Validation(format!("amount {} is out of range", self.0)))
+    }
+
+    pub fn abs(&self) -> Money {
+        Money(self.0.abs())
+    }
+
+    /// Amount of `currency` as the API writes it in `format`.
+    pub fn amount(&self, currency: &str, format: MoneyFormat) -> Amount {
+        match format {
+            MoneyFormat::Minor => Amount::Minor(self.to_number()),
+            MoneyFormat::Decimal => {
+                let (digits, scale) = self.0.as_bigint_and_exponent();
+                let major = BigDecimal::new(digits, scale + minor_digits(currency) as i64);
+
+                Amount::Decimal(major.to_string())
+            },
+        }
+    }
+
+    fn to_number(&self) -> Number {
+        Number::from_str(&self.0.to_string()).unwrap_or_else(|_| Number::from(0))
+    }
+}
+
+impl From<BigDecimal> for Money {
+    fn from(value: BigDecimal) -> Self {
+        Money(value)
+    }
+}
+
+impl Add for &Money {
+    type Output = Money;
+
+    fn add(self, other: &Money) -> Money {
+        Money(&self.0 + &other.0)
+    }
+}
+
+impl Sub for &Money {
+    type Output = Money;
+
+    fn sub(self, other: &Money) -> Money {
+        Money(&self.0 - &other.0)
+    }
+}
+
+impl Neg for &Money {
+    type Output = Money;
+
+    fn neg(self) -> Money {
+        Money(-self.0.clone())
+    }
+}
+
+// reports without a request format, such as reconciliation, write minor units
+impl Serialize for Money {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_number().serialize(serializer)
+    }
+}
+
+impl FromStr for MoneyFormat {
+    type Err = AppError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "minor" => Ok(MoneyFormat::Minor),
+            "decimal" => Ok(MoneyFormat::Decimal),
+            _ => Err(AppError::BadRequest(format!("query parameter `amounts` must be minor or decimal, got `{}`", value))),
+        }
+    }
+}
+
+/// Currencies without a minor unit, IDR included.
+pub const ZERO_DIGIT_CURRENCIES: [&str; 18] = [
+    "BIF", "CLP", "DJF", "GNF", "IDR", "ISK", "JPY", "KMF", "KRW",
+    "PYG", "RWF", "UGX", "UYI", "VND", "VUV", "XAF", "XOF", "XPF",
+];
+
+/// Currencies whose minor unit is a thousandth.
+pub const THREE_DIGIT_CURRENCIES: [&str; 7] = ["BHD", "IQD", "JOD", "KWD", "LYD", "OMR", "TND"];
+
+/// Digits of the minor unit of `currency` as in ISO 4217, except IDR which
+/// this API has always kept in whole rupiah.
+pub fn minor_digits(currency: &str) -> u32 {
+    if ZERO_DIGIT_CURRENCIES.contains(&currency) {
+        return 0;
+    }
+    if THREE_DIGIT_CURRENCIES.contains(&currency) {
+        return 3;
+    }
+
+    2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn money(value: &str) -> Money {
+        Money::from(BigDecimal::from_str(value).unwrap())
+    }
+
+    fn decimal(value: &str, currency: &str) -> String {
+        match money(value).amount(currency, MoneyFormat::Decimal) {
+            Amount::Decimal(text) => text,
+            other => panic!("expected a decimal amount, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn to_minor_reads_whole_units() {
+        assert_eq!(money("1250").to_minor().unwrap(), 1250);
+        assert_eq!(money("-1250").to_minor().unwrap(), -1250);
+        assert_eq!(money("0").to_minor().unwrap(), 0);
+    }
+
+    #[test]
+    fn to_minor_accepts_a_scale_without_fraction() {
+        assert_eq!(money("1250.00").to_minor().unwrap(), 1250);
+        assert_eq!(money("-7.000").to_minor().unwrap(), -7);
+    }
+
+    #[test]
+    fn to_minor_refuses_fractions_instead_of_rounding() {
+        for value in ["12.5", "-12.5", "0.01", "99.99"] {
+            assert!(matches!(money(value).to_minor(), Err(AppError::Validation(_))), "{} was accepted", value);
+        }
+    }
+
+    #[test]
+    fn to_minor_refuses_values_out_of_i64() {
+        assert_eq!(money("9223372036854775807").to_minor().unwrap(), i64::MAX);
+        assert_eq!(money("-9223372036854775808").to_minor().unwrap(), i64::MIN);
+        assert!(matches!(money("9223372036854775808").to_minor(), Err(AppError::Validation(_))));
+        assert!(matches!(money("-9223372036854775809").to_minor(), Err(AppError::Validation(_))));
+    }
+
+    #[test]
+    fn amount_in_minor_units_is_a_number() {
+        assert_eq!(money("1250").amount("USD", MoneyFormat::Minor), Amount::Minor(Number::from(1250)));
+        assert_eq!(money("-40").amount("USD", MoneyFormat::Minor), Amount::Minor(Number::from(-40)));
+        assert_eq!(serde_json::to_string(&money("1250").amount("IDR", MoneyFormat::Minor)).unwrap(), "1250");
+    }
+
+    #[test]
+    fn amount_in_decimal_follows_the_minor_digits_of_the_currency() {
+        assert_eq!(decimal("1250", "USD"), "12.50");
+        assert_eq!(decimal("5", "EUR"), "0.05");
+        assert_eq!(decimal("1250", "JPY"), "1250");
+        assert_eq!(decimal("1250", "IDR"), "1250");
+        assert_eq!(decimal("1250", "KWD"), "1.250");
+        assert_eq!(decimal("1", "BHD"), "0.001");
+    }
+
+    #[test]
+    fn amount_in_decimal_keeps_the_sign() {
+        assert_eq!(decimal("-1250", "USD"), "-12.50");
+        assert_eq!(decimal("-5", "USD"), "-0.05");
+        assert_eq!(decimal("-1250", "KWD"), "-1.250");
+        assert_eq!(decimal("-1250", "JPY"), "-1250");
+    }
+
+    #[test]
+    fn minor_digits_defaults_to_two() {
+        assert_eq!(minor_digits("USD"), 2);
+        assert_eq!(minor_digits("XYZ"), 2);
+        assert_eq!(minor_digits("IDR"), 0);
+        assert_eq!(minor_digits("OMR"), 3);
+    }
+
+    #[test]
+    fn money_format_parses_the_query_values() {
+        assert_eq!("minor".parse::<MoneyFormat>().unwrap(), MoneyFormat::Minor);
+        assert_eq!("decimal".parse::<MoneyFormat>().unwrap(), MoneyFormat::Decimal);
+        assert!(matches!("major".parse::<MoneyFormat>(), Err(AppError::BadRequest(_))));
+    }
+}
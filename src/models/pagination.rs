@@ -0,0 +1,270 @@
+use crate::errors::AppError;
+
+use serde::Serialize;
+use sqlx::types::chrono::{DateTime, Utc};
+
+pub const DEFAULT_LIMIT: i64 = 50;
+pub const MAX_LIMIT: i64 = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+/// Sortable field of a resource, `name` as used in `?sort=` and `column` in sql.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortField {
+    pub name: &'static str,
+    pub column: &'static str,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sort {
+    pub field: SortField,
+    pub order: SortOrder,
+}
+
+/// Keyset position of the last row of a page: its sort value, with the id
+/// breaking ties between rows sharing that value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cursor {
+    pub field: String,
+    pub value: String,
+    pub id: i32,
+}
+
+#[derive(Debug, Clone)]
+pub struct PageParams {
+    pub limit: i64,
+    pub cursor: Option<Cursor>,
+    pub sort: Sort,
+}
+
+/// Envelope of every list response.
+#[derive(Debug, Serialize)]
+pub struct Page<T> {
+    pub data: Vec<T>,
+    #[serde(rename = "nextCursor")]
+    pub next_cursor: Option<String>,
+}
+
+/// Rows that can be listed page by page.
+pub trait Paginated {
+    fn cursor_value(&self, field: &SortField) -> String;
+    fn cursor_id(&self) -> i32;
+}
+
+impl Sort {
+    /// Parses `?sort=field` (ascending) or `?sort=-field` (descending) against
+    /// the fields a resource allows.
+    pub fn parse(value: &str, fields: &[SortField]) -> Result<Self, AppError> {
+        let (name, order) = match value.strip_prefix('-') {
+            Some(name) => (name, SortOrder::Desc),
+            None => (value, SortOrder::Asc),
+        };
+
+        let field = fields.iter().find(|field| field.name == name).ok_or_else(|| {
+            let names: Vec<&str> = fields.iter().map(|field| field.name).collect();
+            AppError::BadRequest(format!("cannot sort by `{}`, expected one of {}", name, names.join(", ")))
+        })?;
+
+        Ok(Self { field: *field, order })
+    }
+}
+
+impl Cursor {
+    /// Hex encoded `field|value|id`, opaque to clients.
+    pub fn encode(&self) -> String {
+        format!("{}|{}|{}", self.field, self.value, self.id)
+            .bytes()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    pub fn decode(cursor: &str) -> Result<Self, AppError> {
+        let invalid = || AppError::BadRequest(String::from("query parameter `cursor` is invalid"));
+
+        if !cursor.len().is_multiple_of(2) {
+            return Err(invalid());
+        }
+
+        let bytes = (0..cursor.len())
+            .step_by(2)
+            .map(|i| cursor.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(invalid)?;
+
+        let raw = String::from_utf8(bytes).map_err(|_| invalid())?;
+        let (field, rest) = raw.split_once('|').ok_or_else(invalid)?;
+        let (value, id) = rest.rsplit_once('|').ok_or_else(invalid)?;
+
+        Ok(Self {
+            field: field.to_string(),
+            value: value.to_string(),
+            id: id.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl PageParams {
+    pub fn new(limit: i64, cursor: Option<Cursor>, sort: Sort) -> Result<Self, AppError> {
+        if !(1..=MAX_LIMIT).contains(&limit) {
+            return Err(AppError::BadRequest(format!("query parameter `limit` must be between 1 and {}", MAX_LIMIT)));
+        }
+
+        if let Some(cursor) = &cursor {
+            if cursor.field != sort.field.name {
+                return Err(AppError::BadRequest(String::from("query parameter `cursor` belongs to another sort")));
+            }
+        }
+
+        Ok(Self { limit, cursor, sort })
+    }
+
+    /// Rows to fetch: one more than the page so we know whether another page follows.
+    pub fn fetch_limit(&self) -> i64 {
+        self.limit + 1
+    }
+
+    /// Drops the extra row fetched by [`PageParams::fetch_limit`] and builds
+    /// the cursor of the following page, if any.
+    pub fn to_page<T: Paginated>(&self, mut rows: Vec<T>) -> (Vec<T>, Option<String>) {
+        if rows.len() as i64 <= self.limit {
+            return (rows, None);
+        }
+
+        rows.truncate(self.limit as usize);
+        let next_cursor = rows.last().map(|last| Cursor {
+            field: self.sort.field.name.to_string(),
+            value: last.cursor_value(&self.sort.field),
+            id: last.cursor_id(),
+        }.encode());
+
+        (rows, next_cursor)
+    }
+}
+
+/// Cursor value of a timestamp column, in a format MySQL compares against `DATETIME`.
+pub fn datetime_cursor(value: &DateTime<Utc>) -> String {
+    value.format("%Y-%m-%d %H:%M:%S%.6f").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIELDS: &[SortField] = &[
+        SortField { name: "id", column: "id" },
+        SortField { name: "createdAt", column: "created_at" },
+    ];
+
+    fn cursor(value: &str) -> Cursor {
+        Cursor { field: String::from("createdAt"), value: value.to_string(), id: 42 }
+    }
+
+    fn hex(raw: &str) -> String {
+        raw.bytes().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    #[test]
+    fn sort_parses_ascending_and_descending() {
+        let sort = Sort::parse("createdAt", FIELDS).unwrap();
+        assert_eq!(sort.field, FIELDS[1]);
+        assert_eq!(sort.order, SortOrder::Asc);
+
+        let sort = Sort::parse("-id", FIELDS).unwrap();
+        assert_eq!(sort.field, FIELDS[0]);
+        assert_eq!(sort.order, SortOrder::Desc);
+    }
+
+    #[test]
+    fn sort_refuses_unknown_fields() {
+        for value in ["name", "-name", "", "-", "--id", "created_at", "ID"] {
+            assert!(matches!(Sort::parse(value, FIELDS), Err(AppError::BadRequest(_))), "`{}` was accepted", value);
+        }
+    }
+
+    #[test]
+    fn cursor_round_trips() {
+        for value in ["2023-11-27 08:30:00.000000", "", "a|b|c", "ünïcode"] {
+            let cursor = cursor(value);
+            assert_eq!(Cursor::decode(&cursor.encode()).unwrap(), cursor);
+        }
+    }
+
+    #[test]
+    fn cursor_encodes_as_lowercase_hex() {
+        let encoded = cursor("7").encode();
+
+        assert_eq!(encoded, hex("createdAt|7|42"));
+        assert!(encoded.chars().all(|c| c.is_ascii_hexdigit() && !c.is_ascii_uppercase()));
+    }
+
+    #[test]
+    fn cursor_keeps_separators_inside_the_value() {
+        let decoded = Cursor::decode(&hex("createdAt|a|b|7")).unwrap();
+
+        assert_eq!(decoded.field, "createdAt");
+        assert_eq!(decoded.value, "a|b");
+        assert_eq!(decoded.id, 7);
+    }
+
+    #[test]
+    fn cursor_refuses_tampered_input() {
+        let encoded = cursor("7").encode();
+        let tampered = [
+            String::new(),
+            encoded[1..].to_string(),
+            format!("{}zz", encoded),
+            encoded.replace('6', "g"),
+            hex("createdAt"),
+            hex("createdAt|7"),
+            hex("createdAt|7|x"),
+            hex("createdAt|7|99999999999"),
+            String::from("ff"),
+        ];
+
+        for cursor in tampered.iter() {
+            assert!(matches!(Cursor::decode(cursor), Err(AppError::BadRequest(_))), "`{}` was accepted", cursor);
+        }
+    }
+
+    #[test]
+    fn page_params_check_limit_and_cursor_field() {
+        let sort = Sort::parse("-createdAt", FIELDS).unwrap();
+
+        assert!(PageParams::new(1, None, sort).is_ok());
+        assert!(PageParams::new(MAX_LIMIT, Some(cursor("7")), sort).is_ok());
+        assert!(matches!(PageParams::new(0, None, sort), Err(AppError::BadRequest(_))));
+        assert!(matches!(PageParams::new(MAX_LIMIT + 1, None, sort), Err(AppError::BadRequest(_))));
+
+        let other = Cursor { field: String::from("id"), value: String::from("7"), id: 7 };
+        assert!(matches!(PageParams::new(10, Some(other), sort), Err(AppError::BadRequest(_))));
+    }
+
+    struct Row(i32);
+
+    impl Paginated for Row {
+        fn cursor_value(&self, _field: &SortField) -> String {
+            self.0.to_string()
+        }
+
+        fn cursor_id(&self) -> i32 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn to_page_points_the_cursor_at_the_last_row_kept() {
+        let page = PageParams::new(2, None, Sort::parse("id", FIELDS).unwrap()).unwrap();
+
+        let (rows, next) = page.to_page(vec![Row(1), Row(2)]);
+        assert_eq!(rows.len(), 2);
+        assert!(next.is_none());
+
+        let (rows, next) = page.to_page(vec![Row(1), Row(2), Row(3)]);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(Cursor::decode(&next.unwrap()).unwrap(), Cursor { field: String::from("id"), value: String::from("2"), id: 2 });
+    }
+}
@@ -0,0 +1,18 @@
+use serde::Serialize;
+
+/// Rows removed for good by a purge, per table.
+#[derive(Debug, Default, Serialize)]
+pub struct PurgeReport {
+    pub transactions: u64,
+    pub budgets: u64,
+    pub categories: u64,
+    #[serde(rename = "categoryTypes")]
+    pub category_types: u64,
+    pub accounts: u64,
+}
+
+impl PurgeReport {
+    pub fn total(&self) -> u64 {
+        self.transactions + self.budgets + self.categories + self.category_types + self.accounts
+    }
+}
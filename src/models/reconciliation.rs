@@ -0,0 +1,123 @@
+use crate::models::accounts::ExistAccount;
+use crate::models::money::Money;
+use crate::models::trx_cat_budgets::ExistTrxCatBudget;
+use crate::models::trxs::ExistTrx;
+
+use serde::Serialize;
+
+/// Result of auditing the stored balances against `tbltransactions`.
+#[derive(Debug, Default, Serialize)]
+pub struct ReconcileReport {
+    pub accounts: Vec<AccountDiscrepancy>,
+    pub budgets: Vec<BudgetDiscrepancy>,
+    /// Whether the discrepancies listed were written back.
+    pub repaired: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AccountDiscrepancy {
+    pub accountid: i32,
+    #[serde(rename = "storedBalance")]
+    pub stored_balance: Money,
+    #[serde(rename = "expectedBalance")]
+    pub expected_balance: Money,
+    #[serde(rename = "brokenChain")]
+    pub broken_chain: Vec<ChainBreak>,
+}
+
+/// Trx whose running balance does not follow from the one before it.
+#[derive(Debug, Serialize)]
+pub struct ChainBreak {
+    pub trxid: i32,
+    #[serde(rename = "balanceBefore")]
+    pub balance_before: Money,
+    #[serde(rename = "balanceAfter")]
+    pub balance_after: Money,
+    #[serde(rename = "expectedBefore")]
+    pub expected_before: Money,
+    #[serde(rename = "expectedAfter")]
+    pub expected_after: Money,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BudgetDiscrepancy {
+    pub budgetid: i32,
+    pub categoryid: i32,
+    pub periode: String,
+    #[serde(rename = "storedSpent")]
+    pub stored_spent: Money,
+    #[serde(rename = "expectedSpent")]
+    pub expected_spent: Money,
+    #[serde(rename = "storedAvailable")]
+    pub stored_available: Money,
+    #[serde(rename = "expectedAvailable")]
+    pub expected_available: Money,
+}
+
+impl ReconcileReport {
+    pub fn is_clean(&self) -> bool {
+        self.accounts.is_empty() && self.budgets.is_empty()
+    }
+}
+
+/// Walks the ledger of `account`, `trxs` being ordered by `datetime` then `id`.
+/// Amounts are compared as stored, so a fractional or oversized value shows
+/// up as a discrepancy instead of failing the audit.
+///
+/// The chain is anchored on the `balance_before` of the first trx; every later
+/// trx must start where the previous one ended and the account balance must
+/// equal the end of the chain. An account without trxs has nothing to check.
+pub fn check_ledger(account: &ExistAccount, trxs: &[ExistTrx]) -> Option<AccountDiscrepancy> {
+    let mut balance = match trxs.first() {
+        Some(first) => first.balance_before.clone(),
+        None => return None,
+    };
+
+    let mut broken_chain: Vec<ChainBreak> = Vec::new();
+    for trx in trxs.iter() {
+        let expected_before = balance.clone();
+        balance = &balance + &(&trx.credit - &trx.debit);
+
+        if trx.balance_before != expected_before || trx.balance_after != balance {
+            broken_chain.push(ChainBreak {
+                trxid: trx.id,
+                balance_before: trx.balance_before.clone(),
+                balance_after: trx.balance_after.clone(),
+                expected_before,
+                expected_after: balance.clone(),
+            });
+        }
+    }
+
+    if broken_chain.is_empty() && account.balance == balance {
+        return None;
+    }
+
+    Some(AccountDiscrepancy {
+        accountid: account.id,
+        stored_balance: account.balance.clone(),
+        expected_balance: balance,
+        broken_chain,
+    })
+}
+
+/// Compares a budget against what its trxs actually spent; `available` is
+/// expected to be what is left of `allocated` and the amount `carried` over.
+pub fn check_budget(budget: &ExistTrxCatBudget, actual_spent: i64) -> Option<BudgetDiscrepancy> {
+    let actual_spent = Money::from_minor(actual_spent);
+    let expected_available = &(&budget.allocated + &budget.carried) - &actual_spent;
+
+    if budget.spent == actual_spent && budget.available == expected_available {
+        return None;
+    }
+
+    Some(BudgetDiscrepancy {
+        budgetid: budget.id,
+        categoryid: budget.categoryid,
+        periode: budget.periode.clone(),
+        stored_spent: budget.spent.clone(),
+        expected_spent: actual_spent,
+        stored_available: budget.available.clone(),
+        expected_available,
+    })
+}
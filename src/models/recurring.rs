@@ -0,0 +1,620 @@
+use crate::errors::AppError;
+use crate::models::money::{Amount, Money, MoneyFormat};
+use crate::models::pagination::{Paginated, SortField, datetime_cursor};
+use crate::models::trxs::{NewTrx, TrxModel, DATETIME_FORMAT, validate_datetime};
+
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use chrono::{Datelike, Duration};
+use sqlx::types::chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use sqlx::FromRow;
+
+/// Most occurrences `GET /recurring/{id}/upcoming` lists at once.
+pub const MAX_UPCOMING: usize = 100;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RecurringModel {
+    pub id: i32,
+    pub credit: Amount,
+    pub debit: Amount,
+    /// Currency of the account, the amounts are in it.
+    pub currency: String,
+    pub description: Option<String>,
+    pub accountid: i32,
+    pub categoryid: i32,
+    pub frequency: String,
+    pub every: i32,
+    #[serde(rename = "dayOfMonth")]
+    pub day_of_month: Option<i32>,
+    #[serde(rename = "startDate")]
+    pub start_date: DateTime<Utc>,
+    #[serde(rename = "endDate")]
+    pub end_date: Option<DateTime<Utc>>,
+    pub occurrences: Option<i32>,
+    /// Occurrences already posted or skipped.
+    pub posted: i32,
+    /// Next occurrence to post, none once the schedule is over.
+    #[serde(rename = "nextDate")]
+    pub next_date: Option<DateTime<Utc>>,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Occurrence of a template still to come.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UpcomingModel {
+    /// Position of the occurrence in the schedule, starting at 1.
+    pub occurrence: i32,
+    pub datetime: DateTime<Utc>,
+}
+
+/// Occurrence posted early on `POST /recurring/{id}/post`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PostedModel {
+    pub recurring: RecurringModel,
+    pub trx: TrxModel,
+}
+
+/// Outcome of a run of the recurring job.
+#[derive(Debug, Default, Serialize)]
+pub struct RecurringReport {
+    /// Templates that posted their due occurrences.
+    pub templates: u64,
+    /// Trxs posted, a template catching up posts several.
+    pub posted: u64,
+    /// Templates left for the next run after an error.
+    pub failed: u64,
+}
+
+#[derive(Debug, Default, Clone, FromRow)]
+pub struct ExistRecurring {
+    pub id: i32,
+    pub credit: Money,
+    pub debit: Money,
+    pub currency: String,
+    pub description: Option<String>,
+    pub accountid: i32,
+    pub categoryid: i32,
+    pub frequency: String,
+    pub every: i32,
+    pub dayofmonth: Option<i32>,
+    pub startdate: DateTime<Utc>,
+    pub enddate: Option<DateTime<Utc>>,
+    pub occurrences: Option<i32>,
+    pub posted: i32,
+    pub nextdate: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct NewRecurring {
+    pub credit: i64,
+    pub debit: i64,
+    pub description: Option<String>,
+    pub accountid: i32,
+    pub categoryid: i32,
+    /// `daily`, `weekly` or `monthly`.
+    pub frequency: String,
+    /// Days, weeks or months between two occurrences, 1 by default.
+    pub every: Option<i32>,
+    /// Day a monthly template falls on, the day of `startDate` by default.
+    #[serde(rename = "dayOfMonth")]
+    pub day_of_month: Option<i32>,
+    #[serde(rename = "startDate")]
+    pub start_date: String,
+    #[serde(rename = "endDate")]
+    pub end_date: Option<String>,
+    /// Number of occurrences after which the template stops.
+    pub occurrences: Option<i32>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct UpdateRecurring {
+    pub credit: Option<i64>,
+    pub debit: Option<i64>,
+    pub description: Option<String>,
+    pub accountid: Option<i32>,
+    pub categoryid: Option<i32>,
+    pub frequency: Option<String>,
+    pub every: Option<i32>,
+    #[serde(rename = "dayOfMonth")]
+    pub day_of_month: Option<i32>,
+    #[serde(rename = "startDate")]
+    pub start_date: Option<String>,
+    #[serde(rename = "endDate")]
+    pub end_date: Option<String>,
+    pub occurrences: Option<i32>,
+}
+
+impl UpdateRecurring {
+    /// Whether the update moves the dates of the occurrences.
+    pub fn reschedules(&self) -> bool {
+        self.frequency.is_some()
+            || self.every.is_some()
+            || self.day_of_month.is_some()
+            || self.start_date.is_some()
+            || self.end_date.is_some()
+            || self.occurrences.is_some()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+impl Frequency {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Frequency::Daily => "daily",
+            Frequency::Weekly => "weekly",
+            Frequency::Monthly => "monthly",
+        }
+    }
+}
+
+impl FromStr for Frequency {
+    type Err = AppError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "daily" => Ok(Frequency::Daily),
+            "weekly" => Ok(Frequency::Weekly),
+            "monthly" => Ok(Frequency::Monthly),
+            _ => Err(AppError::Validation(format!("`frequency` must be daily, weekly or monthly, got `{}`", value))),
+        }
+    }
+}
+
+/// When the occurrences of a template fall.
+///
+/// Daily and weekly occurrences are `every` days or weeks apart from `start`.
+/// Monthly ones fall on `day_of_month`, or the last day of a shorter month,
+/// from the month of `start`, or the next one when that day is already past.
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    pub frequency: Frequency,
+    pub every: i32,
+    pub day_of_month: Option<i32>,
+    pub start: NaiveDateTime,
+    pub end: Option<NaiveDateTime>,
+    pub occurrences: Option<i32>,
+}
+
+impl Schedule {
+    pub fn from_exist(data: &ExistRecurring) -> Result<Self, AppError> {
+        Ok(Schedule {
+            frequency: data.frequency.parse()?,
+            every: data.every,
+            day_of_month: data.dayofmonth,
+            start: data.startdate.naive_utc(),
+            end: data.enddate.map(|end| end.naive_utc()),
+            occurrences: data.occurrences,
+        })
+    }
+
+    /// Schedule of `data` with the changes of `update` applied.
+    pub fn from_update(data: &ExistRecurring, update: &UpdateRecurring) -> Result<Self, AppError> {
+        let mut schedule = Schedule::from_exist(data)?;
+
+        if let Some(frequency) = &update.frequency {
+            schedule.frequency = frequency.parse()?;
+            // a day of month only belongs to a monthly schedule
+            if schedule.frequency != Frequency::Monthly {
+                schedule.day_of_month = None;
+            }
+        }
+        if let Some(every) = update.every {
+            schedule.every = every;
+        }
+        if update.day_of_month.is_some() {
+            schedule.day_of_month = update.day_of_month;
+        }
+        if let Some(start) = &update.start_date {
+            schedule.start = parse_datetime(start)?;
+        }
+        if let Some(end) = &update.end_date {
+            schedule.end = Some(parse_datetime(end)?);
+        }
+        if update.occurrences.is_some() {
+            schedule.occurrences = update.occurrences;
+        }
+
+        schedule.validate()?;
+
+        Ok(schedule)
+    }
+
+    pub fn from_new(data: &NewRecurring) -> Result<Self, AppError> {
+        let schedule = Schedule {
+            frequency: data.frequency.parse()?,
+            every: data.every.unwrap_or(1),
+            day_of_month: data.day_of_month,
+            start: parse_datetime(&data.start_date)?,
+            end: match &data.end_date {
+                Some(end) => Some(parse_datetime(end)?),
+                None => None,
+            },
+            occurrences: data.occurrences,
+        };
+
+        schedule.validate()?;
+
+        Ok(schedule)
+    }
+
+    fn validate(&self) -> Result<(), AppError> {
+        if self.every <= 0 {
+            return Err(AppError::Validation(String::from("`every` must be greater than 0")));
+        }
+
+        match (self.frequency, self.day_of_month) {
+            (Frequency::Monthly, Some(day)) if !(1..=31).contains(&day) => {
+                return Err(AppError::Validation(String::from("`dayOfMonth` must be between 1 and 31")));
+            },
+            (Frequency::Daily | Frequency::Weekly, Some(_)) => {
+                return Err(AppError::Validation(String::from("`dayOfMonth` only applies to a monthly frequency")));
+            },
+            _ => {},
+        }
+
+        if matches!(self.end, Some(end) if end < self.start) {
+            return Err(AppError::Validation(String::from("`endDate` must not be before `startDate`")));
+        }
+
+        if matches!(self.occurrences, Some(occurrences) if occurrences <= 0) {
+            return Err(AppError::Validation(String::from("`occurrences` must be greater than 0")));
+        }
+
+        Ok(())
+    }
+
+    /// First occurrence at or after `from`, ignoring the end of the schedule.
+    pub fn first_from(&self, from: NaiveDateTime) -> Option<NaiveDateTime> {
+        let mut date = match self.frequency {
+            Frequency::Monthly => {
+                let date = self.in_month(self.start.year(), self.start.month())?;
+                match date < self.start {
+                    true => self.in_month_after(self.start, 1)?,
+                    false => date,
+                }
+            },
+            Frequency::Daily | Frequency::Weekly => {
+                if from <= self.start {
+                    return Some(self.start);
+                }
+
+                // jump straight to the step at or after `from`
+                let step = self.step_seconds();
+                let steps = ((from - self.start).num_seconds() + step - 1) / step;
+                return self.start.checked_add_signed(Duration::seconds(steps.checked_mul(step)?));
+            },
+        };
+
+        while date < from {
+            date = self.next_after(date)?;
+        }
+
+        Some(date)
+    }
+
+    /// Occurrence following the one at `date`, ignoring the end of the schedule.
+    pub fn next_after(&self, date: NaiveDateTime) -> Option<NaiveDateTime> {
+        match self.frequency {
+            Frequency::Monthly => self.in_month_after(date, self.every),
+            Frequency::Daily | Frequency::Weekly => date.checked_add_signed(Duration::seconds(self.step_seconds())),
+        }
+    }
+
+    /// `date` when it is still part of the schedule once `posted` occurrences
+    /// went by, none past the end date or the number of occurrences.
+    pub fn within(&self, date: Option<NaiveDateTime>, posted: i32) -> Option<NaiveDateTime> {
+        let date = date?;
+
+        if matches!(self.occurrences, Some(occurrences) if posted >= occurrences) {
+            return None;
+        }
+
+        if matches!(self.end, Some(end) if date > end) {
+            return None;
+        }
+
+        Some(date)
+    }
+
+    /// Up to `limit` occurrences from `next`, the one after `posted` went by.
+    pub fn upcoming(&self, next: Option<NaiveDateTime>, posted: i32, limit: usize) -> Vec<(i32, NaiveDateTime)> {
+        let mut upcoming = Vec::new();
+        let mut next = self.within(next, posted);
+        let mut posted = posted;
+
+        while let Some(date) = next {
+            if upcoming.len() >= limit {
+                break;
+            }
+
+            posted += 1;
+            upcoming.push((posted, date));
+            next = self.within(self.next_after(date), posted);
+        }
+
+        upcoming
+    }
+
+    fn step_seconds(&self) -> i64 {
+        let days = match self.frequency {
+            Frequency::Weekly => 7,
+            _ => 1,
+        };
+
+        i64::from(self.every) * days * 24 * 60 * 60
+    }
+
+    /// Occurrence in the month `months` after the one of `date`.
+    fn in_month_after(&self, date: NaiveDateTime, months: i32) -> Option<NaiveDateTime> {
+        let index = date.year().checked_mul(12)?.checked_add(date.month0() as i32)?.checked_add(months)?;
+
+        self.in_month(index.div_euclid(12), index.rem_euclid(12) as u32 + 1)
+    }
+
+    /// Occurrence in `month` of `year`, on the last day of the month when it is
+    /// shorter than the day of the schedule.
+    fn in_month(&self, year: i32, month: u32) -> Option<NaiveDateTime> {
+        let day = self.day_of_month.unwrap_or(self.start.day() as i32) as u32;
+        let first = NaiveDate::from_ymd_opt(year, month, 1)?;
+        let last = first.checked_add_signed(Duration::days(31))?.with_day(1)?.pred_opt()?;
+
+        Some(first.with_day(day.min(last.day()))?.and_time(self.start.time()))
+    }
+}
+
+/// Trx an occurrence of `data` posts, dated `datetime`.
+pub fn occurrence_trx(data: &ExistRecurring, datetime: NaiveDateTime) -> Result<NewTrx, AppError> {
+    Ok(NewTrx {
+        credit: data.credit.to_minor()?,
+        debit: data.debit.to_minor()?,
+        description: data.description.clone(),
+        datetime: datetime.format(DATETIME_FORMAT).to_string(),
+        accountid: data.accountid,
+        categoryid: Some(data.categoryid),
+        splits: None,
+    })
+}
+
+pub fn build_model_from_exist(data: ExistRecurring, format: MoneyFormat) -> RecurringModel {
+    RecurringModel {
+        id: data.id,
+        credit: data.credit.amount(&data.currency, format),
+        debit: data.debit.amount(&data.currency, format),
+        currency: data.currency,
+        description: data.description,
+        accountid: data.accountid,
+        categoryid: data.categoryid,
+        frequency: data.frequency,
+        every: data.every,
+        day_of_month: data.dayofmonth,
+        start_date: data.startdate,
+        end_date: data.enddate,
+        occurrences: data.occurrences,
+        posted: data.posted,
+        next_date: data.nextdate,
+        created_at: data.created_at,
+        updated_at: data.updated_at,
+    }
+}
+
+pub fn build_upcoming_model((occurrence, datetime): (i32, NaiveDateTime)) -> UpcomingModel {
+    UpcomingModel {
+        occurrence,
+        datetime: DateTime::from_naive_utc_and_offset(datetime, Utc),
+    }
+}
+
+pub fn validate_new_recurring(data: &NewRecurring) -> Result<(), AppError> {
+    validate_amounts(data.credit, data.debit)
+}
+
+pub fn validate_update_recurring(data: &UpdateRecurring) -> Result<(), AppError> {
+    if data.credit.is_some() != data.debit.is_some() {
+        return Err(AppError::Validation(String::from("credit and debit must be updated together")));
+    }
+
+    match (data.credit, data.debit) {
+        (Some(credit), Some(debit)) => validate_amounts(credit, debit),
+        _ => Ok(()),
+    }
+}
+
+fn validate_amounts(credit: i64, debit: i64) -> Result<(), AppError> {
+    if credit < 0 || debit < 0 {
+        return Err(AppError::Validation(String::from("credit and debit must not be negative")));
+    }
+
+    if credit == 0 && debit == 0 {
+        return Err(AppError::Validation(String::from("a recurring transaction needs a credit or a debit")));
+    }
+
+    if credit > 0 && debit > 0 {
+        return Err(AppError::Validation(String::from("a recurring transaction is either a credit or a debit, the other one must be 0")));
+    }
+
+    Ok(())
+}
+
+fn parse_datetime(datetime: &str) -> Result<NaiveDateTime, AppError> {
+    validate_datetime(datetime)?;
+
+    NaiveDateTime::parse_from_str(datetime, DATETIME_FORMAT)
+        .map_err(|_| AppError::Validation(format!("datetime `{}` must use format yyyy-MM-dd HH:mm:ss", datetime)))
+}
+
+pub const RECURRING_SORT_FIELDS: &[SortField] = &[
+    SortField { name: "id", column: "id" },
+    SortField { name: "createdAt", column: "created_at" },
+];
+
+impl Paginated for ExistRecurring {
+    fn cursor_value(&self, field: &SortField) -> String {
+        match field.column {
+            "created_at" => datetime_cursor(&self.created_at),
+            _ => self.id.to_string(),
+        }
+    }
+
+    fn cursor_id(&self) -> i32 {
+        self.id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(year: i32, month: u32, day: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap().and_hms_opt(9, 30, 0).unwrap()
+    }
+
+    fn schedule(frequency: Frequency, every: i32, start: NaiveDateTime) -> Schedule {
+        Schedule { frequency, every, day_of_month: None, start, end: None, occurrences: None }
+    }
+
+    /// `count` occurrences from the first one at or after the start.
+    fn dates(schedule: &Schedule, count: usize) -> Vec<NaiveDateTime> {
+        let mut dates = vec![schedule.first_from(schedule.start).unwrap()];
+        while dates.len() < count {
+            dates.push(schedule.next_after(*dates.last().unwrap()).unwrap());
+        }
+        dates
+    }
+
+    #[test]
+    fn monthly_day_31_clamps_through_february_of_a_leap_year() {
+        let schedule = schedule(Frequency::Monthly, 1, at(2024, 1, 31));
+
+        assert_eq!(dates(&schedule, 5), vec![at(2024, 1, 31), at(2024, 2, 29), at(2024, 3, 31), at(2024, 4, 30), at(2024, 5, 31)]);
+    }
+
+    #[test]
+    fn monthly_day_31_clamps_through_february_of_a_common_year() {
+        let schedule = schedule(Frequency::Monthly, 1, at(2023, 1, 31));
+
+        assert_eq!(dates(&schedule, 3), vec![at(2023, 1, 31), at(2023, 2, 28), at(2023, 3, 31)]);
+    }
+
+    #[test]
+    fn monthly_day_29_only_clamps_in_common_years() {
+        let mut schedule = schedule(Frequency::Monthly, 12, at(2023, 2, 1));
+        schedule.day_of_month = Some(29);
+
+        assert_eq!(dates(&schedule, 3), vec![at(2023, 2, 28), at(2024, 2, 29), at(2025, 2, 28)]);
+    }
+
+    #[test]
+    fn monthly_every_2_crosses_the_year_and_keeps_the_day() {
+        let schedule = schedule(Frequency::Monthly, 2, at(2023, 12, 31));
+
+        assert_eq!(dates(&schedule, 4), vec![at(2023, 12, 31), at(2024, 2, 29), at(2024, 4, 30), at(2024, 6, 30)]);
+        assert_eq!(schedule.next_after(at(2024, 6, 30)), Some(at(2024, 8, 31)));
+    }
+
+    #[test]
+    fn monthly_day_of_month_starts_in_the_next_month_once_past() {
+        let mut schedule = schedule(Frequency::Monthly, 1, at(2023, 1, 15));
+        schedule.day_of_month = Some(30);
+        assert_eq!(schedule.first_from(schedule.start), Some(at(2023, 1, 30)));
+
+        schedule.start = at(2023, 1, 31);
+        schedule.day_of_month = Some(15);
+        assert_eq!(schedule.first_from(schedule.start), Some(at(2023, 2, 15)));
+    }
+
+    #[test]
+    fn monthly_catches_up_from_a_past_start() {
+        let schedule = schedule(Frequency::Monthly, 2, at(2023, 1, 31));
+
+        // Jan 31, Mar 31, May 31, then Jul 31 is the first not before June
+        assert_eq!(schedule.first_from(at(2023, 6, 1)), Some(at(2023, 7, 31)));
+        assert_eq!(schedule.first_from(at(2023, 5, 31)), Some(at(2023, 5, 31)));
+    }
+
+    #[test]
+    fn weekly_every_2_catches_up_from_a_past_start() {
+        let schedule = schedule(Frequency::Weekly, 2, at(2023, 1, 2));
+
+        // Jan 2, 16, 30, Feb 13, 27, then Mar 13
+        assert_eq!(schedule.first_from(at(2023, 3, 1)), Some(at(2023, 3, 13)));
+        assert_eq!(schedule.first_from(at(2023, 2, 27)), Some(at(2023, 2, 27)));
+        assert_eq!(schedule.first_from(at(2023, 2, 27).checked_add_signed(Duration::seconds(1)).unwrap()), Some(at(2023, 3, 13)));
+        assert_eq!(schedule.next_after(at(2023, 3, 13)), Some(at(2023, 3, 27)));
+    }
+
+    #[test]
+    fn daily_starts_at_the_start_when_it_is_ahead() {
+        let schedule = schedule(Frequency::Daily, 3, at(2023, 6, 1));
+
+        assert_eq!(schedule.first_from(at(2023, 1, 1)), Some(at(2023, 6, 1)));
+        assert_eq!(schedule.first_from(at(2023, 6, 2)), Some(at(2023, 6, 4)));
+    }
+
+    #[test]
+    fn within_stops_after_the_occurrences_and_past_the_end() {
+        let mut schedule = schedule(Frequency::Daily, 1, at(2023, 6, 1));
+        schedule.occurrences = Some(3);
+        schedule.end = Some(at(2023, 6, 10));
+
+        assert_eq!(schedule.within(None, 0), None);
+        assert_eq!(schedule.within(Some(at(2023, 6, 3)), 2), Some(at(2023, 6, 3)));
+        assert_eq!(schedule.within(Some(at(2023, 6, 4)), 3), None);
+        assert_eq!(schedule.within(Some(at(2023, 6, 10)), 0), Some(at(2023, 6, 10)));
+        assert_eq!(schedule.within(Some(at(2023, 6, 11)), 0), None);
+    }
+
+    #[test]
+    fn upcoming_ends_with_the_occurrences_mid_catch_up() {
+        let mut schedule = schedule(Frequency::Daily, 1, at(2023, 6, 1));
+        schedule.occurrences = Some(3);
+
+        // one went by, a long overdue schedule only has two left
+        let next = schedule.first_from(at(2023, 6, 2));
+        assert_eq!(schedule.upcoming(next, 1, MAX_UPCOMING), vec![(2, at(2023, 6, 2)), (3, at(2023, 6, 3))]);
+        assert!(schedule.upcoming(Some(at(2023, 6, 4)), 3, MAX_UPCOMING).is_empty());
+    }
+
+    #[test]
+    fn upcoming_ends_at_the_end_date_included() {
+        let mut schedule = schedule(Frequency::Weekly, 1, at(2023, 6, 1));
+        schedule.end = Some(at(2023, 6, 15));
+
+        assert_eq!(schedule.upcoming(Some(schedule.start), 0, MAX_UPCOMING), vec![(1, at(2023, 6, 1)), (2, at(2023, 6, 8)), (3, at(2023, 6, 15))]);
+    }
+
+    #[test]
+    fn upcoming_stops_at_the_limit() {
+        let schedule = schedule(Frequency::Monthly, 1, at(2023, 1, 31));
+
+        assert_eq!(schedule.upcoming(Some(schedule.start), 4, 2), vec![(5, at(2023, 1, 31)), (6, at(2023, 2, 28))]);
+    }
+
+    #[test]
+    fn validate_refuses_inconsistent_schedules() {
+        let valid = schedule(Frequency::Monthly, 1, at(2023, 1, 31));
+        assert!(valid.validate().is_ok());
+
+        let mut invalid = [valid.clone(), valid.clone(), valid.clone(), valid.clone(), valid.clone()];
+        invalid[0].every = 0;
+        invalid[1].day_of_month = Some(32);
+        invalid[2].frequency = Frequency::Weekly;
+        invalid[2].day_of_month = Some(1);
+        invalid[3].end = Some(at(2023, 1, 30));
+        invalid[4].occurrences = Some(0);
+
+        for schedule in invalid.iter() {
+            assert!(matches!(schedule.validate(), Err(AppError::Validation(_))), "{:?} was accepted", schedule);
+        }
+    }
+}
@@ -0,0 +1,177 @@
+
+use crate::errors::AppError;
+use crate::models::exchange_rates::{convert_amount, parse_rate, rate_to_number};
+use crate::models::money::{Amount, Money, MoneyFormat};
+use crate::models::pagination::{Paginated, SortField, datetime_cursor};
+use crate::models::trxs::{TrxModel, ExistTrx, build_trx_model, validate_datetime};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Number;
+use sqlx::types::chrono::{DateTime, Utc};
+use sqlx::FromRow;
+use sqlx::types::BigDecimal;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TransferModel {
+    pub id: i32,
+    /// Amount leaving the source account, in its currency.
+    pub amount: Amount,
+    pub currency: String,
+    /// Amount reaching the destination account, in its currency.
+    #[serde(rename = "toAmount")]
+    pub to_amount: Amount,
+    #[serde(rename = "toCurrency")]
+    pub to_currency: String,
+    /// Destination currency per unit of the source currency.
+    pub rate: Number,
+    pub description: Option<String>,
+    pub datetime: DateTime<Utc>,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: DateTime<Utc>,
+    pub fromaccountid: i32,
+    pub toaccountid: i32,
+    /// Debit leg on the source account.
+    pub debit: Option<TrxModel>,
+    /// Credit leg on the destination account.
+    pub credit: Option<TrxModel>,
+}
+
+#[derive(Debug, Default, Clone, FromRow)]
+pub struct ExistTransfer {
+    pub id: i32,
+    pub amount: Money,
+    pub currency: String,
+    pub toamount: Money,
+    pub tocurrency: String,
+    pub rate: BigDecimal,
+    pub description: Option<String>,
+    pub datetime: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub fromaccountid: i32,
+    pub toaccountid: i32,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ExistTransferWithTrxs {
+    pub transfer: ExistTransfer,
+    pub debit: Option<ExistTrx>,
+    pub credit: Option<ExistTrx>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct NewTransfer {
+    pub amount: i64,
+    /// Required between accounts of different currencies.
+    pub rate: Option<Number>,
+    pub description: Option<String>,
+    pub datetime: String,
+    pub fromaccountid: i32,
+    pub toaccountid: i32,
+}
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct UpdateTransfer {
+    pub amount: Option<i64>,
+    pub rate: Option<Number>,
+    pub description: Option<String>,
+    pub datetime: Option<String>,
+}
+
+pub fn build_model_from_exist(data: ExistTransferWithTrxs, format: MoneyFormat) -> TransferModel {
+    let transfer = data.transfer;
+
+    TransferModel {
+        id: transfer.id,
+        amount: transfer.amount.amount(&transfer.currency, format),
+        currency: transfer.currency,
+        to_amount: transfer.toamount.amount(&transfer.tocurrency, format),
+        to_currency: transfer.tocurrency,
+        rate: rate_to_number(&transfer.rate),
+        description: transfer.description,
+        datetime: transfer.datetime,
+        created_at: transfer.created_at,
+        updated_at: transfer.updated_at,
+        fromaccountid: transfer.fromaccountid,
+        toaccountid: transfer.toaccountid,
+        debit: data.debit.map(|trx| build_trx_model(trx, format)),
+        credit: data.credit.map(|trx| build_trx_model(trx, format)),
+    }
+}
+
+pub fn validate_new_transfer(transfer: &NewTransfer) -> Result<(), AppError> {
+    if transfer.fromaccountid == transfer.toaccountid {
+        return Err(AppError::Validation(String::from("a transfer needs two different accounts")));
+    }
+
+    validate_amount(transfer.amount)?;
+    validate_datetime(&transfer.datetime)
+}
+
+pub fn validate_update_transfer(transfer: &UpdateTransfer) -> Result<(), AppError> {
+    if let Some(amount) = transfer.amount {
+        validate_amount(amount)?;
+    }
+
+    match &transfer.datetime {
+        Some(datetime) => validate_datetime(datetime),
+        None => Ok(()),
+    }
+}
+
+/// Rate of a transfer from a `from` to a `to` currency account, which must be
+/// given exactly when the currencies differ, or be 1.
+pub fn transfer_rate(from: &str, to: &str, rate: &Option<Number>) -> Result<BigDecimal, AppError> {
+    let one = BigDecimal::from(1);
+
+    match rate {
+        Some(rate) => {
+            let rate = parse_rate(rate)?;
+            if from == to && rate != one {
+                return Err(AppError::Validation(format!("both accounts hold {}, `rate` must be 1", from)));
+            }
+
+            Ok(rate)
+        },
+        None if from == to => Ok(one),
+        None => Err(AppError::Validation(format!("`rate` is required to transfer from {} to {}", from, to))),
+    }
+}
+
+/// Amount credited to the destination account.
+pub fn transfer_to_amount(amount: i64, rate: &BigDecimal, from: &str, to: &str) -> Result<i64, AppError> {
+    let to_amount = convert_amount(amount, rate, from, to)?;
+    if to_amount <= 0 {
+        return Err(AppError::Validation(String::from("transfer amount is 0 after applying `rate`")));
+    }
+
+    Ok(to_amount)
+}
+
+fn validate_amount(amount: i64) -> Result<(), AppError> {
+    if amount <= 0 {
+        return Err(AppError::Validation(String::from("transfer amount must be greater than 0")));
+    }
+
+    Ok(())
+}
+
+pub const TRANSFER_SORT_FIELDS: &[SortField] = &[
+    SortField { name: "datetime", column: "datetime" },
+    SortField { name: "id", column: "id" },
+];
+
+impl Paginated for ExistTransfer {
+    fn cursor_value(&self, field: &SortField) -> String {
+        match field.column {
+            "datetime" => datetime_cursor(&self.datetime),
+            _ => self.id.to_string(),
+        }
+    }
+
+    fn cursor_id(&self) -> i32 {
+        self.id
+    }
+}
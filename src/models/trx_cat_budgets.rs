@@ -1,19 +1,43 @@
+use crate::errors::AppError;
+use crate::models::audit::Snapshot;
+use crate::models::exchange_rates::DEFAULT_CURRENCY;
+use crate::models::money::{Amount, Money, MoneyFormat};
+use crate::models::pagination::{Paginated, SortField, datetime_cursor};
+
+use std::str::FromStr;
+use chrono::{Datelike, Duration, IsoWeek, Weekday};
 use serde::{Deserialize, Serialize};
-use sqlx::types::chrono::{DateTime, Utc};
+use sqlx::types::chrono::{DateTime, NaiveDate, Utc};
 use sqlx::FromRow;
-use sqlx::types::BigDecimal;
 
-#[derive(Debug, FromRow, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct TrxCatBudgetModel {
     pub id: i32,
     pub periode: String,
-    pub allocated: i64,
-    pub spent: i64,
-    pub available: i64,
+    /// `weekly`, `monthly` or `yearly`, none for a budget kept from before periods were dated.
+    #[serde(rename = "periodType")]
+    pub period_type: Option<String>,
+    /// First and last day of the period, both included.
+    #[serde(rename = "periodStart")]
+    pub period_start: Option<NaiveDate>,
+    #[serde(rename = "periodEnd")]
+    pub period_end: Option<NaiveDate>,
+    pub allocated: Amount,
+    /// Left over, or overspent when negative, from the previous period.
+    pub carried: Amount,
+    pub spent: Amount,
+    pub available: Amount,
+    #[serde(rename = "carryOver")]
+    pub carry_over: String,
+    pub rollover: bool,
+    /// Percents of `allocated` raising an alert once `spent` reaches them.
+    pub thresholds: Vec<u32>,
     #[serde(rename = "createdAt")]
     pub created_at: DateTime<Utc>,
     #[serde(rename = "updatedAt")]
     pub updated_at: DateTime<Utc>,
+    #[serde(rename = "deletedAt")]
+    pub deleted_at: Option<DateTime<Utc>>,
     pub categoryid: i32,
 }
 
@@ -21,11 +45,19 @@ pub struct TrxCatBudgetModel {
 pub struct ExistTrxCatBudget {
     pub id: i32,
     pub periode: String,
-    pub allocated: BigDecimal,
-    pub spent: BigDecimal,
-    pub available: BigDecimal,
+    pub periodtype: Option<String>,
+    pub periodstart: Option<NaiveDate>,
+    pub periodend: Option<NaiveDate>,
+    pub allocated: Money,
+    pub carried: Money,
+    pub spent: Money,
+    pub available: Money,
+    pub carryover: String,
+    pub rollover: bool,
+    pub thresholds: String,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub deleted_at: Option<DateTime<Utc>>,
     pub categoryid: i32,
 }
 
@@ -33,17 +65,37 @@ pub struct ExistTrxCatBudget {
 pub struct AddTrxCatBudget {
     pub periode: String,
     pub allocated: i64,
+    #[serde(default)]
     pub spent: i64,
-    pub available: i64
+    #[serde(default)]
+    pub available: i64,
+    #[serde(rename = "carryOver")]
+    pub carry_over: Option<String>,
+    pub rollover: Option<bool>,
+    pub thresholds: Option<Vec<u32>>,
 }
 
 #[derive(Debug, Default, Clone, FromRow, Deserialize, Serialize)]
 pub struct NewTrxCatBudget {
+    /// A month `2023-11`, an ISO week `2023-W48` or a year `2023`.
     pub periode: String,
     pub allocated: i64,
+    /// Ignored when budgets are derived, they are computed from the trxs instead.
+    #[serde(default)]
     pub spent: i64,
+    #[serde(default)]
     pub available: i64,
     pub categoryid: i32,
+    /// What the rollover brings into the next period, `none` by default.
+    #[serde(rename = "carryOver")]
+    pub carry_over: Option<String>,
+    /// Whether the rollover creates the next period from this one, true by default.
+    pub rollover: Option<bool>,
+    /// Percents of `allocated` to raise an alert at, such as `[80, 100]`, none by default.
+    pub thresholds: Option<Vec<u32>>,
+    /// Brought in from the previous period by the rollover, never read from a request.
+    #[serde(skip)]
+    pub carried: i64,
 }
 
 #[derive(Debug, Default, Clone, FromRow, Deserialize, Serialize)]
@@ -53,4 +105,500 @@ pub struct UpdateTrxCatBudget {
     pub spent: Option<i64>, 
     pub available: Option<i64>,
     pub categoryid: Option<i32>,
-}
\ No newline at end of file
+    #[serde(rename = "carryOver")]
+    pub carry_over: Option<String>,
+    pub rollover: Option<bool>,
+    pub thresholds: Option<Vec<u32>>,
+}
+
+/// Body of `POST /trx_cats_budgets/rebuild`, every category when `categoryid` is left out.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct RebuildTrxCatBudget {
+    pub periode: String,
+    pub categoryid: Option<i32>,
+}
+
+/// What the trxs of one account `currency` add to the `spent` of a budget,
+/// in the base currency. `missing` is the first day one of them had no rate.
+#[derive(Debug, Clone, FromRow)]
+pub struct BudgetSpent {
+    pub budgetid: i32,
+    pub currency: String,
+    pub spent: Money,
+    pub missing: Option<NaiveDate>,
+}
+
+impl ExistTrxCatBudget {
+    /// Replaces `spent`, `available` following it.
+    pub fn set_spent(&mut self, spent: i64) {
+        self.spent = Money::from_minor(spent);
+        self.available = &(&self.allocated + &self.carried) - &self.spent;
+    }
+
+    /// Thresholds `spent` has reached, none while nothing is allocated.
+    pub fn crossed_thresholds(&self) -> Result<Vec<u32>, AppError> {
+        let allocated = i128::from(self.allocated.to_minor()?);
+        let spent = i128::from(self.spent.to_minor()?);
+
+        if allocated <= 0 {
+            return Ok(Vec::new());
+        }
+
+        Ok(parse_thresholds(&self.thresholds)
+            .into_iter()
+            .filter(|threshold| spent * 100 >= allocated * i128::from(*threshold))
+            .collect())
+    }
+}
+
+/// Budget amounts are kept in the base currency, `currency` is only used to
+/// render them in the requested format.
+pub fn build_model_from_exist(data: ExistTrxCatBudget, currency: &str, format: MoneyFormat) -> TrxCatBudgetModel {
+    TrxCatBudgetModel {
+        id: data.id,
+        periode: data.periode,
+        period_type: data.periodtype,
+        period_start: data.periodstart,
+        period_end: data.periodend,
+        allocated: data.allocated.amount(currency, format),
+        carried: data.carried.amount(currency, format),
+        spent: data.spent.amount(currency, format),
+        available: data.available.amount(currency, format),
+        carry_over: data.carryover,
+        rollover: data.rollover,
+        thresholds: parse_thresholds(&data.thresholds),
+        created_at: data.created_at,
+        updated_at: data.updated_at,
+        deleted_at: data.deleted_at,
+        categoryid: data.categoryid,
+    }
+}
+
+pub fn validate_new_budget(budget: &NewTrxCatBudget) -> Result<(), AppError> {
+    validate_budget_fields(&budget.periode, budget.allocated, &budget.carry_over, &budget.thresholds)
+}
+
+pub fn validate_budget_fields(periode: &str, allocated: i64, carry_over: &Option<String>, thresholds: &Option<Vec<u32>>) -> Result<(), AppError> {
+    if periode.trim().is_empty() {
+        return Err(AppError::Validation(String::from("budget periode is required")));
+    }
+
+    BudgetPeriod::parse(periode)?;
+
+    if allocated < 0 {
+        return Err(AppError::Validation(String::from("budget allocated must not be negative")));
+    }
+
+    if let Some(carry_over) = carry_over {
+        carry_over.parse::<CarryOver>()?;
+    }
+
+    if let Some(thresholds) = thresholds {
+        validate_thresholds(thresholds)?;
+    }
+
+    Ok(())
+}
+
+pub const MAX_THRESHOLDS: usize = 10;
+
+pub fn validate_thresholds(thresholds: &[u32]) -> Result<(), AppError> {
+    if thresholds.len() > MAX_THRESHOLDS {
+        return Err(AppError::Validation(format!("a budget has at most {} thresholds", MAX_THRESHOLDS)));
+    }
+
+    if let Some(threshold) = thresholds.iter().find(|threshold| !(1..=1000).contains(*threshold)) {
+        return Err(AppError::Validation(format!("budget thresholds must be percents from 1 to 1000, got `{}`", threshold)));
+    }
+
+    Ok(())
+}
+
+/// Thresholds as stored, sorted and without repeats, `80,100`.
+pub fn format_thresholds(thresholds: &[u32]) -> String {
+    let mut thresholds = thresholds.to_vec();
+    thresholds.sort_unstable();
+    thresholds.dedup();
+
+    thresholds.iter().map(|threshold| threshold.to_string()).collect::<Vec<String>>().join(",")
+}
+
+pub fn parse_thresholds(thresholds: &str) -> Vec<u32> {
+    thresholds
+        .split(',')
+        .filter_map(|threshold| threshold.trim().parse().ok())
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeriodType {
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+impl PeriodType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PeriodType::Weekly => "weekly",
+            PeriodType::Monthly => "monthly",
+            PeriodType::Yearly => "yearly",
+        }
+    }
+}
+
+impl FromStr for PeriodType {
+    type Err = AppError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "weekly" => Ok(PeriodType::Weekly),
+            "monthly" => Ok(PeriodType::Monthly),
+            "yearly" => Ok(PeriodType::Yearly),
+            _ => Err(AppError::Validation(format!("budget period type must be weekly, monthly or yearly, got `{}`", value))),
+        }
+    }
+}
+
+/// Date range a budget covers, from `start` to `end` included.
+///
+/// Written as its `periode` label: a month `2023-11`, an ISO week `2023-W48`
+/// starting on Monday, or a year `2023`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BudgetPeriod {
+    pub kind: PeriodType,
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+}
+
+impl BudgetPeriod {
+    pub fn parse(periode: &str) -> Result<Self, AppError> {
+        let periode = periode.trim();
+        let digits = |value: &str, len: usize| value.len() == len && value.chars().all(|c| c.is_ascii_digit());
+
+        let first = match periode.split_once('-') {
+            None if digits(periode, 4) => periode
+                .parse()
+                .ok()
+                .and_then(|year| NaiveDate::from_ymd_opt(year, 1, 1))
+                .map(|date| (PeriodType::Yearly, date)),
+            Some((year, week)) if digits(year, 4) && week.starts_with('W') && digits(&week[1..], 2) => {
+                match (year.parse(), week[1..].parse()) {
+                    (Ok(year), Ok(week)) => NaiveDate::from_isoywd_opt(year, week, Weekday::Mon).map(|date| (PeriodType::Weekly, date)),
+                    _ => None,
+                }
+            },
+            Some((year, month)) if digits(year, 4) && digits(month, 2) => match (year.parse(), month.parse()) {
+                (Ok(year), Ok(month)) => NaiveDate::from_ymd_opt(year, month, 1).map(|date| (PeriodType::Monthly, date)),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        first
+            .and_then(|(kind, date)| BudgetPeriod::containing(kind, date))
+            .ok_or_else(|| AppError::Validation(format!(
+                "budget periode `{}` must be a month (2023-11), an ISO week (2023-W48) or a year (2023)",
+                periode,
+            )))
+    }
+
+    /// Period of `kind` the day `date` falls in.
+    pub fn containing(kind: PeriodType, date: NaiveDate) -> Option<Self> {
+        let (start, next) = match kind {
+            PeriodType::Weekly => {
+                let start = date.checked_sub_signed(Duration::days(i64::from(date.weekday().num_days_from_monday())))?;
+                (start, start.checked_add_signed(Duration::days(7))?)
+            },
+            PeriodType::Monthly => {
+                let start = date.with_day(1)?;
+                let next = match start.month() {
+                    12 => NaiveDate::from_ymd_opt(start.year() + 1, 1, 1)?,
+                    month => NaiveDate::from_ymd_opt(start.year(), month + 1, 1)?,
+                };
+                (start, next)
+            },
+            PeriodType::Yearly => {
+                let start = NaiveDate::from_ymd_opt(date.year(), 1, 1)?;
+                (start, NaiveDate::from_ymd_opt(date.year() + 1, 1, 1)?)
+            },
+        };
+
+        Some(BudgetPeriod { kind, start, end: next.pred_opt()? })
+    }
+
+    /// Stored period of `budget`, none for a budget without dates.
+    pub fn of(budget: &ExistTrxCatBudget) -> Result<Option<Self>, AppError> {
+        match (&budget.periodtype, budget.periodstart, budget.periodend) {
+            (Some(kind), Some(start), Some(end)) => Ok(Some(BudgetPeriod { kind: kind.parse()?, start, end })),
+            _ => Ok(None),
+        }
+    }
+
+    /// Period right after this one, of the same type.
+    pub fn next(&self) -> Option<Self> {
+        BudgetPeriod::containing(self.kind, self.end.succ_opt()?)
+    }
+
+    pub fn label(&self) -> String {
+        match self.kind {
+            PeriodType::Weekly => {
+                let week: IsoWeek = self.start.iso_week();
+                format!("{}-W{:02}", week.year(), week.week())
+            },
+            PeriodType::Monthly => self.start.format("%Y-%m").to_string(),
+            PeriodType::Yearly => self.start.format("%Y").to_string(),
+        }
+    }
+}
+
+/// What the rollover brings from a budget into the next period: nothing, what
+/// is left, what was overspent, or both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CarryOver {
+    #[default]
+    None,
+    Unspent,
+    Overspent,
+    All,
+}
+
+impl CarryOver {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CarryOver::None => "none",
+            CarryOver::Unspent => "unspent",
+            CarryOver::Overspent => "overspent",
+            CarryOver::All => "all",
+        }
+    }
+
+    /// Amount carried into the next period when `available` is left.
+    pub fn carried(&self, available: i64) -> i64 {
+        match self {
+            CarryOver::None => 0,
+            CarryOver::Unspent => available.max(0),
+            CarryOver::Overspent => available.min(0),
+            CarryOver::All => available,
+        }
+    }
+}
+
+impl FromStr for CarryOver {
+    type Err = AppError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "none" => Ok(CarryOver::None),
+            "unspent" => Ok(CarryOver::Unspent),
+            "overspent" => Ok(CarryOver::Overspent),
+            "all" => Ok(CarryOver::All),
+            _ => Err(AppError::Validation(format!("`carryOver` must be none, unspent, overspent or all, got `{}`", value))),
+        }
+    }
+}
+
+/// Budget of the period after `budget`, allocated the same, with what
+/// `budget` carries over.
+pub fn rollover_budget(budget: &ExistTrxCatBudget, period: &BudgetPeriod) -> Result<NewTrxCatBudget, AppError> {
+    let carry_over: CarryOver = budget.carryover.parse()?;
+    let allocated = budget.allocated.to_minor()?;
+    let carried = carry_over.carried(budget.available.to_minor()?);
+
+    Ok(NewTrxCatBudget {
+        periode: period.label(),
+        allocated,
+        spent: 0,
+        available: allocated.saturating_add(carried),
+        categoryid: budget.categoryid,
+        carry_over: Some(carry_over.as_str().to_string()),
+        rollover: Some(budget.rollover),
+        thresholds: Some(parse_thresholds(&budget.thresholds)),
+        carried,
+    })
+}
+
+pub const BUDGET_SORT_FIELDS: &[SortField] = &[
+    SortField { name: "id", column: "id" },
+    SortField { name: "createdAt", column: "created_at" },
+];
+
+impl Paginated for ExistTrxCatBudget {
+    fn cursor_value(&self, field: &SortField) -> String {
+        match field.column {
+            "created_at" => datetime_cursor(&self.created_at),
+            _ => self.id.to_string(),
+        }
+    }
+
+    fn cursor_id(&self) -> i32 {
+        self.id
+    }
+}
+
+impl Snapshot for ExistTrxCatBudget {
+    fn snapshot(&self) -> serde_json::Value {
+        let budget = build_model_from_exist(self.clone(), DEFAULT_CURRENCY, MoneyFormat::Minor);
+
+        serde_json::to_value(budget).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn period(periode: &str) -> BudgetPeriod {
+        BudgetPeriod::parse(periode).unwrap()
+    }
+
+    fn budget(allocated: i64, spent: i64, carry_over: CarryOver) -> ExistTrxCatBudget {
+        let period = period("2023-11");
+        let mut budget = ExistTrxCatBudget {
+            id: 7,
+            periode: period.label(),
+            periodtype: Some(period.kind.as_str().to_string()),
+            periodstart: Some(period.start),
+            periodend: Some(period.end),
+            allocated: Money::from_minor(allocated),
+            carryover: carry_over.as_str().to_string(),
+            rollover: true,
+            thresholds: String::from("80,100"),
+            categoryid: 3,
+            ..ExistTrxCatBudget::default()
+        };
+        budget.set_spent(spent);
+        budget
+    }
+
+    #[test]
+    fn parse_reads_a_month() {
+        assert_eq!(period("2023-11"), BudgetPeriod { kind: PeriodType::Monthly, start: date(2023, 11, 1), end: date(2023, 11, 30) });
+        assert_eq!(period("2023-12").end, date(2023, 12, 31));
+        assert_eq!(period("2024-02").end, date(2024, 2, 29));
+        assert_eq!(period("2023-02").end, date(2023, 2, 28));
+    }
+
+    #[test]
+    fn parse_reads_an_iso_week_from_monday_to_sunday() {
+        assert_eq!(period("2023-W48"), BudgetPeriod { kind: PeriodType::Weekly, start: date(2023, 11, 27), end: date(2023, 12, 3) });
+        // week 1 holds the first thursday, it may start the year before
+        assert_eq!(period("2023-W01").start, date(2023, 1, 2));
+        assert_eq!(period("2026-W01").start, date(2025, 12, 29));
+        // 2020 is a 53 week year, its last week ends in 2021
+        assert_eq!(period("2020-W53"), BudgetPeriod { kind: PeriodType::Weekly, start: date(2020, 12, 28), end: date(2021, 1, 3) });
+    }
+
+    #[test]
+    fn parse_reads_a_year() {
+        assert_eq!(period("2023"), BudgetPeriod { kind: PeriodType::Yearly, start: date(2023, 1, 1), end: date(2023, 12, 31) });
+        assert_eq!(period(" 2024 ").end, date(2024, 12, 31));
+    }
+
+    #[test]
+    fn parse_refuses_invalid_labels() {
+        let labels = [
+            "", "23", "20230", "2023-", "2023-1", "2023-00", "2023-13", "2023-W00", "2023-W53", "2023-W5",
+            "2023-w48", "2023-W480", "2023/11", "2023-11-01", "Nov 2023", "abcd", "-2023", "+202",
+        ];
+
+        for label in labels {
+            assert!(matches!(BudgetPeriod::parse(label), Err(AppError::Validation(_))), "`{}` was accepted", label);
+        }
+    }
+
+    #[test]
+    fn label_writes_the_canonical_periode() {
+        for label in ["2023-11", "2024-02", "2023-W48", "2020-W53", "2026-W01", "2023"] {
+            assert_eq!(period(label).label(), label);
+        }
+    }
+
+    #[test]
+    fn containing_finds_the_period_of_a_day() {
+        let sunday = date(2023, 12, 3);
+
+        assert_eq!(BudgetPeriod::containing(PeriodType::Weekly, sunday), Some(period("2023-W48")));
+        assert_eq!(BudgetPeriod::containing(PeriodType::Monthly, sunday), Some(period("2023-12")));
+        assert_eq!(BudgetPeriod::containing(PeriodType::Yearly, sunday), Some(period("2023")));
+    }
+
+    #[test]
+    fn next_follows_on_across_years() {
+        assert_eq!(period("2023-12").next(), Some(period("2024-01")));
+        assert_eq!(period("2023-W52").next(), Some(period("2024-W01")));
+        assert_eq!(period("2020-W53").next(), Some(period("2021-W01")));
+        assert_eq!(period("2023").next(), Some(period("2024")));
+    }
+
+    #[test]
+    fn of_reads_the_stored_period() {
+        let mut budget = budget(1000, 0, CarryOver::None);
+        assert_eq!(BudgetPeriod::of(&budget).unwrap(), Some(period("2023-11")));
+
+        budget.periodtype = Some(String::from("daily"));
+        assert!(matches!(BudgetPeriod::of(&budget), Err(AppError::Validation(_))));
+
+        // kept from before periods were dated
+        budget.periodtype = None;
+        assert_eq!(BudgetPeriod::of(&budget).unwrap(), None);
+    }
+
+    #[test]
+    fn carry_over_parses_its_names() {
+        for carry_over in [CarryOver::None, CarryOver::Unspent, CarryOver::Overspent, CarryOver::All] {
+            assert_eq!(carry_over.as_str().parse::<CarryOver>().unwrap(), carry_over);
+        }
+        assert!(matches!("some".parse::<CarryOver>(), Err(AppError::Validation(_))));
+    }
+
+    #[test]
+    fn rollover_carries_what_carry_over_asks_for() {
+        // (carry over, spent of 1000 allocated, carried into the next period)
+        let cases = [
+            (CarryOver::None, 700, 0),
+            (CarryOver::None, 1200, 0),
+            (CarryOver::Unspent, 700, 300),
+            (CarryOver::Unspent, 1200, 0),
+            (CarryOver::Overspent, 700, 0),
+            (CarryOver::Overspent, 1200, -200),
+            (CarryOver::All, 700, 300),
+            (CarryOver::All, 1200, -200),
+        ];
+
+        for (carry_over, spent, carried) in cases {
+            let next = rollover_budget(&budget(1000, spent, carry_over), &period("2023-12")).unwrap();
+
+            assert_eq!(next.carried, carried, "{:?} with {} spent", carry_over, spent);
+            assert_eq!(next.available, 1000 + carried, "{:?} with {} spent", carry_over, spent);
+        }
+    }
+
+    #[test]
+    fn rollover_starts_the_next_period_like_the_previous_one() {
+        let next = rollover_budget(&budget(1000, 700, CarryOver::Unspent), &period("2023-12")).unwrap();
+
+        assert_eq!(next.periode, "2023-12");
+        assert_eq!(next.allocated, 1000);
+        assert_eq!(next.spent, 0);
+        assert_eq!(next.categoryid, 3);
+        assert_eq!(next.carry_over.as_deref(), Some("unspent"));
+        assert_eq!(next.rollover, Some(true));
+        assert_eq!(next.thresholds, Some(vec![80, 100]));
+    }
+
+    #[test]
+    fn rollover_carries_what_was_carried_in_too() {
+        let mut previous = budget(1000, 700, CarryOver::All);
+        previous.carried = Money::from_minor(-500);
+        previous.set_spent(700);
+
+        let next = rollover_budget(&previous, &period("2023-12")).unwrap();
+        assert_eq!(next.carried, -200);
+        assert_eq!(next.available, 800);
+    }
+}
@@ -1,7 +1,11 @@
 
-use crate::models::bigdecimal_to_int;
+use crate::errors::AppError;
+use crate::models::audit::Snapshot;
+use crate::models::pagination::{Paginated, SortField, datetime_cursor};
 use crate::models::cat_types::{CatTypeModel, ExistCatType};
-use crate::models::trx_cat_budgets::{TrxCatBudgetModel, ExistTrxCatBudget, AddTrxCatBudget};
+use crate::models::exchange_rates::DEFAULT_CURRENCY;
+use crate::models::money::MoneyFormat;
+use crate::models::trx_cat_budgets::{self, TrxCatBudgetModel, ExistTrxCatBudget, AddTrxCatBudget, validate_budget_fields};
 
 use serde::{Deserialize, Serialize};
 use sqlx::types::chrono::{DateTime, Utc};
@@ -16,6 +20,8 @@ pub struct TrxCatModel {
     pub created_at: DateTime<Utc>,
     #[serde(rename = "updatedAt")]
     pub updated_at: DateTime<Utc>,
+    #[serde(rename = "deletedAt")]
+    pub deleted_at: Option<DateTime<Utc>>,
     pub typeid: i32,
     pub budget: Option<TrxCatBudgetModel>
 }
@@ -29,6 +35,8 @@ pub struct TrxCatModelWithType {
     pub created_at: DateTime<Utc>,
     #[serde(rename = "updatedAt")]
     pub updated_at: DateTime<Utc>,
+    #[serde(rename = "deletedAt")]
+    pub deleted_at: Option<DateTime<Utc>>,
     pub typeid: i32,
     pub r#type: CatTypeModel,
     pub budget: Option<TrxCatBudgetModel>
@@ -41,6 +49,7 @@ pub struct ExistTrxCat {
     pub description: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub deleted_at: Option<DateTime<Utc>>,
     pub typeid: i32
 }
 
@@ -51,6 +60,7 @@ pub struct ExistTrxCatWithBudget {
     pub description: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub deleted_at: Option<DateTime<Utc>>,
     pub typeid: i32,
     pub budget: Option<ExistTrxCatBudget>
 }
@@ -62,6 +72,7 @@ pub struct ExistTrxCatWithBudgetType {
     pub description: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub deleted_at: Option<DateTime<Utc>>,
     pub typeid: i32,
     pub r#type: ExistCatType,
     pub budget: Option<ExistTrxCatBudget>
@@ -81,22 +92,13 @@ pub struct UpdateTrxCat {
     pub description: Option<String>,
 }
 
-pub fn detail_model_from_exist(data: ExistTrxCatWithBudgetType) -> TrxCatModelWithType {
+pub fn detail_model_from_exist(data: ExistTrxCatWithBudgetType, base_currency: &str, format: MoneyFormat) -> TrxCatModelWithType {
     let mut budget: Option<TrxCatBudgetModel> = None;
     let data_type: ExistCatType = data.r#type;
 
     if data.budget.clone() != None {
         let budget_field = data.budget.clone().unwrap();
-        budget = Some( TrxCatBudgetModel {
-            id: budget_field.id,
-            periode: budget_field.periode,
-            allocated: bigdecimal_to_int(budget_field.allocated),
-            spent: bigdecimal_to_int(budget_field.spent),
-            available: bigdecimal_to_int(budget_field.available),
-            created_at: budget_field.created_at,
-            updated_at: budget_field.updated_at,
-            categoryid: budget_field.categoryid,
-        } )
+        budget = Some( trx_cat_budgets::build_model_from_exist(budget_field, base_currency, format) )
     }
 
     return TrxCatModelWithType {
@@ -105,34 +107,28 @@ pub fn detail_model_from_exist(data: ExistTrxCatWithBudgetType) -> TrxCatModelWi
         description: data.description, 
         created_at: data.created_at,
         updated_at: data.updated_at,
+        deleted_at: data.deleted_at,
         typeid: data.typeid,
         r#type: CatTypeModel{
             id: data_type.id,
             r#type: data_type.r#type,
+            direction: data_type.direction,
             description: data_type.description,
             icon: data_type.icon,
             created_at: data_type.created_at,
             updated_at: data_type.updated_at,
+            deleted_at: data_type.deleted_at,
         },
         budget: budget
     }
 }
 
-pub fn build_model_from_exist(data: ExistTrxCatWithBudget) -> TrxCatModel {
+pub fn build_model_from_exist(data: ExistTrxCatWithBudget, base_currency: &str, format: MoneyFormat) -> TrxCatModel {
     let mut budget: Option<TrxCatBudgetModel> = None;
 
     if data.budget.clone() != None {
         let budget_field = data.budget.clone().unwrap();
-        budget = Some( TrxCatBudgetModel {
-            id: budget_field.id,
-            periode: budget_field.periode,
-            allocated: bigdecimal_to_int(budget_field.allocated),
-            spent: bigdecimal_to_int(budget_field.spent),
-            available: bigdecimal_to_int(budget_field.available),
-            created_at: budget_field.created_at,
-            updated_at: budget_field.updated_at,
-            categoryid: budget_field.categoryid,
-        } )
+        budget = Some( trx_cat_budgets::build_model_from_exist(budget_field, base_currency, format) )
     }
 
     return TrxCatModel {
@@ -141,6 +137,7 @@ pub fn build_model_from_exist(data: ExistTrxCatWithBudget) -> TrxCatModel {
         description: data.description, 
         created_at: data.created_at,
         updated_at: data.updated_at,
+        deleted_at: data.deleted_at,
         typeid: data.typeid,
         budget: budget
     }
@@ -158,6 +155,7 @@ pub fn build_exist_trx_cat_budget_type(
         description: trx_cat.description.clone(),
         created_at: trx_cat.created_at.clone(),
         updated_at: trx_cat.updated_at.clone(),
+        deleted_at: trx_cat.deleted_at,
         typeid: trx_cat.typeid.clone(),
         r#type: cat_type,
         budget: cat_budget
@@ -176,8 +174,48 @@ pub fn build_exist_trx_cat_budget(
         description: trx_cat.description.clone(),
         created_at: trx_cat.created_at.clone(),
         updated_at: trx_cat.updated_at.clone(),
+        deleted_at: trx_cat.deleted_at,
         typeid: trx_cat.typeid.clone(),
         budget: cat_budget
     }
     
-}
\ No newline at end of file
+}
+
+pub fn validate_add_trx_cat(cat: &AddTrxCat) -> Result<(), AppError> {
+    if cat.name.trim().is_empty() {
+        return Err(AppError::Validation(String::from("category name is required")));
+    }
+
+    match &cat.budget {
+        Some(budget) => validate_budget_fields(&budget.periode, budget.allocated, &budget.carry_over, &budget.thresholds),
+        None => Ok(()),
+    }
+}
+
+pub const TRX_CAT_SORT_FIELDS: &[SortField] = &[
+    SortField { name: "id", column: "id" },
+    SortField { name: "name", column: "name" },
+    SortField { name: "createdAt", column: "created_at" },
+];
+
+impl Paginated for ExistTrxCat {
+    fn cursor_value(&self, field: &SortField) -> String {
+        match field.column {
+            "name" => self.name.clone(),
+            "created_at" => datetime_cursor(&self.created_at),
+            _ => self.id.to_string(),
+        }
+    }
+
+    fn cursor_id(&self) -> i32 {
+        self.id
+    }
+}
+
+impl Snapshot for ExistTrxCat {
+    fn snapshot(&self) -> serde_json::Value {
+        let category = build_model_from_exist(build_exist_trx_cat_budget(self.clone(), None), DEFAULT_CURRENCY, MoneyFormat::Minor);
+
+        serde_json::to_value(category).unwrap_or_default()
+    }
+}
@@ -1,93 +1,147 @@
 
-use crate::models::bigdecimal_to_int;
+use crate::errors::AppError;
+use crate::models::audit::Snapshot;
+use crate::models::money::{Amount, Money, MoneyFormat};
+use crate::models::pagination::{Paginated, SortField, datetime_cursor};
 use crate::models::accounts::{AccountModel, ExistAccount};
 use crate::models::trx_cats::{TrxCatModel, ExistTrxCat};
 
 use serde::{Deserialize, Serialize};
-use sqlx::types::chrono::{DateTime, Utc};
+use sqlx::types::chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
 use sqlx::FromRow;
-use sqlx::types::BigDecimal;
 
-#[derive(Debug, FromRow, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct TrxModel {
     pub id: i32,
-    pub credit: i64,
-    pub debit: i64,
+    pub credit: Amount,
+    pub debit: Amount,
+    /// Currency of the account, the amounts are in it.
+    pub currency: String,
     pub description: Option<String>,
     #[serde(rename = "balanceBefore")]
-    pub balance_before: i64,
+    pub balance_before: Amount,
     #[serde(rename = "balanceAfter")]
-    pub balance_after: i64,
+    pub balance_after: Amount,
     pub datetime: DateTime<Utc>,
     #[serde(rename = "createdAt")]
     pub created_at: DateTime<Utc>,
     #[serde(rename = "updatedAt")]
     pub updated_at: DateTime<Utc>,
+    #[serde(rename = "deletedAt")]
+    pub deleted_at: Option<DateTime<Utc>>,
     pub accountid: i32,
-    pub categoryid: i32,
+    pub categoryid: Option<i32>,
+    pub transferid: Option<i32>,
+    pub splits: Vec<TrxSplitModel>,
 }
 
-#[derive(Debug, FromRow, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct TrxModelWithAccCat {
     pub id: i32,
-    pub credit: i64,
-    pub debit: i64,
+    pub credit: Amount,
+    pub debit: Amount,
+    /// Currency of the account, the amounts are in it.
+    pub currency: String,
     pub description: Option<String>,
     #[serde(rename = "balanceBefore")]
-    pub balance_before: i64,
+    pub balance_before: Amount,
     #[serde(rename = "balanceAfter")]
-    pub balance_after: i64,
+    pub balance_after: Amount,
     pub datetime: DateTime<Utc>,
     #[serde(rename = "createdAt")]
     pub created_at: DateTime<Utc>,
     #[serde(rename = "updatedAt")]
     pub updated_at: DateTime<Utc>,
+    #[serde(rename = "deletedAt")]
+    pub deleted_at: Option<DateTime<Utc>>,
     pub accountid: i32,
-    pub categoryid: i32,
+    pub categoryid: Option<i32>,
+    pub transferid: Option<i32>,
+    pub splits: Vec<TrxSplitModel>,
     pub account: AccountModel,
-    pub category: TrxCatModel
+    pub category: Option<TrxCatModel>
+}
+
+/// Share of a trx charged to one category.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TrxSplitModel {
+    pub id: i32,
+    pub categoryid: i32,
+    pub amount: Amount,
+    pub memo: Option<String>,
 }
 
 #[derive(Debug, Default, Clone, FromRow)]
 pub struct ExistTrx {
     pub id: i32,
-    pub credit: BigDecimal,
-    pub debit: BigDecimal,
+    pub credit: Money,
+    pub debit: Money,
+    pub currency: String,
     pub description: Option<String>,
-    pub balance_before: BigDecimal,
-    pub balance_after: BigDecimal,
+    pub balance_before: Money,
+    pub balance_after: Money,
     pub datetime: DateTime<Utc>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub deleted_at: Option<DateTime<Utc>>,
     pub accountid: i32,
-    pub categoryid: i32,
+    pub categoryid: Option<i32>,
+    pub transferid: Option<i32>,
+    /// Split lines of a trx without `categoryid`, loaded separately.
+    #[sqlx(skip)]
+    pub splits: Vec<ExistTrxSplit>,
 }
 
 #[derive(Debug, Default, Clone, FromRow)]
 pub struct ExistTrxWithAccCat {
     pub id: i32,
-    pub credit: BigDecimal,
-    pub debit: BigDecimal,
+    pub credit: Money,
+    pub debit: Money,
+    pub currency: String,
     pub description: Option<String>,
-    pub balance_before: BigDecimal,
-    pub balance_after: BigDecimal,
+    pub balance_before: Money,
+    pub balance_after: Money,
     pub datetime: DateTime<Utc>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub deleted_at: Option<DateTime<Utc>>,
     pub accountid: i32,
-    pub categoryid: i32,
+    pub categoryid: Option<i32>,
+    pub transferid: Option<i32>,
+    pub splits: Vec<ExistTrxSplit>,
     pub account: ExistAccount,
-    pub category: ExistTrxCat
+    pub category: Option<ExistTrxCat>
 }
 
-#[derive(Debug, Default, Clone, FromRow, Deserialize, Serialize)]
+#[derive(Debug, Default, Clone, PartialEq, FromRow)]
+pub struct ExistTrxSplit {
+    pub id: i32,
+    pub transactionid: i32,
+    pub categoryid: i32,
+    /// Positive part of `credit + debit` of the trx.
+    pub amount: Money,
+    pub memo: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
 pub struct NewTrx {
     pub credit: i64,
     pub debit: i64,
     pub description: Option<String>,
     pub datetime: String,
     pub accountid: i32,
+    /// Either a single category or `splits` over several.
+    pub categoryid: Option<i32>,
+    pub splits: Option<Vec<NewTrxSplit>>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+pub struct NewTrxSplit {
     pub categoryid: i32,
+    pub amount: i64,
+    pub memo: Option<String>,
 }
 
 #[derive(Debug, Default, Clone, FromRow, Deserialize, Serialize)]
@@ -99,51 +153,287 @@ pub struct AddTrx {
     pub balance_after: i64,
     pub datetime: String,
     pub accountid: i32,
-    pub categoryid: i32,
+    pub categoryid: Option<i32>,
+    pub transferid: Option<i32>,
 }
 
-#[derive(Debug, Default, Clone, FromRow, Deserialize, Serialize)]
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
 pub struct UpdateTrx {
     pub credit: Option<i64>,
     pub debit: Option<i64>,
     pub description: Option<String>,
     pub datetime: Option<String>,
+    pub accountid: Option<i32>,
+    /// Moves the trx to a single category, dropping its splits.
+    pub categoryid: Option<i32>,
+    /// Replaces the splits, the trx leaving its single category.
+    pub splits: Option<Vec<NewTrxSplit>>,
 }
 
-pub fn build_model_from_exist(data: ExistTrxWithAccCat) -> TrxModelWithAccCat {
+pub fn build_model_from_exist(data: ExistTrxWithAccCat, format: MoneyFormat) -> TrxModelWithAccCat {
     let account = data.account;
     let category = data.category;
+    let splits = data.splits.into_iter().map(|split| build_split_model(split, &data.currency, format)).collect();
 
     return TrxModelWithAccCat {
         id: data.id,
-        credit: bigdecimal_to_int(data.credit),
-        debit: bigdecimal_to_int(data.debit),
+        credit: data.credit.amount(&data.currency, format),
+        debit: data.debit.amount(&data.currency, format),
         description: data.description,
-        balance_before: bigdecimal_to_int(data.balance_before),
-        balance_after: bigdecimal_to_int(data.balance_after),
+        balance_before: data.balance_before.amount(&data.currency, format),
+        balance_after: data.balance_after.amount(&data.currency, format),
+        currency: data.currency,
         datetime: data.datetime,
         created_at: data.created_at,
         updated_at: data.updated_at,
+        deleted_at: data.deleted_at,
         accountid: data.accountid,
         categoryid: data.categoryid,
+        transferid: data.transferid,
+        splits,
         account: AccountModel {
             id: account.id,
             name: account.name,
             description: account.description,
             star: account.star,
             r#type: account.r#type,
-            balance: bigdecimal_to_int(account.balance),
+            balance: account.balance.amount(&account.currency, format),
+            currency: account.currency,
+            archived_at: account.archived_at,
             created_at: account.created_at,
             updated_at: account.updated_at,
+            deleted_at: account.deleted_at,
         },
-        category: TrxCatModel {
+        category: category.map(|category| TrxCatModel {
             id: category.id,
             name: category.name,
             description: category.description,
             created_at: category.created_at,
             updated_at: category.updated_at,
+            deleted_at: category.deleted_at,
             typeid: category.typeid,
             budget: None
-        },
+        }),
+    }
+}
+
+pub fn build_trx_model(data: ExistTrx, format: MoneyFormat) -> TrxModel {
+    let splits = data.splits.into_iter().map(|split| build_split_model(split, &data.currency, format)).collect();
+
+    TrxModel {
+        id: data.id,
+        credit: data.credit.amount(&data.currency, format),
+        debit: data.debit.amount(&data.currency, format),
+        description: data.description,
+        balance_before: data.balance_before.amount(&data.currency, format),
+        balance_after: data.balance_after.amount(&data.currency, format),
+        currency: data.currency,
+        datetime: data.datetime,
+        created_at: data.created_at,
+        updated_at: data.updated_at,
+        deleted_at: data.deleted_at,
+        accountid: data.accountid,
+        categoryid: data.categoryid,
+        transferid: data.transferid,
+        splits,
+    }
+}
+
+pub fn build_split_model(data: ExistTrxSplit, currency: &str, format: MoneyFormat) -> TrxSplitModel {
+    TrxSplitModel {
+        id: data.id,
+        categoryid: data.categoryid,
+        amount: data.amount.amount(currency, format),
+        memo: data.memo,
+    }
+}
+
+/// Share of `credit - debit` of `trx` that goes to `split`, signed like it.
+pub fn split_amount(trx: &ExistTrx, split: &ExistTrxSplit) -> Result<i64, AppError> {
+    let amount = split.amount.to_minor()?;
+
+    match trx.debit > trx.credit {
+        true => Ok(-amount),
+        false => Ok(amount),
+    }
+}
+
+pub const DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+pub fn validate_new_trx(trx: &NewTrx) -> Result<(), AppError> {
+    validate_amounts(Some(trx.credit), Some(trx.debit))?;
+
+    match (trx.categoryid, &trx.splits) {
+        (Some(_), None) => {},
+        (None, Some(splits)) => validate_splits(trx.credit.saturating_add(trx.debit), splits)?,
+        _ => return Err(AppError::Validation(String::from("a transaction needs either `categoryid` or `splits`"))),
+    }
+
+    validate_datetime(&trx.datetime)
+}
+
+/// Splits need at least one line, each with a positive amount, adding up to
+/// the `total` the trx moves.
+pub fn validate_splits(total: i64, splits: &[NewTrxSplit]) -> Result<(), AppError> {
+    if splits.is_empty() {
+        return Err(AppError::Validation(String::from("`splits` must not be empty")));
     }
-}
\ No newline at end of file
+
+    if splits.iter().any(|split| split.amount <= 0) {
+        return Err(AppError::Validation(String::from("split amounts must be greater than 0")));
+    }
+
+    let sum = splits.iter().try_fold(0i64, |sum, split| sum.checked_add(split.amount));
+    if sum != Some(total) {
+        return Err(AppError::Validation(format!("split amounts must add up to the transaction total {}", total)));
+    }
+
+    Ok(())
+}
+
+pub fn validate_update_trx(trx: &UpdateTrx) -> Result<(), AppError> {
+    if trx.credit.is_some() != trx.debit.is_some() {
+        return Err(AppError::Validation(String::from("credit and debit must be updated together")));
+    }
+
+    if trx.categoryid.is_some() && trx.splits.is_some() {
+        return Err(AppError::Validation(String::from("a transaction takes either `categoryid` or `splits`")));
+    }
+
+    validate_amounts(trx.credit, trx.debit)?;
+
+    match &trx.datetime {
+        Some(datetime) => validate_datetime(datetime),
+        None => Ok(()),
+    }
+}
+
+/// A trx moves money one way, its amount being the credit or the debit, so
+/// budgets and splits read the same `credit - debit` from it.
+fn validate_amounts(credit: Option<i64>, debit: Option<i64>) -> Result<(), AppError> {
+    let (credit, debit) = (credit.unwrap_or(0), debit.unwrap_or(0));
+    if credit < 0 || debit < 0 {
+        return Err(AppError::Validation(String::from("credit and debit must not be negative")));
+    }
+
+    if credit > 0 && debit > 0 {
+        return Err(AppError::Validation(String::from("a transaction is either a credit or a debit, the other one must be 0")));
+    }
+
+    Ok(())
+}
+
+pub fn validate_datetime(datetime: &str) -> Result<(), AppError> {
+    match NaiveDateTime::parse_from_str(datetime, DATETIME_FORMAT) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(AppError::Validation(format!("datetime `{}` must use format yyyy-MM-dd HH:mm:ss", datetime))),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrxDirection {
+    Credit,
+    Debit,
+}
+
+/// Conditions of `GET /trxs`, every field is optional and they combine with `AND`.
+#[derive(Debug, Default, Clone)]
+pub struct TrxFilter {
+    pub from: Option<NaiveDateTime>,
+    pub to: Option<NaiveDateTime>,
+    pub min_amount: Option<i64>,
+    pub max_amount: Option<i64>,
+    pub direction: Option<TrxDirection>,
+    pub accountids: Vec<i32>,
+    pub categoryids: Vec<i32>,
+    pub typeid: Option<i32>,
+    pub q: Option<String>,
+    /// `Some(true)` keeps only transfer legs, `Some(false)` leaves them out.
+    pub transfer: Option<bool>,
+    pub include_deleted: bool,
+}
+
+impl std::str::FromStr for TrxDirection {
+    type Err = AppError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "credit" => Ok(TrxDirection::Credit),
+            "debit" => Ok(TrxDirection::Debit),
+            _ => Err(AppError::BadRequest(format!("query parameter `type` must be credit or debit, got `{}`", value))),
+        }
+    }
+}
+
+/// Parses a `from`/`to` bound, either `yyyy-MM-dd HH:mm:ss` or a bare date.
+///
+/// A bare date covers the whole day: it starts at midnight for `from` and
+/// ends at 23:59:59 for `to`.
+pub fn parse_filter_datetime(key: &str, value: &str, end_of_day: bool) -> Result<NaiveDateTime, AppError> {
+    if let Ok(datetime) = NaiveDateTime::parse_from_str(value, DATETIME_FORMAT) {
+        return Ok(datetime);
+    }
+
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| {
+        AppError::BadRequest(format!("query parameter `{}` must use format yyyy-MM-dd or yyyy-MM-dd HH:mm:ss", key))
+    })?;
+
+    let time = match end_of_day {
+        true => date.and_hms_opt(23, 59, 59),
+        false => date.and_hms_opt(0, 0, 0),
+    };
+
+    time.ok_or_else(|| AppError::BadRequest(format!("query parameter `{}` is not a valid date", key)))
+}
+
+pub fn validate_trx_filter(filter: &TrxFilter) -> Result<(), AppError> {
+    if let (Some(from), Some(to)) = (filter.from, filter.to) {
+        if from > to {
+            return Err(AppError::Validation(String::from("`from` must not be after `to`")));
+        }
+    }
+
+    if filter.min_amount.unwrap_or(0) < 0 || filter.max_amount.unwrap_or(0) < 0 {
+        return Err(AppError::Validation(String::from("`minAmount` and `maxAmount` must not be negative")));
+    }
+
+    if let (Some(min), Some(max)) = (filter.min_amount, filter.max_amount) {
+        if min > max {
+            return Err(AppError::Validation(String::from("`minAmount` must not be greater than `maxAmount`")));
+        }
+    }
+
+    if let Some(q) = &filter.q {
+        if q.chars().count() > 100 {
+            return Err(AppError::Validation(String::from("`q` must not exceed 100 characters")));
+        }
+    }
+
+    Ok(())
+}
+
+pub const TRX_SORT_FIELDS: &[SortField] = &[
+    SortField { name: "datetime", column: "datetime" },
+    SortField { name: "createdAt", column: "created_at" },
+    SortField { name: "id", column: "id" },
+];
+
+impl Paginated for ExistTrx {
+    fn cursor_value(&self, field: &SortField) -> String {
+        match field.column {
+            "datetime" => datetime_cursor(&self.datetime),
+            "created_at" => datetime_cursor(&self.created_at),
+            _ => self.id.to_string(),
+        }
+    }
+
+    fn cursor_id(&self) -> i32 {
+        self.id
+    }
+}
+
+impl Snapshot for ExistTrx {
+    fn snapshot(&self) -> serde_json::Value {
+        serde_json::to_value(build_trx_model(self.clone(), MoneyFormat::Minor)).unwrap_or_default()
+    }
+}
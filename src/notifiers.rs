@@ -0,0 +1,217 @@
+use crate::config::{AlertsConfig, NotifierKind};
+use crate::models::alerts::{ExistAlert, build_model_from_exist};
+use crate::models::money::{Amount, MoneyFormat};
+
+use std::time::Duration;
+use chrono::Utc;
+use hyper::client::HttpConnector;
+use hyper::{header, Body, Client, Method, Request, Uri};
+use tokio::io::{AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+type GenericError = Box<dyn std::error::Error + Send + Sync>;
+type Result<T> = std::result::Result<T, GenericError>;
+
+/// Longest a notifier may take to deliver one alert.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Where a budget alert is delivered.
+#[async_trait::async_trait]
+pub trait Notifier: Send + Sync {
+    fn name(&self) -> &'static str;
+    async fn notify(&self, alert: &ExistAlert, currency: &str) -> Result<()>;
+}
+
+/// Notifiers picked in `alerts.notifiers`, in that order.
+pub fn from_config(config: &AlertsConfig) -> Result<Vec<Box<dyn Notifier>>> {
+    let mut notifiers: Vec<Box<dyn Notifier>> = Vec::new();
+
+    for kind in config.notifiers.iter() {
+        match kind {
+            NotifierKind::Log => notifiers.push(Box::new(LogNotifier)),
+            NotifierKind::Webhook => notifiers.push(Box::new(WebhookNotifier::new(&config.webhook_url)?)),
+            NotifierKind::Email => notifiers.push(Box::new(EmailNotifier {
+                host: config.smtp_host.clone(),
+                port: config.smtp_port,
+                from: config.smtp_from.clone(),
+                to: config.smtp_to.clone(),
+            })),
+        }
+    }
+
+    Ok(notifiers)
+}
+
+/// Writes the alert to the log as a warning.
+pub struct LogNotifier;
+
+#[async_trait::async_trait]
+impl Notifier for LogNotifier {
+    fn name(&self) -> &'static str {
+        "log"
+    }
+
+    async fn notify(&self, alert: &ExistAlert, currency: &str) -> Result<()> {
+        log::warn!("{}", summary(alert, currency));
+
+        Ok(())
+    }
+}
+
+/// Posts the alert, as the API returns it, to a url. Any 2xx answer counts as
+/// delivered.
+pub struct WebhookNotifier {
+    url: Uri,
+    client: Client<HttpConnector>,
+}
+
+impl WebhookNotifier {
+    pub fn new(url: &str) -> Result<Self> {
+        Ok(Self {
+            url: url.parse()?,
+            client: Client::new(),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl Notifier for WebhookNotifier {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    async fn notify(&self, alert: &ExistAlert, currency: &str) -> Result<()> {
+        let body = serde_json::to_string(&build_model_from_exist(alert.clone(), currency, MoneyFormat::Minor))?;
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri(self.url.clone())
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body))?;
+
+        let res = tokio::time::timeout(TIMEOUT, self.client.request(req))
+            .await
+            .map_err(|_| "webhook timed out")??;
+
+        match res.status().is_success() {
+            true => Ok(()),
+            false => Err(format!("webhook answered {}", res.status()).into()),
+        }
+    }
+}
+
+/// Mails the alert through an SMTP relay, without authentication or TLS, so
+/// meant for a relay on the same host or network.
+pub struct EmailNotifier {
+    host: String,
+    port: u16,
+    from: String,
+    to: Vec<String>,
+}
+
+impl EmailNotifier {
+    async fn send(&self, subject: &str, text: &str) -> Result<()> {
+        let stream = TcpStream::connect((self.host.as_str(), self.port)).await?;
+        let (read, mut write) = stream.into_split();
+        let mut read = BufReader::new(read);
+
+        smtp_reply(&mut read, 220).await?;
+        smtp_command(&mut read, &mut write, "EHLO localhost", 250).await?;
+        smtp_command(&mut read, &mut write, &format!("MAIL FROM:<{}>", self.from), 250).await?;
+        for to in self.to.iter() {
+            smtp_command(&mut read, &mut write, &format!("RCPT TO:<{}>", to), 250).await?;
+        }
+        smtp_command(&mut read, &mut write, "DATA", 354).await?;
+
+        let mut message = format!(
+            "From: <{}>\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n",
+            self.from,
+            self.to.iter().map(|to| format!("<{}>", to)).collect::<Vec<String>>().join(", "),
+            subject,
+            Utc::now().to_rfc2822(),
+        );
+        // a line starting with a dot gets another one, a lone dot ends the message
+        for line in text.lines() {
+            if line.starts_with('.') {
+                message.push('.');
+            }
+            message.push_str(line);
+            message.push_str("\r\n");
+        }
+        smtp_command(&mut read, &mut write, &format!("{}.", message), 250).await?;
+
+        smtp_command(&mut read, &mut write, "QUIT", 221).await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl Notifier for EmailNotifier {
+    fn name(&self) -> &'static str {
+        "email"
+    }
+
+    async fn notify(&self, alert: &ExistAlert, currency: &str) -> Result<()> {
+        let subject = format!("Budget alert: category {} reached {}% for {}", alert.categoryid, alert.threshold, alert.periode);
+
+        tokio::time::timeout(TIMEOUT, self.send(&subject, &summary(alert, currency)))
+            .await
+            .map_err(|_| "smtp timed out")?
+    }
+}
+
+async fn smtp_command<R, W>(read: &mut R, write: &mut W, command: &str, expected: u16) -> Result<()>
+where
+    R: AsyncBufReadExt + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    write.write_all(command.as_bytes()).await?;
+    write.write_all(b"\r\n").await?;
+    write.flush().await?;
+
+    smtp_reply(read, expected).await
+}
+
+/// Reads a reply, the last line of a multiline one has a space after its
+/// code. Any code of the class of `expected` is accepted, `251` as `250`.
+async fn smtp_reply<R: AsyncBufReadExt + Unpin>(read: &mut R, expected: u16) -> Result<()> {
+    loop {
+        let mut line = String::new();
+        if read.read_line(&mut line).await? == 0 {
+            return Err("smtp server closed the connection".into());
+        }
+
+        let code: u16 = line
+            .get(0..3)
+            .and_then(|code| code.parse().ok())
+            .ok_or_else(|| format!("unexpected smtp reply `{}`", line.trim_end()))?;
+
+        if line.as_bytes().get(3) == Some(&b'-') {
+            continue;
+        }
+
+        return match code / 100 == expected / 100 {
+            true => Ok(()),
+            false => Err(format!("smtp server answered `{}`", line.trim_end()).into()),
+        };
+    }
+}
+
+/// One line telling which budget reached which threshold.
+fn summary(alert: &ExistAlert, currency: &str) -> String {
+    let text = |amount: Amount| match amount {
+        Amount::Minor(value) => value.to_string(),
+        Amount::Decimal(value) => value,
+    };
+
+    format!(
+        "budget {} of category {} for {} reached {}% of its allocation: spent {} of {} {}",
+        alert.budgetid,
+        alert.categoryid,
+        alert.periode,
+        alert.threshold,
+        text(alert.spent.amount(currency, MoneyFormat::Decimal)),
+        text(alert.allocated.amount(currency, MoneyFormat::Decimal)),
+        currency,
+    )
+}
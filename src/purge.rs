@@ -0,0 +1,49 @@
+use crate::config::PurgeConfig;
+use crate::errors::AppError;
+use crate::models::purge::PurgeReport;
+use crate::repositories::purge::{PurgeRepo, Trait};
+
+use chrono::{Duration, Utc};
+use sqlx::mysql::MySqlPool;
+
+/// Entry point of the `purge` subcommand, a single run whether or not the
+/// background job is enabled.
+pub async fn command(pool: &MySqlPool, config: &PurgeConfig, args: &[String]) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if let Some(other) = args.first() {
+        return Err(format!("unknown purge argument `{}`", other).into());
+    }
+
+    let report = run(pool, config.retention_days).await?;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+
+    Ok(())
+}
+
+/// Starts the background job purging soft deleted rows every `interval`.
+pub fn spawn(pool: MySqlPool, config: PurgeConfig) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(config.interval());
+
+        loop {
+            interval.tick().await;
+
+            match run(&pool, config.retention_days).await {
+                Ok(report) if report.total() > 0 => log::info!("purged soft deleted rows: {:?}", report),
+                Ok(_) => {}
+                Err(err) => log::error!("purge failed: {}", err),
+            }
+        }
+    });
+}
+
+/// Hard deletes the rows soft deleted more than `retention_days` ago.
+async fn run(pool: &MySqlPool, retention_days: u32) -> Result<PurgeReport, AppError> {
+    let before = Utc::now() - Duration::days(i64::from(retention_days));
+
+    let repo = PurgeRepo::new(pool.clone());
+    let mut tx = repo.start_transaction().await?;
+    let report = tx.purge(before).await?;
+    tx.commit().await?;
+
+    Ok(report)
+}
@@ -0,0 +1,33 @@
+use crate::repositories::reconciliation::{ReconcileRepo, Trait};
+
+use std::process;
+use sqlx::mysql::MySqlPool;
+
+/// Entry point of the `reconcile` subcommand.
+///
+/// `reconcile` prints the discrepancies as JSON and exits with status 1 when
+/// there are any, `reconcile --repair` also writes the expected values back.
+pub async fn command(pool: &MySqlPool, base_currency: &str, args: &[String]) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let repair = match args.first().map(String::as_str) {
+        None => false,
+        Some("--repair") => true,
+        Some(other) => {
+            return Err(format!("unknown reconcile argument `{}`, expected --repair", other).into());
+        }
+    };
+
+    let repo = ReconcileRepo::new(pool.clone()).with_base_currency(base_currency);
+
+    // a repair is applied all at once or not at all
+    let mut tx = repo.start_transaction().await?;
+    let report = tx.reconcile(repair).await?;
+    tx.commit().await?;
+
+    println!("{}", serde_json::to_string_pretty(&report)?);
+
+    if !repair && !report.is_clean() {
+        process::exit(1);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,70 @@
+use crate::config::RecurringConfig;
+use crate::errors::AppError;
+use crate::models::recurring::RecurringReport;
+use crate::repositories::recurring::{RecurringRepo, RecurringTrait, Trait};
+
+use chrono::Utc;
+use sqlx::mysql::MySqlPool;
+
+/// Actor the audit log credits with the trxs the job posts.
+const ACTOR: &str = "recurring";
+
+/// Entry point of the `recurring` subcommand, a single run whether or not the
+/// background job is enabled.
+pub async fn command(pool: &MySqlPool, base_currency: &str, args: &[String]) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if let Some(other) = args.first() {
+        return Err(format!("unknown recurring argument `{}`", other).into());
+    }
+
+    let report = run(pool, base_currency).await?;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+
+    Ok(())
+}
+
+/// Starts the background job posting the due recurring trxs every `interval`.
+pub fn spawn(pool: MySqlPool, config: RecurringConfig, base_currency: String) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(config.interval());
+
+        loop {
+            interval.tick().await;
+
+            match run(&pool, &base_currency).await {
+                Ok(report) if report.posted > 0 || report.failed > 0 => log::info!("posted recurring transactions: {:?}", report),
+                Ok(_) => {}
+                Err(err) => log::error!("recurring run failed: {}", err),
+            }
+        }
+    });
+}
+
+/// Posts every occurrence due by now, each template in its own db transaction
+/// so one failing template does not hold back the others.
+async fn run(pool: &MySqlPool, base_currency: &str) -> Result<RecurringReport, AppError> {
+    let now = Utc::now().naive_utc();
+
+    let mut repo = RecurringRepo::new(pool.clone())
+        .with_actor(Some(String::from(ACTOR)))
+        .with_base_currency(base_currency);
+    let ids = repo.recurring_due_ids(now).await?;
+
+    let mut report = RecurringReport::default();
+    for id in ids {
+        let mut tx = repo.start_transaction().await?;
+
+        match tx.recurring_post_due(id, now).await {
+            Ok(trxs) => {
+                tx.commit().await?;
+                report.templates += 1;
+                report.posted += trxs.len() as u64;
+            },
+            Err(err) => {
+                log::error!("recurring transaction {} not posted: {}", id, err);
+                report.failed += 1;
+            },
+        }
+    }
+
+    Ok(report)
+}
@@ -1,7 +1,14 @@
 
-use crate::models::accounts::{ ExistAccount, NewAccount, UpdateAccount };
-use crate::repositories::{ Executor, UpdateQuery };
-
+use crate::models::accounts::{ AccountDeletePolicy, ExistAccount, NewAccount, UpdateAccount, validate_new_account };
+use crate::models::audit::{ AuditAction, AuditEntity };
+use crate::models::exchange_rates::{ DEFAULT_CURRENCY, parse_currency };
+use crate::models::pagination::PageParams;
+use crate::errors::AppError;
+use crate::repositories::{ Executor, UpdateQuery, last_insert_id, push_ids, push_not_deleted, push_page };
+use crate::repositories::audit;
+use crate::repositories::trxs;
+
+use std::collections::HashMap;
 use futures_util::{future::BoxFuture, FutureExt};
 use sqlx::{MySql, MySqlPool};
 use sqlx_mysql::MySqlQueryResult;
@@ -11,48 +18,71 @@ pub trait Trait: Send + Sync + AccountTrait {
     fn clone_boxed(&self) -> Box<dyn Trait>;
     async fn start_transaction(
         &self,
-    ) -> Result<Box<dyn TransactionTrait>, Box<dyn std::error::Error + Send + Sync + 'static>>;
+    ) -> Result<Box<dyn TransactionTrait>, AppError>;
 }
 
 #[async_trait::async_trait]
 pub trait TransactionTrait: Send + Sync + AccountTrait {
     async fn commit(
         self: Box<Self>,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>>;
+    ) -> Result<(), AppError>;
 }
 
 #[async_trait::async_trait]
 pub trait AccountTrait {
     async fn account_list(
         &mut self,
-    ) -> Result<Vec<ExistAccount>, Box<dyn std::error::Error + Send + Sync + 'static>>;
+        page: PageParams,
+        include_archived: bool,
+        include_deleted: bool,
+    ) -> Result<(Vec<ExistAccount>, Option<String>), AppError>;
     async fn account_detail(
         &mut self,
         id: i32,
-    ) -> Result<ExistAccount, Box<dyn std::error::Error + Send + Sync + 'static>>;
+        include_deleted: bool,
+    ) -> Result<ExistAccount, AppError>;
     async fn account_add(
         &mut self,
         account: NewAccount,
-    ) -> Result<ExistAccount, Box<dyn std::error::Error + Send + Sync + 'static>>;
+    ) -> Result<ExistAccount, AppError>;
     async fn account_update(
         &mut self,
         id: i32,
         account: UpdateAccount,
-    ) -> Result<ExistAccount, Box<dyn std::error::Error + Send + Sync + 'static>>;
+    ) -> Result<ExistAccount, AppError>;
     async fn account_delete(
         &mut self,
         id: i32,
-    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync + 'static>>;
+        policy: AccountDeletePolicy,
+    ) -> Result<bool, AppError>;
+    async fn account_restore(
+        &mut self,
+        id: i32,
+    ) -> Result<ExistAccount, AppError>;
 }
 
 #[derive(Debug, Clone)]
 pub struct AccountRepo<E = MySqlPool> {
     db: E,
+    /// Who the audit log credits with the writes.
+    actor: Option<String>,
+    /// Currency the category budgets are kept in.
+    base_currency: String,
 }
 
 impl AccountRepo {
     pub fn new(pool: MySqlPool) -> Self {
-        Self { db: pool }
+        Self { db: pool, actor: None, base_currency: String::from(DEFAULT_CURRENCY) }
+    }
+
+    pub fn with_actor(mut self, actor: Option<String>) -> Self {
+        self.actor = actor;
+        self
+    }
+
+    pub fn with_base_currency(mut self, base_currency: &str) -> Self {
+        self.base_currency = base_currency.to_string();
+        self
     }
 }
 
@@ -64,10 +94,10 @@ impl Trait for AccountRepo {
 
     async fn start_transaction(
         &self,
-    ) -> Result<Box<dyn TransactionTrait>, Box<dyn std::error::Error + Send + Sync + 'static>> {
+    ) -> Result<Box<dyn TransactionTrait>, AppError> {
         let tx = self.db.begin().await?;
 
-        Ok(Box::new(AccountRepo { db: tx }))
+        Ok(Box::new(AccountRepo { db: tx, actor: self.actor.clone(), base_currency: self.base_currency.clone() }))
     }
 }
 
@@ -75,7 +105,7 @@ impl Trait for AccountRepo {
 impl TransactionTrait for AccountRepo<sqlx::Transaction<'static, MySql>> {
     async fn commit(
         self: Box<Self>,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+    ) -> Result<(), AppError> {
         self.db.commit().await?;
 
         Ok(())
@@ -86,17 +116,24 @@ impl TransactionTrait for AccountRepo<sqlx::Transaction<'static, MySql>> {
 impl<E: 'static + Executor> AccountTrait for AccountRepo<E> {
     async fn account_list(
         &mut self,
-    ) -> Result<Vec<ExistAccount>, Box<dyn std::error::Error + Send + Sync + 'static>> {
-        let accounts = query_list_accounts(&mut self.db).await;
+        page: PageParams,
+        include_archived: bool,
+        include_deleted: bool,
+    ) -> Result<(Vec<ExistAccount>, Option<String>), AppError> {
+        let accounts = query_list_accounts(&mut self.db, &page, include_archived, include_deleted).await?;
 
-        Ok(accounts)
+        Ok(page.to_page(accounts))
     }
 
     async fn account_detail(
         &mut self,
         id: i32,
-    ) -> Result<ExistAccount, Box<dyn std::error::Error + Send + Sync + 'static>> {
-        let account = query_detail_account(&mut self.db, id).await;
+        include_deleted: bool,
+    ) -> Result<ExistAccount, AppError> {
+        let account = match include_deleted {
+            true => query_find_account(&mut self.db, id).await?,
+            false => query_detail_account(&mut self.db, id).await?,
+        };
 
         Ok(account)
     }
@@ -104,12 +141,20 @@ impl<E: 'static + Executor> AccountTrait for AccountRepo<E> {
     async fn account_add(
         &mut self,
         account: NewAccount,
-    ) -> Result<ExistAccount, Box<dyn std::error::Error + Send + Sync + 'static>> {
+    ) -> Result<ExistAccount, AppError> {
 
-        let add = query_add_account(&mut self.db, account).await;
-        let account_id = i32::try_from(add.last_insert_id()).unwrap();
+        validate_new_account(&account)?;
 
-        let account = query_detail_account(&mut self.db, account_id).await;
+        let currency = match &account.currency {
+            Some(currency) => parse_currency("currency", currency)?,
+            None => self.base_currency.clone(),
+        };
+
+        let add = query_add_account(&mut self.db, NewAccount { currency: Some(currency), ..account }).await?;
+        let account_id = last_insert_id(&add)?;
+
+        let account = query_detail_account(&mut self.db, account_id).await?;
+        audit::record(&mut self.db, &self.actor, AuditEntity::Account, account_id, AuditAction::Add, None, Some(&account)).await?;
 
         Ok(account)
     }
@@ -118,11 +163,26 @@ impl<E: 'static + Executor> AccountTrait for AccountRepo<E> {
         &mut self,
         id: i32,
         account: UpdateAccount,
-    ) -> Result<ExistAccount, Box<dyn std::error::Error + Send + Sync + 'static>> {
-        
-        let _ = query_update_account(&mut self.db, id, account).await;
+    ) -> Result<ExistAccount, AppError> {
+
+        let before = query_detail_account(&mut self.db, id).await?;
+
+        // trxs are recorded in the account currency, it is fixed once there are any
+        let account = match &account.currency {
+            Some(currency) => {
+                let currency = parse_currency("currency", currency)?;
+                if currency != before.currency && trxs::query_count_account_trxs(&mut self.db, id).await? > 0 {
+                    return Err(AppError::Conflict(format!("account {} has transactions, its currency cannot change", id)));
+                }
+                UpdateAccount { currency: Some(currency), ..account }
+            }
+            None => account,
+        };
+
+        query_update_account(&mut self.db, id, account).await?;
 
-        let account = query_detail_account(&mut self.db, id).await;
+        let account = query_detail_account(&mut self.db, id).await?;
+        audit::record(&mut self.db, &self.actor, AuditEntity::Account, id, AuditAction::Update, Some(&before), Some(&account)).await?;
 
         Ok(account)
     }
@@ -130,28 +190,148 @@ impl<E: 'static + Executor> AccountTrait for AccountRepo<E> {
     async fn account_delete(
         &mut self,
         id: i32,
-    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync + 'static>> {
+        policy: AccountDeletePolicy,
+    ) -> Result<bool, AppError> {
+
+        let to = match policy {
+            AccountDeletePolicy::Reassign(to) => Some(to),
+            _ => None,
+        };
+        trxs::lock_account_ledger(&mut self.db, id, to).await?;
+
+        // account must exist
+        let before = query_detail_account(&mut self.db, id).await?;
+
+        // an archived account keeps everything
+        if policy == AccountDeletePolicy::Archive {
+            query_archive_account(&mut self.db, id).await?;
+
+            let after = query_find_account(&mut self.db, id).await?;
+            audit::record(&mut self.db, &self.actor, AuditEntity::Account, id, AuditAction::Delete, Some(&before), Some(&after)).await?;
+
+            return Ok(true);
+        }
+
+        let ledger = trxs::query_account_ledger(&mut self.db, id).await?;
+        match policy {
+            AccountDeletePolicy::Cascade => trxs::release_account_trxs(&mut self.db, &self.base_currency, id, &ledger).await?,
+            AccountDeletePolicy::Reassign(to) => trxs::reassign_account_trxs(&mut self.db, &self.actor, id, to, &ledger).await?,
+            _ => {
+                if !ledger.is_empty() {
+                    return Err(AppError::Conflict(format!(
+                        "account {} has {} transactions, delete it with policy=cascade, reassign or archive",
+                        id,
+                        ledger.len(),
+                    )));
+                }
+            }
+        }
+
+        let res = query_delete_account(&mut self.db, id).await?;
+        if res.rows_affected() == 0 {
+            return Err(AppError::NotFound(format!("account {} not found", id)));
+        }
+
+        // cascaded trxs share the deleted_at of their account, restoring it brings them back
+        let after = query_find_account(&mut self.db, id).await?;
+        if policy == AccountDeletePolicy::Cascade {
+            if let Some(deleted_at) = after.deleted_at {
+                trxs::delete_account_trxs(&mut self.db, &self.actor, id, deleted_at, &ledger).await?;
+            }
+        }
+
+        audit::record(&mut self.db, &self.actor, AuditEntity::Account, id, AuditAction::Delete, Some(&before), Some(&after)).await?;
+
+        Ok(true)
+    }
+
+    async fn account_restore(
+        &mut self,
+        id: i32,
+    ) -> Result<ExistAccount, AppError> {
+
+        trxs::lock_account_ledger(&mut self.db, id, None).await?;
+
+        let before = query_find_account(&mut self.db, id).await?;
+        let deleted_at = match before.deleted_at {
+            Some(deleted_at) => deleted_at,
+            None => return Err(AppError::Conflict(format!("account {} is not deleted", id))),
+        };
+
+        query_restore_account(&mut self.db, id).await?;
 
-        let res = query_delete_account(&mut self.db, id).await;
-        let success_delete = res.rows_affected() != 0;
+        // trxs deleted along with the account
+        trxs::restore_account_trxs(&mut self.db, &self.actor, &self.base_currency, id, deleted_at).await?;
 
-        Ok(success_delete)
+        let account = query_detail_account(&mut self.db, id).await?;
+        audit::record(&mut self.db, &self.actor, AuditEntity::Account, id, AuditAction::Restore, Some(&before), Some(&account)).await?;
+
+        Ok(account)
     }
 }
 
 pub fn query_list_accounts<'a>(
     db: &'a mut impl Executor,
-) -> BoxFuture<'a, Vec<ExistAccount>> {
+    page: &'a PageParams,
+    include_archived: bool,
+    include_deleted: bool,
+) -> BoxFuture<'a, Result<Vec<ExistAccount>, AppError>> {
     async move {
         let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tblaccounts"#);
 
+        // archived and deleted accounts are only listed on request
+        if !include_archived {
+            query.push(" WHERE tblaccounts.archived_at IS NULL");
+        }
+        let has_where = push_not_deleted(&mut query, "tblaccounts", include_deleted, !include_archived);
+        push_page(&mut query, page, "tblaccounts", has_where);
+
         let accounts = query
             .build_query_as::<ExistAccount>()
             .fetch_all(db.as_executor())
-            .await
-            .unwrap();
+            .await?;
+
+        Ok(accounts)
+    }
+    .boxed()
+}
+
+/// Every account that is not deleted, by id.
+pub fn query_all_accounts<'a>(
+    db: &'a mut impl Executor,
+) -> BoxFuture<'a, Result<Vec<ExistAccount>, AppError>> {
+    async move {
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tblaccounts WHERE deleted_at IS NULL ORDER BY id ASC"#);
+
+        let accounts = query
+            .build_query_as::<ExistAccount>()
+            .fetch_all(db.as_executor())
+            .await?;
+
+        Ok(accounts)
+    }
+    .boxed()
+}
+
+/// Accounts with the given ids, deleted ones included, keyed by id, in a single round-trip.
+pub fn query_accounts_by_ids<'a>(
+    db: &'a mut impl Executor,
+    ids: &'a [i32],
+) -> BoxFuture<'a, Result<HashMap<i32, ExistAccount>, AppError>> {
+    async move {
+        if ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tblaccounts WHERE id IN "#);
+        push_ids(&mut query, ids);
+
+        let rows = query
+            .build_query_as::<ExistAccount>()
+            .fetch_all(db.as_executor())
+            .await?;
 
-        accounts
+        Ok(rows.into_iter().map(|row| (row.id, row)).collect())
     }
     .boxed()
 }
@@ -159,18 +339,68 @@ pub fn query_list_accounts<'a>(
 pub fn query_detail_account<'a>(
     db: &'a mut impl Executor,
     id: i32
-) -> BoxFuture<'a, ExistAccount> {
+) -> BoxFuture<'a, Result<ExistAccount, AppError>> {
     async move {
         let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tblaccounts WHERE id = "#);
 
         let accounts = query
             .push_bind(id)
+            .push(" AND deleted_at IS NULL")
             .build_query_as::<ExistAccount>()
-            .fetch_one(db.as_executor())
-            .await
-            .unwrap();
+            .fetch_optional(db.as_executor())
+            .await?;
 
-        accounts
+        accounts.ok_or_else(|| AppError::NotFound(format!("account {} not found", id)))
+    }
+    .boxed()
+}
+
+/// Locks the rows of accounts `ids`, in id order, until the db transaction
+/// ends. Every write to a ledger takes it before its first plain read: under
+/// REPEATABLE READ that read fixes the snapshot, and one taken before waiting
+/// on the lock would miss the trxs the other writer just committed.
+pub fn lock_accounts<'a>(
+    db: &'a mut impl Executor,
+    ids: &'a [i32],
+) -> BoxFuture<'a, Result<(), AppError>> {
+    async move {
+        if ids.is_empty() {
+            return Ok(());
+        }
+
+        let mut ids = ids.to_vec();
+        ids.sort_unstable();
+        ids.dedup();
+
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT id FROM tblaccounts WHERE id IN "#);
+        push_ids(&mut query, &ids);
+        query.push(" ORDER BY id ASC FOR UPDATE");
+
+        query
+            .build_query_scalar::<i32>()
+            .fetch_all(db.as_executor())
+            .await?;
+
+        Ok(())
+    }
+    .boxed()
+}
+
+/// Like `query_detail_account`, deleted accounts included.
+pub fn query_find_account<'a>(
+    db: &'a mut impl Executor,
+    id: i32
+) -> BoxFuture<'a, Result<ExistAccount, AppError>> {
+    async move {
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tblaccounts WHERE id = "#);
+
+        let accounts = query
+            .push_bind(id)
+            .build_query_as::<ExistAccount>()
+            .fetch_optional(db.as_executor())
+            .await?;
+
+        accounts.ok_or_else(|| AppError::NotFound(format!("account {} not found", id)))
     }
     .boxed()
 }
@@ -178,19 +408,20 @@ pub fn query_detail_account<'a>(
 pub fn query_add_account<'a>(
     db: &'a mut impl Executor,
     account: NewAccount,
-) -> BoxFuture<'a, MySqlQueryResult> {
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
     async move {
 
         let account_name = account.name;
         let account_balance = account.balance.to_string();
         let account_star = Into::<i32>::into(account.star).to_string();
         let account_type = account.r#type.to_string();
+        let account_currency = account.currency.unwrap_or_else(|| String::from(DEFAULT_CURRENCY));
         let account_desc: String = match account.description { 
             Some(_) => account.description.unwrap().to_string(),
             None => "".to_string()
         };
-        let values = vec![account_name, account_desc, account_star, account_type, account_balance];
-        let mut query = sqlx::QueryBuilder::new(r#"INSERT INTO tblaccounts (name, description, star, type, balance) VALUES ("#);
+        let values = [account_name, account_desc, account_star, account_type, account_currency, account_balance];
+        let mut query = sqlx::QueryBuilder::new(r#"INSERT INTO tblaccounts (name, description, star, type, currency, balance) VALUES ("#);
 
         let mut separated = query.separated(", ");
         for value in values.iter() {
@@ -201,10 +432,9 @@ pub fn query_add_account<'a>(
         let add = query
             .build()
             .execute(db.as_executor())
-            .await
-            .unwrap();
+            .await?;
 
-        add
+        Ok(add)
     }
     .boxed()
 }
@@ -213,7 +443,7 @@ pub fn update_acc_balance<'a>(
     db: &'a mut impl Executor,
     id: i32,
     amount: i64,
-) -> BoxFuture<'a, MySqlQueryResult> {
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
     async move {
 
         let mut query = sqlx::QueryBuilder::new(r#"UPDATE tblaccounts SET "#);
@@ -224,10 +454,9 @@ pub fn update_acc_balance<'a>(
         let res = query
             .build()
             .execute(db.as_executor())
-            .await
-            .unwrap();
+            .await?;
 
-        res
+        Ok(res)
     }
     .boxed()
 }
@@ -236,44 +465,51 @@ pub fn query_update_account<'a>(
     db: &'a mut impl Executor,
     id: i32,
     account: UpdateAccount,
-) -> BoxFuture<'a, MySqlQueryResult> {
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
     async move {
 
         let mut query = sqlx::QueryBuilder::new(r#"UPDATE tblaccounts SET "#);
         let mut updates: Vec<UpdateQuery> = Vec::new();
 
-        if account.name.is_some() {
+        if let Some(name) = account.name {
             updates.push(UpdateQuery {
                 key: "name".to_string(),
-                value: account.name.unwrap().to_string(),
+                value: name.to_string(),
             })
         }
 
-        if account.description.is_some() {
+        if let Some(description) = account.description {
             updates.push(UpdateQuery {
                 key: "description".to_string(),
-                value: account.description.unwrap().to_string(),
+                value: description.to_string(),
             })
         }
 
-        if account.star.is_some() {
+        if let Some(star) = account.star {
             updates.push(UpdateQuery {
                 key: "star".to_string(),
-                value: Into::<i32>::into(account.star.unwrap()).to_string(),
+                value: Into::<i32>::into(star).to_string(),
             })
         }
 
-        if account.r#type.is_some() {
+        if let Some(r#type) = account.r#type {
             updates.push(UpdateQuery {
                 key: "type".to_string(),
-                value: account.r#type.unwrap().to_string(),
+                value: r#type.to_string(),
+            })
+        }
+
+        if let Some(currency) = account.currency {
+            updates.push(UpdateQuery {
+                key: "currency".to_string(),
+                value: currency,
             })
         }
 
-        if account.balance.is_some() {
+        if let Some(balance) = account.balance {
             updates.push(UpdateQuery {
                 key: "balance".to_string(),
-                value: account.balance.unwrap().to_string(),
+                value: balance.to_string(),
             })
         }
 
@@ -287,32 +523,78 @@ pub fn query_update_account<'a>(
         separated
             .push("updated_at = current_timestamp()")
             .push_unseparated(" WHERE id = ")
-            .push_bind_unseparated(id);
+            .push_bind_unseparated(id)
+            .push_unseparated(" AND deleted_at IS NULL");
         
         let res = query.build()
             .execute(db.as_executor())
-            .await
-            .unwrap();
+            .await?;
+
+        Ok(res)
+    }
+    .boxed()
+}
+
+fn query_archive_account<'a>(
+    db: &'a mut impl Executor,
+    id: i32,
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"UPDATE tblaccounts SET "#);
+        query.push("archived_at = COALESCE(archived_at, current_timestamp())")
+            .push(" , updated_at = current_timestamp() ")
+            .push(" WHERE id = ").push_bind(id);
+
+        let res = query
+            .build()
+            .execute(db.as_executor())
+            .await?;
 
-        res
+        Ok(res)
     }
     .boxed()
 }
 
+/// Soft deletes the account, the purge job removes it for good.
 pub fn query_delete_account<'a>(
     db: &'a mut impl Executor,
     id: i32,
-) -> BoxFuture<'a, MySqlQueryResult> {
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
     async move {
 
-        let mut query = sqlx::QueryBuilder::new(r#"DELETE FROM tblaccounts WHERE id = "#);
-        let res = query.push_bind(id)
+        let mut query = sqlx::QueryBuilder::new(r#"UPDATE tblaccounts SET "#);
+        query.push("deleted_at = current_timestamp()")
+            .push(" WHERE id = ").push_bind(id)
+            .push(" AND deleted_at IS NULL");
+
+        let res = query
+            .build()
+            .execute(db.as_executor())
+            .await?;
+
+        Ok(res)
+    }
+    .boxed()
+}
+
+fn query_restore_account<'a>(
+    db: &'a mut impl Executor,
+    id: i32,
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"UPDATE tblaccounts SET "#);
+        query.push("deleted_at = NULL")
+            .push(" , updated_at = current_timestamp() ")
+            .push(" WHERE id = ").push_bind(id);
+
+        let res = query
             .build()
             .execute(db.as_executor())
-            .await
-            .unwrap();
+            .await?;
 
-        res
+        Ok(res)
     }
     .boxed()
 }
\ No newline at end of file
@@ -0,0 +1,221 @@
+use crate::models::alerts::{ AlertFilter, ExistAlert };
+use crate::models::pagination::PageParams;
+use crate::models::trx_cat_budgets::ExistTrxCatBudget;
+use crate::errors::AppError;
+use crate::repositories::{ Executor, push_page };
+
+use futures_util::{future::BoxFuture, FutureExt};
+use sqlx::MySqlPool;
+use sqlx_mysql::MySqlQueryResult;
+
+#[async_trait::async_trait]
+pub trait AlertTrait {
+    async fn alerts_list(
+        &mut self,
+        filter: AlertFilter,
+        page: PageParams,
+    ) -> Result<(Vec<ExistAlert>, Option<String>), AppError>;
+    async fn alerts_pending(
+        &mut self,
+        limit: i64,
+    ) -> Result<Vec<ExistAlert>, AppError>;
+    async fn alert_notified(
+        &mut self,
+        id: i32,
+    ) -> Result<(), AppError>;
+    async fn alert_failed(
+        &mut self,
+        id: i32,
+    ) -> Result<(), AppError>;
+}
+
+#[derive(Debug, Clone)]
+pub struct AlertRepo<E = MySqlPool> {
+    db: E,
+}
+
+impl AlertRepo {
+    pub fn new(pool: MySqlPool) -> Self {
+        Self { db: pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl<E: 'static + Executor> AlertTrait for AlertRepo<E> {
+    async fn alerts_list(
+        &mut self,
+        filter: AlertFilter,
+        page: PageParams,
+    ) -> Result<(Vec<ExistAlert>, Option<String>), AppError> {
+
+        let alerts = query_list_alerts(&mut self.db, &filter, &page).await?;
+
+        Ok(page.to_page(alerts))
+    }
+
+    async fn alerts_pending(
+        &mut self,
+        limit: i64,
+    ) -> Result<Vec<ExistAlert>, AppError> {
+
+        let alerts = query_pending_alerts(&mut self.db, limit).await?;
+
+        Ok(alerts)
+    }
+
+    async fn alert_notified(
+        &mut self,
+        id: i32,
+    ) -> Result<(), AppError> {
+
+        query_set_alert_notified(&mut self.db, id).await?;
+
+        Ok(())
+    }
+
+    async fn alert_failed(
+        &mut self,
+        id: i32,
+    ) -> Result<(), AppError> {
+
+        query_set_alert_attempted(&mut self.db, id).await?;
+
+        Ok(())
+    }
+}
+
+/// Raises an alert for each threshold `spent` of `budget` has reached, in the
+/// db transaction of the change itself. A threshold already raised for the
+/// budget is left alone, so it alerts once per period.
+pub fn record_crossed<'a>(
+    db: &'a mut impl Executor,
+    budget: &'a ExistTrxCatBudget,
+) -> BoxFuture<'a, Result<(), AppError>> {
+    async move {
+
+        for threshold in budget.crossed_thresholds()? {
+            query_add_alert(db, budget, threshold).await?;
+        }
+
+        Ok(())
+    }
+    .boxed()
+}
+
+fn query_add_alert<'a>(
+    db: &'a mut impl Executor,
+    budget: &'a ExistTrxCatBudget,
+    threshold: u32,
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
+    async move {
+        // the unique (budgetid, threshold) index keeps the first alert of a threshold
+        let mut query = sqlx::QueryBuilder::new(r#"INSERT IGNORE INTO tblbudgetalerts (budgetid, categoryid, periode, threshold, allocated, spent) VALUES ("#);
+
+        let mut separated = query.separated(", ");
+        separated.push_bind(budget.id);
+        separated.push_bind(budget.categoryid);
+        separated.push_bind(&budget.periode);
+        separated.push_bind(threshold);
+        separated.push_bind(&budget.allocated);
+        separated.push_bind(&budget.spent);
+        separated.push_unseparated(") ");
+
+        let add = query
+            .build()
+            .execute(db.as_executor())
+            .await?;
+
+        Ok(add)
+    }
+    .boxed()
+}
+
+fn query_list_alerts<'a>(
+    db: &'a mut impl Executor,
+    filter: &'a AlertFilter,
+    page: &'a PageParams,
+) -> BoxFuture<'a, Result<Vec<ExistAlert>, AppError>> {
+    async move {
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tblbudgetalerts WHERE 1 = 1"#);
+
+        if let Some(budgetid) = filter.budgetid {
+            query.push(" AND budgetid = ").push_bind(budgetid);
+        }
+
+        if let Some(categoryid) = filter.categoryid {
+            query.push(" AND categoryid = ").push_bind(categoryid);
+        }
+
+        match filter.notified {
+            Some(true) => { query.push(" AND notified_at IS NOT NULL"); },
+            Some(false) => { query.push(" AND notified_at IS NULL"); },
+            None => {},
+        }
+
+        push_page(&mut query, page, "tblbudgetalerts", true);
+
+        let alerts = query
+            .build_query_as::<ExistAlert>()
+            .fetch_all(db.as_executor())
+            .await?;
+
+        Ok(alerts)
+    }
+    .boxed()
+}
+
+/// Pending alerts never tried first, then those tried the longest ago.
+fn query_pending_alerts<'a>(
+    db: &'a mut impl Executor,
+    limit: i64,
+) -> BoxFuture<'a, Result<Vec<ExistAlert>, AppError>> {
+    async move {
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tblbudgetalerts WHERE notified_at IS NULL
+            ORDER BY attempted_at IS NOT NULL ASC, attempted_at ASC, id ASC LIMIT "#);
+        query.push_bind(limit);
+
+        let alerts = query
+            .build_query_as::<ExistAlert>()
+            .fetch_all(db.as_executor())
+            .await?;
+
+        Ok(alerts)
+    }
+    .boxed()
+}
+
+fn query_set_alert_notified<'a>(
+    db: &'a mut impl Executor,
+    id: i32,
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
+    async move {
+        let mut query = sqlx::QueryBuilder::new(r#"UPDATE tblbudgetalerts SET notified_at = current_timestamp() WHERE id = "#);
+        query.push_bind(id);
+
+        let res = query
+            .build()
+            .execute(db.as_executor())
+            .await?;
+
+        Ok(res)
+    }
+    .boxed()
+}
+
+fn query_set_alert_attempted<'a>(
+    db: &'a mut impl Executor,
+    id: i32,
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
+    async move {
+        let mut query = sqlx::QueryBuilder::new(r#"UPDATE tblbudgetalerts SET attempts = attempts + 1, attempted_at = current_timestamp() WHERE id = "#);
+        query.push_bind(id);
+
+        let res = query
+            .build()
+            .execute(db.as_executor())
+            .await?;
+
+        Ok(res)
+    }
+    .boxed()
+}
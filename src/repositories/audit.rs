@@ -0,0 +1,135 @@
+
+use crate::models::audit::{ AuditAction, AuditEntity, AuditFilter, ExistAudit, NewAudit, Snapshot, validate_audit_filter };
+use crate::models::pagination::PageParams;
+use crate::errors::AppError;
+use crate::repositories::{ Executor, push_page };
+
+use futures_util::{future::BoxFuture, FutureExt};
+use sqlx::MySqlPool;
+use sqlx_mysql::MySqlQueryResult;
+
+#[async_trait::async_trait]
+pub trait AuditTrait {
+    async fn audit_list(
+        &mut self,
+        filter: AuditFilter,
+        page: PageParams,
+    ) -> Result<(Vec<ExistAudit>, Option<String>), AppError>;
+}
+
+#[derive(Debug, Clone)]
+pub struct AuditRepo<E = MySqlPool> {
+    db: E,
+}
+
+impl AuditRepo {
+    pub fn new(pool: MySqlPool) -> Self {
+        Self { db: pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl<E: 'static + Executor> AuditTrait for AuditRepo<E> {
+    async fn audit_list(
+        &mut self,
+        filter: AuditFilter,
+        page: PageParams,
+    ) -> Result<(Vec<ExistAudit>, Option<String>), AppError> {
+
+        validate_audit_filter(&filter)?;
+
+        let entries = query_list_audit(&mut self.db, &filter, &page).await?;
+
+        Ok(page.to_page(entries))
+    }
+}
+
+/// Logs `action` on an entity with its state before and after, in the db
+/// transaction of the change itself.
+pub fn record<'a, T: Snapshot>(
+    db: &'a mut impl Executor,
+    actor: &Option<String>,
+    entity: AuditEntity,
+    entityid: i32,
+    action: AuditAction,
+    before: Option<&T>,
+    after: Option<&T>,
+) -> BoxFuture<'a, Result<(), AppError>> {
+    let audit = NewAudit {
+        entity,
+        entityid,
+        action,
+        actor: actor.clone(),
+        before: before.map(Snapshot::snapshot),
+        after: after.map(Snapshot::snapshot),
+    };
+
+    async move {
+        query_add_audit(db, audit).await?;
+
+        Ok(())
+    }
+    .boxed()
+}
+
+fn query_add_audit<'a>(
+    db: &'a mut impl Executor,
+    audit: NewAudit,
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
+    async move {
+        let mut query = sqlx::QueryBuilder::new(r#"INSERT INTO tblauditlog (entity, entityid, action, actor, before_json, after_json) VALUES ("#);
+
+        let mut separated = query.separated(", ");
+        separated.push_bind(audit.entity.as_str());
+        separated.push_bind(audit.entityid);
+        separated.push_bind(audit.action.as_str());
+        separated.push_bind(audit.actor);
+        separated.push_bind(audit.before.map(|value| value.to_string()));
+        separated.push_bind(audit.after.map(|value| value.to_string()));
+        separated.push_unseparated(") ");
+
+        let add = query
+            .build()
+            .execute(db.as_executor())
+            .await?;
+
+        Ok(add)
+    }
+    .boxed()
+}
+
+fn query_list_audit<'a>(
+    db: &'a mut impl Executor,
+    filter: &'a AuditFilter,
+    page: &'a PageParams,
+) -> BoxFuture<'a, Result<Vec<ExistAudit>, AppError>> {
+    async move {
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tblauditlog WHERE 1 = 1"#);
+
+        if let Some(entity) = filter.entity {
+            query.push(" AND entity = ").push_bind(entity.as_str());
+        }
+
+        if let Some(entityid) = filter.entityid {
+            query.push(" AND entityid = ").push_bind(entityid);
+        }
+
+        if let Some(from) = filter.from {
+            query.push(" AND created_at >= ").push_bind(from);
+        }
+
+        if let Some(to) = filter.to {
+            query.push(" AND created_at <= ").push_bind(to);
+        }
+
+        push_page(&mut query, page, "tblauditlog", true);
+
+        let entries = query
+            .build_query_as::<ExistAudit>()
+            .fetch_all(db.as_executor())
+            .await?;
+
+        Ok(entries)
+    }
+    .boxed()
+}
@@ -1,8 +1,19 @@
 
-use crate::models::cat_types::{ ExistCatType, ExistCatTypeWithBudget, AddCatType, UpdateCatType };
-use crate::repositories::{ Executor, UpdateQuery };
-
+use crate::models::cat_types::{ Direction, ExistCatType, ExistCatTypeWithBudget, AddCatType, UpdateCatType, validate_add_cat_type, validate_update_cat_type };
+use crate::models::audit::{ AuditAction, AuditEntity };
+use crate::models::exchange_rates::DEFAULT_CURRENCY;
+use crate::models::money::Money;
+use crate::models::trx_cat_budgets::ExistTrxCatBudget;
+use crate::errors::AppError;
+use crate::repositories::{ Executor, UpdateQuery, last_insert_id, push_ids, push_not_deleted };
+use crate::repositories::audit;
+use crate::repositories::exchange_rates;
+use crate::repositories::trx_cat_budgets;
+use crate::repositories::trx_cats;
+
+use std::collections::HashMap;
 use futures_util::{future::BoxFuture, FutureExt};
+use sqlx::types::chrono::Utc;
 use sqlx::{MySql, MySqlPool};
 use sqlx_mysql::MySqlQueryResult;
 
@@ -11,14 +22,14 @@ pub trait Trait: Send + Sync + CatTypeTrait {
     fn clone_boxed(&self) -> Box<dyn Trait>;
     async fn start_transaction(
         &self,
-    ) -> Result<Box<dyn TransactionTrait>, Box<dyn std::error::Error + Send + Sync + 'static>>;
+    ) -> Result<Box<dyn TransactionTrait>, AppError>;
 }
 
 #[async_trait::async_trait]
 pub trait TransactionTrait: Send + Sync + CatTypeTrait {
     async fn commit(
         self: Box<Self>,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>>;
+    ) -> Result<(), AppError>;
 }
 
 #[async_trait::async_trait]
@@ -26,38 +37,68 @@ pub trait CatTypeTrait {
 
     async fn cat_types_list(
         &mut self,
-    ) -> Result<Vec<ExistCatTypeWithBudget>, Box<dyn std::error::Error + Send + Sync + 'static>>;
+        include_deleted: bool,
+        currency: Option<String>,
+    ) -> Result<Vec<ExistCatTypeWithBudget>, AppError>;
 
     async fn cat_type_detail(
         &mut self,
         id: i32,
-    ) -> Result<ExistCatTypeWithBudget, Box<dyn std::error::Error + Send + Sync + 'static>>;
+        include_deleted: bool,
+        currency: Option<String>,
+    ) -> Result<ExistCatTypeWithBudget, AppError>;
 
     async fn cat_type_add(
         &mut self,
         cat_type: AddCatType,
-    ) -> Result<ExistCatTypeWithBudget, Box<dyn std::error::Error + Send + Sync + 'static>>;
+    ) -> Result<ExistCatTypeWithBudget, AppError>;
 
     async fn cat_type_update(
         &mut self,
         id: i32,
         cat_type: UpdateCatType,
-    ) -> Result<ExistCatTypeWithBudget, Box<dyn std::error::Error + Send + Sync + 'static>>;
+    ) -> Result<ExistCatTypeWithBudget, AppError>;
 
     async fn cat_type_delete(
         &mut self,
         id: i32,
-    ) -> Result<ExistCatTypeWithBudget, Box<dyn std::error::Error + Send + Sync + 'static>>;
+    ) -> Result<ExistCatTypeWithBudget, AppError>;
+
+    async fn cat_type_restore(
+        &mut self,
+        id: i32,
+    ) -> Result<ExistCatTypeWithBudget, AppError>;
 }
 
 #[derive(Debug, Clone)]
 pub struct CatTypeRepo<E = MySqlPool> {
     db: E,
+    /// Who the audit log credits with the writes.
+    actor: Option<String>,
+    /// Currency the category budgets are kept in.
+    base_currency: String,
+    /// Whether budget `spent` and `available` are computed from the trxs when read.
+    derived_budgets: bool,
 }
 
 impl CatTypeRepo {
     pub fn new(pool: MySqlPool) -> Self {
-        Self { db: pool }
+        Self { db: pool, actor: None, base_currency: String::from(DEFAULT_CURRENCY), derived_budgets: false }
+    }
+
+    pub fn with_actor(mut self, actor: Option<String>) -> Self {
+        self.actor = actor;
+        self
+    }
+
+    pub fn with_base_currency(mut self, base_currency: &str) -> Self {
+        self.base_currency = base_currency.to_string();
+        self
+    }
+
+    pub fn with_derived_budgets(mut self, derived_budgets: bool) -> Self {
+        self.derived_budgets = derived_budgets;
+        self
     }
 }
 
@@ -69,10 +110,10 @@ impl Trait for CatTypeRepo {
 
     async fn start_transaction(
         &self,
-    ) -> Result<Box<dyn TransactionTrait>, Box<dyn std::error::Error + Send + Sync + 'static>> {
+    ) -> Result<Box<dyn TransactionTrait>, AppError> {
         let tx = self.db.begin().await?;
 
-        Ok(Box::new(CatTypeRepo { db: tx }))
+        Ok(Box::new(CatTypeRepo { db: tx, actor: self.actor.clone(), base_currency: self.base_currency.clone(), derived_budgets: self.derived_budgets }))
     }
 }
 
@@ -80,7 +121,7 @@ impl Trait for CatTypeRepo {
 impl TransactionTrait for CatTypeRepo<sqlx::Transaction<'static, MySql>> {
     async fn commit(
         self: Box<Self>,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+    ) -> Result<(), AppError> {
         self.db.commit().await?;
 
         Ok(())
@@ -92,30 +133,60 @@ impl<E: 'static + Executor> CatTypeTrait for CatTypeRepo<E> {
 
     async fn cat_types_list(
         &mut self,
-    ) -> Result<Vec<ExistCatTypeWithBudget>, Box<dyn std::error::Error + Send + Sync + 'static>> {
-        let cat_types = query_list_cat_types(&mut self.db).await;
+        include_deleted: bool,
+        currency: Option<String>,
+    ) -> Result<Vec<ExistCatTypeWithBudget>, AppError> {
+        let mut cat_types = query_list_cat_types(&mut self.db, include_deleted).await?;
+
+        if self.derived_budgets {
+            derive_totals(&mut self.db, &mut cat_types, &self.base_currency).await?;
+        }
+
+        // budget totals are kept in the base currency
+        if let Some(currency) = currency {
+            for cat_type in cat_types.iter_mut() {
+                convert_totals(&mut self.db, cat_type, &self.base_currency, &currency).await?;
+            }
+        }
+
         Ok(cat_types)
     }
 
     async fn cat_type_detail(
         &mut self,
         id: i32,
-    ) -> Result<ExistCatTypeWithBudget, Box<dyn std::error::Error + Send + Sync + 'static>> {
+        include_deleted: bool,
+        currency: Option<String>,
+    ) -> Result<ExistCatTypeWithBudget, AppError> {
         // detail cat type
-        let cat_types = query_detail_cat_type(&mut self.db, id).await;
-        Ok(cat_types)
+        let mut cat_type = query_detail_cat_type(&mut self.db, id, include_deleted).await?;
+
+        if self.derived_budgets {
+            derive_totals(&mut self.db, std::slice::from_mut(&mut cat_type), &self.base_currency).await?;
+        }
+
+        if let Some(currency) = currency {
+            convert_totals(&mut self.db, &mut cat_type, &self.base_currency, &currency).await?;
+        }
+
+        Ok(cat_type)
     }
 
     async fn cat_type_add(
         &mut self,
         cat_type: AddCatType,
-    ) -> Result<ExistCatTypeWithBudget, Box<dyn std::error::Error + Send + Sync + 'static>> {
+    ) -> Result<ExistCatTypeWithBudget, AppError> {
+        validate_add_cat_type(&cat_type)?;
+
         // add cat type
-        let add = query_add_cat_type(&mut self.db, cat_type).await;
-        let cat_type_id = i32::try_from(add.last_insert_id()).unwrap();
+        let add = query_add_cat_type(&mut self.db, cat_type).await?;
+        let cat_type_id = last_insert_id(&add)?;
+
+        let after = query_find_type(&mut self.db, cat_type_id).await?;
+        audit::record(&mut self.db, &self.actor, AuditEntity::CatType, cat_type_id, AuditAction::Add, None, Some(&after)).await?;
 
         // detail cat type
-        let cat_type = query_detail_cat_type(&mut self.db, cat_type_id).await;
+        let cat_type = query_detail_cat_type(&mut self.db, cat_type_id, false).await?;
         Ok(cat_type)
     }
 
@@ -123,71 +194,196 @@ impl<E: 'static + Executor> CatTypeTrait for CatTypeRepo<E> {
         &mut self,
         id: i32,
         cat_type: UpdateCatType,
-    ) -> Result<ExistCatTypeWithBudget, Box<dyn std::error::Error + Send + Sync + 'static>> {
+    ) -> Result<ExistCatTypeWithBudget, AppError> {
+        validate_update_cat_type(&cat_type)?;
+        let before = query_find_type(&mut self.db, id).await?;
+
         // update cat type
-        let _ = query_update_cat_type(&mut self.db, id, cat_type).await;
+        query_update_cat_type(&mut self.db, id, cat_type).await?;
+
+        // trxs are charged and released by the direction of the type at the time
+        let after = query_find_type(&mut self.db, id).await?;
+        if after.direction != before.direction {
+            let ids = trx_cats::query_trx_cat_ids_by_type(&mut self.db, id).await?;
+            trx_cat_budgets::rebuild_category_budgets(&mut self.db, &self.base_currency, &ids).await?;
+        }
+        audit::record(&mut self.db, &self.actor, AuditEntity::CatType, id, AuditAction::Update, Some(&before), Some(&after)).await?;
 
         // cat type detail
-        let cat_type = query_detail_cat_type(&mut self.db, id).await;
+        let cat_type = query_detail_cat_type(&mut self.db, id, false).await?;
         Ok(cat_type)
     }
 
     async fn cat_type_delete(
         &mut self,
         id: i32,
-    ) -> Result<ExistCatTypeWithBudget, Box<dyn std::error::Error + Send + Sync + 'static>> {
+    ) -> Result<ExistCatTypeWithBudget, AppError> {
         // cat type detail
-        let cat_type = query_detail_cat_type(&mut self.db, id).await;
+        let cat_type = query_detail_cat_type(&mut self.db, id, false).await?;
+        // categories of the type have to be deleted first
+        let count = trx_cats::query_count_trx_cats_by_type(&mut self.db, id).await?;
+        if count > 0 {
+            return Err(AppError::Conflict(format!("category type {} still has {} categories", id, count)));
+        }
+        let before = query_find_type(&mut self.db, id).await?;
         // delete cat type
-        let _ = query_delete_cat_type(&mut self.db, id).await;
+        query_delete_cat_type(&mut self.db, id).await?;
+        let after = query_find_type(&mut self.db, id).await?;
+        audit::record(&mut self.db, &self.actor, AuditEntity::CatType, id, AuditAction::Delete, Some(&before), Some(&after)).await?;
+        Ok(cat_type)
+    }
+
+    async fn cat_type_restore(
+        &mut self,
+        id: i32,
+    ) -> Result<ExistCatTypeWithBudget, AppError> {
+        // cat type detail
+        let cat_type = query_find_type(&mut self.db, id).await?;
+        if cat_type.deleted_at.is_none() {
+            return Err(AppError::Conflict(format!("category type {} is not deleted", id)));
+        }
+        // restore cat type
+        query_restore_cat_type(&mut self.db, id).await?;
+        let after = query_find_type(&mut self.db, id).await?;
+        audit::record(&mut self.db, &self.actor, AuditEntity::CatType, id, AuditAction::Restore, Some(&cat_type), Some(&after)).await?;
+        let cat_type = query_detail_cat_type(&mut self.db, id, false).await?;
         Ok(cat_type)
     }
 }
 
+/// Sums the budget totals of the cat types again, `spent` and `available` of
+/// each budget computed from the trxs, those of every type at once.
+fn derive_totals<'a>(
+    db: &'a mut impl Executor,
+    cat_types: &'a mut [ExistCatTypeWithBudget],
+    base_currency: &'a str,
+) -> BoxFuture<'a, Result<(), AppError>> {
+    async move {
+
+        let mut type_budgets: Vec<Vec<ExistTrxCatBudget>> = Vec::new();
+        for cat_type in cat_types.iter() {
+            type_budgets.push(trx_cat_budgets::query_latest_budgets_by_typeid(db, cat_type.id).await?);
+        }
+        trx_cat_budgets::derive_budgets(db, base_currency, type_budgets.iter_mut().flatten()).await?;
+
+        for (cat_type, budgets) in cat_types.iter_mut().zip(type_budgets.iter()) {
+            if budgets.is_empty() {
+                continue;
+            }
+
+            let mut allocated = Money::default();
+            let mut spent = Money::default();
+            let mut available = Money::default();
+            for budget in budgets.iter() {
+                allocated = &allocated + &budget.allocated;
+                spent = &spent + &budget.spent;
+                available = &available + &budget.available;
+            }
+
+            cat_type.allocated = Some(allocated);
+            cat_type.spent = Some(spent);
+            cat_type.available = Some(available);
+        }
+
+        Ok(())
+    }
+    .boxed()
+}
+
+/// Converts the budget totals of a cat type from `from` to `to` at the latest rate.
+fn convert_totals<'a>(
+    db: &'a mut impl Executor,
+    cat_type: &'a mut ExistCatTypeWithBudget,
+    from: &'a str,
+    to: &'a str,
+) -> BoxFuture<'a, Result<(), AppError>> {
+    async move {
+
+        let today = Utc::now().date_naive();
+        for total in [&mut cat_type.allocated, &mut cat_type.spent, &mut cat_type.available] {
+            if let Some(value) = total.take() {
+                let value = exchange_rates::convert(db, value.to_minor()?, from, to, today).await?;
+                *total = Some(Money::from_minor(value));
+            }
+        }
+
+        Ok(())
+    }
+    .boxed()
+}
+
 fn query_list_cat_types<'a>(
     db: &'a mut impl Executor,
-) -> BoxFuture<'a, Vec<ExistCatTypeWithBudget>> {
+    include_deleted: bool,
+) -> BoxFuture<'a, Result<Vec<ExistCatTypeWithBudget>, AppError>> {
     async move {
+        // totals only count categories and budgets that are not deleted
         let mut query = sqlx::QueryBuilder::new(r#"SELECT 
             t.*, SUM(t3.allocated) as allocated, SUM(t3.spent) as spent, SUM(t3.available) as available
             FROM tblcategorytypes t 
-            LEFT JOIN tbltransactioncategories t2 ON t2.typeid = t.id 
+            LEFT JOIN tbltransactioncategories t2 ON t2.typeid = t.id AND t2.deleted_at IS NULL
             LEFT JOIN tblcategorybudgets t3 ON t3.categoryid = t2.id AND t3.id = (
-                SELECT MAX(budget.id) from tblcategorybudgets budget WHERE budget.categoryid = t2.id
-            )
-            GROUP by t.id 
-            ORDER by t.id ASC"#);
+                SELECT MAX(budget.id) from tblcategorybudgets budget WHERE budget.categoryid = t2.id AND budget.deleted_at IS NULL
+            )"#);
+
+        push_not_deleted(&mut query, "t", include_deleted, false);
+        query.push(" GROUP by t.id ORDER by t.id ASC");
 
         let cat_types = query
             .build_query_as::<ExistCatTypeWithBudget>()
             .fetch_all(db.as_executor())
-            .await
-            .unwrap();
+            .await?;
 
-        cat_types
+        Ok(cat_types)
     }
     .boxed()
 }
 
 fn query_detail_cat_type<'a>(
     db: &'a mut impl Executor,
-    id: i32
-) -> BoxFuture<'a, ExistCatTypeWithBudget> {
+    id: i32,
+    include_deleted: bool,
+) -> BoxFuture<'a, Result<ExistCatTypeWithBudget, AppError>> {
     async move {
         let mut query = sqlx::QueryBuilder::new(r#"SELECT t.*, SUM(t3.allocated) as allocated, SUM(t3.spent) as spent, SUM(t3.available) as available
             FROM tblcategorytypes t 
-            LEFT JOIN tbltransactioncategories t2 ON t2.typeid = t.id 
-            LEFT JOIN tblcategorybudgets t3 ON t3.categoryid = t2.id AND t3.id = (SELECT MAX(budget.id) from tblcategorybudgets budget WHERE budget.categoryid = t2.id)
+            LEFT JOIN tbltransactioncategories t2 ON t2.typeid = t.id AND t2.deleted_at IS NULL
+            LEFT JOIN tblcategorybudgets t3 ON t3.categoryid = t2.id AND t3.id = (SELECT MAX(budget.id) from tblcategorybudgets budget WHERE budget.categoryid = t2.id AND budget.deleted_at IS NULL)
             WHERE t.id = "#);
 
+        query.push_bind(id);
+        push_not_deleted(&mut query, "t", include_deleted, true);
+
         let cat_type = query
-            .push_bind(id)
+            .push(" GROUP by t.id")
             .build_query_as::<ExistCatTypeWithBudget>()
-            .fetch_one(db.as_executor())
-            .await
-            .unwrap();
+            .fetch_optional(db.as_executor())
+            .await?;
 
-        cat_type
+        cat_type.ok_or_else(|| AppError::NotFound(format!("category type {} not found", id)))
+    }
+    .boxed()
+}
+
+/// Category types with the given ids, deleted ones included, keyed by id, in a single round-trip.
+pub fn query_types_by_ids<'a>(
+    db: &'a mut impl Executor,
+    ids: &'a [i32],
+) -> BoxFuture<'a, Result<HashMap<i32, ExistCatType>, AppError>> {
+    async move {
+        if ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tblcategorytypes WHERE id IN "#);
+        push_ids(&mut query, ids);
+
+        let rows = query
+            .build_query_as::<ExistCatType>()
+            .fetch_all(db.as_executor())
+            .await?;
+
+        Ok(rows.into_iter().map(|row| (row.id, row)).collect())
     }
     .boxed()
 }
@@ -195,18 +391,37 @@ fn query_detail_cat_type<'a>(
 pub fn query_detail_type<'a>(
     db: &'a mut impl Executor,
     id: i32
-) -> BoxFuture<'a, ExistCatType> {
+) -> BoxFuture<'a, Result<ExistCatType, AppError>> {
+    async move {
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tblcategorytypes WHERE id = "#);
+
+        let cat_type = query
+            .push_bind(id)
+            .push(" AND deleted_at IS NULL")
+            .build_query_as::<ExistCatType>()
+            .fetch_optional(db.as_executor())
+            .await?;
+
+        cat_type.ok_or_else(|| AppError::NotFound(format!("category type {} not found", id)))
+    }
+    .boxed()
+}
+
+/// Like `query_detail_type`, deleted category types included.
+pub fn query_find_type<'a>(
+    db: &'a mut impl Executor,
+    id: i32
+) -> BoxFuture<'a, Result<ExistCatType, AppError>> {
     async move {
         let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tblcategorytypes WHERE id = "#);
 
         let cat_type = query
             .push_bind(id)
             .build_query_as::<ExistCatType>()
-            .fetch_one(db.as_executor())
-            .await
-            .unwrap();
+            .fetch_optional(db.as_executor())
+            .await?;
 
-        cat_type
+        cat_type.ok_or_else(|| AppError::NotFound(format!("category type {} not found", id)))
     }
     .boxed()
 }
@@ -214,7 +429,7 @@ pub fn query_detail_type<'a>(
 fn query_add_cat_type<'a>(
     db: &'a mut impl Executor,
     cat_type: AddCatType,
-) -> BoxFuture<'a, MySqlQueryResult> {
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
     async move {
 
         let desc: String = match cat_type.description { 
@@ -222,13 +437,19 @@ fn query_add_cat_type<'a>(
             None => "".to_string()
         };
 
+        let direction = match cat_type.direction {
+            Some(direction) => direction,
+            None => Direction::default().as_str().to_string(),
+        };
+
         let values = vec![
             cat_type.r#type.to_string(),
+            direction,
             desc,
             cat_type.icon.to_string(),
         ];
 
-        let mut query = sqlx::QueryBuilder::new(r#"INSERT INTO tblcategorytypes (type, description, icon) VALUES ("#);
+        let mut query = sqlx::QueryBuilder::new(r#"INSERT INTO tblcategorytypes (type, direction, description, icon) VALUES ("#);
 
         let mut separated = query.separated(", ");
         for value in values.iter() {
@@ -239,10 +460,9 @@ fn query_add_cat_type<'a>(
         let add = query
             .build()
             .execute(db.as_executor())
-            .await
-            .unwrap();
+            .await?;
 
-        add
+        Ok(add)
     }
     .boxed()
 }
@@ -251,30 +471,37 @@ fn query_update_cat_type<'a>(
     db: &'a mut impl Executor,
     id: i32,
     cat_type: UpdateCatType,
-) -> BoxFuture<'a, MySqlQueryResult> {
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
     async move {
 
         let mut query = sqlx::QueryBuilder::new(r#"UPDATE tblcategorytypes SET "#);
         let mut updates: Vec<UpdateQuery> = Vec::new();
 
-        if cat_type.r#type.is_some() {
+        if let Some(r#type) = cat_type.r#type {
             updates.push(UpdateQuery {
                 key: "type".to_string(),
-                value: cat_type.r#type.unwrap().to_string(),
+                value: r#type.to_string(),
             })
         }
 
-        if cat_type.description.is_some() {
+        if let Some(direction) = cat_type.direction {
+            updates.push(UpdateQuery {
+                key: "direction".to_string(),
+                value: direction.to_string(),
+            })
+        }
+
+        if let Some(description) = cat_type.description {
             updates.push(UpdateQuery {
                 key: "description".to_string(),
-                value: cat_type.description.unwrap().to_string(),
+                value: description.to_string(),
             })
         }
 
-        if cat_type.icon.is_some() {
+        if let Some(icon) = cat_type.icon {
             updates.push(UpdateQuery {
                 key: "icon".to_string(),
-                value: cat_type.icon.unwrap().to_string(),
+                value: icon.to_string(),
             })
         }
 
@@ -288,14 +515,14 @@ fn query_update_cat_type<'a>(
         separated
             .push("updated_at = current_timestamp()")
             .push_unseparated(" WHERE id = ")
-            .push_bind_unseparated(id);
+            .push_bind_unseparated(id)
+            .push_unseparated(" AND deleted_at IS NULL");
         
         let res = query.build()
             .execute(db.as_executor())
-            .await
-            .unwrap();
+            .await?;
 
-        res
+        Ok(res)
     }
     .boxed()
 }
@@ -303,17 +530,41 @@ fn query_update_cat_type<'a>(
 fn query_delete_cat_type<'a>(
     db: &'a mut impl Executor,
     id: i32,
-) -> BoxFuture<'a, MySqlQueryResult> {
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
     async move {
 
-        let mut query = sqlx::QueryBuilder::new(r#"DELETE FROM tblcategorytypes WHERE id = "#);
-        let res = query.push_bind(id)
+        let mut query = sqlx::QueryBuilder::new(r#"UPDATE tblcategorytypes SET "#);
+        query.push("deleted_at = current_timestamp()")
+            .push(" WHERE id = ").push_bind(id)
+            .push(" AND deleted_at IS NULL");
+
+        let res = query
+            .build()
+            .execute(db.as_executor())
+            .await?;
+
+        Ok(res)
+    }
+    .boxed()
+}
+
+fn query_restore_cat_type<'a>(
+    db: &'a mut impl Executor,
+    id: i32,
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"UPDATE tblcategorytypes SET "#);
+        query.push("deleted_at = NULL")
+            .push(" , updated_at = current_timestamp() ")
+            .push(" WHERE id = ").push_bind(id);
+
+        let res = query
             .build()
             .execute(db.as_executor())
-            .await
-            .unwrap();
+            .await?;
 
-        res
+        Ok(res)
     }
     .boxed()
 }
\ No newline at end of file
@@ -0,0 +1,404 @@
+
+use crate::models::exchange_rates::{ ExistExchangeRate, ExchangeRateFilter, ImportReport, NewExchangeRate, UpdateExchangeRate };
+use crate::models::exchange_rates::{ convert_amount, parse_currency, parse_rate, parse_rate_date, validate_new_rate };
+use crate::models::pagination::PageParams;
+use crate::errors::AppError;
+use crate::repositories::{ Executor, push_page };
+
+use futures_util::{future::BoxFuture, FutureExt};
+use sqlx::{MySql, MySqlPool};
+use sqlx::types::BigDecimal;
+use sqlx::types::chrono::NaiveDate;
+use sqlx_mysql::MySqlQueryResult;
+
+#[async_trait::async_trait]
+pub trait Trait: Send + Sync + ExchangeRateTrait {
+    async fn start_transaction(
+        &self,
+    ) -> Result<Box<dyn TransactionTrait>, AppError>;
+}
+
+#[async_trait::async_trait]
+pub trait TransactionTrait: Send + Sync + ExchangeRateTrait {
+    async fn commit(
+        self: Box<Self>,
+    ) -> Result<(), AppError>;
+}
+
+#[async_trait::async_trait]
+pub trait ExchangeRateTrait {
+    async fn rates_list(
+        &mut self,
+        filter: ExchangeRateFilter,
+        page: PageParams,
+    ) -> Result<(Vec<ExistExchangeRate>, Option<String>), AppError>;
+    async fn rate_detail(
+        &mut self,
+        id: i32,
+    ) -> Result<ExistExchangeRate, AppError>;
+    async fn rate_add(
+        &mut self,
+        rate: NewExchangeRate,
+    ) -> Result<ExistExchangeRate, AppError>;
+    async fn rate_update(
+        &mut self,
+        id: i32,
+        rate: UpdateExchangeRate,
+    ) -> Result<ExistExchangeRate, AppError>;
+    async fn rate_delete(
+        &mut self,
+        id: i32,
+    ) -> Result<ExistExchangeRate, AppError>;
+    async fn rates_import(
+        &mut self,
+        rates: Vec<NewExchangeRate>,
+    ) -> Result<ImportReport, AppError>;
+}
+
+#[derive(Debug, Clone)]
+pub struct ExchangeRateRepo<E = MySqlPool> {
+    db: E,
+}
+
+impl ExchangeRateRepo {
+    pub fn new(pool: MySqlPool) -> Self {
+        Self { db: pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl Trait for ExchangeRateRepo {
+    async fn start_transaction(
+        &self,
+    ) -> Result<Box<dyn TransactionTrait>, AppError> {
+        let tx = self.db.begin().await?;
+
+        Ok(Box::new(ExchangeRateRepo { db: tx }))
+    }
+}
+
+#[async_trait::async_trait]
+impl TransactionTrait for ExchangeRateRepo<sqlx::Transaction<'static, MySql>> {
+    async fn commit(
+        self: Box<Self>,
+    ) -> Result<(), AppError> {
+        self.db.commit().await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl<E: 'static + Executor> ExchangeRateTrait for ExchangeRateRepo<E> {
+    async fn rates_list(
+        &mut self,
+        filter: ExchangeRateFilter,
+        page: PageParams,
+    ) -> Result<(Vec<ExistExchangeRate>, Option<String>), AppError> {
+
+        let rates = query_list_rates(&mut self.db, &filter, &page).await?;
+
+        Ok(page.to_page(rates))
+    }
+
+    async fn rate_detail(
+        &mut self,
+        id: i32,
+    ) -> Result<ExistExchangeRate, AppError> {
+
+        let rate = query_detail_rate(&mut self.db, id).await?;
+
+        Ok(rate)
+    }
+
+    async fn rate_add(
+        &mut self,
+        rate: NewExchangeRate,
+    ) -> Result<ExistExchangeRate, AppError> {
+
+        let rate = upsert_rate(&mut self.db, rate).await?;
+
+        Ok(rate)
+    }
+
+    async fn rate_update(
+        &mut self,
+        id: i32,
+        rate: UpdateExchangeRate,
+    ) -> Result<ExistExchangeRate, AppError> {
+
+        // rate detail
+        query_detail_rate(&mut self.db, id).await?;
+
+        if let Some(rate) = rate.rate {
+            let rate = parse_rate(&rate)?;
+            query_update_rate(&mut self.db, id, rate).await?;
+        }
+
+        let rate = query_detail_rate(&mut self.db, id).await?;
+
+        Ok(rate)
+    }
+
+    async fn rate_delete(
+        &mut self,
+        id: i32,
+    ) -> Result<ExistExchangeRate, AppError> {
+
+        // rate detail
+        let rate = query_detail_rate(&mut self.db, id).await?;
+
+        query_delete_rate(&mut self.db, id).await?;
+
+        Ok(rate)
+    }
+
+    async fn rates_import(
+        &mut self,
+        rates: Vec<NewExchangeRate>,
+    ) -> Result<ImportReport, AppError> {
+
+        let imported = rates.len();
+        for rate in rates.into_iter() {
+            upsert_rate(&mut self.db, rate).await?;
+        }
+
+        Ok(ImportReport { imported })
+    }
+}
+
+/// Adds the rate of a pair on a date, replacing the one already there.
+fn upsert_rate<'a>(
+    db: &'a mut impl Executor,
+    rate: NewExchangeRate,
+) -> BoxFuture<'a, Result<ExistExchangeRate, AppError>> {
+    async move {
+
+        validate_new_rate(&rate)?;
+
+        let currency = parse_currency("currency", &rate.currency)?;
+        let base = parse_currency("base", &rate.base)?;
+        let value = parse_rate(&rate.rate)?;
+        let date = parse_rate_date(&rate.date)?;
+
+        query_upsert_rate(db, &currency, &base, value, date).await?;
+
+        let rate = query_rate_by_pair_date(db, &currency, &base, date).await?;
+
+        Ok(rate)
+    }
+    .boxed()
+}
+
+/// Value of one `from` in `to` on `date`: the latest rate of the pair dated
+/// on or before it, or the inverse of the latest `to` → `from` rate.
+pub fn rate_between<'a>(
+    db: &'a mut impl Executor,
+    from: &'a str,
+    to: &'a str,
+    date: NaiveDate,
+) -> BoxFuture<'a, Result<BigDecimal, AppError>> {
+    async move {
+
+        if from == to {
+            return Ok(BigDecimal::from(1));
+        }
+
+        let rate = match query_rate_on(db, from, to, date).await? {
+            Some(rate) => rate,
+            None => return Err(AppError::Validation(format!("no exchange rate from {} to {} on or before {}", from, to, date))),
+        };
+
+        match rate.currency == from {
+            true => Ok(rate.rate),
+            false => Ok(BigDecimal::from(1) / rate.rate),
+        }
+    }
+    .boxed()
+}
+
+/// `amount` of currency `from` in currency `to`, at the rate of `date`.
+pub fn convert<'a>(
+    db: &'a mut impl Executor,
+    amount: i64,
+    from: &'a str,
+    to: &'a str,
+    date: NaiveDate,
+) -> BoxFuture<'a, Result<i64, AppError>> {
+    async move {
+
+        if from == to || amount == 0 {
+            return Ok(amount);
+        }
+
+        let rate = rate_between(db, from, to, date).await?;
+
+        convert_amount(amount, &rate, from, to)
+    }
+    .boxed()
+}
+
+fn query_list_rates<'a>(
+    db: &'a mut impl Executor,
+    filter: &'a ExchangeRateFilter,
+    page: &'a PageParams,
+) -> BoxFuture<'a, Result<Vec<ExistExchangeRate>, AppError>> {
+    async move {
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tblexchangerates WHERE 1 = 1"#);
+
+        if let Some(currency) = &filter.currency {
+            query.push(" AND currency = ").push_bind(currency.clone());
+        }
+
+        if let Some(base) = &filter.base {
+            query.push(" AND base = ").push_bind(base.clone());
+        }
+
+        push_page(&mut query, page, "tblexchangerates", true);
+
+        let rates = query
+            .build_query_as::<ExistExchangeRate>()
+            .fetch_all(db.as_executor())
+            .await?;
+
+        Ok(rates)
+    }
+    .boxed()
+}
+
+fn query_detail_rate<'a>(
+    db: &'a mut impl Executor,
+    id: i32,
+) -> BoxFuture<'a, Result<ExistExchangeRate, AppError>> {
+    async move {
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tblexchangerates WHERE id = "#);
+
+        let rate = query
+            .push_bind(id)
+            .build_query_as::<ExistExchangeRate>()
+            .fetch_optional(db.as_executor())
+            .await?;
+
+        rate.ok_or_else(|| AppError::NotFound(format!("exchange rate {} not found", id)))
+    }
+    .boxed()
+}
+
+fn query_rate_by_pair_date<'a>(
+    db: &'a mut impl Executor,
+    currency: &'a str,
+    base: &'a str,
+    date: NaiveDate,
+) -> BoxFuture<'a, Result<ExistExchangeRate, AppError>> {
+    async move {
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tblexchangerates WHERE currency = "#);
+        query.push_bind(currency)
+            .push(" AND base = ").push_bind(base)
+            .push(" AND date = ").push_bind(date);
+
+        let rate = query
+            .build_query_as::<ExistExchangeRate>()
+            .fetch_optional(db.as_executor())
+            .await?;
+
+        rate.ok_or_else(|| AppError::NotFound(format!("exchange rate {} to {} on {} not found", currency, base, date)))
+    }
+    .boxed()
+}
+
+/// Latest rate between both currencies, in either direction, dated on or
+/// before `date`; a direct rate wins over an inverse one of the same date.
+fn query_rate_on<'a>(
+    db: &'a mut impl Executor,
+    from: &'a str,
+    to: &'a str,
+    date: NaiveDate,
+) -> BoxFuture<'a, Result<Option<ExistExchangeRate>, AppError>> {
+    async move {
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tblexchangerates WHERE ((currency = "#);
+        query.push_bind(from)
+            .push(" AND base = ").push_bind(to)
+            .push(") OR (currency = ").push_bind(to)
+            .push(" AND base = ").push_bind(from)
+            .push(")) AND date <= ").push_bind(date)
+            .push(" ORDER BY date DESC, (currency = ").push_bind(from)
+            .push(") DESC LIMIT 1");
+
+        let rate = query
+            .build_query_as::<ExistExchangeRate>()
+            .fetch_optional(db.as_executor())
+            .await?;
+
+        Ok(rate)
+    }
+    .boxed()
+}
+
+fn query_upsert_rate<'a>(
+    db: &'a mut impl Executor,
+    currency: &'a str,
+    base: &'a str,
+    rate: BigDecimal,
+    date: NaiveDate,
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"INSERT INTO tblexchangerates (currency, base, rate, date) VALUES ("#);
+
+        let mut separated = query.separated(", ");
+        separated.push_bind(currency);
+        separated.push_bind(base);
+        separated.push_bind(rate);
+        separated.push_bind(date);
+        separated.push_unseparated(") ");
+
+        query.push(" ON DUPLICATE KEY UPDATE rate = VALUES(rate), updated_at = current_timestamp()");
+
+        let add = query
+            .build()
+            .execute(db.as_executor())
+            .await?;
+
+        Ok(add)
+    }
+    .boxed()
+}
+
+fn query_update_rate<'a>(
+    db: &'a mut impl Executor,
+    id: i32,
+    rate: BigDecimal,
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"UPDATE tblexchangerates SET rate = "#);
+        query.push_bind(rate)
+            .push(" , updated_at = current_timestamp() ")
+            .push(" WHERE id = ").push_bind(id);
+
+        let res = query
+            .build()
+            .execute(db.as_executor())
+            .await?;
+
+        Ok(res)
+    }
+    .boxed()
+}
+
+fn query_delete_rate<'a>(
+    db: &'a mut impl Executor,
+    id: i32,
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"DELETE FROM tblexchangerates WHERE id = "#);
+        let res = query.push_bind(id)
+            .build()
+            .execute(db.as_executor())
+            .await?;
+
+        Ok(res)
+    }
+    .boxed()
+}
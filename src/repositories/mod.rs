@@ -1,10 +1,23 @@
 
 pub mod accounts;
+pub mod alerts;
+pub mod audit;
 pub mod cat_types;
+pub mod exchange_rates;
+pub mod purge;
+pub mod recurring;
+pub mod reconciliation;
+pub mod transfers;
 pub mod trx_cats;
 pub mod trx_cat_budgets;
 pub mod trxs;
 
+use crate::errors::AppError;
+use crate::models::pagination::{PageParams, SortOrder};
+use std::collections::HashSet;
+use sqlx::{MySql, QueryBuilder};
+use sqlx_mysql::MySqlQueryResult;
+
 #[derive(Debug)]
 pub struct UpdateQuery {
     key: String,
@@ -32,6 +45,9 @@ impl Executor for sqlx::MySqlPool {
     }
 }
 
+/// Executor of the repos handed out by `start_transaction`. Handlers run every
+/// write through one of them, so its steps land together on `commit` and
+/// dropping it on an error rolls them all back.
 impl Executor for sqlx::Transaction<'static, sqlx::MySql> {
     type Executor<'this> = &'this mut sqlx::MySqlConnection;
 
@@ -42,4 +58,96 @@ impl Executor for sqlx::Transaction<'static, sqlx::MySql> {
     fn as_executor(&mut self) -> Self::Executor<'_> {
         self
     }
-}
\ No newline at end of file
+}
+
+/// Id of the row created by an `INSERT`, as stored in our `INT` primary keys.
+pub fn last_insert_id(res: &MySqlQueryResult) -> Result<i32, AppError> {
+    i32::try_from(res.last_insert_id()).map_err(|_| {
+        AppError::Database(sqlx::Error::Protocol(format!(
+            "inserted id {} does not fit in INT", res.last_insert_id()
+        )))
+    })
+}
+
+/// Appends the keyset condition, ordering and limit of `page` to a list query
+/// on `table`; `has_where` tells whether the query already has a `WHERE`.
+pub fn push_page(
+    query: &mut QueryBuilder<'_, MySql>,
+    page: &PageParams,
+    table: &str,
+    has_where: bool,
+) {
+    let column = format!("{}.{}", table, page.sort.field.column);
+    let id = format!("{}.id", table);
+    let (cmp, order) = match page.sort.order {
+        SortOrder::Asc => (">", "ASC"),
+        SortOrder::Desc => ("<", "DESC"),
+    };
+    let sort_by_id = page.sort.field.column == "id";
+
+    if let Some(cursor) = &page.cursor {
+        query.push(if has_where { " AND " } else { " WHERE " });
+
+        if sort_by_id {
+            query.push(format!("{} {} ", id, cmp)).push_bind(cursor.id);
+        } else {
+            // (value, id) keyset: rows past the cursor value, or on it with a later id
+            query
+                .push(format!("({} {} ", column, cmp))
+                .push_bind(cursor.value.clone())
+                .push(format!(" OR ({} = ", column))
+                .push_bind(cursor.value.clone())
+                .push(format!(" AND {} {} ", id, cmp))
+                .push_bind(cursor.id)
+                .push("))");
+        }
+    }
+
+    match sort_by_id {
+        true => query.push(format!(" ORDER by {} {}", id, order)),
+        false => query.push(format!(" ORDER by {} {}, {} {}", column, order, id, order)),
+    };
+
+    query.push(" LIMIT ").push_bind(page.fetch_limit());
+}
+
+/// Hides the soft-deleted rows of `table` unless `include_deleted` is set;
+/// returns whether the query has a `WHERE` afterwards.
+pub fn push_not_deleted(
+    query: &mut QueryBuilder<'_, MySql>,
+    table: &str,
+    include_deleted: bool,
+    has_where: bool,
+) -> bool {
+    if include_deleted {
+        return has_where;
+    }
+
+    query.push(if has_where { " AND " } else { " WHERE " });
+    query.push(format!("{}.deleted_at IS NULL", table));
+
+    true
+}
+
+/// Appends `(?, ?, ...)` binding every id, for `IN` lookups.
+pub fn push_ids(query: &mut QueryBuilder<'_, MySql>, ids: &[i32]) {
+    query.push("(");
+    let mut separated = query.separated(", ");
+    for id in ids.iter() {
+        separated.push_bind(*id);
+    }
+    separated.push_unseparated(")");
+}
+
+/// Distinct ids, in first-seen order.
+pub fn unique_ids(values: impl IntoIterator<Item = i32>) -> Vec<i32> {
+    let mut seen: HashSet<i32> = HashSet::new();
+    let mut ids: Vec<i32> = Vec::new();
+    for value in values {
+        if seen.insert(value) {
+            ids.push(value);
+        }
+    }
+
+    ids
+}
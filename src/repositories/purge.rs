@@ -0,0 +1,130 @@
+
+use crate::models::purge::PurgeReport;
+use crate::errors::AppError;
+use crate::repositories::Executor;
+
+use futures_util::{future::BoxFuture, FutureExt};
+use sqlx::{MySql, MySqlPool};
+use sqlx::types::chrono::{DateTime, Utc};
+
+#[async_trait::async_trait]
+pub trait Trait: Send + Sync + PurgeTrait {
+    async fn start_transaction(
+        &self,
+    ) -> Result<Box<dyn TransactionTrait>, AppError>;
+}
+
+#[async_trait::async_trait]
+pub trait TransactionTrait: Send + Sync + PurgeTrait {
+    async fn commit(
+        self: Box<Self>,
+    ) -> Result<(), AppError>;
+}
+
+#[async_trait::async_trait]
+pub trait PurgeTrait {
+    async fn purge(
+        &mut self,
+        before: DateTime<Utc>,
+    ) -> Result<PurgeReport, AppError>;
+}
+
+#[derive(Debug, Clone)]
+pub struct PurgeRepo<E = MySqlPool> {
+    db: E,
+}
+
+impl PurgeRepo {
+    pub fn new(pool: MySqlPool) -> Self {
+        Self { db: pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl Trait for PurgeRepo {
+    async fn start_transaction(
+        &self,
+    ) -> Result<Box<dyn TransactionTrait>, AppError> {
+        let tx = self.db.begin().await?;
+
+        Ok(Box::new(PurgeRepo { db: tx }))
+    }
+}
+
+#[async_trait::async_trait]
+impl TransactionTrait for PurgeRepo<sqlx::Transaction<'static, MySql>> {
+    async fn commit(
+        self: Box<Self>,
+    ) -> Result<(), AppError> {
+        self.db.commit().await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl<E: 'static + Executor> PurgeTrait for PurgeRepo<E> {
+    /// Hard deletes every row soft deleted before `before`, children first.
+    /// A row still referenced by a row that stays, deleted or not, is kept
+    /// for a later run.
+    async fn purge(
+        &mut self,
+        before: DateTime<Utc>,
+    ) -> Result<PurgeReport, AppError> {
+
+        let report = PurgeReport {
+            transactions: query_purge(&mut self.db, "tbltransactions", "", before).await?,
+            budgets: query_purge(&mut self.db, "tblcategorybudgets", "", before).await?,
+            categories: query_purge(
+                &mut self.db,
+                "tbltransactioncategories",
+                " AND id NOT IN (SELECT categoryid FROM tbltransactions WHERE categoryid IS NOT NULL)
+                  AND id NOT IN (SELECT categoryid FROM tbltransactionsplits)
+                  AND id NOT IN (SELECT categoryid FROM tblcategorybudgets)
+                  AND id NOT IN (SELECT categoryid FROM tblrecurringtrxs)",
+                before,
+            ).await?,
+            category_types: query_purge(
+                &mut self.db,
+                "tblcategorytypes",
+                " AND id NOT IN (SELECT typeid FROM tbltransactioncategories)",
+                before,
+            ).await?,
+            accounts: query_purge(
+                &mut self.db,
+                "tblaccounts",
+                " AND id NOT IN (SELECT accountid FROM tbltransactions)
+                  AND id NOT IN (SELECT fromaccountid FROM tbltransfers)
+                  AND id NOT IN (SELECT toaccountid FROM tbltransfers)
+                  AND id NOT IN (SELECT accountid FROM tblrecurringtrxs)",
+                before,
+            ).await?,
+        };
+
+        Ok(report)
+    }
+}
+
+/// Deletes the rows of `table` soft deleted before `before` that also match
+/// `unreferenced`, returning how many went.
+fn query_purge<'a>(
+    db: &'a mut impl Executor,
+    table: &'a str,
+    unreferenced: &'a str,
+    before: DateTime<Utc>,
+) -> BoxFuture<'a, Result<u64, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(format!("DELETE FROM {} WHERE deleted_at < ", table));
+        query.push_bind(before)
+            .push(unreferenced);
+
+        let res = query
+            .build()
+            .execute(db.as_executor())
+            .await?;
+
+        Ok(res.rows_affected())
+    }
+    .boxed()
+}
\ No newline at end of file
@@ -0,0 +1,136 @@
+
+use crate::models::exchange_rates::DEFAULT_CURRENCY;
+use crate::models::reconciliation::{ ReconcileReport, check_budget, check_ledger };
+use crate::models::trx_cat_budgets::BudgetPeriod;
+use crate::errors::AppError;
+use crate::repositories::Executor;
+use crate::repositories::accounts;
+use crate::repositories::trx_cat_budgets;
+use crate::repositories::trxs;
+
+use sqlx::{MySql, MySqlPool};
+
+#[async_trait::async_trait]
+pub trait Trait: Send + Sync + ReconcileTrait {
+    async fn start_transaction(
+        &self,
+    ) -> Result<Box<dyn TransactionTrait>, AppError>;
+}
+
+#[async_trait::async_trait]
+pub trait TransactionTrait: Send + Sync + ReconcileTrait {
+    async fn commit(
+        self: Box<Self>,
+    ) -> Result<(), AppError>;
+}
+
+#[async_trait::async_trait]
+pub trait ReconcileTrait {
+    async fn reconcile(
+        &mut self,
+        repair: bool,
+    ) -> Result<ReconcileReport, AppError>;
+}
+
+#[derive(Debug, Clone)]
+pub struct ReconcileRepo<E = MySqlPool> {
+    db: E,
+    /// Currency the category budgets are kept in.
+    base_currency: String,
+}
+
+impl ReconcileRepo {
+    pub fn new(pool: MySqlPool) -> Self {
+        Self { db: pool, base_currency: String::from(DEFAULT_CURRENCY) }
+    }
+
+    pub fn with_base_currency(mut self, base_currency: &str) -> Self {
+        self.base_currency = base_currency.to_string();
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl Trait for ReconcileRepo {
+    async fn start_transaction(
+        &self,
+    ) -> Result<Box<dyn TransactionTrait>, AppError> {
+        let tx = self.db.begin().await?;
+
+        Ok(Box::new(ReconcileRepo { db: tx, base_currency: self.base_currency.clone() }))
+    }
+}
+
+#[async_trait::async_trait]
+impl TransactionTrait for ReconcileRepo<sqlx::Transaction<'static, MySql>> {
+    async fn commit(
+        self: Box<Self>,
+    ) -> Result<(), AppError> {
+        self.db.commit().await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl<E: 'static + Executor> ReconcileTrait for ReconcileRepo<E> {
+    /// Audits every account ledger and budget, writing the expected values
+    /// back when `repair` is set.
+    async fn reconcile(
+        &mut self,
+        repair: bool,
+    ) -> Result<ReconcileReport, AppError> {
+
+        let mut report = ReconcileReport { repaired: repair, ..Default::default() };
+
+        // account balances and running balance chains
+        let data_accounts = accounts::query_all_accounts(&mut self.db).await?;
+        for account in data_accounts.iter() {
+
+            let ledger = trxs::query_account_ledger(&mut self.db, account.id).await?;
+            let discrepancy = match check_ledger(account, &ledger) {
+                Some(discrepancy) => discrepancy,
+                None => continue,
+            };
+
+            if repair {
+                for broken in discrepancy.broken_chain.iter() {
+                    trxs::query_set_trx_balance(&mut self.db, broken.trxid, broken.expected_before.to_minor()?, broken.expected_after.to_minor()?).await?;
+                }
+
+                let drift = (&discrepancy.expected_balance - &discrepancy.stored_balance).to_minor()?;
+                if drift != 0 {
+                    accounts::update_acc_balance(&mut self.db, account.id, drift).await?;
+                }
+            }
+
+            report.accounts.push(discrepancy);
+        }
+
+        // budgets are charged by the trxs of their category dated within their period,
+        // those without a period are never charged
+        let data_budgets = trx_cat_budgets::query_all_budgets(&mut self.db).await?;
+        let ids: Vec<i32> = data_budgets.iter().map(|budget| budget.id).collect();
+        let data_spent = trx_cat_budgets::budgets_spent(&mut self.db, &self.base_currency, &ids).await?;
+        for budget in data_budgets.iter() {
+
+            if BudgetPeriod::of(budget)?.is_none() {
+                continue;
+            }
+            let spent = data_spent.get(&budget.id).copied().unwrap_or(0);
+
+            let discrepancy = match check_budget(budget, spent) {
+                Some(discrepancy) => discrepancy,
+                None => continue,
+            };
+
+            if repair {
+                trx_cat_budgets::query_set_budget_spent(&mut self.db, budget.id, discrepancy.expected_spent.to_minor()?, discrepancy.expected_available.to_minor()?).await?;
+            }
+
+            report.budgets.push(discrepancy);
+        }
+
+        Ok(report)
+    }
+}
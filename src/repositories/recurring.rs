@@ -0,0 +1,548 @@
+use crate::models::accounts::validate_active;
+use crate::models::exchange_rates::DEFAULT_CURRENCY;
+use crate::models::recurring::{ ExistRecurring, NewRecurring, UpdateRecurring, Schedule, occurrence_trx, validate_new_recurring, validate_update_recurring };
+use crate::models::trxs::{ ExistTrx, DATETIME_FORMAT };
+use crate::models::pagination::PageParams;
+use crate::errors::AppError;
+use crate::repositories::{ Executor, last_insert_id, push_page };
+use crate::repositories::accounts;
+use crate::repositories::trx_cats;
+use crate::repositories::trxs;
+
+use futures_util::{future::BoxFuture, FutureExt};
+use sqlx::{MySql, MySqlPool};
+use sqlx::types::chrono::{NaiveDateTime, Utc};
+use sqlx_mysql::MySqlQueryResult;
+
+#[async_trait::async_trait]
+pub trait Trait: Send + Sync + RecurringTrait {
+    async fn start_transaction(
+        &self,
+    ) -> Result<Box<dyn TransactionTrait>, AppError>;
+}
+
+#[async_trait::async_trait]
+pub trait TransactionTrait: Send + Sync + RecurringTrait {
+    async fn commit(
+        self: Box<Self>,
+    ) -> Result<(), AppError>;
+}
+
+#[async_trait::async_trait]
+pub trait RecurringTrait {
+    async fn recurring_list(
+        &mut self,
+        page: PageParams,
+    ) -> Result<(Vec<ExistRecurring>, Option<String>), AppError>;
+    async fn recurring_detail(
+        &mut self,
+        id: i32,
+    ) -> Result<ExistRecurring, AppError>;
+    async fn recurring_add(
+        &mut self,
+        recurring: NewRecurring,
+    ) -> Result<ExistRecurring, AppError>;
+    async fn recurring_update(
+        &mut self,
+        id: i32,
+        recurring: UpdateRecurring,
+    ) -> Result<ExistRecurring, AppError>;
+    async fn recurring_delete(
+        &mut self,
+        id: i32,
+    ) -> Result<ExistRecurring, AppError>;
+    async fn recurring_upcoming(
+        &mut self,
+        id: i32,
+        limit: usize,
+    ) -> Result<Vec<(i32, NaiveDateTime)>, AppError>;
+    async fn recurring_skip(
+        &mut self,
+        id: i32,
+    ) -> Result<ExistRecurring, AppError>;
+    async fn recurring_post(
+        &mut self,
+        id: i32,
+    ) -> Result<(ExistRecurring, ExistTrx), AppError>;
+    async fn recurring_due_ids(
+        &mut self,
+        now: NaiveDateTime,
+    ) -> Result<Vec<i32>, AppError>;
+    async fn recurring_post_due(
+        &mut self,
+        id: i32,
+        now: NaiveDateTime,
+    ) -> Result<Vec<ExistTrx>, AppError>;
+}
+
+#[derive(Debug, Clone)]
+pub struct RecurringRepo<E = MySqlPool> {
+    db: E,
+    /// Who the audit log credits with the posted trxs.
+    actor: Option<String>,
+    /// Currency the category budgets are kept in.
+    base_currency: String,
+}
+
+impl RecurringRepo {
+    pub fn new(pool: MySqlPool) -> Self {
+        Self { db: pool, actor: None, base_currency: String::from(DEFAULT_CURRENCY) }
+    }
+
+    pub fn with_actor(mut self, actor: Option<String>) -> Self {
+        self.actor = actor;
+        self
+    }
+
+    pub fn with_base_currency(mut self, base_currency: &str) -> Self {
+        self.base_currency = base_currency.to_string();
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl Trait for RecurringRepo {
+    async fn start_transaction(
+        &self,
+    ) -> Result<Box<dyn TransactionTrait>, AppError> {
+        let tx = self.db.begin().await?;
+
+        Ok(Box::new(RecurringRepo { db: tx, actor: self.actor.clone(), base_currency: self.base_currency.clone() }))
+    }
+}
+
+#[async_trait::async_trait]
+impl TransactionTrait for RecurringRepo<sqlx::Transaction<'static, MySql>> {
+    async fn commit(
+        self: Box<Self>,
+    ) -> Result<(), AppError> {
+        self.db.commit().await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl<E: 'static + Executor> RecurringTrait for RecurringRepo<E> {
+    async fn recurring_list(
+        &mut self,
+        page: PageParams,
+    ) -> Result<(Vec<ExistRecurring>, Option<String>), AppError> {
+
+        let data_recurring = query_list_recurring(&mut self.db, &page).await?;
+
+        Ok(page.to_page(data_recurring))
+    }
+
+    async fn recurring_detail(
+        &mut self,
+        id: i32,
+    ) -> Result<ExistRecurring, AppError> {
+
+        let recurring = query_detail_recurring(&mut self.db, id).await?;
+
+        Ok(recurring)
+    }
+
+    async fn recurring_add(
+        &mut self,
+        recurring: NewRecurring,
+    ) -> Result<ExistRecurring, AppError> {
+
+        validate_new_recurring(&recurring)?;
+        let schedule = Schedule::from_new(&recurring)?;
+
+        // the template posts in the currency of its account
+        let account = accounts::query_detail_account(&mut self.db, recurring.accountid).await?;
+        validate_active(&account)?;
+        trx_cats::query_detail_trx_cats(&mut self.db, recurring.categoryid).await?;
+
+        // occurrences from `startDate` on, past ones are posted by the next run of the job
+        let next = schedule.within(schedule.first_from(schedule.start), 0);
+
+        let add = query_add_recurring(&mut self.db, &recurring, &schedule, &account.currency, next).await?;
+        let recurring_id = last_insert_id(&add)?;
+
+        let recurring = query_detail_recurring(&mut self.db, recurring_id).await?;
+
+        Ok(recurring)
+    }
+
+    async fn recurring_update(
+        &mut self,
+        id: i32,
+        recurring: UpdateRecurring,
+    ) -> Result<ExistRecurring, AppError> {
+
+        validate_update_recurring(&recurring)?;
+
+        let exist = query_lock_recurring(&mut self.db, id).await?;
+
+        // moving to another account takes its currency
+        let currency = match recurring.accountid {
+            Some(acc_id) => {
+                let account = accounts::query_detail_account(&mut self.db, acc_id).await?;
+                validate_active(&account)?;
+                Some(account.currency)
+            },
+            None => None,
+        };
+        if let Some(cat_id) = recurring.categoryid {
+            trx_cats::query_detail_trx_cats(&mut self.db, cat_id).await?;
+        }
+
+        // a new schedule restarts from its start date or now, whichever is later,
+        // so the occurrences already gone by are not posted again
+        let schedule = match recurring.reschedules() {
+            true => {
+                let schedule = Schedule::from_update(&exist, &recurring)?;
+                let from = schedule.start.max(Utc::now().naive_utc());
+                let next = schedule.within(schedule.first_from(from), exist.posted);
+                Some((schedule, next))
+            },
+            false => None,
+        };
+
+        query_update_recurring(&mut self.db, id, &recurring, currency, schedule.as_ref()).await?;
+
+        let recurring = query_detail_recurring(&mut self.db, id).await?;
+
+        Ok(recurring)
+    }
+
+    async fn recurring_delete(
+        &mut self,
+        id: i32,
+    ) -> Result<ExistRecurring, AppError> {
+
+        // the trxs already posted stay
+        let recurring = query_detail_recurring(&mut self.db, id).await?;
+        query_delete_recurring(&mut self.db, id).await?;
+
+        Ok(recurring)
+    }
+
+    async fn recurring_upcoming(
+        &mut self,
+        id: i32,
+        limit: usize,
+    ) -> Result<Vec<(i32, NaiveDateTime)>, AppError> {
+
+        let recurring = query_detail_recurring(&mut self.db, id).await?;
+        let schedule = Schedule::from_exist(&recurring)?;
+
+        let next = recurring.nextdate.map(|next| next.naive_utc());
+
+        Ok(schedule.upcoming(next, recurring.posted, limit))
+    }
+
+    async fn recurring_skip(
+        &mut self,
+        id: i32,
+    ) -> Result<ExistRecurring, AppError> {
+
+        let recurring = query_lock_recurring(&mut self.db, id).await?;
+        let next = next_occurrence(&recurring)?;
+
+        advance(&mut self.db, &recurring, next).await?;
+
+        let recurring = query_detail_recurring(&mut self.db, id).await?;
+
+        Ok(recurring)
+    }
+
+    async fn recurring_post(
+        &mut self,
+        id: i32,
+    ) -> Result<(ExistRecurring, ExistTrx), AppError> {
+
+        let recurring = query_lock_recurring(&mut self.db, id).await?;
+        let next = next_occurrence(&recurring)?;
+
+        // posted early, the trx is dated now instead of its due date
+        let now = Utc::now().naive_utc();
+        let trx = occurrence_trx(&recurring, now.min(next))?;
+        let trx = trxs::add_trx(&mut self.db, &self.actor, &self.base_currency, trx).await?;
+
+        advance(&mut self.db, &recurring, next).await?;
+
+        let recurring = query_detail_recurring(&mut self.db, id).await?;
+
+        Ok((recurring, trx))
+    }
+
+    async fn recurring_due_ids(
+        &mut self,
+        now: NaiveDateTime,
+    ) -> Result<Vec<i32>, AppError> {
+
+        let ids = query_due_recurring_ids(&mut self.db, now).await?;
+
+        Ok(ids)
+    }
+
+    async fn recurring_post_due(
+        &mut self,
+        id: i32,
+        now: NaiveDateTime,
+    ) -> Result<Vec<ExistTrx>, AppError> {
+
+        let mut recurring = query_lock_recurring(&mut self.db, id).await?;
+        let schedule = Schedule::from_exist(&recurring)?;
+
+        // catch up on every occurrence due by now, each dated on its due date
+        let mut posted: Vec<ExistTrx> = Vec::new();
+        let mut next = recurring.nextdate.map(|next| next.naive_utc());
+        while let Some(date) = next.filter(|date| *date <= now) {
+            let trx = occurrence_trx(&recurring, date)?;
+            posted.push(trxs::add_trx(&mut self.db, &self.actor, &self.base_currency, trx).await?);
+
+            recurring.posted += 1;
+            next = schedule.within(schedule.next_after(date), recurring.posted);
+        }
+
+        query_set_next(&mut self.db, id, recurring.posted, next).await?;
+
+        Ok(posted)
+    }
+}
+
+/// Next occurrence of `recurring`, a conflict once its schedule is over.
+fn next_occurrence(recurring: &ExistRecurring) -> Result<NaiveDateTime, AppError> {
+    match recurring.nextdate {
+        Some(next) => Ok(next.naive_utc()),
+        None => Err(AppError::Conflict(format!("recurring transaction {} has no occurrence left", recurring.id))),
+    }
+}
+
+/// Moves `recurring` past its occurrence at `date`.
+fn advance<'a>(
+    db: &'a mut impl Executor,
+    recurring: &'a ExistRecurring,
+    date: NaiveDateTime,
+) -> BoxFuture<'a, Result<(), AppError>> {
+    async move {
+
+        let schedule = Schedule::from_exist(recurring)?;
+        let posted = recurring.posted + 1;
+        let next = schedule.within(schedule.next_after(date), posted);
+
+        query_set_next(db, recurring.id, posted, next).await?;
+
+        Ok(())
+    }
+    .boxed()
+}
+
+fn query_list_recurring<'a>(
+    db: &'a mut impl Executor,
+    page: &'a PageParams,
+) -> BoxFuture<'a, Result<Vec<ExistRecurring>, AppError>> {
+    async move {
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tblrecurringtrxs"#);
+        push_page(&mut query, page, "tblrecurringtrxs", false);
+
+        let recurring = query
+            .build_query_as::<ExistRecurring>()
+            .fetch_all(db.as_executor())
+            .await?;
+
+        Ok(recurring)
+    }
+    .boxed()
+}
+
+fn query_detail_recurring<'a>(
+    db: &'a mut impl Executor,
+    id: i32
+) -> BoxFuture<'a, Result<ExistRecurring, AppError>> {
+    async move {
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tblrecurringtrxs WHERE id = "#);
+
+        let recurring = query
+            .push_bind(id)
+            .build_query_as::<ExistRecurring>()
+            .fetch_optional(db.as_executor())
+            .await?;
+
+        recurring.ok_or_else(|| AppError::NotFound(format!("recurring transaction {} not found", id)))
+    }
+    .boxed()
+}
+
+/// Like `query_detail_recurring`, holding the row until the db transaction
+/// ends so the job and a request never post the same occurrence twice.
+fn query_lock_recurring<'a>(
+    db: &'a mut impl Executor,
+    id: i32
+) -> BoxFuture<'a, Result<ExistRecurring, AppError>> {
+    async move {
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tblrecurringtrxs WHERE id = "#);
+
+        let recurring = query
+            .push_bind(id)
+            .push(" FOR UPDATE")
+            .build_query_as::<ExistRecurring>()
+            .fetch_optional(db.as_executor())
+            .await?;
+
+        recurring.ok_or_else(|| AppError::NotFound(format!("recurring transaction {} not found", id)))
+    }
+    .boxed()
+}
+
+/// Templates with an occurrence due by `now`, leaving out those whose account
+/// or category is deleted or whose account is archived.
+fn query_due_recurring_ids<'a>(
+    db: &'a mut impl Executor,
+    now: NaiveDateTime,
+) -> BoxFuture<'a, Result<Vec<i32>, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT r.id FROM tblrecurringtrxs r
+            JOIN tblaccounts a ON a.id = r.accountid AND a.deleted_at IS NULL AND a.archived_at IS NULL
+            JOIN tbltransactioncategories c ON c.id = r.categoryid AND c.deleted_at IS NULL
+            WHERE r.nextdate <= "#);
+        query.push_bind(now)
+            .push(" ORDER BY r.nextdate ASC, r.id ASC");
+
+        let ids = query
+            .build_query_scalar::<i32>()
+            .fetch_all(db.as_executor())
+            .await?;
+
+        Ok(ids)
+    }
+    .boxed()
+}
+
+fn query_add_recurring<'a>(
+    db: &'a mut impl Executor,
+    recurring: &'a NewRecurring,
+    schedule: &'a Schedule,
+    currency: &'a str,
+    next: Option<NaiveDateTime>,
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"INSERT INTO tblrecurringtrxs (credit, debit, currency, description, accountid, categoryid, frequency, every, dayofmonth, startdate, enddate, occurrences, nextdate) VALUES ("#);
+
+        let mut separated = query.separated(", ");
+        separated.push_bind(recurring.credit);
+        separated.push_bind(recurring.debit);
+        separated.push_bind(currency);
+        separated.push_bind(recurring.description.clone());
+        separated.push_bind(recurring.accountid);
+        separated.push_bind(recurring.categoryid);
+        separated.push_bind(schedule.frequency.as_str());
+        separated.push_bind(schedule.every);
+        separated.push_bind(schedule.day_of_month);
+        separated.push_bind(schedule.start.format(DATETIME_FORMAT).to_string());
+        separated.push_bind(schedule.end.map(|end| end.format(DATETIME_FORMAT).to_string()));
+        separated.push_bind(schedule.occurrences);
+        separated.push_bind(next.map(|next| next.format(DATETIME_FORMAT).to_string()));
+        separated.push_unseparated(") ");
+
+        let add = query
+            .build()
+            .execute(db.as_executor())
+            .await?;
+
+        Ok(add)
+    }
+    .boxed()
+}
+
+fn query_update_recurring<'a>(
+    db: &'a mut impl Executor,
+    id: i32,
+    recurring: &'a UpdateRecurring,
+    currency: Option<String>,
+    schedule: Option<&'a (Schedule, Option<NaiveDateTime>)>,
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"UPDATE tblrecurringtrxs SET "#);
+        let mut separated = query.separated(", ");
+
+        if let (Some(credit), Some(debit)) = (recurring.credit, recurring.debit) {
+            separated.push("credit = ").push_bind_unseparated(credit);
+            separated.push("debit = ").push_bind_unseparated(debit);
+        }
+
+        if let Some(description) = &recurring.description {
+            separated.push("description = ").push_bind_unseparated(description.clone());
+        }
+
+        if let (Some(acc_id), Some(currency)) = (recurring.accountid, currency) {
+            separated.push("accountid = ").push_bind_unseparated(acc_id);
+            separated.push("currency = ").push_bind_unseparated(currency);
+        }
+
+        if let Some(cat_id) = recurring.categoryid {
+            separated.push("categoryid = ").push_bind_unseparated(cat_id);
+        }
+
+        if let Some((schedule, next)) = schedule {
+            separated.push("frequency = ").push_bind_unseparated(schedule.frequency.as_str());
+            separated.push("every = ").push_bind_unseparated(schedule.every);
+            separated.push("dayofmonth = ").push_bind_unseparated(schedule.day_of_month);
+            separated.push("startdate = ").push_bind_unseparated(schedule.start.format(DATETIME_FORMAT).to_string());
+            separated.push("enddate = ").push_bind_unseparated(schedule.end.map(|end| end.format(DATETIME_FORMAT).to_string()));
+            separated.push("occurrences = ").push_bind_unseparated(schedule.occurrences);
+            separated.push("nextdate = ").push_bind_unseparated(next.map(|next| next.format(DATETIME_FORMAT).to_string()));
+        }
+
+        separated
+            .push("updated_at = current_timestamp()")
+            .push_unseparated(" WHERE id = ")
+            .push_bind_unseparated(id);
+
+        let res = query.build()
+            .execute(db.as_executor())
+            .await?;
+
+        Ok(res)
+    }
+    .boxed()
+}
+
+fn query_set_next<'a>(
+    db: &'a mut impl Executor,
+    id: i32,
+    posted: i32,
+    next: Option<NaiveDateTime>,
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"UPDATE tblrecurringtrxs SET posted = "#);
+        query.push_bind(posted)
+            .push(" , nextdate = ").push_bind(next.map(|next| next.format(DATETIME_FORMAT).to_string()))
+            .push(" , updated_at = current_timestamp() WHERE id = ").push_bind(id);
+
+        let res = query
+            .build()
+            .execute(db.as_executor())
+            .await?;
+
+        Ok(res)
+    }
+    .boxed()
+}
+
+fn query_delete_recurring<'a>(
+    db: &'a mut impl Executor,
+    id: i32,
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"DELETE FROM tblrecurringtrxs WHERE id = "#);
+        let res = query.push_bind(id)
+            .build()
+            .execute(db.as_executor())
+            .await?;
+
+        Ok(res)
+    }
+    .boxed()
+}
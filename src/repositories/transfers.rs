@@ -0,0 +1,615 @@
+
+use crate::models::audit::{ AuditAction, AuditEntity };
+use crate::models::transfers::{ ExistTransfer, ExistTransferWithTrxs, NewTransfer, UpdateTransfer, transfer_rate, transfer_to_amount, validate_new_transfer, validate_update_transfer };
+use crate::models::trxs::{ ExistTrx, AddTrx, UpdateTrx };
+use crate::models::pagination::PageParams;
+use crate::errors::AppError;
+use crate::repositories::{ Executor, last_insert_id, push_ids, push_page };
+use crate::repositories::accounts;
+use crate::repositories::audit;
+use crate::repositories::trxs;
+
+use futures_util::{future::BoxFuture, FutureExt};
+use sqlx::types::BigDecimal;
+use sqlx::{MySql, MySqlPool};
+use sqlx_mysql::MySqlQueryResult;
+
+#[async_trait::async_trait]
+pub trait Trait: Send + Sync + TransferTrait {
+    async fn start_transaction(
+        &self,
+    ) -> Result<Box<dyn TransactionTrait>, AppError>;
+}
+
+#[async_trait::async_trait]
+pub trait TransactionTrait: Send + Sync + TransferTrait {
+    async fn commit(
+        self: Box<Self>,
+    ) -> Result<(), AppError>;
+}
+
+#[async_trait::async_trait]
+pub trait TransferTrait {
+    async fn transfers_list(
+        &mut self,
+        page: PageParams,
+    ) -> Result<(Vec<ExistTransferWithTrxs>, Option<String>), AppError>;
+    async fn transfer_detail(
+        &mut self,
+        id: i32,
+    ) -> Result<ExistTransferWithTrxs, AppError>;
+    async fn transfer_add(
+        &mut self,
+        transfer: NewTransfer,
+    ) -> Result<ExistTransferWithTrxs, AppError>;
+    async fn transfer_update(
+        &mut self,
+        id: i32,
+        transfer: UpdateTransfer,
+    ) -> Result<ExistTransferWithTrxs, AppError>;
+    async fn transfer_delete(
+        &mut self,
+        id: i32,
+    ) -> Result<ExistTransferWithTrxs, AppError>;
+}
+
+#[derive(Debug, Clone)]
+pub struct TransferRepo<E = MySqlPool> {
+    db: E,
+    /// Who the audit log credits with the writes.
+    actor: Option<String>,
+}
+
+impl TransferRepo {
+    pub fn new(pool: MySqlPool) -> Self {
+        Self { db: pool, actor: None }
+    }
+
+    pub fn with_actor(mut self, actor: Option<String>) -> Self {
+        self.actor = actor;
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl Trait for TransferRepo {
+    async fn start_transaction(
+        &self,
+    ) -> Result<Box<dyn TransactionTrait>, AppError> {
+        let tx = self.db.begin().await?;
+
+        Ok(Box::new(TransferRepo { db: tx, actor: self.actor.clone() }))
+    }
+}
+
+#[async_trait::async_trait]
+impl TransactionTrait for TransferRepo<sqlx::Transaction<'static, MySql>> {
+    async fn commit(
+        self: Box<Self>,
+    ) -> Result<(), AppError> {
+        self.db.commit().await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl<E: 'static + Executor> TransferTrait for TransferRepo<E> {
+    async fn transfers_list(
+        &mut self,
+        page: PageParams,
+    ) -> Result<(Vec<ExistTransferWithTrxs>, Option<String>), AppError> {
+
+        let data_transfers = query_list_transfers(&mut self.db, &page).await?;
+        let (data_transfers, next_cursor) = page.to_page(data_transfers);
+
+        // load the legs of the whole page at once
+        let ids: Vec<i32> = data_transfers.iter().map(|transfer| transfer.id).collect();
+        let legs = query_transfer_trxs(&mut self.db, &ids).await?;
+
+        let transfers = data_transfers
+            .into_iter()
+            .map(|transfer| with_legs(transfer, &legs))
+            .collect();
+
+        Ok((transfers, next_cursor))
+    }
+
+    async fn transfer_detail(
+        &mut self,
+        id: i32,
+    ) -> Result<ExistTransferWithTrxs, AppError> {
+
+        let transfer = detail_transfer(&mut self.db, id).await?;
+
+        Ok(transfer)
+    }
+
+    async fn transfer_add(
+        &mut self,
+        transfer: NewTransfer,
+    ) -> Result<ExistTransferWithTrxs, AppError> {
+
+        validate_new_transfer(&transfer)?;
+        accounts::lock_accounts(&mut self.db, &[transfer.fromaccountid, transfer.toaccountid]).await?;
+
+        // each leg is recorded in the currency of its account
+        let from = accounts::query_detail_account(&mut self.db, transfer.fromaccountid).await?;
+        let to = accounts::query_detail_account(&mut self.db, transfer.toaccountid).await?;
+        let rate = transfer_rate(&from.currency, &to.currency, &transfer.rate)?;
+        let to_amount = transfer_to_amount(transfer.amount, &rate, &from.currency, &to.currency)?;
+
+        // add transfer
+        let add = query_add_transfer(&mut self.db, &transfer, (&from.currency, &to.currency), to_amount, &rate).await?;
+        let transfer_id = last_insert_id(&add)?;
+
+        // debit leg out of the source account
+        let debit = AddTrx {
+            credit: 0,
+            debit: transfer.amount,
+            description: transfer.description.clone(),
+            balance_before: 0,
+            balance_after: 0,
+            datetime: transfer.datetime.clone(),
+            accountid: transfer.fromaccountid,
+            categoryid: None,
+            transferid: Some(transfer_id),
+        };
+        trxs::add_trx_with_balance(&mut self.db, debit).await?;
+
+        // credit leg into the destination account
+        let credit = AddTrx {
+            credit: to_amount,
+            debit: 0,
+            description: transfer.description,
+            balance_before: 0,
+            balance_after: 0,
+            datetime: transfer.datetime,
+            accountid: transfer.toaccountid,
+            categoryid: None,
+            transferid: Some(transfer_id),
+        };
+        trxs::add_trx_with_balance(&mut self.db, credit).await?;
+
+        // detail transfer
+        let transfer = detail_transfer(&mut self.db, transfer_id).await?;
+        for leg in [&transfer.debit, &transfer.credit].into_iter().flatten() {
+            audit::record(&mut self.db, &self.actor, AuditEntity::Trx, leg.id, AuditAction::Add, None, Some(leg)).await?;
+        }
+
+        Ok(transfer)
+    }
+
+    async fn transfer_update(
+        &mut self,
+        id: i32,
+        transfer: UpdateTransfer,
+    ) -> Result<ExistTransferWithTrxs, AppError> {
+
+        lock_transfer_accounts(&mut self.db, id).await?;
+        update_transfer(&mut self.db, &self.actor, id, transfer).await?;
+
+        // detail transfer
+        let transfer = detail_transfer(&mut self.db, id).await?;
+
+        Ok(transfer)
+    }
+
+    async fn transfer_delete(
+        &mut self,
+        id: i32,
+    ) -> Result<ExistTransferWithTrxs, AppError> {
+
+        lock_transfer_accounts(&mut self.db, id).await?;
+
+        // detail transfer
+        let transfer = detail_transfer(&mut self.db, id).await?;
+
+        delete_transfer(&mut self.db, &self.actor, id).await?;
+
+        Ok(transfer)
+    }
+}
+
+/// Transfer change requested through one of its legs on `PATCH /trxs/{id}`.
+///
+/// The leg keeps its direction, so its new amount is `credit + debit`. The
+/// credit leg of a cross-currency transfer holds the converted amount, the
+/// transfer amount is then changed on `PATCH /transfers/{id}` only.
+pub fn update_from_leg<'a>(
+    db: &'a mut impl Executor,
+    leg: &'a ExistTrx,
+    trx: &'a UpdateTrx,
+) -> BoxFuture<'a, Result<UpdateTransfer, AppError>> {
+    async move {
+
+        let amount = match (trx.credit, trx.debit) {
+            (Some(credit), Some(debit)) => Some(credit + debit),
+            _ => None,
+        };
+
+        if let (Some(_), Some(transfer_id)) = (amount, leg.transferid) {
+            let transfer = query_detail_transfer(db, transfer_id).await?;
+            if leg.accountid == transfer.toaccountid && transfer.rate != BigDecimal::from(1) {
+                return Err(AppError::Validation(format!(
+                    "transfer {} converts between currencies, change its amount on /transfers/{}",
+                    transfer_id, transfer_id,
+                )));
+            }
+        }
+
+        Ok(UpdateTransfer {
+            amount,
+            rate: None,
+            description: trx.description.clone(),
+            datetime: trx.datetime.clone(),
+        })
+    }
+    .boxed()
+}
+
+/// Updates a transfer and both of its legs, logging each leg.
+pub fn update_transfer<'a>(
+    db: &'a mut impl Executor,
+    actor: &'a Option<String>,
+    id: i32,
+    transfer: UpdateTransfer,
+) -> BoxFuture<'a, Result<(), AppError>> {
+    async move {
+
+        validate_update_transfer(&transfer)?;
+
+        let exist = detail_transfer(db, id).await?;
+        let amount = match transfer.amount {
+            Some(amount) => amount,
+            None => exist.transfer.amount.to_minor()?,
+        };
+
+        // a new rate is checked against the account currencies, the stored one is kept otherwise
+        let rate = match transfer.rate {
+            Some(_) => {
+                let from = accounts::query_find_account(db, exist.transfer.fromaccountid).await?;
+                let to = accounts::query_find_account(db, exist.transfer.toaccountid).await?;
+                transfer_rate(&from.currency, &to.currency, &transfer.rate)?
+            },
+            None => exist.transfer.rate.clone(),
+        };
+        let to_amount = transfer_to_amount(amount, &rate, &exist.transfer.currency, &exist.transfer.tocurrency)?;
+
+        // both legs move in opposite directions, the credit leg by the converted amount
+        let legs = [(exist.debit, 0, amount), (exist.credit, to_amount, 0)];
+        for (leg, credit, debit) in legs {
+            let leg = match leg {
+                Some(leg) => leg,
+                None => continue,
+            };
+
+            let update = UpdateTrx {
+                credit: Some(credit),
+                debit: Some(debit),
+                description: transfer.description.clone(),
+                datetime: transfer.datetime.clone(),
+                accountid: None,
+                categoryid: None,
+                splits: None,
+            };
+            let updated = trxs::update_trx_with_balance(db, &leg, update).await?;
+            audit::record(db, actor, AuditEntity::Trx, leg.id, AuditAction::Update, Some(&leg), Some(&updated)).await?;
+        }
+
+        // update transfer
+        query_update_transfer(db, id, &transfer, to_amount, &rate).await?;
+
+        Ok(())
+    }
+    .boxed()
+}
+
+/// Deletes a transfer and both of its legs, reverting their balances and
+/// logging each leg.
+pub fn delete_transfer<'a>(
+    db: &'a mut impl Executor,
+    actor: &'a Option<String>,
+    id: i32,
+) -> BoxFuture<'a, Result<(), AppError>> {
+    async move {
+
+        let exist = detail_transfer(db, id).await?;
+
+        for leg in [exist.debit, exist.credit].into_iter().flatten() {
+            trxs::delete_trx_with_balance(db, &leg).await?;
+
+            // transfer legs are gone for good, there is no state after
+            audit::record(db, actor, AuditEntity::Trx, leg.id, AuditAction::Delete, Some(&leg), None).await?;
+        }
+
+        // delete transfer
+        query_delete_transfer(db, id).await?;
+
+        Ok(())
+    }
+    .boxed()
+}
+
+/// Locks transfer `id` and both of its accounts, ahead of any read of their ledgers.
+pub fn lock_transfer_accounts<'a>(
+    db: &'a mut impl Executor,
+    id: i32,
+) -> BoxFuture<'a, Result<(), AppError>> {
+    async move {
+
+        let transfer = query_lock_transfer(db, id).await?;
+        accounts::lock_accounts(db, &[transfer.fromaccountid, transfer.toaccountid]).await?;
+
+        Ok(())
+    }
+    .boxed()
+}
+
+fn detail_transfer<'a>(
+    db: &'a mut impl Executor,
+    id: i32,
+) -> BoxFuture<'a, Result<ExistTransferWithTrxs, AppError>> {
+    async move {
+
+        let transfer = query_detail_transfer(db, id).await?;
+        let legs = query_transfer_trxs(db, &[id]).await?;
+
+        Ok(with_legs(transfer, &legs))
+    }
+    .boxed()
+}
+
+fn with_legs(transfer: ExistTransfer, legs: &[ExistTrx]) -> ExistTransferWithTrxs {
+    let leg = |account_id: i32| legs
+        .iter()
+        .find(|leg| leg.transferid == Some(transfer.id) && leg.accountid == account_id)
+        .cloned();
+
+    ExistTransferWithTrxs {
+        debit: leg(transfer.fromaccountid),
+        credit: leg(transfer.toaccountid),
+        transfer,
+    }
+}
+
+fn query_list_transfers<'a>(
+    db: &'a mut impl Executor,
+    page: &'a PageParams,
+) -> BoxFuture<'a, Result<Vec<ExistTransfer>, AppError>> {
+    async move {
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tbltransfers"#);
+        push_page(&mut query, page, "tbltransfers", false);
+
+        let transfers = query
+            .build_query_as::<ExistTransfer>()
+            .fetch_all(db.as_executor())
+            .await?;
+
+        Ok(transfers)
+    }
+    .boxed()
+}
+
+fn query_detail_transfer<'a>(
+    db: &'a mut impl Executor,
+    id: i32
+) -> BoxFuture<'a, Result<ExistTransfer, AppError>> {
+    async move {
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tbltransfers WHERE id = "#);
+
+        let transfer = query
+            .push_bind(id)
+            .build_query_as::<ExistTransfer>()
+            .fetch_optional(db.as_executor())
+            .await?;
+
+        transfer.ok_or_else(|| AppError::NotFound(format!("transfer {} not found", id)))
+    }
+    .boxed()
+}
+
+/// Like `query_detail_transfer`, a locking read that takes no snapshot.
+pub fn query_lock_transfer<'a>(
+    db: &'a mut impl Executor,
+    id: i32
+) -> BoxFuture<'a, Result<ExistTransfer, AppError>> {
+    async move {
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tbltransfers WHERE id = "#);
+
+        let transfer = query
+            .push_bind(id)
+            .push(" FOR UPDATE")
+            .build_query_as::<ExistTransfer>()
+            .fetch_optional(db.as_executor())
+            .await?;
+
+        transfer.ok_or_else(|| AppError::NotFound(format!("transfer {} not found", id)))
+    }
+    .boxed()
+}
+
+/// Transfers from or to account `acc_id`, locked.
+pub fn query_lock_account_transfers<'a>(
+    db: &'a mut impl Executor,
+    acc_id: i32
+) -> BoxFuture<'a, Result<Vec<ExistTransfer>, AppError>> {
+    async move {
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tbltransfers WHERE fromaccountid = "#);
+        query.push_bind(acc_id)
+            .push(" OR toaccountid = ").push_bind(acc_id)
+            .push(" FOR UPDATE");
+
+        let transfers = query
+            .build_query_as::<ExistTransfer>()
+            .fetch_all(db.as_executor())
+            .await?;
+
+        Ok(transfers)
+    }
+    .boxed()
+}
+
+fn query_transfer_trxs<'a>(
+    db: &'a mut impl Executor,
+    ids: &'a [i32],
+) -> BoxFuture<'a, Result<Vec<ExistTrx>, AppError>> {
+    async move {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tbltransactions WHERE transferid IN "#);
+        push_ids(&mut query, ids);
+
+        let trxs = query
+            .build_query_as::<ExistTrx>()
+            .fetch_all(db.as_executor())
+            .await?;
+
+        Ok(trxs)
+    }
+    .boxed()
+}
+
+/// Number of transfers between the two accounts, in either direction.
+pub fn query_count_transfers_between<'a>(
+    db: &'a mut impl Executor,
+    acc_id: i32,
+    other_id: i32,
+) -> BoxFuture<'a, Result<i64, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT COUNT(*) FROM tbltransfers WHERE (fromaccountid = "#);
+        query.push_bind(acc_id)
+            .push(" AND toaccountid = ").push_bind(other_id)
+            .push(") OR (fromaccountid = ").push_bind(other_id)
+            .push(" AND toaccountid = ").push_bind(acc_id)
+            .push(")");
+
+        let count = query
+            .build_query_scalar::<i64>()
+            .fetch_one(db.as_executor())
+            .await?;
+
+        Ok(count)
+    }
+    .boxed()
+}
+
+/// Points every transfer of account `from` at account `to`.
+pub fn query_reassign_transfers<'a>(
+    db: &'a mut impl Executor,
+    from: i32,
+    to: i32,
+) -> BoxFuture<'a, Result<(), AppError>> {
+    async move {
+
+        for column in ["fromaccountid", "toaccountid"] {
+            let mut query = sqlx::QueryBuilder::new(r#"UPDATE tbltransfers SET "#);
+            query.push(column).push(" = ").push_bind(to)
+                .push(" , updated_at = current_timestamp() ")
+                .push(" WHERE ").push(column).push(" = ").push_bind(from);
+
+            query
+                .build()
+                .execute(db.as_executor())
+                .await?;
+        }
+
+        Ok(())
+    }
+    .boxed()
+}
+
+fn query_add_transfer<'a>(
+    db: &'a mut impl Executor,
+    transfer: &'a NewTransfer,
+    (currency, to_currency): (&'a str, &'a str),
+    to_amount: i64,
+    rate: &'a BigDecimal,
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"INSERT INTO tbltransfers (amount, currency, toamount, tocurrency, rate, description, datetime, fromaccountid, toaccountid) VALUES ("#);
+
+        let mut separated = query.separated(", ");
+        separated.push_bind(transfer.amount);
+        separated.push_bind(currency);
+        separated.push_bind(to_amount);
+        separated.push_bind(to_currency);
+        separated.push_bind(rate.clone());
+        separated.push_bind(transfer.description.clone().unwrap_or_default());
+        separated.push_bind(transfer.datetime.clone());
+        separated.push_bind(transfer.fromaccountid);
+        separated.push_bind(transfer.toaccountid);
+        separated.push_unseparated(") ");
+
+        let add = query
+            .build()
+            .execute(db.as_executor())
+            .await?;
+
+        Ok(add)
+    }
+    .boxed()
+}
+
+fn query_update_transfer<'a>(
+    db: &'a mut impl Executor,
+    id: i32,
+    transfer: &'a UpdateTransfer,
+    to_amount: i64,
+    rate: &'a BigDecimal,
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"UPDATE tbltransfers SET "#);
+        let mut separated = query.separated(", ");
+
+        if let Some(amount) = transfer.amount {
+            separated.push("amount = ").push_bind_unseparated(amount);
+        }
+
+        // the converted amount follows the amount and the rate
+        separated.push("toamount = ").push_bind_unseparated(to_amount);
+        separated.push("rate = ").push_bind_unseparated(rate.clone());
+
+        if let Some(description) = &transfer.description {
+            separated.push("description = ").push_bind_unseparated(description.clone());
+        }
+
+        if let Some(datetime) = &transfer.datetime {
+            separated.push("datetime = ").push_bind_unseparated(datetime.clone());
+        }
+
+        separated
+            .push("updated_at = current_timestamp()")
+            .push_unseparated(" WHERE id = ")
+            .push_bind_unseparated(id);
+
+        let res = query.build()
+            .execute(db.as_executor())
+            .await?;
+
+        Ok(res)
+    }
+    .boxed()
+}
+
+fn query_delete_transfer<'a>(
+    db: &'a mut impl Executor,
+    id: i32,
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"DELETE FROM tbltransfers WHERE id = "#);
+        let res = query.push_bind(id)
+            .build()
+            .execute(db.as_executor())
+            .await?;
+
+        Ok(res)
+    }
+    .boxed()
+}
@@ -1,59 +1,108 @@
 
-use crate::models::trx_cat_budgets::{ ExistTrxCatBudget, NewTrxCatBudget, UpdateTrxCatBudget };
-use crate::repositories::{ Executor, UpdateQuery };
+use crate::models::trx_cat_budgets::{ ExistTrxCatBudget, NewTrxCatBudget, UpdateTrxCatBudget, RebuildTrxCatBudget, BudgetPeriod, BudgetSpent, CarryOver, rollover_budget, validate_new_budget, validate_thresholds, format_thresholds };
+use crate::models::exchange_rates::DEFAULT_CURRENCY;
+use crate::models::money::{ Money, THREE_DIGIT_CURRENCIES, ZERO_DIGIT_CURRENCIES, minor_digits };
+use crate::models::audit::{ AuditAction, AuditEntity };
+use crate::models::pagination::PageParams;
+use crate::errors::AppError;
+use crate::repositories::{ Executor, UpdateQuery, last_insert_id, push_ids, push_not_deleted, push_page };
+use crate::repositories::alerts;
+use crate::repositories::audit;
+use crate::repositories::trx_cats;
 
+use std::collections::HashMap;
 use futures_util::{future::BoxFuture, FutureExt};
-use sqlx::{MySql, MySqlPool};
+use sqlx::{MySql, MySqlPool, QueryBuilder};
+use sqlx::types::chrono::{DateTime, NaiveDate, Utc};
 use sqlx_mysql::MySqlQueryResult;
 
+/// Budgets whose `spent` is summed by one query, each id being bound twice.
+const SPENT_BATCH: usize = 1000;
+
 #[async_trait::async_trait]
 pub trait Trait: Send + Sync + TrxCatBudgetTrait {
     fn clone_boxed(&self) -> Box<dyn Trait>;
     async fn start_transaction(
         &self,
-    ) -> Result<Box<dyn TransactionTrait>, Box<dyn std::error::Error + Send + Sync + 'static>>;
+    ) -> Result<Box<dyn TransactionTrait>, AppError>;
 }
 
 #[async_trait::async_trait]
 pub trait TransactionTrait: Send + Sync + TrxCatBudgetTrait {
     async fn commit(
         self: Box<Self>,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>>;
+    ) -> Result<(), AppError>;
 }
 
 #[async_trait::async_trait]
 pub trait TrxCatBudgetTrait {
     async fn trx_cat_budget_list(
         &mut self,
-        categoryid: i32
-    ) -> Result<Vec<ExistTrxCatBudget>, Box<dyn std::error::Error + Send + Sync + 'static>>;
+        categoryid: i32,
+        page: PageParams,
+        include_deleted: bool,
+    ) -> Result<(Vec<ExistTrxCatBudget>, Option<String>), AppError>;
     async fn trx_cat_budget_detail(
         &mut self,
         id: i32,
-    ) -> Result<ExistTrxCatBudget, Box<dyn std::error::Error + Send + Sync + 'static>>;
+        include_deleted: bool,
+    ) -> Result<ExistTrxCatBudget, AppError>;
     async fn trx_cat_budget_add(
         &mut self,
         budget: NewTrxCatBudget,
-    ) -> Result<ExistTrxCatBudget, Box<dyn std::error::Error + Send + Sync + 'static>>;
+    ) -> Result<ExistTrxCatBudget, AppError>;
     async fn trx_cat_budget_update(
         &mut self,
         id: i32,
         budget: UpdateTrxCatBudget,
-    ) -> Result<ExistTrxCatBudget, Box<dyn std::error::Error + Send + Sync + 'static>>;
+    ) -> Result<ExistTrxCatBudget, AppError>;
     async fn trx_cat_budget_delete(
         &mut self,
         id: i32,
-    ) -> Result<ExistTrxCatBudget, Box<dyn std::error::Error + Send + Sync + 'static>>;
+    ) -> Result<ExistTrxCatBudget, AppError>;
+    async fn trx_cat_budget_restore(
+        &mut self,
+        id: i32,
+    ) -> Result<ExistTrxCatBudget, AppError>;
+    async fn trx_cat_budget_rollover(
+        &mut self,
+        today: NaiveDate,
+    ) -> Result<Vec<ExistTrxCatBudget>, AppError>;
+    async fn trx_cat_budget_rebuild(
+        &mut self,
+        rebuild: RebuildTrxCatBudget,
+    ) -> Result<Vec<ExistTrxCatBudget>, AppError>;
 }
 
 #[derive(Debug, Clone)]
 pub struct TrxCatBudgetRepo<E = MySqlPool> {
     db: E,
+    /// Who the audit log credits with the writes.
+    actor: Option<String>,
+    /// Currency the category budgets are kept in.
+    base_currency: String,
+    /// Whether `spent` and `available` are computed from the trxs when read.
+    derived_budgets: bool,
 }
 
 impl TrxCatBudgetRepo {
     pub fn new(pool: MySqlPool) -> Self {
-        Self { db: pool }
+        Self { db: pool, actor: None, base_currency: String::from(DEFAULT_CURRENCY), derived_budgets: false }
+    }
+
+    pub fn with_actor(mut self, actor: Option<String>) -> Self {
+        self.actor = actor;
+        self
+    }
+
+    pub fn with_base_currency(mut self, base_currency: &str) -> Self {
+        self.base_currency = base_currency.to_string();
+        self
+    }
+
+    pub fn with_derived_budgets(mut self, derived_budgets: bool) -> Self {
+        self.derived_budgets = derived_budgets;
+        self
     }
 }
 
@@ -65,10 +114,10 @@ impl Trait for TrxCatBudgetRepo {
 
     async fn start_transaction(
         &self,
-    ) -> Result<Box<dyn TransactionTrait>, Box<dyn std::error::Error + Send + Sync + 'static>> {
+    ) -> Result<Box<dyn TransactionTrait>, AppError> {
         let tx = self.db.begin().await?;
 
-        Ok(Box::new(TrxCatBudgetRepo { db: tx }))
+        Ok(Box::new(TrxCatBudgetRepo { db: tx, actor: self.actor.clone(), base_currency: self.base_currency.clone(), derived_budgets: self.derived_budgets }))
     }
 }
 
@@ -76,7 +125,7 @@ impl Trait for TrxCatBudgetRepo {
 impl TransactionTrait for TrxCatBudgetRepo<sqlx::Transaction<'static, MySql>> {
     async fn commit(
         self: Box<Self>,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+    ) -> Result<(), AppError> {
         self.db.commit().await?;
 
         Ok(())
@@ -87,33 +136,52 @@ impl TransactionTrait for TrxCatBudgetRepo<sqlx::Transaction<'static, MySql>> {
 impl<E: 'static + Executor> TrxCatBudgetTrait for TrxCatBudgetRepo<E> {
     async fn trx_cat_budget_list(
         &mut self,
-        categoryid: i32
-    ) -> Result<Vec<ExistTrxCatBudget>, Box<dyn std::error::Error + Send + Sync + 'static>> {
+        categoryid: i32,
+        page: PageParams,
+        include_deleted: bool,
+    ) -> Result<(Vec<ExistTrxCatBudget>, Option<String>), AppError> {
 
-        let budget = query_list_trx_cat_budget(&mut self.db, categoryid).await;
-        Ok(budget)
+        let mut budget = query_list_trx_cat_budget(&mut self.db, categoryid, &page, include_deleted).await?;
+        if self.derived_budgets {
+            derive_budgets(&mut self.db, &self.base_currency, budget.iter_mut()).await?;
+        }
+        Ok(page.to_page(budget))
     }
 
     async fn trx_cat_budget_detail(
         &mut self,
         id: i32,
-    ) -> Result<ExistTrxCatBudget, Box<dyn std::error::Error + Send + Sync + 'static>> {
+        include_deleted: bool,
+    ) -> Result<ExistTrxCatBudget, AppError> {
 
-        let budget = query_detail_trx_cat_budget(&mut self.db, id).await;
+        let mut budget = match include_deleted {
+            true => query_find_trx_cat_budget(&mut self.db, id).await?,
+            false => query_detail_trx_cat_budget(&mut self.db, id).await?,
+        };
+        if self.derived_budgets {
+            derive_budget(&mut self.db, &self.base_currency, &mut budget).await?;
+        }
         Ok(budget)
     }
 
     async fn trx_cat_budget_add(
         &mut self,
         add_budget: NewTrxCatBudget,
-    ) -> Result<ExistTrxCatBudget, Box<dyn std::error::Error + Send + Sync + 'static>> {
+    ) -> Result<ExistTrxCatBudget, AppError> {
+
+        // trx cat must exist and not be deleted
+        trx_cats::query_detail_trx_cats(&mut self.db, add_budget.categoryid).await?;
 
         // add trx cat budget
-        let add = query_add_trx_cat_budget(&mut self.db, add_budget).await;
-        let budget_id = i32::try_from(add.last_insert_id()).unwrap();
+        let budget_id = add_budget_period(&mut self.db, add_budget).await?;
 
-        // detail trx cat budget
-        let budget = query_detail_trx_cat_budget(&mut self.db, budget_id).await;
+        // detail trx cat budget, derived ones start from the trxs already made in the period
+        let mut budget = query_detail_trx_cat_budget(&mut self.db, budget_id).await?;
+        if self.derived_budgets {
+            budget = rebuild_budget(&mut self.db, &self.base_currency, &budget).await?;
+        }
+        alerts::record_crossed(&mut self.db, &budget).await?;
+        audit::record(&mut self.db, &self.actor, AuditEntity::TrxCatBudget, budget_id, AuditAction::Add, None, Some(&budget)).await?;
         Ok(budget)
     }
 
@@ -121,46 +189,385 @@ impl<E: 'static + Executor> TrxCatBudgetTrait for TrxCatBudgetRepo<E> {
         &mut self,
         id: i32,
         budget: UpdateTrxCatBudget,
-    ) -> Result<ExistTrxCatBudget, Box<dyn std::error::Error + Send + Sync + 'static>> {
+    ) -> Result<ExistTrxCatBudget, AppError> {
+
+        let before = query_detail_trx_cat_budget(&mut self.db, id).await?;
+
+        if self.derived_budgets && (budget.spent.is_some() || budget.available.is_some()) {
+            return Err(AppError::Validation(String::from("budget spent and available are derived from the transactions")));
+        }
+
+        if let Some(carry_over) = &budget.carry_over {
+            carry_over.parse::<CarryOver>()?;
+        }
+
+        if let Some(thresholds) = &budget.thresholds {
+            validate_thresholds(thresholds)?;
+        }
+
+        // a new period or category must not overlap another budget of the category
+        let period = match &budget.periode {
+            Some(periode) => Some(BudgetPeriod::parse(periode)?),
+            None => BudgetPeriod::of(&before)?,
+        };
+        let cat_id = budget.categoryid.unwrap_or(before.categoryid);
+        if budget.categoryid.is_some() {
+            trx_cats::query_detail_trx_cats(&mut self.db, cat_id).await?;
+        }
+        if let Some(period) = &period {
+            validate_no_overlap(&mut self.db, cat_id, period, Some(id)).await?;
+        }
+
+        // counters not set in the request follow the new period, category or allocation
+        let moved = budget.periode.is_some() || budget.categoryid.is_some();
+        let reallocated = budget.allocated.is_some();
+        let spent_set = budget.spent.is_some();
+        let available_set = budget.available.is_some();
 
         // update trx cat budget
-        let _ = query_update_trx_cat_budget(&mut self.db, id, budget).await;
+        let period = budget.periode.as_ref().and(period);
+        query_update_trx_cat_budget(&mut self.db, id, budget, period).await?;
 
-        // trx cat budget detail
-        let budget = query_detail_trx_cat_budget(&mut self.db, id).await;
+        // trx cat budget detail, the spent of the old period or category is left behind
+        let mut budget = query_detail_trx_cat_budget(&mut self.db, id).await?;
+        if self.derived_budgets || (moved && !spent_set) {
+            budget = rebuild_budget(&mut self.db, &self.base_currency, &budget).await?;
+        }
+        if !self.derived_budgets && (moved || reallocated) && !available_set {
+            let mut followed = budget.clone();
+            followed.set_spent(budget.spent.to_minor()?);
+            if followed != budget {
+                query_set_budget_spent(&mut self.db, id, followed.spent.to_minor()?, followed.available.to_minor()?).await?;
+                budget = query_detail_trx_cat_budget(&mut self.db, id).await?;
+            }
+        }
+        // a lower allocation or a new spent may reach a threshold
+        alerts::record_crossed(&mut self.db, &budget).await?;
+        audit::record(&mut self.db, &self.actor, AuditEntity::TrxCatBudget, id, AuditAction::Update, Some(&before), Some(&budget)).await?;
         Ok(budget)
     }
 
     async fn trx_cat_budget_delete(
         &mut self,
         id: i32,
-    ) -> Result<ExistTrxCatBudget, Box<dyn std::error::Error + Send + Sync + 'static>> {
+    ) -> Result<ExistTrxCatBudget, AppError> {
 
         // trx cat budget detail
-        let budget = query_detail_trx_cat_budget(&mut self.db, id).await;
+        let budget = query_detail_trx_cat_budget(&mut self.db, id).await?;
 
         // delete trx cat budget
-        let _ = query_delete_cat_budget(&mut self.db, id).await;
+        query_delete_cat_budget(&mut self.db, id).await?;
+
+        let after = query_find_trx_cat_budget(&mut self.db, id).await?;
+        audit::record(&mut self.db, &self.actor, AuditEntity::TrxCatBudget, id, AuditAction::Delete, Some(&budget), Some(&after)).await?;
+
+        Ok(budget)
+    }
+
+    async fn trx_cat_budget_restore(
+        &mut self,
+        id: i32,
+    ) -> Result<ExistTrxCatBudget, AppError> {
 
+        // trx cat budget detail
+        let budget = query_find_trx_cat_budget(&mut self.db, id).await?;
+        if budget.deleted_at.is_none() {
+            return Err(AppError::Conflict(format!("budget {} is not deleted", id)));
+        }
+
+        // trx cat has to be restored first
+        let category = trx_cats::query_find_trx_cats(&mut self.db, budget.categoryid).await?;
+        if category.deleted_at.is_some() {
+            return Err(AppError::Conflict(format!("category {} of budget {} is deleted", category.id, id)));
+        }
+
+        // restore trx cat budget
+        query_restore_cat_budget(&mut self.db, id).await?;
+
+        let before = budget;
+        let budget = query_detail_trx_cat_budget(&mut self.db, id).await?;
+        audit::record(&mut self.db, &self.actor, AuditEntity::TrxCatBudget, id, AuditAction::Restore, Some(&before), Some(&budget)).await?;
         Ok(budget)
     }
+
+    /// Creates, for each category whose latest period ended before `today`,
+    /// the budgets of the periods up to the one containing `today`, each from
+    /// the one before it.
+    async fn trx_cat_budget_rollover(
+        &mut self,
+        today: NaiveDate,
+    ) -> Result<Vec<ExistTrxCatBudget>, AppError> {
+
+        let mut created: Vec<ExistTrxCatBudget> = Vec::new();
+
+        for mut budget in query_rollover_budgets(&mut self.db).await? {
+            let mut period = match BudgetPeriod::of(&budget)? {
+                Some(period) => period,
+                None => continue,
+            };
+
+            while period.end < today {
+                let next = match period.next() {
+                    Some(next) => next,
+                    None => break,
+                };
+
+                // a budget added by hand for the next period stops the rollover
+                if query_overlapping_budget(&mut self.db, budget.categoryid, &next, None).await?.is_some() {
+                    break;
+                }
+
+                // what is carried over follows the trxs, not the stored counters
+                if self.derived_budgets {
+                    derive_budget(&mut self.db, &self.base_currency, &mut budget).await?;
+                }
+
+                let budget_id = add_budget_period(&mut self.db, rollover_budget(&budget, &next)?).await?;
+                budget = query_detail_trx_cat_budget(&mut self.db, budget_id).await?;
+                audit::record(&mut self.db, &self.actor, AuditEntity::TrxCatBudget, budget_id, AuditAction::Add, None, Some(&budget)).await?;
+
+                created.push(budget.clone());
+                period = next;
+            }
+        }
+
+        Ok(created)
+    }
+
+    /// Recomputes `spent` and `available` of the budgets of `periode` from
+    /// the trxs, of one category or of every category.
+    async fn trx_cat_budget_rebuild(
+        &mut self,
+        rebuild: RebuildTrxCatBudget,
+    ) -> Result<Vec<ExistTrxCatBudget>, AppError> {
+
+        let period = BudgetPeriod::parse(&rebuild.periode)?;
+        let budgets = query_period_budgets(&mut self.db, &period, rebuild.categoryid).await?;
+
+        if let Some(cat_id) = rebuild.categoryid {
+            trx_cats::query_detail_trx_cats(&mut self.db, cat_id).await?;
+            if budgets.is_empty() {
+                return Err(AppError::NotFound(format!("category {} has no budget for {}", cat_id, period.label())));
+            }
+        }
+
+        let rebuilt = rebuild_budgets(&mut self.db, &self.base_currency, &budgets).await?;
+        for (budget, before) in rebuilt.iter().zip(budgets.iter()) {
+            if budget != before {
+                alerts::record_crossed(&mut self.db, budget).await?;
+                audit::record(&mut self.db, &self.actor, AuditEntity::TrxCatBudget, budget.id, AuditAction::Update, Some(before), Some(budget)).await?;
+            }
+        }
+
+        Ok(rebuilt)
+    }
+}
+
+/// What the trxs of each budget of `ids` dated within its period spend, or
+/// receive for an income category, in the base currency. Budgets no trx is
+/// charged to are left out, as are those without a period.
+pub fn budgets_spent<'a>(
+    db: &'a mut impl Executor,
+    base_currency: &'a str,
+    ids: &'a [i32],
+) -> BoxFuture<'a, Result<HashMap<i32, i64>, AppError>> {
+    async move {
+
+        let mut spent: HashMap<i32, i64> = HashMap::new();
+        for chunk in ids.chunks(SPENT_BATCH) {
+            for row in query_budgets_spent(db, base_currency, chunk).await? {
+                if let Some(date) = row.missing {
+                    return Err(AppError::Validation(format!("no exchange rate from {} to {} on or before {}", row.currency, base_currency, date)));
+                }
+
+                let amount = row.spent.to_minor()?;
+                let total = spent.entry(row.budgetid).or_default();
+                *total = match total.checked_add(amount) {
+                    Some(sum) => sum,
+                    // refused the way a single amount out of range is
+                    None => (&Money::from_minor(*total) + &row.spent).to_minor()?,
+                };
+            }
+        }
+
+        Ok(spent)
+    }
+    .boxed()
+}
+
+/// Replaces the stored `spent` and `available` of `budgets` by the ones
+/// computed from the trxs, without writing them.
+pub fn derive_budgets<'a, I>(
+    db: &'a mut impl Executor,
+    base_currency: &'a str,
+    budgets: I,
+) -> BoxFuture<'a, Result<(), AppError>>
+where
+    I: IntoIterator<Item = &'a mut ExistTrxCatBudget> + Send + 'a,
+    I::IntoIter: Send,
+{
+    async move {
+
+        let budgets: Vec<&mut ExistTrxCatBudget> = budgets.into_iter().collect();
+        let ids: Vec<i32> = budgets.iter().map(|budget| budget.id).collect();
+        let spent = budgets_spent(db, base_currency, &ids).await?;
+
+        for budget in budgets {
+            if BudgetPeriod::of(budget)?.is_some() {
+                budget.set_spent(spent.get(&budget.id).copied().unwrap_or(0));
+            }
+        }
+
+        Ok(())
+    }
+    .boxed()
+}
+
+/// [`derive_budgets`] of a single budget.
+pub fn derive_budget<'a>(
+    db: &'a mut impl Executor,
+    base_currency: &'a str,
+    budget: &'a mut ExistTrxCatBudget,
+) -> BoxFuture<'a, Result<(), AppError>> {
+    derive_budgets(db, base_currency, std::iter::once(budget))
+}
+
+/// Writes the `spent` and `available` computed from the trxs back to each of
+/// `budgets` that drifted, returning them as stored.
+pub fn rebuild_budgets<'a>(
+    db: &'a mut impl Executor,
+    base_currency: &'a str,
+    budgets: &'a [ExistTrxCatBudget],
+) -> BoxFuture<'a, Result<Vec<ExistTrxCatBudget>, AppError>> {
+    async move {
+
+        let mut rebuilt: Vec<ExistTrxCatBudget> = budgets.to_vec();
+        derive_budgets(db, base_currency, rebuilt.iter_mut()).await?;
+
+        for (budget, before) in rebuilt.iter_mut().zip(budgets.iter()) {
+            if budget != before {
+                query_set_budget_spent(db, budget.id, budget.spent.to_minor()?, budget.available.to_minor()?).await?;
+                *budget = query_detail_trx_cat_budget(db, budget.id).await?;
+            }
+        }
+
+        Ok(rebuilt)
+    }
+    .boxed()
+}
+
+/// [`rebuild_budgets`] of a single budget.
+pub fn rebuild_budget<'a>(
+    db: &'a mut impl Executor,
+    base_currency: &'a str,
+    budget: &'a ExistTrxCatBudget,
+) -> BoxFuture<'a, Result<ExistTrxCatBudget, AppError>> {
+    async move {
+
+        let mut rebuilt = rebuild_budgets(db, base_currency, std::slice::from_ref(budget)).await?;
+        match rebuilt.pop() {
+            Some(budget) => Ok(budget),
+            None => Err(AppError::NotFound(format!("budget {} not found", budget.id))),
+        }
+    }
+    .boxed()
+}
+
+/// Recomputes every dated budget from the trxs, returns how many changed.
+pub fn rebuild_all_budgets<'a>(
+    db: &'a mut impl Executor,
+    base_currency: &'a str,
+) -> BoxFuture<'a, Result<usize, AppError>> {
+    async move {
+
+        let budgets = query_all_budgets(db).await?;
+        let rebuilt = rebuild_budgets(db, base_currency, &budgets).await?;
+
+        Ok(rebuilt.iter().zip(budgets.iter()).filter(|(budget, before)| budget != before).count())
+    }
+    .boxed()
+}
+
+/// Recomputes from the trxs every dated budget of the categories `ids`, once
+/// their trxs count another way. A trx is released by the direction its
+/// category has then, so without this it would take back from `spent` what
+/// it never added.
+pub fn rebuild_category_budgets<'a>(
+    db: &'a mut impl Executor,
+    base_currency: &'a str,
+    ids: &'a [i32],
+) -> BoxFuture<'a, Result<(), AppError>> {
+    async move {
+
+        let budgets = query_dated_budgets_by_catids(db, ids).await?;
+        for budget in rebuild_budgets(db, base_currency, &budgets).await? {
+            alerts::record_crossed(db, &budget).await?;
+        }
+
+        Ok(())
+    }
+    .boxed()
+}
+
+/// Adds a budget for the period its `periode` names, refusing one that
+/// overlaps another budget of the category. Returns the id of the budget.
+pub fn add_budget_period<'a>(
+    db: &'a mut impl Executor,
+    budget: NewTrxCatBudget,
+) -> BoxFuture<'a, Result<i32, AppError>> {
+    async move {
+
+        validate_new_budget(&budget)?;
+
+        let period = BudgetPeriod::parse(&budget.periode)?;
+        validate_no_overlap(db, budget.categoryid, &period, None).await?;
+
+        let add = query_add_trx_cat_budget(db, budget, &period).await?;
+        let budget_id = last_insert_id(&add)?;
+
+        Ok(budget_id)
+    }
+    .boxed()
+}
+
+fn validate_no_overlap<'a>(
+    db: &'a mut impl Executor,
+    categoryid: i32,
+    period: &'a BudgetPeriod,
+    exclude_id: Option<i32>,
+) -> BoxFuture<'a, Result<(), AppError>> {
+    async move {
+
+        if let Some(other) = query_overlapping_budget(db, categoryid, period, exclude_id).await? {
+            return Err(AppError::Conflict(format!(
+                "category {} already has budget {} for {}, overlapping {}",
+                categoryid, other.id, other.periode, period.label(),
+            )));
+        }
+
+        Ok(())
+    }
+    .boxed()
 }
 
 pub fn query_list_trx_cat_budget<'a>(
     db: &'a mut impl Executor,
-    categoryid: i32
-) -> BoxFuture<'a, Vec<ExistTrxCatBudget>> {
+    categoryid: i32,
+    page: &'a PageParams,
+    include_deleted: bool,
+) -> BoxFuture<'a, Result<Vec<ExistTrxCatBudget>, AppError>> {
     async move {
         let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tblcategorybudgets WHERE categoryid = "#);
+        query.push_bind(categoryid);
+        push_not_deleted(&mut query, "tblcategorybudgets", include_deleted, true);
+        push_page(&mut query, page, "tblcategorybudgets", true);
 
         let trx = query
-            .push_bind(categoryid)
             .build_query_as::<ExistTrxCatBudget>()
             .fetch_all(db.as_executor())
-            .await
-            .unwrap();
+            .await?;
 
-        trx
+        Ok(trx)
     }
     .boxed()
 }
@@ -168,18 +575,37 @@ pub fn query_list_trx_cat_budget<'a>(
 pub fn query_detail_trx_cat_budget<'a>(
     db: &'a mut impl Executor,
     id: i32
-) -> BoxFuture<'a, ExistTrxCatBudget> {
+) -> BoxFuture<'a, Result<ExistTrxCatBudget, AppError>> {
     async move {
         let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tblcategorybudgets WHERE id = "#);
 
         let trx = query
             .push_bind(id)
+            .push(" AND deleted_at IS NULL")
             .build_query_as::<ExistTrxCatBudget>()
-            .fetch_one(db.as_executor())
-            .await
-            .unwrap();
+            .fetch_optional(db.as_executor())
+            .await?;
 
-        trx
+        trx.ok_or_else(|| AppError::NotFound(format!("budget {} not found", id)))
+    }
+    .boxed()
+}
+
+/// Like `query_detail_trx_cat_budget`, deleted budgets included.
+pub fn query_find_trx_cat_budget<'a>(
+    db: &'a mut impl Executor,
+    id: i32
+) -> BoxFuture<'a, Result<ExistTrxCatBudget, AppError>> {
+    async move {
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tblcategorybudgets WHERE id = "#);
+
+        let trx = query
+            .push_bind(id)
+            .build_query_as::<ExistTrxCatBudget>()
+            .fetch_optional(db.as_executor())
+            .await?;
+
+        trx.ok_or_else(|| AppError::NotFound(format!("budget {} not found", id)))
     }
     .boxed()
 }
@@ -187,23 +613,273 @@ pub fn query_detail_trx_cat_budget<'a>(
 pub fn query_latest_trx_cat_budget_by_catid<'a>(
     db: &'a mut impl Executor,
     id: i32
-) -> BoxFuture<'a, Option<ExistTrxCatBudget>> {
+) -> BoxFuture<'a, Result<Option<ExistTrxCatBudget>, AppError>> {
     async move {
-        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tblcategorybudgets WHERE categoryid = "#);
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tblcategorybudgets WHERE deleted_at IS NULL AND categoryid = "#);
         query
             .push_bind(id)
             .push(" ORDER By id DESC")
             .push(" LIMIT 1");
 
-        let result = query
+        let budget = query
             .build_query_as::<ExistTrxCatBudget>()
-            .fetch_one(db.as_executor())
-            .await;
+            .fetch_optional(db.as_executor())
+            .await?;
+
+        Ok(budget)
+    }
+    .boxed()
+}
 
-        match result {
-            Err(sqlx::Error::RowNotFound) => None,
-            _ => Some(result.unwrap()),
+/// Latest budget of each category, keyed by category id, in a single round-trip.
+pub fn query_latest_budgets_by_catids<'a>(
+    db: &'a mut impl Executor,
+    ids: &'a [i32],
+) -> BoxFuture<'a, Result<HashMap<i32, ExistTrxCatBudget>, AppError>> {
+    async move {
+        if ids.is_empty() {
+            return Ok(HashMap::new());
         }
+
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT budget.* FROM tblcategorybudgets budget
+            INNER JOIN (
+                SELECT MAX(id) AS id FROM tblcategorybudgets WHERE deleted_at IS NULL AND categoryid IN "#);
+        push_ids(&mut query, ids);
+        query.push(r#" GROUP by categoryid
+            ) latest ON latest.id = budget.id"#);
+
+        let budgets = query
+            .build_query_as::<ExistTrxCatBudget>()
+            .fetch_all(db.as_executor())
+            .await?;
+
+        Ok(budgets.into_iter().map(|budget| (budget.categoryid, budget)).collect())
+    }
+    .boxed()
+}
+
+/// Budgets with a period of the categories `ids` that are not deleted.
+fn query_dated_budgets_by_catids<'a>(
+    db: &'a mut impl Executor,
+    ids: &'a [i32],
+) -> BoxFuture<'a, Result<Vec<ExistTrxCatBudget>, AppError>> {
+    async move {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tblcategorybudgets WHERE deleted_at IS NULL AND periodstart IS NOT NULL AND categoryid IN "#);
+        push_ids(&mut query, ids);
+
+        let budgets = query
+            .build_query_as::<ExistTrxCatBudget>()
+            .fetch_all(db.as_executor())
+            .await?;
+
+        Ok(budgets)
+    }
+    .boxed()
+}
+
+/// Every budget that is not deleted, grouped by category and in creation order within one.
+pub fn query_all_budgets<'a>(
+    db: &'a mut impl Executor,
+) -> BoxFuture<'a, Result<Vec<ExistTrxCatBudget>, AppError>> {
+    async move {
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tblcategorybudgets WHERE deleted_at IS NULL ORDER BY categoryid ASC, id ASC"#);
+
+        let budgets = query
+            .build_query_as::<ExistTrxCatBudget>()
+            .fetch_all(db.as_executor())
+            .await?;
+
+        Ok(budgets)
+    }
+    .boxed()
+}
+
+/// Sums the trx lines charged to the budgets `ids` the way `trxs::budget_lines`
+/// charges them: the line of the category or each split line in it, signed
+/// by the direction of its type, converted and rounded one by one at the
+/// rate of its day.
+fn query_budgets_spent<'a>(
+    db: &'a mut impl Executor,
+    base_currency: &'a str,
+    ids: &'a [i32],
+) -> BoxFuture<'a, Result<Vec<BudgetSpent>, AppError>> {
+    async move {
+
+        let lines = [
+            ("t.credit - t.debit", "JOIN tbltransactions t ON t.categoryid = b.categoryid"),
+            (
+                "IF(t.debit > t.credit, -s.amount, s.amount)",
+                "JOIN tbltransactionsplits s ON s.categoryid = b.categoryid
+                JOIN tbltransactions t ON t.id = s.transactionid AND t.categoryid IS NULL",
+            ),
+        ];
+        let base_unit: i64 = 10_i64.pow(minor_digits(base_currency));
+
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT l.budgetid, l.currency,
+            COALESCE(SUM(ROUND(l.amount, 0)), 0) AS spent,
+            MIN(IF(l.amount IS NULL, l.day, NULL)) AS missing
+            FROM (SELECT v.budgetid, v.currency, v.day, IF(v.currency = "#);
+        query.push_bind(base_currency)
+            .push(r#" OR v.amount = 0, v.amount, (
+                SELECT IF(r.currency = v.currency, v.amount * r.rate, CAST(v.amount AS DECIMAL(65, 30)) / r.rate)
+                FROM tblexchangerates r
+                WHERE ((r.currency = v.currency AND r.base = "#).push_bind(base_currency)
+            .push(") OR (r.currency = ").push_bind(base_currency)
+            .push(r#" AND r.base = v.currency)) AND r.date <= v.day
+                ORDER BY r.date DESC, (r.currency = v.currency) DESC LIMIT 1
+            )) AS amount FROM ("#);
+
+        // amounts are moved to the minor unit of the base currency before the rate applies
+        for (i, (amount, join)) in lines.iter().enumerate() {
+            if i > 0 {
+                query.push(" UNION ALL ");
+            }
+            query.push(format!(r#"SELECT b.id AS budgetid, a.currency, DATE(t.datetime) AS day,
+                IF(ct.direction = 'income', 1, -1) * ({}) * "#, amount))
+                .push_bind(base_unit)
+                .push(" / CASE WHEN a.currency IN ");
+            push_currencies(&mut query, &ZERO_DIGIT_CURRENCIES);
+            query.push(" THEN 1 WHEN a.currency IN ");
+            push_currencies(&mut query, &THREE_DIGIT_CURRENCIES);
+            query.push(format!(r#" THEN 1000 ELSE 100 END AS amount
+                FROM tblcategorybudgets b
+                JOIN tbltransactioncategories c ON c.id = b.categoryid
+                JOIN tblcategorytypes ct ON ct.id = c.typeid
+                {}
+                JOIN tblaccounts a ON a.id = t.accountid
+                WHERE ct.direction IN ('income', 'expense') AND t.deleted_at IS NULL
+                AND t.datetime >= b.periodstart AND t.datetime < DATE_ADD(b.periodend, INTERVAL 1 DAY)
+                AND b.id IN "#, join));
+            push_ids(&mut query, ids);
+        }
+        query.push(") v) l GROUP BY l.budgetid, l.currency");
+
+        let spent = query
+            .build_query_as::<BudgetSpent>()
+            .fetch_all(db.as_executor())
+            .await?;
+
+        Ok(spent)
+    }
+    .boxed()
+}
+
+fn push_currencies(query: &mut QueryBuilder<'_, MySql>, currencies: &[&'static str]) {
+    query.push("(");
+    let mut separated = query.separated(", ");
+    for currency in currencies.iter() {
+        separated.push_bind(*currency);
+    }
+    separated.push_unseparated(")");
+}
+
+/// Budget of `categoryid`, other than `exclude_id`, whose period overlaps `period`.
+pub fn query_overlapping_budget<'a>(
+    db: &'a mut impl Executor,
+    categoryid: i32,
+    period: &'a BudgetPeriod,
+    exclude_id: Option<i32>,
+) -> BoxFuture<'a, Result<Option<ExistTrxCatBudget>, AppError>> {
+    async move {
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tblcategorybudgets WHERE deleted_at IS NULL AND categoryid = "#);
+        query.push_bind(categoryid)
+            .push(" AND periodstart <= ").push_bind(period.end)
+            .push(" AND periodend >= ").push_bind(period.start);
+
+        if let Some(id) = exclude_id {
+            query.push(" AND id <> ").push_bind(id);
+        }
+
+        let budget = query
+            .push(" LIMIT 1")
+            .build_query_as::<ExistTrxCatBudget>()
+            .fetch_optional(db.as_executor())
+            .await?;
+
+        Ok(budget)
+    }
+    .boxed()
+}
+
+/// Budgets of `period`, of one category or of every category still in use.
+fn query_period_budgets<'a>(
+    db: &'a mut impl Executor,
+    period: &'a BudgetPeriod,
+    categoryid: Option<i32>,
+) -> BoxFuture<'a, Result<Vec<ExistTrxCatBudget>, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT budget.* FROM tblcategorybudgets budget
+            INNER JOIN tbltransactioncategories category ON category.id = budget.categoryid AND category.deleted_at IS NULL
+            WHERE budget.deleted_at IS NULL AND budget.periodtype = "#);
+        query.push_bind(period.kind.as_str())
+            .push(" AND budget.periodstart = ").push_bind(period.start);
+
+        if let Some(categoryid) = categoryid {
+            query.push(" AND budget.categoryid = ").push_bind(categoryid);
+        }
+        query.push(" ORDER BY budget.categoryid ASC");
+
+        let budgets = query
+            .build_query_as::<ExistTrxCatBudget>()
+            .fetch_all(db.as_executor())
+            .await?;
+
+        Ok(budgets)
+    }
+    .boxed()
+}
+
+/// Latest budgets of the categories of type `typeid` still in use.
+pub fn query_latest_budgets_by_typeid<'a>(
+    db: &'a mut impl Executor,
+    typeid: i32,
+) -> BoxFuture<'a, Result<Vec<ExistTrxCatBudget>, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT budget.* FROM tblcategorybudgets budget
+            INNER JOIN tbltransactioncategories category ON category.id = budget.categoryid AND category.deleted_at IS NULL
+            WHERE category.typeid = "#);
+        query.push_bind(typeid)
+            .push(r#" AND budget.id = (
+                SELECT MAX(latest.id) FROM tblcategorybudgets latest
+                WHERE latest.deleted_at IS NULL AND latest.categoryid = budget.categoryid
+            )"#);
+
+        let budgets = query
+            .build_query_as::<ExistTrxCatBudget>()
+            .fetch_all(db.as_executor())
+            .await?;
+
+        Ok(budgets)
+    }
+    .boxed()
+}
+
+/// Latest dated budget of each category still in use, when it rolls over.
+fn query_rollover_budgets<'a>(
+    db: &'a mut impl Executor,
+) -> BoxFuture<'a, Result<Vec<ExistTrxCatBudget>, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT budget.* FROM tblcategorybudgets budget
+            INNER JOIN tbltransactioncategories category ON category.id = budget.categoryid AND category.deleted_at IS NULL
+            WHERE budget.deleted_at IS NULL AND budget.rollover = 1 AND budget.periodstart = (
+                SELECT MAX(latest.periodstart) FROM tblcategorybudgets latest
+                WHERE latest.deleted_at IS NULL AND latest.categoryid = budget.categoryid
+            )
+            ORDER BY budget.categoryid ASC, budget.id ASC"#);
+
+        let budgets = query
+            .build_query_as::<ExistTrxCatBudget>()
+            .fetch_all(db.as_executor())
+            .await?;
+
+        Ok(budgets)
     }
     .boxed()
 }
@@ -211,18 +887,31 @@ pub fn query_latest_trx_cat_budget_by_catid<'a>(
 pub fn query_add_trx_cat_budget<'a>(
     db: &'a mut impl Executor,
     trx_cat_budget: NewTrxCatBudget,
-) -> BoxFuture<'a, MySqlQueryResult> {
+    period: &'a BudgetPeriod,
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
     async move {
 
+        let carry_over = match &trx_cat_budget.carry_over {
+            Some(carry_over) => carry_over.parse::<CarryOver>()?,
+            None => CarryOver::default(),
+        };
+
         let values = vec![
-            trx_cat_budget.periode.to_string(),
+            period.label(),
+            period.kind.as_str().to_string(),
+            period.start.to_string(),
+            period.end.to_string(),
             trx_cat_budget.allocated.to_string(),
+            trx_cat_budget.carried.to_string(),
             trx_cat_budget.spent.to_string(),
             trx_cat_budget.available.to_string(),
+            carry_over.as_str().to_string(),
+            (trx_cat_budget.rollover.unwrap_or(true) as i32).to_string(),
+            format_thresholds(trx_cat_budget.thresholds.as_deref().unwrap_or_default()),
             trx_cat_budget.categoryid.to_string(),
         ];
 
-        let mut query = sqlx::QueryBuilder::new(r#"INSERT INTO tblcategorybudgets (periode, allocated, spent, available, categoryid) VALUES ("#);
+        let mut query = sqlx::QueryBuilder::new(r#"INSERT INTO tblcategorybudgets (periode, periodtype, periodstart, periodend, allocated, carried, spent, available, carryover, rollover, thresholds, categoryid) VALUES ("#);
 
         let mut separated = query.separated(", ");
         for value in values.iter() {
@@ -233,36 +922,97 @@ pub fn query_add_trx_cat_budget<'a>(
         let add = query
             .build()
             .execute(db.as_executor())
-            .await
-            .unwrap();
+            .await?;
 
-        add
+        Ok(add)
     }
     .boxed()
 }
 
+/// Adds `amount` to the `spent` of the budget of the category whose period
+/// contains `date`, if there is one. A refund is a negative amount.
 pub fn query_update_trx_cat_badget<'a>(
     db: &'a mut impl Executor,
     catid: i32,
+    date: NaiveDate,
+    amount: i64,
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"UPDATE tblcategorybudgets SET "#);
+        query.push(" spent = spent +  ").push_bind(amount)
+            .push(" , available = available - ").push_bind(amount)
+            .push(" , updated_at = current_timestamp() ")
+            .push(" WHERE deleted_at IS NULL AND categoryid = ").push_bind(catid)
+            .push(" AND periodstart <= ").push_bind(date)
+            .push(" AND periodend >= ").push_bind(date)
+            .push(" LIMIT 1 ");
+
+        let res = query
+            .build()
+            .execute(db.as_executor())
+            .await?;
+
+        // a charge may take spent up to a threshold of the budget
+        if res.rows_affected() > 0 && amount > 0 {
+            if let Some(budget) = query_budget_on(db, catid, date).await? {
+                alerts::record_crossed(db, &budget).await?;
+            }
+        }
+
+        Ok(res)
+    }
+    .boxed()
+}
+
+/// Budget of the category whose period contains `date`.
+fn query_budget_on<'a>(
+    db: &'a mut impl Executor,
+    catid: i32,
+    date: NaiveDate,
+) -> BoxFuture<'a, Result<Option<ExistTrxCatBudget>, AppError>> {
+    async move {
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tblcategorybudgets WHERE deleted_at IS NULL AND categoryid = "#);
+        query.push_bind(catid)
+            .push(" AND periodstart <= ").push_bind(date)
+            .push(" AND periodend >= ").push_bind(date)
+            .push(" LIMIT 1");
+
+        let budget = query
+            .build_query_as::<ExistTrxCatBudget>()
+            .fetch_optional(db.as_executor())
+            .await?;
+
+        Ok(budget)
+    }
+    .boxed()
+}
+
+/// Takes the amount of a removed trx dated `date` back out of the budget of
+/// the category whose period contains that date.
+pub fn query_release_trx_cat_badget<'a>(
+    db: &'a mut impl Executor,
+    catid: i32,
+    date: NaiveDate,
     amount: i64,
-) -> BoxFuture<'a, MySqlQueryResult> {
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
     async move {
 
-        let u_amount = if amount.is_negative() == false {amount} else {amount * -1};
         let mut query = sqlx::QueryBuilder::new(r#"UPDATE tblcategorybudgets SET "#);
-        query.push(" spent = spent +  ").push_bind(u_amount)
-            .push(" , available = available - ").push_bind(u_amount)
+        query.push(" spent = spent - ").push_bind(amount)
+            .push(" , available = available + ").push_bind(amount)
             .push(" , updated_at = current_timestamp() ")
-            .push(" WHERE categoryid = ").push_bind(catid)
-            .push(" ORDER By id DESC LIMIT 1 ");
+            .push(" WHERE deleted_at IS NULL AND categoryid = ").push_bind(catid)
+            .push(" AND periodstart <= ").push_bind(date)
+            .push(" AND periodend >= ").push_bind(date)
+            .push(" LIMIT 1 ");
 
         let res = query
             .build()
             .execute(db.as_executor())
-            .await
-            .unwrap();
+            .await?;
 
-        res
+        Ok(res)
     }
     .boxed()
 }
@@ -271,17 +1021,19 @@ pub fn query_update_trx_cat_budget<'a>(
     db: &'a mut impl Executor,
     id: i32,
     trx_cat_budget: UpdateTrxCatBudget,
-) -> BoxFuture<'a, MySqlQueryResult> {
+    period: Option<BudgetPeriod>,
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
     async move {
 
         let mut query = sqlx::QueryBuilder::new(r#"UPDATE tblcategorybudgets SET "#);
         let mut updates: Vec<UpdateQuery> = Vec::new();
 
-        if trx_cat_budget.periode.is_some() {
-            updates.push(UpdateQuery {
-                key: "periode".to_string(),
-                value: trx_cat_budget.periode.unwrap().to_string(),
-            })
+        // the label is written the canonical way, with the dates it stands for
+        if let Some(period) = period {
+            updates.push(UpdateQuery { key: "periode".to_string(), value: period.label() });
+            updates.push(UpdateQuery { key: "periodtype".to_string(), value: period.kind.as_str().to_string() });
+            updates.push(UpdateQuery { key: "periodstart".to_string(), value: period.start.to_string() });
+            updates.push(UpdateQuery { key: "periodend".to_string(), value: period.end.to_string() });
         }
 
         if trx_cat_budget.allocated.is_some() {
@@ -305,6 +1057,18 @@ pub fn query_update_trx_cat_budget<'a>(
             })
         }
 
+        if let Some(carry_over) = trx_cat_budget.carry_over {
+            updates.push(UpdateQuery { key: "carryover".to_string(), value: carry_over });
+        }
+
+        if let Some(rollover) = trx_cat_budget.rollover {
+            updates.push(UpdateQuery { key: "rollover".to_string(), value: (rollover as i32).to_string() });
+        }
+
+        if let Some(thresholds) = trx_cat_budget.thresholds {
+            updates.push(UpdateQuery { key: "thresholds".to_string(), value: format_thresholds(&thresholds) });
+        }
+
         let mut separated = query.separated(", ");
         for update in updates.iter() {
             separated.push(update.key.clone())
@@ -315,14 +1079,14 @@ pub fn query_update_trx_cat_budget<'a>(
         separated
             .push("updated_at = current_timestamp()")
             .push_unseparated(" WHERE id = ")
-            .push_bind_unseparated(id);
+            .push_bind_unseparated(id)
+            .push_unseparated(" AND deleted_at IS NULL");
         
         let res = query.build()
             .execute(db.as_executor())
-            .await
-            .unwrap();
+            .await?;
 
-        res
+        Ok(res)
     }
     .boxed()
 }
@@ -330,36 +1094,112 @@ pub fn query_update_trx_cat_budget<'a>(
 pub fn query_delete_cat_budget<'a>(
     db: &'a mut impl Executor,
     id: i32,
-) -> BoxFuture<'a, MySqlQueryResult> {
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
     async move {
 
-        let mut query = sqlx::QueryBuilder::new(r#"DELETE FROM tblcategorybudgets WHERE id = "#);
-        let res = query.push_bind(id)
+        let mut query = sqlx::QueryBuilder::new(r#"UPDATE tblcategorybudgets SET "#);
+        query.push("deleted_at = current_timestamp()")
+            .push(" WHERE id = ").push_bind(id)
+            .push(" AND deleted_at IS NULL");
+
+        let res = query
             .build()
             .execute(db.as_executor())
-            .await
-            .unwrap();
+            .await?;
 
-        res
+        Ok(res)
     }
     .boxed()
 }
 
+fn query_restore_cat_budget<'a>(
+    db: &'a mut impl Executor,
+    id: i32,
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"UPDATE tblcategorybudgets SET "#);
+        query.push("deleted_at = NULL")
+            .push(" , updated_at = current_timestamp() ")
+            .push(" WHERE id = ").push_bind(id);
+
+        let res = query
+            .build()
+            .execute(db.as_executor())
+            .await?;
+
+        Ok(res)
+    }
+    .boxed()
+}
+
+/// Soft deletes the remaining budgets of a category with the `deleted_at` of the category itself.
 pub fn query_delete_cat_budget_by_catid<'a>(
     db: &'a mut impl Executor,
     categoryid: i32,
-) -> BoxFuture<'a, MySqlQueryResult> {
+    deleted_at: DateTime<Utc>,
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
     async move {
 
-        let mut query = sqlx::QueryBuilder::new(r#"DELETE FROM tblcategorybudgets WHERE categoryid = "#);
+        let mut query = sqlx::QueryBuilder::new(r#"UPDATE tblcategorybudgets SET "#);
+        query.push("deleted_at = ").push_bind(deleted_at)
+            .push(" WHERE categoryid = ").push_bind(categoryid)
+            .push(" AND deleted_at IS NULL");
+
         let res = query
-            .push_bind(categoryid)
             .build()
             .execute(db.as_executor())
-            .await
-            .unwrap();
+            .await?;
 
-        res
+        Ok(res)
     }
     .boxed()
-}
\ No newline at end of file
+}
+
+/// Brings back the budgets deleted together with their category at `deleted_at`.
+pub fn query_restore_cat_budget_by_catid<'a>(
+    db: &'a mut impl Executor,
+    categoryid: i32,
+    deleted_at: DateTime<Utc>,
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"UPDATE tblcategorybudgets SET "#);
+        query.push("deleted_at = NULL")
+            .push(" , updated_at = current_timestamp() ")
+            .push(" WHERE categoryid = ").push_bind(categoryid)
+            .push(" AND deleted_at = ").push_bind(deleted_at);
+
+        let res = query
+            .build()
+            .execute(db.as_executor())
+            .await?;
+
+        Ok(res)
+    }
+    .boxed()
+}
+
+pub fn query_set_budget_spent<'a>(
+    db: &'a mut impl Executor,
+    id: i32,
+    spent: i64,
+    available: i64,
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"UPDATE tblcategorybudgets SET "#);
+        query.push("spent = ").push_bind(spent)
+            .push(" , available = ").push_bind(available)
+            .push(" , updated_at = current_timestamp() ")
+            .push(" WHERE id = ").push_bind(id);
+
+        let res = query
+            .build()
+            .execute(db.as_executor())
+            .await?;
+
+        Ok(res)
+    }
+    .boxed()
+}
@@ -1,11 +1,19 @@
 
+use crate::models::cat_types::Direction;
+use crate::models::exchange_rates::DEFAULT_CURRENCY;
 use crate::models::trx_cats;
 use crate::models::trx_cats::{ ExistTrxCat, ExistTrxCatWithBudgetType, ExistTrxCatWithBudget, AddTrxCat, UpdateTrxCat };
+use crate::models::trx_cats::validate_add_trx_cat;
 use crate::models::trx_cat_budgets::{ ExistTrxCatBudget, NewTrxCatBudget };
-use crate::repositories::{ Executor, UpdateQuery };
+use crate::models::audit::{ AuditAction, AuditEntity };
+use crate::models::pagination::PageParams;
+use crate::errors::AppError;
+use crate::repositories::{ Executor, UpdateQuery, last_insert_id, push_ids, push_not_deleted, push_page, unique_ids };
+use crate::repositories::audit;
 use crate::repositories::cat_types;
 use crate::repositories::trx_cat_budgets;
 
+use std::collections::HashMap;
 use futures_util::{future::BoxFuture, FutureExt};
 use sqlx::{MySql, MySqlPool};
 use sqlx_mysql::MySqlQueryResult;
@@ -15,14 +23,14 @@ pub trait Trait: Send + Sync + TrxCatTrait {
     fn clone_boxed(&self) -> Box<dyn Trait>;
     async fn start_transaction(
         &self,
-    ) -> Result<Box<dyn TransactionTrait>, Box<dyn std::error::Error + Send + Sync + 'static>>;
+    ) -> Result<Box<dyn TransactionTrait>, AppError>;
 }
 
 #[async_trait::async_trait]
 pub trait TransactionTrait: Send + Sync + TrxCatTrait {
     async fn commit(
         self: Box<Self>,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>>;
+    ) -> Result<(), AppError>;
 }
 
 #[async_trait::async_trait]
@@ -30,34 +38,62 @@ pub trait TrxCatTrait {
     async fn trx_cats_list(
         &mut self,
         typeid: i32,
-    ) -> Result<Vec<ExistTrxCatWithBudgetType>, Box<dyn std::error::Error + Send + Sync + 'static>>;
+        page: PageParams,
+        include_deleted: bool,
+    ) -> Result<(Vec<ExistTrxCatWithBudgetType>, Option<String>), AppError>;
     async fn trx_cats_detail(
         &mut self,
         id: i32,
-    ) -> Result<ExistTrxCatWithBudgetType, Box<dyn std::error::Error + Send + Sync + 'static>>;
+        include_deleted: bool,
+    ) -> Result<ExistTrxCatWithBudgetType, AppError>;
     async fn trx_cats_add(
         &mut self,
         account: AddTrxCat,
-    ) -> Result<ExistTrxCatWithBudget, Box<dyn std::error::Error + Send + Sync + 'static>>;
+    ) -> Result<ExistTrxCatWithBudget, AppError>;
     async fn trx_cats_update(
         &mut self,
         id: i32,
         account: UpdateTrxCat,
-    ) -> Result<ExistTrxCatWithBudget, Box<dyn std::error::Error + Send + Sync + 'static>>;
+    ) -> Result<ExistTrxCatWithBudget, AppError>;
     async fn trx_cats_delete(
         &mut self,
         id: i32,
-    ) -> Result<ExistTrxCatWithBudget, Box<dyn std::error::Error + Send + Sync + 'static>>;
+    ) -> Result<ExistTrxCatWithBudget, AppError>;
+    async fn trx_cats_restore(
+        &mut self,
+        id: i32,
+    ) -> Result<ExistTrxCatWithBudget, AppError>;
 }
 
 #[derive(Debug, Clone)]
 pub struct TrxCatRepo<E = MySqlPool> {
     db: E,
+    /// Who the audit log credits with the writes.
+    actor: Option<String>,
+    /// Currency the category budgets are kept in.
+    base_currency: String,
+    /// Whether budget `spent` and `available` are computed from the trxs when read.
+    derived_budgets: bool,
 }
 
 impl TrxCatRepo {
     pub fn new(pool: MySqlPool) -> Self {
-        Self { db: pool }
+        Self { db: pool, actor: None, base_currency: String::from(DEFAULT_CURRENCY), derived_budgets: false }
+    }
+
+    pub fn with_actor(mut self, actor: Option<String>) -> Self {
+        self.actor = actor;
+        self
+    }
+
+    pub fn with_base_currency(mut self, base_currency: &str) -> Self {
+        self.base_currency = base_currency.to_string();
+        self
+    }
+
+    pub fn with_derived_budgets(mut self, derived_budgets: bool) -> Self {
+        self.derived_budgets = derived_budgets;
+        self
     }
 }
 
@@ -69,10 +105,10 @@ impl Trait for TrxCatRepo {
 
     async fn start_transaction(
         &self,
-    ) -> Result<Box<dyn TransactionTrait>, Box<dyn std::error::Error + Send + Sync + 'static>> {
+    ) -> Result<Box<dyn TransactionTrait>, AppError> {
         let tx = self.db.begin().await?;
 
-        Ok(Box::new(TrxCatRepo { db: tx }))
+        Ok(Box::new(TrxCatRepo { db: tx, actor: self.actor.clone(), base_currency: self.base_currency.clone(), derived_budgets: self.derived_budgets }))
     }
 }
 
@@ -80,7 +116,7 @@ impl Trait for TrxCatRepo {
 impl TransactionTrait for TrxCatRepo<sqlx::Transaction<'static, MySql>> {
     async fn commit(
         self: Box<Self>,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+    ) -> Result<(), AppError> {
         self.db.commit().await?;
 
         Ok(())
@@ -92,42 +128,63 @@ impl<E: 'static + Executor> TrxCatTrait for TrxCatRepo<E> {
     async fn trx_cats_list(
         &mut self,
         filter_type_id: i32,
-    ) -> Result<Vec<ExistTrxCatWithBudgetType>, Box<dyn std::error::Error + Send + Sync + 'static>> {
+        page: PageParams,
+        include_deleted: bool,
+    ) -> Result<(Vec<ExistTrxCatWithBudgetType>, Option<String>), AppError> {
 
         let mut data_cats: Vec<ExistTrxCatWithBudgetType> = Vec::new();
 
-        let trx_cats: Vec<ExistTrxCat> = query_list_trx_cats(&mut self.db, filter_type_id).await;
-        for cat in trx_cats.iter() {
-            
-            let id = cat.id;
-            let typeid = cat.typeid;
+        let trx_cats: Vec<ExistTrxCat> = query_list_trx_cats(&mut self.db, filter_type_id, &page, include_deleted).await?;
+        let (trx_cats, next_cursor) = page.to_page(trx_cats);
 
-            let data_type = cat_types::query_detail_type(&mut self.db, typeid).await;
+        // load types and latest budgets of the whole page at once
+        let type_ids = unique_ids(trx_cats.iter().map(|cat| cat.typeid));
+        let cat_ids = unique_ids(trx_cats.iter().map(|cat| cat.id));
+        let data_types = cat_types::query_types_by_ids(&mut self.db, &type_ids).await?;
+        let mut data_budgets = trx_cat_budgets::query_latest_budgets_by_catids(&mut self.db, &cat_ids).await?;
+        if self.derived_budgets {
+            trx_cat_budgets::derive_budgets(&mut self.db, &self.base_currency, data_budgets.values_mut()).await?;
+        }
+
+        for cat in trx_cats.into_iter() {
+
+            let data_type = match data_types.get(&cat.typeid) {
+                Some(data_type) => data_type.clone(),
+                None => return Err(AppError::NotFound(format!("category type {} not found", cat.typeid))),
+            };
 
             // detail trx cat budget
-            let data_budget: Option<ExistTrxCatBudget> = trx_cat_budgets::query_latest_trx_cat_budget_by_catid(&mut self.db, id).await;
-            
-            let trx_cat: ExistTrxCatWithBudgetType = trx_cats::build_exist_trx_cat_budget_type(cat.clone(), data_type, data_budget);
+            let data_budget: Option<ExistTrxCatBudget> = data_budgets.remove(&cat.id);
+
+            let trx_cat: ExistTrxCatWithBudgetType = trx_cats::build_exist_trx_cat_budget_type(cat, data_type, data_budget);
             data_cats.push(trx_cat);
 
         }
 
-        Ok(data_cats)
+        Ok((data_cats, next_cursor))
     }
 
     async fn trx_cats_detail(
         &mut self,
         id: i32,
-    ) -> Result<ExistTrxCatWithBudgetType, Box<dyn std::error::Error + Send + Sync + 'static>> {
+        include_deleted: bool,
+    ) -> Result<ExistTrxCatWithBudgetType, AppError> {
 
         // detail trx cat
-        let data_cat: ExistTrxCat = query_detail_trx_cats(&mut self.db, id).await;
+        let data_cat: ExistTrxCat = match include_deleted {
+            true => query_find_trx_cats(&mut self.db, id).await?,
+            false => query_detail_trx_cats(&mut self.db, id).await?,
+        };
 
+        // a category keeps showing its type once it is deleted
         let typeid = data_cat.typeid;
-        let data_type = cat_types::query_detail_type(&mut self.db, typeid).await;
+        let data_type = cat_types::query_find_type(&mut self.db, typeid).await?;
 
         // detail trx cat budget
-        let data_budget: Option<ExistTrxCatBudget> = trx_cat_budgets::query_latest_trx_cat_budget_by_catid(&mut self.db, id).await;
+        let mut data_budget: Option<ExistTrxCatBudget> = trx_cat_budgets::query_latest_trx_cat_budget_by_catid(&mut self.db, id).await?;
+        if let (true, Some(budget)) = (self.derived_budgets, data_budget.as_mut()) {
+            trx_cat_budgets::derive_budget(&mut self.db, &self.base_currency, budget).await?;
+        }
         
         let trx_cat: ExistTrxCatWithBudgetType = trx_cats::build_exist_trx_cat_budget_type(data_cat, data_type, data_budget);
         Ok(trx_cat)
@@ -136,13 +193,18 @@ impl<E: 'static + Executor> TrxCatTrait for TrxCatRepo<E> {
     async fn trx_cats_add(
         &mut self,
         cat: AddTrxCat,
-    ) -> Result<ExistTrxCatWithBudget, Box<dyn std::error::Error + Send + Sync + 'static>> {
+    ) -> Result<ExistTrxCatWithBudget, AppError> {
+
+        validate_add_trx_cat(&cat)?;
+
+        // cat type must exist and not be deleted
+        cat_types::query_detail_type(&mut self.db, cat.typeid).await?;
 
         let mut data_budget: Option<ExistTrxCatBudget> = None;
 
         // add trx cat
-        let add_trx_cat = query_add_trx_cats(&mut self.db, cat.clone()).await;
-        let trx_cat_id = i32::try_from(add_trx_cat.last_insert_id()).unwrap();
+        let add_trx_cat = query_add_trx_cats(&mut self.db, cat.clone()).await?;
+        let trx_cat_id = last_insert_id(&add_trx_cat)?;
 
         if cat.budget.clone() != None {
 
@@ -153,17 +215,29 @@ impl<E: 'static + Executor> TrxCatTrait for TrxCatRepo<E> {
                 spent: budget.spent,
                 available: budget.available,
                 categoryid: trx_cat_id,
+                carry_over: budget.carry_over,
+                rollover: budget.rollover,
+                thresholds: budget.thresholds,
+                carried: 0,
             };
 
-            let _ = trx_cat_budgets::query_add_trx_cat_budget(&mut self.db, add_budget).await;
-            
-            // detail trx cat budget
-            data_budget = trx_cat_budgets::query_latest_trx_cat_budget_by_catid(&mut self.db, trx_cat_id).await;
+            let budget_id = trx_cat_budgets::add_budget_period(&mut self.db, add_budget).await?;
+
+            // detail trx cat budget, a derived one ignores the given spent and available
+            let mut budget = trx_cat_budgets::query_detail_trx_cat_budget(&mut self.db, budget_id).await?;
+            if self.derived_budgets {
+                budget = trx_cat_budgets::rebuild_budget(&mut self.db, &self.base_currency, &budget).await?;
+            }
+            data_budget = Some(budget);
 
         }
 
         // detail trx cat
-        let data_cat: ExistTrxCat = query_detail_trx_cats(&mut self.db, trx_cat_id).await;
+        let data_cat: ExistTrxCat = query_detail_trx_cats(&mut self.db, trx_cat_id).await?;
+        audit::record(&mut self.db, &self.actor, AuditEntity::TrxCat, trx_cat_id, AuditAction::Add, None, Some(&data_cat)).await?;
+        if let Some(budget) = &data_budget {
+            audit::record(&mut self.db, &self.actor, AuditEntity::TrxCatBudget, budget.id, AuditAction::Add, None, Some(budget)).await?;
+        }
 
         let trx_cat: ExistTrxCatWithBudget = trx_cats::build_exist_trx_cat_budget(data_cat, data_budget);
         Ok(trx_cat)
@@ -173,13 +247,16 @@ impl<E: 'static + Executor> TrxCatTrait for TrxCatRepo<E> {
         &mut self,
         id: i32,
         cat: UpdateTrxCat,
-    ) -> Result<ExistTrxCatWithBudget, Box<dyn std::error::Error + Send + Sync + 'static>> {
+    ) -> Result<ExistTrxCatWithBudget, AppError> {
+
+        let before = query_detail_trx_cats(&mut self.db, id).await?;
 
         // update trx cat
-        let _ = query_update_trx_cats(&mut self.db, id, cat).await;
+        query_update_trx_cats(&mut self.db, id, cat).await?;
 
         // trx cat detail
-        let data_cat = query_detail_trx_cats(&mut self.db, id).await;
+        let data_cat = query_detail_trx_cats(&mut self.db, id).await?;
+        audit::record(&mut self.db, &self.actor, AuditEntity::TrxCat, id, AuditAction::Update, Some(&before), Some(&data_cat)).await?;
 
         let trx_cat: ExistTrxCatWithBudget = trx_cats::build_exist_trx_cat_budget(data_cat, None);
 
@@ -189,27 +266,70 @@ impl<E: 'static + Executor> TrxCatTrait for TrxCatRepo<E> {
     async fn trx_cats_delete(
         &mut self,
         id: i32,
-    ) -> Result<ExistTrxCatWithBudget, Box<dyn std::error::Error + Send + Sync + 'static>> {
+    ) -> Result<ExistTrxCatWithBudget, AppError> {
 
         // trx cat detail
-        let data_cat = query_detail_trx_cats(&mut self.db, id).await;
-
-        // delete trx cat budget
-        let _ = trx_cat_budgets::query_delete_cat_budget_by_catid(&mut self.db, id).await;
+        let data_cat = query_detail_trx_cats(&mut self.db, id).await?;
 
         // delete trx cat
-        let _ = query_delete_trx_cats(&mut self.db, id).await;
+        query_delete_trx_cats(&mut self.db, id).await?;
+
+        // delete trx cat budget, with the deleted_at of the category so restoring it brings them back
+        let deleted = query_find_trx_cats(&mut self.db, id).await?;
+        if let Some(deleted_at) = deleted.deleted_at {
+            trx_cat_budgets::query_delete_cat_budget_by_catid(&mut self.db, id, deleted_at).await?;
+        }
+        audit::record(&mut self.db, &self.actor, AuditEntity::TrxCat, id, AuditAction::Delete, Some(&data_cat), Some(&deleted)).await?;
 
         let trx_cat: ExistTrxCatWithBudget = trx_cats::build_exist_trx_cat_budget(data_cat, None);
 
         Ok(trx_cat)
     }
+
+    async fn trx_cats_restore(
+        &mut self,
+        id: i32,
+    ) -> Result<ExistTrxCatWithBudget, AppError> {
+
+        // trx cat detail
+        let data_cat = query_find_trx_cats(&mut self.db, id).await?;
+        let deleted_at = match data_cat.deleted_at {
+            Some(deleted_at) => deleted_at,
+            None => return Err(AppError::Conflict(format!("category {} is not deleted", id))),
+        };
+
+        // cat type has to be restored first
+        let data_type = cat_types::query_find_type(&mut self.db, data_cat.typeid).await?;
+        if data_type.deleted_at.is_some() {
+            return Err(AppError::Conflict(format!("category type {} of category {} is deleted", data_type.id, id)));
+        }
+
+        // restore trx cat and the budgets deleted along with it
+        query_restore_trx_cats(&mut self.db, id).await?;
+        trx_cat_budgets::query_restore_cat_budget_by_catid(&mut self.db, id, deleted_at).await?;
+        // the direction of the type may have changed while they were deleted
+        trx_cat_budgets::rebuild_category_budgets(&mut self.db, &self.base_currency, &[id]).await?;
+
+        let before = data_cat;
+        let data_cat = query_detail_trx_cats(&mut self.db, id).await?;
+        audit::record(&mut self.db, &self.actor, AuditEntity::TrxCat, id, AuditAction::Restore, Some(&before), Some(&data_cat)).await?;
+        let mut data_budget = trx_cat_budgets::query_latest_trx_cat_budget_by_catid(&mut self.db, id).await?;
+        if let (true, Some(budget)) = (self.derived_budgets, data_budget.as_mut()) {
+            trx_cat_budgets::derive_budget(&mut self.db, &self.base_currency, budget).await?;
+        }
+
+        let trx_cat: ExistTrxCatWithBudget = trx_cats::build_exist_trx_cat_budget(data_cat, data_budget);
+
+        Ok(trx_cat)
+    }
 }
 
 fn query_list_trx_cats<'a>(
     db: &'a mut impl Executor,
-    typeid: i32
-) -> BoxFuture<'a, Vec<ExistTrxCat>> {
+    typeid: i32,
+    page: &'a PageParams,
+    include_deleted: bool,
+) -> BoxFuture<'a, Result<Vec<ExistTrxCat>, AppError>> {
     async move {
         let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tbltransactioncategories"#);
 
@@ -219,13 +339,38 @@ fn query_list_trx_cats<'a>(
                 .push_bind(typeid);
         }
 
+        let has_where = push_not_deleted(&mut query, "tbltransactioncategories", include_deleted, typeid != 0);
+        push_page(&mut query, page, "tbltransactioncategories", has_where);
+
         let trx_cats = query
             .build_query_as::<ExistTrxCat>()
             .fetch_all(db.as_executor())
-            .await
-            .unwrap();
+            .await?;
+
+        Ok(trx_cats)
+    }
+    .boxed()
+}
+
+/// Categories with the given ids, deleted ones included, keyed by id, in a single round-trip.
+pub fn query_trx_cats_by_ids<'a>(
+    db: &'a mut impl Executor,
+    ids: &'a [i32],
+) -> BoxFuture<'a, Result<HashMap<i32, ExistTrxCat>, AppError>> {
+    async move {
+        if ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tbltransactioncategories WHERE id IN "#);
+        push_ids(&mut query, ids);
+
+        let rows = query
+            .build_query_as::<ExistTrxCat>()
+            .fetch_all(db.as_executor())
+            .await?;
 
-        trx_cats
+        Ok(rows.into_iter().map(|row| (row.id, row)).collect())
     }
     .boxed()
 }
@@ -233,18 +378,61 @@ fn query_list_trx_cats<'a>(
 pub fn query_detail_trx_cats<'a>(
     db: &'a mut impl Executor,
     id: i32
-) -> BoxFuture<'a, ExistTrxCat> {
+) -> BoxFuture<'a, Result<ExistTrxCat, AppError>> {
     async move {
         let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tbltransactioncategories WHERE id = "#);
 
         let trx_cat = query
             .push_bind(id)
+            .push(" AND deleted_at IS NULL")
             .build_query_as::<ExistTrxCat>()
-            .fetch_one(db.as_executor())
-            .await
-            .unwrap();
+            .fetch_optional(db.as_executor())
+            .await?;
 
-        trx_cat
+        trx_cat.ok_or_else(|| AppError::NotFound(format!("category {} not found", id)))
+    }
+    .boxed()
+}
+
+/// Like `query_detail_trx_cats`, deleted categories included.
+pub fn query_find_trx_cats<'a>(
+    db: &'a mut impl Executor,
+    id: i32
+) -> BoxFuture<'a, Result<ExistTrxCat, AppError>> {
+    async move {
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tbltransactioncategories WHERE id = "#);
+
+        let trx_cat = query
+            .push_bind(id)
+            .build_query_as::<ExistTrxCat>()
+            .fetch_optional(db.as_executor())
+            .await?;
+
+        trx_cat.ok_or_else(|| AppError::NotFound(format!("category {} not found", id)))
+    }
+    .boxed()
+}
+
+/// Direction of the type of category `id`, deleted ones included.
+pub fn query_category_direction<'a>(
+    db: &'a mut impl Executor,
+    id: i32
+) -> BoxFuture<'a, Result<Direction, AppError>> {
+    async move {
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT t.direction FROM tbltransactioncategories c
+            JOIN tblcategorytypes t ON t.id = c.typeid
+            WHERE c.id = "#);
+
+        let direction = query
+            .push_bind(id)
+            .build_query_scalar::<String>()
+            .fetch_optional(db.as_executor())
+            .await?;
+
+        match direction {
+            Some(direction) => direction.parse(),
+            None => Err(AppError::NotFound(format!("category {} not found", id))),
+        }
     }
     .boxed()
 }
@@ -252,7 +440,7 @@ pub fn query_detail_trx_cats<'a>(
 fn query_add_trx_cats<'a>(
     db: &'a mut impl Executor,
     cat: AddTrxCat,
-) -> BoxFuture<'a, MySqlQueryResult> {
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
     async move {
 
         let cat_name = cat.name;
@@ -273,10 +461,9 @@ fn query_add_trx_cats<'a>(
         let res = query
             .build()
             .execute(db.as_executor())
-            .await
-            .unwrap();
+            .await?;
 
-        res
+        Ok(res)
     }
     .boxed()
 }
@@ -285,7 +472,7 @@ fn query_update_trx_cats<'a>(
     db: &'a mut impl Executor,
     id: i32,
     cat: UpdateTrxCat,
-) -> BoxFuture<'a, MySqlQueryResult> {
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
     async move {
 
         let mut query = sqlx::QueryBuilder::new(r#"UPDATE tbltransactioncategories SET "#);
@@ -315,15 +502,55 @@ fn query_update_trx_cats<'a>(
         separated
             .push("updated_at = current_timestamp()")
             .push_unseparated(" WHERE id = ")
-            .push_bind_unseparated(id);
+            .push_bind_unseparated(id)
+            .push_unseparated(" AND deleted_at IS NULL");
         
         let res = query
             .build()
             .execute(db.as_executor())
-            .await
-            .unwrap();
+            .await?;
+
+        Ok(res)
+    }
+    .boxed()
+}
+
+/// Ids of the categories of the type, deleted ones included.
+pub fn query_trx_cat_ids_by_type<'a>(
+    db: &'a mut impl Executor,
+    typeid: i32,
+) -> BoxFuture<'a, Result<Vec<i32>, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT id FROM tbltransactioncategories WHERE typeid = "#);
+        query.push_bind(typeid);
 
-        res
+        let ids = query
+            .build_query_scalar::<i32>()
+            .fetch_all(db.as_executor())
+            .await?;
+
+        Ok(ids)
+    }
+    .boxed()
+}
+
+/// Number of categories of the type that are not deleted.
+pub fn query_count_trx_cats_by_type<'a>(
+    db: &'a mut impl Executor,
+    typeid: i32,
+) -> BoxFuture<'a, Result<i64, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT COUNT(*) FROM tbltransactioncategories WHERE deleted_at IS NULL AND typeid = "#);
+        query.push_bind(typeid);
+
+        let count = query
+            .build_query_scalar::<i64>()
+            .fetch_one(db.as_executor())
+            .await?;
+
+        Ok(count)
     }
     .boxed()
 }
@@ -331,19 +558,41 @@ fn query_update_trx_cats<'a>(
 fn query_delete_trx_cats<'a>(
     db: &'a mut impl Executor,
     id: i32,
-) -> BoxFuture<'a, MySqlQueryResult> {
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
     async move {
 
-        let mut query = sqlx::QueryBuilder::new(r#"DELETE FROM tbltransactioncategories WHERE id = "#);
+        let mut query = sqlx::QueryBuilder::new(r#"UPDATE tbltransactioncategories SET "#);
+        query.push("deleted_at = current_timestamp()")
+            .push(" WHERE id = ").push_bind(id)
+            .push(" AND deleted_at IS NULL");
+
+        let res = query
+            .build()
+            .execute(db.as_executor())
+            .await?;
+
+        Ok(res)
+    }
+    .boxed()
+}
+
+fn query_restore_trx_cats<'a>(
+    db: &'a mut impl Executor,
+    id: i32,
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"UPDATE tbltransactioncategories SET "#);
+        query.push("deleted_at = NULL")
+            .push(" , updated_at = current_timestamp() ")
+            .push(" WHERE id = ").push_bind(id);
 
         let res = query
-            .push_bind(id)
             .build()
             .execute(db.as_executor())
-            .await
-            .unwrap();
+            .await?;
 
-            res
+        Ok(res)
     }
     .boxed()
 }
\ No newline at end of file
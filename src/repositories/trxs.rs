@@ -1,13 +1,23 @@
 
-use crate::models::bigdecimal_to_int;
-use crate::models::trxs::{ ExistTrx, ExistTrxWithAccCat, NewTrx, AddTrx, UpdateTrx };
-use crate::repositories::{ Executor, UpdateQuery };
+use crate::models::accounts::validate_active;
+use crate::models::audit::{ AuditAction, AuditEntity };
+use crate::models::exchange_rates::DEFAULT_CURRENCY;
+use crate::models::money::Money;
+use crate::models::trxs::{ ExistTrx, ExistTrxSplit, ExistTrxWithAccCat, NewTrx, NewTrxSplit, AddTrx, UpdateTrx, TrxDirection, TrxFilter, split_amount, validate_new_trx, validate_splits, validate_update_trx, validate_trx_filter };
+use crate::models::pagination::PageParams;
+use crate::errors::AppError;
+use crate::repositories::{ Executor, UpdateQuery, last_insert_id, push_ids, push_not_deleted, push_page, unique_ids };
 use crate::repositories::accounts;
+use crate::repositories::audit;
+use crate::repositories::exchange_rates;
+use crate::repositories::transfers;
 use crate::repositories::trx_cats;
 use crate::repositories::trx_cat_budgets;
 
+use std::collections::HashMap;
 use futures_util::{future::BoxFuture, FutureExt};
 use sqlx::{MySql, MySqlPool};
+use sqlx::types::chrono::{DateTime, Utc};
 use sqlx_mysql::MySqlQueryResult;
 
 #[async_trait::async_trait]
@@ -15,50 +25,69 @@ pub trait Trait: Send + Sync + TrxTrait {
     fn clone_boxed(&self) -> Box<dyn Trait>;
     async fn start_transaction(
         &self,
-    ) -> Result<Box<dyn TransactionTrait>, Box<dyn std::error::Error + Send + Sync + 'static>>;
+    ) -> Result<Box<dyn TransactionTrait>, AppError>;
 }
 
 #[async_trait::async_trait]
 pub trait TransactionTrait: Send + Sync + TrxTrait {
     async fn commit(
         self: Box<Self>,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>>;
+    ) -> Result<(), AppError>;
 }
 
 #[async_trait::async_trait]
 pub trait TrxTrait {
     async fn trxs_list(
         &mut self,
-        accountid: Option<String>,
-        categoryid: Option<String>
-    ) -> Result<Vec<ExistTrxWithAccCat>, Box<dyn std::error::Error + Send + Sync + 'static>>;
+        filter: TrxFilter,
+        page: PageParams,
+    ) -> Result<(Vec<ExistTrxWithAccCat>, Option<String>), AppError>;
     async fn trx_detail(
         &mut self,
         id: i32,
-    ) -> Result<ExistTrxWithAccCat, Box<dyn std::error::Error + Send + Sync + 'static>>;
+        include_deleted: bool,
+    ) -> Result<ExistTrxWithAccCat, AppError>;
     async fn trx_add(
         &mut self,
         account: NewTrx,
-    ) -> Result<ExistTrx, Box<dyn std::error::Error + Send + Sync + 'static>>;
+    ) -> Result<ExistTrx, AppError>;
     async fn trx_update(
         &mut self,
         id: i32,
         account: UpdateTrx,
-    ) -> Result<ExistTrx, Box<dyn std::error::Error + Send + Sync + 'static>>;
+    ) -> Result<ExistTrx, AppError>;
     async fn trx_delete(
         &mut self,
         id: i32,
-    ) -> Result<ExistTrx, Box<dyn std::error::Error + Send + Sync + 'static>>;
+    ) -> Result<ExistTrx, AppError>;
+    async fn trx_restore(
+        &mut self,
+        id: i32,
+    ) -> Result<ExistTrx, AppError>;
 }
 
 #[derive(Debug, Clone)]
 pub struct TrxRepo<E = MySqlPool> {
     db: E,
+    /// Who the audit log credits with the writes.
+    actor: Option<String>,
+    /// Currency the category budgets are kept in.
+    base_currency: String,
 }
 
 impl TrxRepo {
     pub fn new(pool: MySqlPool) -> Self {
-        Self { db: pool }
+        Self { db: pool, actor: None, base_currency: String::from(DEFAULT_CURRENCY) }
+    }
+
+    pub fn with_actor(mut self, actor: Option<String>) -> Self {
+        self.actor = actor;
+        self
+    }
+
+    pub fn with_base_currency(mut self, base_currency: &str) -> Self {
+        self.base_currency = base_currency.to_string();
+        self
     }
 }
 
@@ -70,10 +99,10 @@ impl Trait for TrxRepo {
 
     async fn start_transaction(
         &self,
-    ) -> Result<Box<dyn TransactionTrait>, Box<dyn std::error::Error + Send + Sync + 'static>> {
+    ) -> Result<Box<dyn TransactionTrait>, AppError> {
         let tx = self.db.begin().await?;
 
-        Ok(Box::new(TrxRepo { db: tx }))
+        Ok(Box::new(TrxRepo { db: tx, actor: self.actor.clone(), base_currency: self.base_currency.clone() }))
     }
 }
 
@@ -81,7 +110,7 @@ impl Trait for TrxRepo {
 impl TransactionTrait for TrxRepo<sqlx::Transaction<'static, MySql>> {
     async fn commit(
         self: Box<Self>,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+    ) -> Result<(), AppError> {
         self.db.commit().await?;
 
         Ok(())
@@ -92,33 +121,56 @@ impl TransactionTrait for TrxRepo<sqlx::Transaction<'static, MySql>> {
 impl<E: 'static + Executor> TrxTrait for TrxRepo<E> {
     async fn trxs_list(
         &mut self,
-        accountid: Option<String>,
-        categoryid: Option<String>
-    ) -> Result<Vec<ExistTrxWithAccCat>, Box<dyn std::error::Error + Send + Sync + 'static>> {
+        filter: TrxFilter,
+        page: PageParams,
+    ) -> Result<(Vec<ExistTrxWithAccCat>, Option<String>), AppError> {
+
+        validate_trx_filter(&filter)?;
 
         let mut trxs: Vec<ExistTrxWithAccCat> = Vec::new();
 
-        let data_trxs = query_list_trx(&mut self.db, accountid, categoryid).await;
-        for data in data_trxs.iter() {
+        let data_trxs = query_list_trx(&mut self.db, &filter, &page).await?;
+        let (data_trxs, next_cursor) = page.to_page(data_trxs);
 
-            let acc_id = data.accountid;
-            let cat_id = data.categoryid;
+        // load accounts and categories of the whole page at once
+        let acc_ids = unique_ids(data_trxs.iter().map(|trx| trx.accountid));
+        let cat_ids = unique_ids(data_trxs.iter().filter_map(|trx| trx.categoryid));
+        let data_accounts = accounts::query_accounts_by_ids(&mut self.db, &acc_ids).await?;
+        let data_cats = trx_cats::query_trx_cats_by_ids(&mut self.db, &cat_ids).await?;
+        let trx_ids: Vec<i32> = data_trxs.iter().map(|trx| trx.id).collect();
+        let mut data_splits = query_splits_by_trx_ids(&mut self.db, &trx_ids).await?;
 
-            let account = accounts::query_detail_account(&mut self.db, acc_id).await;
-            let category = trx_cats::query_detail_trx_cats(&mut self.db, cat_id).await;
+        for data in data_trxs.iter() {
+
+            let account = match data_accounts.get(&data.accountid) {
+                Some(account) => account.clone(),
+                None => return Err(AppError::NotFound(format!("account {} not found", data.accountid))),
+            };
+            // transfer legs have no category
+            let category = match data.categoryid {
+                Some(cat_id) => match data_cats.get(&cat_id) {
+                    Some(category) => Some(category.clone()),
+                    None => return Err(AppError::NotFound(format!("category {} not found", cat_id))),
+                },
+                None => None,
+            };
 
             let trx: ExistTrxWithAccCat = ExistTrxWithAccCat {
                 id: data.id,
                 credit: data.credit.clone(),
                 debit: data.debit.clone(),
+                currency: data.currency.clone(),
                 description: data.description.clone(),
                 balance_before: data.balance_before.clone(),
                 balance_after: data.balance_after.clone(),
                 datetime: data.datetime,
                 created_at: data.created_at,
                 updated_at: data.updated_at,
+                deleted_at: data.deleted_at,
                 accountid: data.accountid,
                 categoryid: data.categoryid,
+                transferid: data.transferid,
+                splits: data_splits.remove(&data.id).unwrap_or_default(),
                 account: account,
                 category: category
             };
@@ -126,34 +178,46 @@ impl<E: 'static + Executor> TrxTrait for TrxRepo<E> {
             trxs.push(trx);
         }
 
-        Ok(trxs)
+        Ok((trxs, next_cursor))
     }
 
     async fn trx_detail(
         &mut self,
         id: i32,
-    ) -> Result<ExistTrxWithAccCat, Box<dyn std::error::Error + Send + Sync + 'static>> {
+        include_deleted: bool,
+    ) -> Result<ExistTrxWithAccCat, AppError> {
 
-        let data_trx = query_detail_trx(&mut self.db, id).await;
+        let data_trx = match include_deleted {
+            true => query_find_trx(&mut self.db, id).await?,
+            false => query_detail_trx(&mut self.db, id).await?,
+        };
 
         let acc_id = data_trx.accountid;
-        let cat_id = data_trx.categoryid;
 
-        let account = accounts::query_detail_account(&mut self.db, acc_id).await;
-        let category = trx_cats::query_detail_trx_cats(&mut self.db, cat_id).await;
+        // a trx keeps showing its account and category once they are deleted
+        let account = accounts::query_find_account(&mut self.db, acc_id).await?;
+        let category = match data_trx.categoryid {
+            Some(cat_id) => Some(trx_cats::query_find_trx_cats(&mut self.db, cat_id).await?),
+            None => None,
+        };
+        let splits = query_trx_splits(&mut self.db, data_trx.id).await?;
 
         let trx: ExistTrxWithAccCat = ExistTrxWithAccCat {
             id: data_trx.id,
             credit: data_trx.credit,
             debit: data_trx.debit,
+            currency: data_trx.currency,
             description: data_trx.description,
             balance_before: data_trx.balance_before,
             balance_after: data_trx.balance_after,
             datetime: data_trx.datetime,
             created_at: data_trx.created_at,
             updated_at: data_trx.updated_at,
+            deleted_at: data_trx.deleted_at,
             accountid: data_trx.accountid,
             categoryid: data_trx.categoryid,
+            transferid: data_trx.transferid,
+            splits,
             account: account,
             category: category
         };
@@ -164,38 +228,9 @@ impl<E: 'static + Executor> TrxTrait for TrxRepo<E> {
     async fn trx_add(
         &mut self,
         trx: NewTrx,
-    ) -> Result<ExistTrx, Box<dyn std::error::Error + Send + Sync + 'static>> {
-
-        // account detail
-        let account = accounts::query_detail_account(&mut self.db, trx.accountid).await;
-        let acc_balance = bigdecimal_to_int(account.balance);
-        let amount = trx.credit - trx.debit;
-        let acc_id = trx.accountid;
-        let cat_id = trx.categoryid;
-
-        let add_trx = AddTrx {
-            credit: trx.credit,
-            debit: trx.debit,
-            description: trx.description,
-            balance_before: acc_balance,
-            balance_after: &acc_balance + &amount,
-            datetime: trx.datetime,
-            accountid: acc_id.clone(),
-            categoryid: cat_id.clone(),
-        };
-
-        // add trx
-        let add = query_add_trx(&mut self.db, add_trx).await;
-        let trx_id = i32::try_from(add.last_insert_id()).unwrap();
-
-        // update account balance
-        let _ = accounts::update_acc_balance(&mut self.db, acc_id, amount).await;
+    ) -> Result<ExistTrx, AppError> {
 
-        // update trx cat budget current periode
-        let _ = trx_cat_budgets::query_update_trx_cat_badget(&mut self.db, cat_id, amount).await;
-
-        // detail trx
-        let trx = query_detail_trx(&mut self.db, trx_id).await;
+        let trx = add_trx(&mut self.db, &self.actor, &self.base_currency, trx).await?;
 
         Ok(trx)
     }
@@ -204,304 +239,1381 @@ impl<E: 'static + Executor> TrxTrait for TrxRepo<E> {
         &mut self,
         id: i32,
         trx: UpdateTrx,
-    ) -> Result<ExistTrx, Box<dyn std::error::Error + Send + Sync + 'static>> {
+    ) -> Result<ExistTrx, AppError> {
+
+        validate_update_trx(&trx)?;
+        lock_trx_accounts(&mut self.db, id, trx.accountid).await?;
 
         // trx detail
-        let exist_trx = query_detail_trx(&mut self.db, id).await;
+        let mut exist_trx = query_detail_trx(&mut self.db, id).await?;
+        load_splits(&mut self.db, &mut exist_trx).await?;
 
-        if trx.debit.is_none() == false && trx.credit.is_none() == false {
+        // a transfer leg is updated together with its counterpart
+        if let Some(transfer_id) = exist_trx.transferid {
+            if trx.accountid.is_some() || trx.categoryid.is_some() || trx.splits.is_some() {
+                return Err(AppError::Validation(String::from("account, category and splits of a transfer leg cannot be changed")));
+            }
+
+            // both legs are logged by the transfer update
+            let update = transfers::update_from_leg(&mut self.db, &exist_trx, &trx).await?;
+            transfers::update_transfer(&mut self.db, &self.actor, transfer_id, update).await?;
 
-            let acc_id = exist_trx.accountid;
-            let cat_id = exist_trx.categoryid;
-            let exist_amount = bigdecimal_to_int(&exist_trx.credit - &exist_trx.debit);
-            let new_amount = trx.credit.unwrap() - trx.debit.unwrap();
+            let update_trx = query_detail_trx(&mut self.db, id).await?;
+
+            return Ok(update_trx);
+        }
 
-            if exist_amount != new_amount {
+        // moving to other categories needs them to exist
+        let new_splits = trx.splits.clone();
+        let cat_ids = trx.categoryid.into_iter().chain(new_splits.iter().flatten().map(|split| split.categoryid));
+        for cat_id in unique_ids(cat_ids) {
+            trx_cats::query_detail_trx_cats(&mut self.db, cat_id).await?;
+        }
 
-                let amount = new_amount - exist_amount;
+        // splits, the new ones or those kept, must still add up to the total
+        let credit = match trx.credit {
+            Some(credit) => credit,
+            None => exist_trx.credit.to_minor()?,
+        };
+        let debit = match trx.debit {
+            Some(debit) => debit,
+            None => exist_trx.debit.to_minor()?,
+        };
+        match &new_splits {
+            Some(splits) => validate_splits(credit.saturating_add(debit), splits)?,
+            None if trx.categoryid.is_none() && exist_trx.categoryid.is_none() => {
+                let mut kept: Vec<NewTrxSplit> = Vec::new();
+                for split in exist_trx.splits.iter() {
+                    kept.push(NewTrxSplit { categoryid: split.categoryid, amount: split.amount.to_minor()?, memo: split.memo.clone() });
+                }
+                validate_splits(credit.saturating_add(debit), &kept)?;
+            },
+            None => {},
+        }
 
-                // update trx after & bef balance
-                let _ = update_curr_trx_balance(&mut self.db, id, acc_id.clone(), amount).await;
-                let _ = update_trx_balance(&mut self.db, id, acc_id.clone(), amount).await;
+        // budgets charged before the update, in the base currency at the rate of the trx date
+        let exist_lines = budget_lines(&mut self.db, &self.base_currency, &exist_trx).await?;
+        let to_category = trx.categoryid.is_some();
 
-                // update account balance
-                let _ = accounts::update_acc_balance(&mut self.db, acc_id, amount).await;
+        // update trx credit, debit, desc, datetime, account, category and the balances from there on
+        update_trx_with_balance(&mut self.db, &exist_trx, trx).await?;
 
-                // update trx cat budget current periode
-                let _ = trx_cat_budgets::query_update_trx_cat_badget(&mut self.db, cat_id, amount).await;
+        // a trx is charged either to its category or to its splits
+        if let Some(splits) = new_splits {
+            query_set_trx_category(&mut self.db, id, None).await?;
+            query_delete_trx_splits(&mut self.db, id).await?;
+            for split in splits.iter() {
+                query_add_trx_split(&mut self.db, id, split).await?;
             }
+        } else if to_category {
+            query_delete_trx_splits(&mut self.db, id).await?;
+        }
+
+        let mut update_trx = query_detail_trx(&mut self.db, id).await?;
+        load_splits(&mut self.db, &mut update_trx).await?;
 
+        // move the charges to the new lines, and to the periods of the new date
+        let new_lines = budget_lines(&mut self.db, &self.base_currency, &update_trx).await?;
+        let exist_date = exist_trx.datetime.date_naive();
+        let new_date = update_trx.datetime.date_naive();
+        if new_lines != exist_lines || new_date != exist_date {
+            for (cat_id, amount) in exist_lines {
+                trx_cat_budgets::query_release_trx_cat_badget(&mut self.db, cat_id, exist_date, amount).await?;
+            }
+            for (cat_id, amount) in new_lines {
+                trx_cat_budgets::query_update_trx_cat_badget(&mut self.db, cat_id, new_date, amount).await?;
+            }
         }
 
-        // update trx credit, debit, desc, categoryid
-        let _ = query_update_trx(&mut self.db, id, trx).await;
+        audit::record(&mut self.db, &self.actor, AuditEntity::Trx, id, AuditAction::Update, Some(&exist_trx), Some(&update_trx)).await?;
+
+        Ok(update_trx)
+    }
+
+    async fn trx_delete(
+        &mut self,
+        id: i32,
+    ) -> Result<ExistTrx, AppError> {
+
+        lock_trx_accounts(&mut self.db, id, None).await?;
 
         // trx detail
-        let trx = query_detail_trx(&mut self.db, id).await;
+        let mut trx = query_detail_trx(&mut self.db, id).await?;
+        load_splits(&mut self.db, &mut trx).await?;
+
+        // deleting one leg of a transfer deletes the whole transfer, both legs are logged there
+        if let Some(transfer_id) = trx.transferid {
+            transfers::delete_transfer(&mut self.db, &self.actor, transfer_id).await?;
+
+            return Ok(trx);
+        }
+
+        // update trx after & bef balance, account balance and delete trx
+        delete_trx_with_balance(&mut self.db, &trx).await?;
+
+        // update trx cat budget current periode
+        release_budgets(&mut self.db, &self.base_currency, &trx).await?;
+
+        let mut after = query_find_trx(&mut self.db, id).await?;
+        after.splits = trx.splits.clone();
+        audit::record(&mut self.db, &self.actor, AuditEntity::Trx, id, AuditAction::Delete, Some(&trx), Some(&after)).await?;
 
         Ok(trx)
     }
 
-    async fn trx_delete(
+    async fn trx_restore(
         &mut self,
         id: i32,
-    ) -> Result<ExistTrx, Box<dyn std::error::Error + Send + Sync + 'static>> {
+    ) -> Result<ExistTrx, AppError> {
+
+        lock_trx_accounts(&mut self.db, id, None).await?;
 
         // trx detail
-        let trx = query_detail_trx(&mut self.db, id).await;
-        let amount = bigdecimal_to_int(&trx.debit - &trx.credit);
-        let acc_id = trx.accountid;
-        let cat_id = trx.categoryid;
+        let mut trx = query_find_trx(&mut self.db, id).await?;
+        load_splits(&mut self.db, &mut trx).await?;
+        if trx.deleted_at.is_none() {
+            return Err(AppError::Conflict(format!("transaction {} is not deleted", id)));
+        }
 
-        // update trx after & bef balance
-        let _ = update_trx_balance(&mut self.db, id, acc_id.clone(), amount).await;
+        // account and category have to be restored first
+        let account = accounts::query_find_account(&mut self.db, trx.accountid).await?;
+        if account.deleted_at.is_some() {
+            return Err(AppError::Conflict(format!("account {} of transaction {} is deleted", account.id, id)));
+        }
+        validate_active(&account)?;
+
+        let cat_ids = trx.categoryid.into_iter().chain(trx.splits.iter().map(|split| split.categoryid));
+        for cat_id in unique_ids(cat_ids) {
+            let category = trx_cats::query_find_trx_cats(&mut self.db, cat_id).await?;
+            if category.deleted_at.is_some() {
+                return Err(AppError::Conflict(format!("category {} of transaction {} is deleted", cat_id, id)));
+            }
+        }
+
+        let amount = (&trx.credit - &trx.debit).to_minor()?;
+
+        // restore trx
+        query_restore_trx(&mut self.db, id).await?;
 
         // update account balance
-        let _ = accounts::update_acc_balance(&mut self.db, acc_id, amount).await;
+        accounts::update_acc_balance(&mut self.db, trx.accountid, amount).await?;
+
+        // update trx after & bef balance
+        recompute_balances(&mut self.db, trx.accountid, trx.datetime, trx.id).await?;
 
         // update trx cat budget current periode
-        let _ = trx_cat_budgets::query_update_trx_cat_badget(&mut self.db, cat_id, amount).await;
+        charge_budgets(&mut self.db, &self.base_currency, &trx).await?;
 
-        // delete trx
-        let _ = query_delete_trx(&mut self.db, id).await;
+        // detail trx
+        let before = trx;
+        let mut trx = query_detail_trx(&mut self.db, id).await?;
+        load_splits(&mut self.db, &mut trx).await?;
+        audit::record(&mut self.db, &self.actor, AuditEntity::Trx, id, AuditAction::Restore, Some(&before), Some(&trx)).await?;
 
         Ok(trx)
     }
 }
 
-fn query_list_trx<'a>(
+/// Adds `trx` to its account and charges its category budgets, the path every
+/// new trx takes, whether posted on `/trxs` or by a recurring template.
+pub fn add_trx<'a>(
     db: &'a mut impl Executor,
-    accountid: Option<String>,
-    categoryid: Option<String>
-) -> BoxFuture<'a, Vec<ExistTrx>> {
+    actor: &'a Option<String>,
+    base_currency: &'a str,
+    trx: NewTrx,
+) -> BoxFuture<'a, Result<ExistTrx, AppError>> {
     async move {
-        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tbltransactions"#);
 
-        if accountid.is_some() || categoryid.is_some() {
-            query.push(" WHERE ");
+        validate_new_trx(&trx)?;
+        accounts::lock_accounts(db, &[trx.accountid]).await?;
+
+        // categories must exist and not be deleted
+        let splits = trx.splits.clone().unwrap_or_default();
+        let cat_ids = trx.categoryid.into_iter().chain(splits.iter().map(|split| split.categoryid));
+        for cat_id in unique_ids(cat_ids) {
+            trx_cats::query_detail_trx_cats(db, cat_id).await?;
         }
 
-        let mut conditions: Vec<UpdateQuery> = Vec::new();
+        let add_trx = AddTrx {
+            credit: trx.credit,
+            debit: trx.debit,
+            description: trx.description,
+            balance_before: 0,
+            balance_after: 0,
+            datetime: trx.datetime,
+            accountid: trx.accountid,
+            categoryid: trx.categoryid,
+            transferid: None,
+        };
 
-        if accountid.is_some() {
-            conditions.push(UpdateQuery {
-                key: String::from("accountid"),
-                value: accountid.unwrap(),
-            });
+        // add trx on top of the account balance
+        let trx_id = add_trx_with_balance(db, add_trx).await?;
+        for split in splits.iter() {
+            query_add_trx_split(db, trx_id, split).await?;
         }
 
-        if categoryid.is_some() {
-            conditions.push(UpdateQuery {
-                key: String::from("categoryid"),
-                value: categoryid.unwrap(),
-            });
-        }
+        // detail trx
+        let mut trx = query_detail_trx(db, trx_id).await?;
+        load_splits(db, &mut trx).await?;
 
-        let mut separated = query.separated(" AND ");
-        for condition in conditions.iter() {
-            separated.push(condition.key.clone())
-                .push_unseparated(" = ")
-                .push_bind_unseparated(condition.value.clone());
-        }
+        // update trx cat budget current periode
+        charge_budgets(db, base_currency, &trx).await?;
 
-        separated.push_unseparated(" ORDER by datetime DESC");
+        audit::record(db, actor, AuditEntity::Trx, trx_id, AuditAction::Add, None, Some(&trx)).await?;
 
-        let trxs = query
-            .build_query_as::<ExistTrx>()
-            .fetch_all(db.as_executor())
-            .await
-            .unwrap();
+        Ok(trx)
+    }
+    .boxed()
+}
+
+/// Locks the accounts a change of trx `id` writes to: its own, both of its
+/// transfer when it is a leg, and `other` it may move to.
+pub fn lock_trx_accounts<'a>(
+    db: &'a mut impl Executor,
+    id: i32,
+    other: Option<i32>,
+) -> BoxFuture<'a, Result<(), AppError>> {
+    async move {
+
+        let trx = query_lock_trx(db, id).await?;
+        let mut ids: Vec<i32> = vec![trx.accountid];
+        ids.extend(other);
+        if let Some(transfer_id) = trx.transferid {
+            let transfer = transfers::query_lock_transfer(db, transfer_id).await?;
+            ids.extend([transfer.fromaccountid, transfer.toaccountid]);
+        }
+        accounts::lock_accounts(db, &ids).await?;
 
-        trxs
+        Ok(())
     }
     .boxed()
 }
 
-fn query_detail_trx<'a>(
+/// Locks account `acc_id`, `other` when given and the counterpart account of
+/// each of its transfers: every account a change of its whole ledger writes to.
+pub fn lock_account_ledger<'a>(
     db: &'a mut impl Executor,
-    id: i32
-) -> BoxFuture<'a, ExistTrx> {
+    acc_id: i32,
+    other: Option<i32>,
+) -> BoxFuture<'a, Result<(), AppError>> {
     async move {
-        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tbltransactions WHERE id = "#);
 
-        let trx = query
-            .push_bind(id)
-            .build_query_as::<ExistTrx>()
-            .fetch_one(db.as_executor())
-            .await
-            .unwrap();
+        let mut ids: Vec<i32> = vec![acc_id];
+        ids.extend(other);
+        for transfer in transfers::query_lock_account_transfers(db, acc_id).await? {
+            ids.extend([transfer.fromaccountid, transfer.toaccountid]);
+        }
+        accounts::lock_accounts(db, &ids).await?;
 
-        trx
+        Ok(())
     }
     .boxed()
 }
 
-fn query_add_trx<'a>(
+/// Inserts `trx` at its place in the account ledger and applies it to the
+/// account balance; `balance_before` and `balance_after` of `trx` are ignored.
+pub fn add_trx_with_balance<'a>(
     db: &'a mut impl Executor,
     trx: AddTrx,
-) -> BoxFuture<'a, MySqlQueryResult> {
+) -> BoxFuture<'a, Result<i32, AppError>> {
     async move {
 
-        let desc: String = match trx.description { 
-            Some(_) => trx.description.unwrap().to_string(),
-            None => "".to_string()
-        };
+        let amount = trx.credit - trx.debit;
+        let acc_id = trx.accountid;
 
-        let values = vec![
-            trx.credit.to_string(),
-            trx.debit.to_string(),
-            desc,
-            trx.balance_before.to_string(),
-            trx.balance_after.to_string(),
-            trx.datetime.to_string(),
-            trx.accountid.to_string(),
-            trx.categoryid.to_string(),
-        ];
+        // account must exist and still be in use
+        let account = accounts::query_detail_account(db, acc_id).await?;
+        validate_active(&account)?;
 
-        let mut query = sqlx::QueryBuilder::new(r#"INSERT INTO tbltransactions (credit, debit, description, balance_before, balance_after, datetime, accountid, categoryid) VALUES ("#);
+        // add trx in the account currency, its balances are set by the recompute below
+        let add = query_add_trx(db, AddTrx { balance_before: 0, balance_after: 0, ..trx }, &account.currency).await?;
+        let trx_id = last_insert_id(&add)?;
 
-        let mut separated = query.separated(", ");
-        for value in values.iter() {
-            separated.push_bind(value);
+        // update account balance
+        accounts::update_acc_balance(db, acc_id, amount).await?;
+
+        // a backdated trx moves every later running balance
+        let new_trx = query_detail_trx(db, trx_id).await?;
+        recompute_balances(db, acc_id, new_trx.datetime, new_trx.id).await?;
+
+        Ok(trx_id)
+    }
+    .boxed()
+}
+
+/// Applies `update` to the existing `trx` and recomputes the running balances
+/// from the earlier of its old and new ledger positions. When the trx moves to
+/// another account, its amount leaves the old account and both ledgers are
+/// recomputed, the old one from the old position and the new one from the new.
+///
+/// Returns the updated trx.
+pub fn update_trx_with_balance<'a>(
+    db: &'a mut impl Executor,
+    trx: &'a ExistTrx,
+    update: UpdateTrx,
+) -> BoxFuture<'a, Result<ExistTrx, AppError>> {
+    async move {
+
+        // moving to another account needs it to exist, still be in use and hold the same currency
+        if let Some(acc_id) = update.accountid {
+            if acc_id != trx.accountid {
+                let account = accounts::query_detail_account(db, acc_id).await?;
+                validate_active(&account)?;
+
+                let current = accounts::query_find_account(db, trx.accountid).await?;
+                if account.currency != current.currency {
+                    return Err(AppError::Conflict(format!(
+                        "transaction {} is in {}, account {} holds {}",
+                        trx.id, current.currency, acc_id, account.currency,
+                    )));
+                }
+            }
         }
-        separated.push_unseparated(") ");
 
-        let add = query
-            .build()
-            .execute(db.as_executor())
-            .await
-            .unwrap();
+        query_update_trx(db, trx.id, update).await?;
+        let updated = query_detail_trx(db, trx.id).await?;
+
+        let exist_amount = (&trx.credit - &trx.debit).to_minor()?;
+        let new_amount = (&updated.credit - &updated.debit).to_minor()?;
 
-        add
+        if updated.accountid != trx.accountid {
+
+            // update both account balances
+            accounts::update_acc_balance(db, trx.accountid, -exist_amount).await?;
+            accounts::update_acc_balance(db, updated.accountid, new_amount).await?;
+
+            // update trx after & bef balance of both ledgers
+            recompute_balances(db, trx.accountid, trx.datetime, trx.id).await?;
+            recompute_balances(db, updated.accountid, updated.datetime, updated.id).await?;
+        } else {
+
+            let amount = new_amount - exist_amount;
+
+            // update account balance
+            if amount != 0 {
+                accounts::update_acc_balance(db, trx.accountid, amount).await?;
+            }
+
+            // update trx after & bef balance, starting at the earliest touched position
+            if amount != 0 || updated.datetime != trx.datetime {
+                let datetime = trx.datetime.min(updated.datetime);
+                recompute_balances(db, trx.accountid, datetime, trx.id).await?;
+            }
+        }
+
+        // detail trx, with its recomputed balances
+        let updated = query_detail_trx(db, trx.id).await?;
+
+        Ok(updated)
     }
     .boxed()
 }
 
-fn update_trx_balance<'a>(
+/// Deletes `trx`, takes its amount back out of the account and recomputes the
+/// running balances after it.
+///
+/// Transfer legs go for good together with their transfer, any other trx is
+/// soft deleted and can be restored.
+pub fn delete_trx_with_balance<'a>(
     db: &'a mut impl Executor,
-    id: i32,
+    trx: &'a ExistTrx,
+) -> BoxFuture<'a, Result<(), AppError>> {
+    async move {
+
+        let amount = (&trx.debit - &trx.credit).to_minor()?;
+
+        // delete trx
+        match trx.transferid {
+            Some(_) => query_delete_trx(db, trx.id).await?,
+            None => query_soft_delete_trx(db, trx.id).await?,
+        };
+
+        // update account balance
+        accounts::update_acc_balance(db, trx.accountid, amount).await?;
+
+        // update trx after & bef balance
+        recompute_balances(db, trx.accountid, trx.datetime, trx.id).await?;
+
+        Ok(())
+    }
+    .boxed()
+}
+
+/// Prepares the ledger of an account that is going away: its trxs give their
+/// amount back to the category budgets, and are soft deleted with the account
+/// by `delete_account_trxs`. A transfer would take its leg on the other
+/// account with it, which restoring the account could not bring back, so an
+/// account with transfers is refused.
+pub fn release_account_trxs<'a>(
+    db: &'a mut impl Executor,
+    base_currency: &'a str,
     acc_id: i32,
-    amount: i64,
-) -> BoxFuture<'a, MySqlQueryResult> {
+    ledger: &'a [ExistTrx],
+) -> BoxFuture<'a, Result<(), AppError>> {
     async move {
 
-        let mut query = sqlx::QueryBuilder::new(r#"UPDATE tbltransactions SET "#);
-        query.push("balance_after = balance_after + ").push_bind(amount)
-            .push(" , balance_before = balance_before + ").push_bind(amount)
-            .push(" , updated_at = current_timestamp() ")
-            .push(" WHERE accountid = ").push_bind(acc_id)
-            .push(" AND id > ").push_bind(id);
+        let transfers = ledger.iter().filter(|trx| trx.transferid.is_some()).count();
+        if transfers > 0 {
+            return Err(AppError::Conflict(format!(
+                "account {} has {} transfer transactions, delete the transfers first or delete it with policy=reassign or archive",
+                acc_id,
+                transfers,
+            )));
+        }
 
-        let res = query
-            .build()
-            .execute(db.as_executor())
-            .await
-            .unwrap();
+        for trx in ledger.iter() {
+            release_budgets(db, base_currency, trx).await?;
+        }
 
-        res
+        Ok(())
     }
     .boxed()
 }
 
-fn update_curr_trx_balance<'a>(
+/// Soft deletes the `ledger` of account `acc_id` with the `deleted_at` of the
+/// account itself, logging each trx.
+pub fn delete_account_trxs<'a>(
     db: &'a mut impl Executor,
-    id: i32,
+    actor: &'a Option<String>,
     acc_id: i32,
-    amount: i64,
-) -> BoxFuture<'a, MySqlQueryResult> {
+    deleted_at: DateTime<Utc>,
+    ledger: &'a [ExistTrx],
+) -> BoxFuture<'a, Result<(), AppError>> {
     async move {
 
-        let mut query = sqlx::QueryBuilder::new(r#"UPDATE tbltransactions SET "#);
-        query.push("balance_after = balance_after + ").push_bind(amount)
-            .push(" , updated_at = current_timestamp() ")
-            .push(" WHERE accountid = ").push_bind(acc_id)
-            .push(" AND id = ").push_bind(id);
+        query_delete_account_trxs(db, acc_id, deleted_at).await?;
 
-        let res = query
-            .build()
-            .execute(db.as_executor())
-            .await
-            .unwrap();
+        let after = query_account_trxs_deleted_at(db, acc_id, deleted_at).await?;
+        record_trxs(db, actor, AuditAction::Delete, ledger.to_vec(), after).await?;
 
-        res
+        Ok(())
     }
     .boxed()
 }
 
-fn query_update_trx<'a>(
+/// Brings back the trxs deleted together with account `acc_id` at `deleted_at`
+/// and charges the category budgets again. The account balance was left as is
+/// while they were deleted, so the ledger needs no recompute.
+pub fn restore_account_trxs<'a>(
     db: &'a mut impl Executor,
-    id: i32,
-    trx: UpdateTrx,
-) -> BoxFuture<'a, MySqlQueryResult> {
+    actor: &'a Option<String>,
+    base_currency: &'a str,
+    acc_id: i32,
+    deleted_at: DateTime<Utc>,
+) -> BoxFuture<'a, Result<(), AppError>> {
     async move {
 
-        let mut query = sqlx::QueryBuilder::new(r#"UPDATE tbltransactions SET "#);
-        let mut updates: Vec<UpdateQuery> = Vec::new();
+        let trxs = query_account_trxs_deleted_at(db, acc_id, deleted_at).await?;
+        query_restore_account_trxs(db, acc_id, deleted_at).await?;
 
-        if trx.credit.is_some() {
-            updates.push(UpdateQuery {
-                key: "credit".to_string(),
-                value: trx.credit.unwrap().to_string(),
-            })
+        // update trx cat budget current periode
+        for trx in trxs.iter() {
+            charge_budgets(db, base_currency, trx).await?;
         }
 
-        if trx.debit.is_some() {
-            updates.push(UpdateQuery {
-                key: "debit".to_string(),
-                value: trx.debit.unwrap().to_string(),
-            })
+        let after = query_account_ledger(db, acc_id).await?;
+        record_trxs(db, actor, AuditAction::Restore, trxs, after).await?;
+
+        Ok(())
+    }
+    .boxed()
+}
+
+/// Moves the whole ledger of account `from` to account `to`, transfers
+/// included, and recomputes the running balances of `to` from the earliest
+/// moved trx.
+pub fn reassign_account_trxs<'a>(
+    db: &'a mut impl Executor,
+    actor: &'a Option<String>,
+    from: i32,
+    to: i32,
+    ledger: &'a [ExistTrx],
+) -> BoxFuture<'a, Result<(), AppError>> {
+    async move {
+
+        if from == to {
+            return Err(AppError::Validation(String::from("transactions cannot be reassigned to the same account")));
         }
 
-        if trx.description.is_some() {
-            updates.push(UpdateQuery {
-                key: "description".to_string(),
-                value: trx.description.unwrap().to_string(),
-            })
+        let account = accounts::query_detail_account(db, to).await?;
+        validate_active(&account)?;
+
+        // trxs keep their amounts, both accounts must hold the same currency
+        let current = accounts::query_find_account(db, from).await?;
+        if account.currency != current.currency {
+            return Err(AppError::Conflict(format!(
+                "account {} holds {}, account {} holds {}",
+                from, current.currency, to, account.currency,
+            )));
         }
 
-        if trx.datetime.is_some() {
-            updates.push(UpdateQuery {
-                key: "datetime".to_string(),
-                value: trx.datetime.unwrap().to_string(),
-            })
+        // a transfer between both accounts would end up on a single one
+        if transfers::query_count_transfers_between(db, from, to).await? > 0 {
+            return Err(AppError::Conflict(format!("accounts {} and {} have transfers between them", from, to)));
         }
 
-        let mut separated = query.separated(", ");
-        for update in updates.iter() {
-            separated.push(update.key.clone())
-                .push_unseparated(" = ")
-                .push_bind_unseparated(update.value.clone());
+        let first = match ledger.first() {
+            Some(first) => first,
+            None => return Ok(()),
+        };
+
+        query_reassign_trxs(db, from, to).await?;
+        transfers::query_reassign_transfers(db, from, to).await?;
+
+        // update account balance
+        let mut amount: i64 = 0;
+        for trx in ledger.iter() {
+            amount += (&trx.credit - &trx.debit).to_minor()?;
         }
+        accounts::update_acc_balance(db, to, amount).await?;
 
-        separated
-            .push("updated_at = current_timestamp()")
-            .push_unseparated(" WHERE id = ")
-            .push_bind_unseparated(id);
-        
-        let res = query.build()
-            .execute(db.as_executor())
-            .await
-            .unwrap();
+        // update trx after & bef balance
+        recompute_balances(db, to, first.datetime, first.id).await?;
+
+        let after = query_account_ledger(db, to).await?;
+        record_trxs(db, actor, AuditAction::Update, ledger.to_vec(), after).await?;
 
-        res
+        Ok(())
     }
     .boxed()
 }
 
-fn query_delete_trx<'a>(
+/// Logs `action` on each trx of `before` with its state found in `after`, for
+/// the changes made to a whole ledger at once.
+fn record_trxs<'a>(
     db: &'a mut impl Executor,
-    id: i32,
-) -> BoxFuture<'a, MySqlQueryResult> {
+    actor: &'a Option<String>,
+    action: AuditAction,
+    mut before: Vec<ExistTrx>,
+    after: Vec<ExistTrx>,
+) -> BoxFuture<'a, Result<(), AppError>> {
     async move {
 
-        let mut query = sqlx::QueryBuilder::new(r#"DELETE FROM tbltransactions WHERE id = "#);
-        let res = query.push_bind(id)
+        // splits are part of the logged state, loaded for all trxs at once
+        let ids: Vec<i32> = before.iter().map(|trx| trx.id).collect();
+        let mut splits = query_splits_by_trx_ids(db, &ids).await?;
+        let mut after: HashMap<i32, ExistTrx> = after.into_iter().map(|trx| (trx.id, trx)).collect();
+
+        for trx in before.iter_mut() {
+            trx.splits = splits.remove(&trx.id).unwrap_or_default();
+            let mut update = after.remove(&trx.id);
+            if let Some(update) = update.as_mut() {
+                update.splits = trx.splits.clone();
+            }
+            audit::record(db, actor, AuditEntity::Trx, trx.id, action, Some(&*trx), update.as_ref()).await?;
+        }
+
+        Ok(())
+    }
+    .boxed()
+}
+
+/// `amount` of a trx of account `acc_id` in the base currency the category
+/// budgets are kept in, at the rate of the trx `datetime`.
+pub fn budget_amount<'a>(
+    db: &'a mut impl Executor,
+    base_currency: &'a str,
+    acc_id: i32,
+    amount: i64,
+    datetime: DateTime<Utc>,
+) -> BoxFuture<'a, Result<i64, AppError>> {
+    async move {
+
+        let account = accounts::query_find_account(db, acc_id).await?;
+        let amount = exchange_rates::convert(db, amount, &account.currency, base_currency, datetime.date_naive()).await?;
+
+        Ok(amount)
+    }
+    .boxed()
+}
+
+/// Budget charges of `trx`: its category, or each category it is split over,
+/// with what it adds to `spent` in the base currency. Transfer legs, and
+/// categories whose type is not budgeted, have none.
+pub fn budget_lines<'a>(
+    db: &'a mut impl Executor,
+    base_currency: &'a str,
+    trx: &'a ExistTrx,
+) -> BoxFuture<'a, Result<Vec<(i32, i64)>, AppError>> {
+    async move {
+
+        let mut lines: Vec<(i32, i64)> = Vec::new();
+        match trx.categoryid {
+            Some(cat_id) => lines.push((cat_id, (&trx.credit - &trx.debit).to_minor()?)),
+            None => {
+                for split in query_trx_splits(db, trx.id).await?.iter() {
+                    lines.push((split.categoryid, split_amount(trx, split)?));
+                }
+            },
+        }
+
+        let mut charges: Vec<(i32, i64)> = Vec::new();
+        for (cat_id, amount) in lines {
+            let direction = trx_cats::query_category_direction(db, cat_id).await?;
+            if !direction.budgeted() {
+                continue;
+            }
+            let amount = budget_amount(db, base_currency, trx.accountid, amount, trx.datetime).await?;
+            charges.push((cat_id, direction.spent(amount)));
+        }
+
+        Ok(charges)
+    }
+    .boxed()
+}
+
+/// Charges `trx` to the budget of each of its categories for the period of its date.
+pub fn charge_budgets<'a>(
+    db: &'a mut impl Executor,
+    base_currency: &'a str,
+    trx: &'a ExistTrx,
+) -> BoxFuture<'a, Result<(), AppError>> {
+    async move {
+
+        for (cat_id, amount) in budget_lines(db, base_currency, trx).await? {
+            trx_cat_budgets::query_update_trx_cat_badget(db, cat_id, trx.datetime.date_naive(), amount).await?;
+        }
+
+        Ok(())
+    }
+    .boxed()
+}
+
+/// Takes the charges of `trx` back out of the budgets it was charged to.
+pub fn release_budgets<'a>(
+    db: &'a mut impl Executor,
+    base_currency: &'a str,
+    trx: &'a ExistTrx,
+) -> BoxFuture<'a, Result<(), AppError>> {
+    async move {
+
+        for (cat_id, amount) in budget_lines(db, base_currency, trx).await? {
+            trx_cat_budgets::query_release_trx_cat_badget(db, cat_id, trx.datetime.date_naive(), amount).await?;
+        }
+
+        Ok(())
+    }
+    .boxed()
+}
+
+/// Fills `splits` of `trx` from `tbltransactionsplits`.
+pub fn load_splits<'a>(
+    db: &'a mut impl Executor,
+    trx: &'a mut ExistTrx,
+) -> BoxFuture<'a, Result<(), AppError>> {
+    async move {
+
+        trx.splits = query_trx_splits(db, trx.id).await?;
+
+        Ok(())
+    }
+    .boxed()
+}
+
+/// Rewrites `balance_before`/`balance_after` of every trx of the account at or
+/// after the ledger position `(datetime, id)`, the ledger being ordered by
+/// `datetime` then `id`.
+///
+/// The chain starts from the balance after the previous trx, or for the first
+/// trx from the opening balance: the account balance minus all its trxs.
+pub fn recompute_balances<'a>(
+    db: &'a mut impl Executor,
+    acc_id: i32,
+    datetime: DateTime<Utc>,
+    id: i32,
+) -> BoxFuture<'a, Result<(), AppError>> {
+    async move {
+
+        let mut balance = match query_prev_balance(db, acc_id, datetime, id).await? {
+            Some(balance) => balance.to_minor()?,
+            None => {
+                let account = accounts::query_detail_account(db, acc_id).await?;
+                let total = query_sum_amount(db, acc_id).await?;
+                (&account.balance - &total).to_minor()?
+            }
+        };
+
+        let trxs = query_ledger_from(db, acc_id, datetime, id).await?;
+        for trx in trxs.iter() {
+            let balance_before = balance;
+            balance += (&trx.credit - &trx.debit).to_minor()?;
+
+            // only touch rows whose chain actually moved
+            if trx.balance_before.to_minor()? != balance_before
+                || trx.balance_after.to_minor()? != balance
+            {
+                query_set_trx_balance(db, trx.id, balance_before, balance).await?;
+            }
+        }
+
+        Ok(())
+    }
+    .boxed()
+}
+
+fn query_list_trx<'a>(
+    db: &'a mut impl Executor,
+    filter: &'a TrxFilter,
+    page: &'a PageParams,
+) -> BoxFuture<'a, Result<Vec<ExistTrx>, AppError>> {
+    async move {
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tbltransactions WHERE 1 = 1"#);
+
+        if let Some(from) = filter.from {
+            query.push(" AND datetime >= ").push_bind(from);
+        }
+
+        if let Some(to) = filter.to {
+            query.push(" AND datetime <= ").push_bind(to);
+        }
+
+        // a transaction moves `credit + debit`, one of them being zero
+        if let Some(min_amount) = filter.min_amount {
+            query.push(" AND (credit + debit) >= ").push_bind(min_amount);
+        }
+
+        if let Some(max_amount) = filter.max_amount {
+            query.push(" AND (credit + debit) <= ").push_bind(max_amount);
+        }
+
+        match filter.direction {
+            Some(TrxDirection::Credit) => { query.push(" AND credit > 0"); }
+            Some(TrxDirection::Debit) => { query.push(" AND debit > 0"); }
+            None => {}
+        }
+
+        if !filter.accountids.is_empty() {
+            query.push(" AND accountid IN ");
+            push_ids(&mut query, &filter.accountids);
+        }
+
+        // split trxs match on the category of any of their lines
+        if !filter.categoryids.is_empty() {
+            query.push(" AND (categoryid IN ");
+            push_ids(&mut query, &filter.categoryids);
+            query.push(" OR id IN (SELECT transactionid FROM tbltransactionsplits WHERE categoryid IN ");
+            push_ids(&mut query, &filter.categoryids);
+            query.push("))");
+        }
+
+        if let Some(typeid) = filter.typeid {
+            query
+                .push(" AND (categoryid IN (SELECT id FROM tbltransactioncategories WHERE typeid = ")
+                .push_bind(typeid)
+                .push(") OR id IN (SELECT split.transactionid FROM tbltransactionsplits split JOIN tbltransactioncategories category ON category.id = split.categoryid WHERE category.typeid = ")
+                .push_bind(typeid)
+                .push("))");
+        }
+
+        if let Some(q) = &filter.q {
+            query
+                .push(" AND LOWER(description) LIKE ")
+                .push_bind(like_pattern(q));
+        }
+
+        match filter.transfer {
+            Some(true) => { query.push(" AND transferid IS NOT NULL"); }
+            Some(false) => { query.push(" AND transferid IS NULL"); }
+            None => {}
+        }
+
+        push_not_deleted(&mut query, "tbltransactions", filter.include_deleted, true);
+
+        push_page(&mut query, page, "tbltransactions", true);
+
+        let trxs = query
+            .build_query_as::<ExistTrx>()
+            .fetch_all(db.as_executor())
+            .await?;
+
+        Ok(trxs)
+    }
+    .boxed()
+}
+
+/// `%q%` in lower case, with the `LIKE` wildcards of `q` escaped by MySQL's default `\`.
+fn like_pattern(q: &str) -> String {
+    let escaped = q
+        .to_lowercase()
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+
+    format!("%{}%", escaped)
+}
+
+pub fn query_detail_trx<'a>(
+    db: &'a mut impl Executor,
+    id: i32
+) -> BoxFuture<'a, Result<ExistTrx, AppError>> {
+    async move {
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tbltransactions WHERE id = "#);
+
+        let trx = query
+            .push_bind(id)
+            .push(" AND deleted_at IS NULL")
+            .build_query_as::<ExistTrx>()
+            .fetch_optional(db.as_executor())
+            .await?;
+
+        trx.ok_or_else(|| AppError::NotFound(format!("transaction {} not found", id)))
+    }
+    .boxed()
+}
+
+/// Like `query_detail_trx`, deleted trxs included.
+pub fn query_find_trx<'a>(
+    db: &'a mut impl Executor,
+    id: i32
+) -> BoxFuture<'a, Result<ExistTrx, AppError>> {
+    async move {
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tbltransactions WHERE id = "#);
+
+        let trx = query
+            .push_bind(id)
+            .build_query_as::<ExistTrx>()
+            .fetch_optional(db.as_executor())
+            .await?;
+
+        trx.ok_or_else(|| AppError::NotFound(format!("transaction {} not found", id)))
+    }
+    .boxed()
+}
+
+/// Like `query_find_trx`, a locking read that takes no snapshot.
+fn query_lock_trx<'a>(
+    db: &'a mut impl Executor,
+    id: i32
+) -> BoxFuture<'a, Result<ExistTrx, AppError>> {
+    async move {
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tbltransactions WHERE id = "#);
+
+        let trx = query
+            .push_bind(id)
+            .push(" FOR UPDATE")
+            .build_query_as::<ExistTrx>()
+            .fetch_optional(db.as_executor())
+            .await?;
+
+        trx.ok_or_else(|| AppError::NotFound(format!("transaction {} not found", id)))
+    }
+    .boxed()
+}
+
+fn query_add_trx<'a>(
+    db: &'a mut impl Executor,
+    trx: AddTrx,
+    currency: &'a str,
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
+    async move {
+
+        let desc: String = match trx.description { 
+            Some(_) => trx.description.unwrap().to_string(),
+            None => "".to_string()
+        };
+
+        let values = vec![
+            trx.credit.to_string(),
+            trx.debit.to_string(),
+            currency.to_string(),
+            desc,
+            trx.balance_before.to_string(),
+            trx.balance_after.to_string(),
+            trx.datetime.to_string(),
+            trx.accountid.to_string(),
+        ];
+
+        let mut query = sqlx::QueryBuilder::new(r#"INSERT INTO tbltransactions (credit, debit, currency, description, balance_before, balance_after, datetime, accountid, categoryid, transferid) VALUES ("#);
+
+        let mut separated = query.separated(", ");
+        for value in values.iter() {
+            separated.push_bind(value);
+        }
+
+        // NULL for transfer legs and regular trxs respectively
+        separated.push_bind(trx.categoryid);
+        separated.push_bind(trx.transferid);
+        separated.push_unseparated(") ");
+
+        let add = query
+            .build()
+            .execute(db.as_executor())
+            .await?;
+
+        Ok(add)
+    }
+    .boxed()
+}
+
+/// `balance_after` of the trx right before the ledger position `(datetime, id)`.
+fn query_prev_balance<'a>(
+    db: &'a mut impl Executor,
+    acc_id: i32,
+    datetime: DateTime<Utc>,
+    id: i32,
+) -> BoxFuture<'a, Result<Option<Money>, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT balance_after FROM tbltransactions WHERE deleted_at IS NULL AND accountid = "#);
+        query.push_bind(acc_id)
+            .push(" AND (datetime < ").push_bind(datetime)
+            .push(" OR (datetime = ").push_bind(datetime)
+            .push(" AND id < ").push_bind(id)
+            .push(")) ORDER BY datetime DESC, id DESC LIMIT 1");
+
+        let balance = query
+            .build_query_scalar::<Money>()
+            .fetch_optional(db.as_executor())
+            .await?;
+
+        Ok(balance)
+    }
+    .boxed()
+}
+
+/// Sum of `credit - debit` over every trx of the account, deleted ones left out.
+fn query_sum_amount<'a>(
+    db: &'a mut impl Executor,
+    acc_id: i32,
+) -> BoxFuture<'a, Result<Money, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT COALESCE(SUM(credit - debit), 0) FROM tbltransactions WHERE deleted_at IS NULL AND accountid = "#);
+        query.push_bind(acc_id);
+
+        let total = query
+            .build_query_scalar::<Money>()
+            .fetch_one(db.as_executor())
+            .await?;
+
+        Ok(total)
+    }
+    .boxed()
+}
+
+/// Every trx of the account that is not deleted, in ledger order.
+pub fn query_account_ledger<'a>(
+    db: &'a mut impl Executor,
+    acc_id: i32,
+) -> BoxFuture<'a, Result<Vec<ExistTrx>, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tbltransactions WHERE deleted_at IS NULL AND accountid = "#);
+        query.push_bind(acc_id)
+            .push(" ORDER BY datetime ASC, id ASC");
+
+        let trxs = query
+            .build_query_as::<ExistTrx>()
+            .fetch_all(db.as_executor())
+            .await?;
+
+        Ok(trxs)
+    }
+    .boxed()
+}
+
+/// Number of trxs the account ever had, deleted ones included.
+pub fn query_count_account_trxs<'a>(
+    db: &'a mut impl Executor,
+    acc_id: i32,
+) -> BoxFuture<'a, Result<i64, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT COUNT(*) FROM tbltransactions WHERE accountid = "#);
+        query.push_bind(acc_id);
+
+        let count = query
+            .build_query_scalar::<i64>()
+            .fetch_one(db.as_executor())
+            .await?;
+
+        Ok(count)
+    }
+    .boxed()
+}
+
+/// Trxs of the account at or after the ledger position `(datetime, id)`, in ledger order.
+fn query_ledger_from<'a>(
+    db: &'a mut impl Executor,
+    acc_id: i32,
+    datetime: DateTime<Utc>,
+    id: i32,
+) -> BoxFuture<'a, Result<Vec<ExistTrx>, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tbltransactions WHERE deleted_at IS NULL AND accountid = "#);
+        query.push_bind(acc_id)
+            .push(" AND (datetime > ").push_bind(datetime)
+            .push(" OR (datetime = ").push_bind(datetime)
+            .push(" AND id >= ").push_bind(id)
+            .push(")) ORDER BY datetime ASC, id ASC");
+
+        let trxs = query
+            .build_query_as::<ExistTrx>()
+            .fetch_all(db.as_executor())
+            .await?;
+
+        Ok(trxs)
+    }
+    .boxed()
+}
+
+pub fn query_set_trx_balance<'a>(
+    db: &'a mut impl Executor,
+    id: i32,
+    balance_before: i64,
+    balance_after: i64,
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"UPDATE tbltransactions SET "#);
+        query.push("balance_before = ").push_bind(balance_before)
+            .push(" , balance_after = ").push_bind(balance_after)
+            .push(" , updated_at = current_timestamp() ")
+            .push(" WHERE id = ").push_bind(id);
+
+        let res = query
+            .build()
+            .execute(db.as_executor())
+            .await?;
+
+        Ok(res)
+    }
+    .boxed()
+}
+
+pub fn query_update_trx<'a>(
+    db: &'a mut impl Executor,
+    id: i32,
+    trx: UpdateTrx,
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"UPDATE tbltransactions SET "#);
+        let mut updates: Vec<UpdateQuery> = Vec::new();
+
+        if let Some(credit) = trx.credit {
+            updates.push(UpdateQuery {
+                key: "credit".to_string(),
+                value: credit.to_string(),
+            })
+        }
+
+        if let Some(debit) = trx.debit {
+            updates.push(UpdateQuery {
+                key: "debit".to_string(),
+                value: debit.to_string(),
+            })
+        }
+
+        if let Some(description) = trx.description {
+            updates.push(UpdateQuery {
+                key: "description".to_string(),
+                value: description.to_string(),
+            })
+        }
+
+        if let Some(datetime) = trx.datetime {
+            updates.push(UpdateQuery {
+                key: "datetime".to_string(),
+                value: datetime.to_string(),
+            })
+        }
+
+        if let Some(accountid) = trx.accountid {
+            updates.push(UpdateQuery {
+                key: "accountid".to_string(),
+                value: accountid.to_string(),
+            })
+        }
+
+        if let Some(categoryid) = trx.categoryid {
+            updates.push(UpdateQuery {
+                key: "categoryid".to_string(),
+                value: categoryid.to_string(),
+            })
+        }
+
+        let mut separated = query.separated(", ");
+        for update in updates.iter() {
+            separated.push(update.key.clone())
+                .push_unseparated(" = ")
+                .push_bind_unseparated(update.value.clone());
+        }
+
+        separated
+            .push("updated_at = current_timestamp()")
+            .push_unseparated(" WHERE id = ")
+            .push_bind_unseparated(id);
+        
+        let res = query.build()
+            .execute(db.as_executor())
+            .await?;
+
+        Ok(res)
+    }
+    .boxed()
+}
+
+/// Moves a trx to a single category, or with `None` leaves it to its splits.
+fn query_set_trx_category<'a>(
+    db: &'a mut impl Executor,
+    id: i32,
+    cat_id: Option<i32>,
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"UPDATE tbltransactions SET "#);
+        query.push("categoryid = ").push_bind(cat_id)
+            .push(" , updated_at = current_timestamp() ")
+            .push(" WHERE id = ").push_bind(id);
+
+        let res = query
+            .build()
+            .execute(db.as_executor())
+            .await?;
+
+        Ok(res)
+    }
+    .boxed()
+}
+
+/// Split lines of a trx, in the order they were given.
+pub fn query_trx_splits<'a>(
+    db: &'a mut impl Executor,
+    trx_id: i32,
+) -> BoxFuture<'a, Result<Vec<ExistTrxSplit>, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tbltransactionsplits WHERE transactionid = "#);
+        query.push_bind(trx_id)
+            .push(" ORDER BY id ASC");
+
+        let splits = query
+            .build_query_as::<ExistTrxSplit>()
+            .fetch_all(db.as_executor())
+            .await?;
+
+        Ok(splits)
+    }
+    .boxed()
+}
+
+fn query_splits_by_trx_ids<'a>(
+    db: &'a mut impl Executor,
+    trx_ids: &'a [i32],
+) -> BoxFuture<'a, Result<HashMap<i32, Vec<ExistTrxSplit>>, AppError>> {
+    async move {
+        if trx_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tbltransactionsplits WHERE transactionid IN "#);
+        push_ids(&mut query, trx_ids);
+        query.push(" ORDER BY id ASC");
+
+        let rows = query
+            .build_query_as::<ExistTrxSplit>()
+            .fetch_all(db.as_executor())
+            .await?;
+
+        let mut splits: HashMap<i32, Vec<ExistTrxSplit>> = HashMap::new();
+        for row in rows.into_iter() {
+            splits.entry(row.transactionid).or_default().push(row);
+        }
+
+        Ok(splits)
+    }
+    .boxed()
+}
+
+fn query_add_trx_split<'a>(
+    db: &'a mut impl Executor,
+    trx_id: i32,
+    split: &'a NewTrxSplit,
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"INSERT INTO tbltransactionsplits (transactionid, categoryid, amount, memo) VALUES ("#);
+
+        let mut separated = query.separated(", ");
+        separated.push_bind(trx_id);
+        separated.push_bind(split.categoryid);
+        separated.push_bind(split.amount);
+        separated.push_bind(split.memo.clone());
+        separated.push_unseparated(") ");
+
+        let add = query
+            .build()
+            .execute(db.as_executor())
+            .await?;
+
+        Ok(add)
+    }
+    .boxed()
+}
+
+fn query_delete_trx_splits<'a>(
+    db: &'a mut impl Executor,
+    trx_id: i32,
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"DELETE FROM tbltransactionsplits WHERE transactionid = "#);
+        let res = query.push_bind(trx_id)
+            .build()
+            .execute(db.as_executor())
+            .await?;
+
+        Ok(res)
+    }
+    .boxed()
+}
+
+fn query_reassign_trxs<'a>(
+    db: &'a mut impl Executor,
+    from: i32,
+    to: i32,
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"UPDATE tbltransactions SET "#);
+        query.push("accountid = ").push_bind(to)
+            .push(" , updated_at = current_timestamp() ")
+            .push(" WHERE accountid = ").push_bind(from);
+
+        let res = query
+            .build()
+            .execute(db.as_executor())
+            .await?;
+
+        Ok(res)
+    }
+    .boxed()
+}
+
+fn query_delete_trx<'a>(
+    db: &'a mut impl Executor,
+    id: i32,
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"DELETE FROM tbltransactions WHERE id = "#);
+        let res = query.push_bind(id)
+            .build()
+            .execute(db.as_executor())
+            .await?;
+
+        Ok(res)
+    }
+    .boxed()
+}
+
+fn query_soft_delete_trx<'a>(
+    db: &'a mut impl Executor,
+    id: i32,
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"UPDATE tbltransactions SET "#);
+        query.push("deleted_at = current_timestamp()")
+            .push(" WHERE id = ").push_bind(id)
+            .push(" AND deleted_at IS NULL");
+
+        let res = query
+            .build()
+            .execute(db.as_executor())
+            .await?;
+
+        Ok(res)
+    }
+    .boxed()
+}
+
+fn query_restore_trx<'a>(
+    db: &'a mut impl Executor,
+    id: i32,
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"UPDATE tbltransactions SET "#);
+        query.push("deleted_at = NULL")
+            .push(" , updated_at = current_timestamp() ")
+            .push(" WHERE id = ").push_bind(id);
+
+        let res = query
+            .build()
+            .execute(db.as_executor())
+            .await?;
+
+        Ok(res)
+    }
+    .boxed()
+}
+
+/// Soft deletes every remaining trx of the account with the `deleted_at` of the account itself.
+fn query_delete_account_trxs<'a>(
+    db: &'a mut impl Executor,
+    acc_id: i32,
+    deleted_at: DateTime<Utc>,
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"UPDATE tbltransactions SET "#);
+        query.push("deleted_at = ").push_bind(deleted_at)
+            .push(" WHERE accountid = ").push_bind(acc_id)
+            .push(" AND deleted_at IS NULL");
+
+        let res = query
+            .build()
+            .execute(db.as_executor())
+            .await?;
+
+        Ok(res)
+    }
+    .boxed()
+}
+
+fn query_account_trxs_deleted_at<'a>(
+    db: &'a mut impl Executor,
+    acc_id: i32,
+    deleted_at: DateTime<Utc>,
+) -> BoxFuture<'a, Result<Vec<ExistTrx>, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"SELECT * FROM tbltransactions WHERE accountid = "#);
+        query.push_bind(acc_id)
+            .push(" AND deleted_at = ").push_bind(deleted_at);
+
+        let trxs = query
+            .build_query_as::<ExistTrx>()
+            .fetch_all(db.as_executor())
+            .await?;
+
+        Ok(trxs)
+    }
+    .boxed()
+}
+
+fn query_restore_account_trxs<'a>(
+    db: &'a mut impl Executor,
+    acc_id: i32,
+    deleted_at: DateTime<Utc>,
+) -> BoxFuture<'a, Result<MySqlQueryResult, AppError>> {
+    async move {
+
+        let mut query = sqlx::QueryBuilder::new(r#"UPDATE tbltransactions SET "#);
+        query.push("deleted_at = NULL")
+            .push(" , updated_at = current_timestamp() ")
+            .push(" WHERE accountid = ").push_bind(acc_id)
+            .push(" AND deleted_at = ").push_bind(deleted_at);
+
+        let res = query
             .build()
             .execute(db.as_executor())
-            .await
-            .unwrap();
+            .await?;
 
-        res
+        Ok(res)
     }
     .boxed()
 }
\ No newline at end of file
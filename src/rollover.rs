@@ -0,0 +1,59 @@
+use crate::config::RolloverConfig;
+use crate::errors::AppError;
+use crate::models::trx_cat_budgets::ExistTrxCatBudget;
+use crate::repositories::trx_cat_budgets::{TrxCatBudgetRepo, Trait};
+
+use chrono::Utc;
+use sqlx::mysql::MySqlPool;
+
+/// Actor the audit log credits with the budgets the rollover creates.
+const ACTOR: &str = "rollover";
+
+/// Entry point of the `rollover` subcommand, a single run whether or not the
+/// background job is enabled.
+pub async fn command(pool: &MySqlPool, base_currency: &str, derived_budgets: bool, args: &[String]) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if let Some(other) = args.first() {
+        return Err(format!("unknown rollover argument `{}`", other).into());
+    }
+
+    let created = run(pool, base_currency, derived_budgets).await?;
+    for budget in created.iter() {
+        println!("category {}: budget {} for {}", budget.categoryid, budget.id, budget.periode);
+    }
+    println!("{} budgets created", created.len());
+
+    Ok(())
+}
+
+/// Starts the background job rolling budgets over every `interval`.
+pub fn spawn(pool: MySqlPool, config: RolloverConfig, base_currency: String, derived_budgets: bool) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(config.interval());
+
+        loop {
+            interval.tick().await;
+
+            match run(&pool, &base_currency, derived_budgets).await {
+                Ok(created) if !created.is_empty() => log::info!("rolled over {} budgets", created.len()),
+                Ok(_) => {}
+                Err(err) => log::error!("budget rollover failed: {}", err),
+            }
+        }
+    });
+}
+
+/// Creates the budgets of the periods that started since the latest budget
+/// of each category ended.
+async fn run(pool: &MySqlPool, base_currency: &str, derived_budgets: bool) -> Result<Vec<ExistTrxCatBudget>, AppError> {
+    let today = Utc::now().date_naive();
+
+    let repo = TrxCatBudgetRepo::new(pool.clone())
+        .with_actor(Some(String::from(ACTOR)))
+        .with_base_currency(base_currency)
+        .with_derived_budgets(derived_budgets);
+    let mut tx = repo.start_transaction().await?;
+    let created = tx.trx_cat_budget_rollover(today).await?;
+    tx.commit().await?;
+
+    Ok(created)
+}